@@ -0,0 +1,35 @@
+CREATE TABLE backfill_progress (
+    job_name            TEXT PRIMARY KEY,
+
+    program_id          TEXT NOT NULL,
+
+    -- Oldest signature discovered so far; paging resumes `before` it.
+    discovery_cursor    TEXT,
+    discovery_complete  BOOLEAN NOT NULL DEFAULT false,
+
+    last_processed_slot       BIGINT,
+    last_processed_signature  TEXT,
+
+    started_at          TIMESTAMP NOT NULL DEFAULT now(),
+    updated_at          TIMESTAMP NOT NULL DEFAULT now()
+);
+
+
+CREATE TABLE backfill_signatures (
+    tx_signature    TEXT PRIMARY KEY,
+
+    job_name        TEXT NOT NULL,
+    slot            BIGINT NOT NULL,
+
+    -- Discovery order; higher values are older within the same slot.
+    seq             BIGSERIAL NOT NULL,
+
+    processed       BOOLEAN NOT NULL DEFAULT false,
+
+    CONSTRAINT fk_backfill_job
+        FOREIGN KEY (job_name)
+        REFERENCES backfill_progress(job_name)
+        ON DELETE CASCADE
+);
+
+CREATE INDEX idx_backfill_pending ON backfill_signatures(job_name, processed, slot, seq DESC);
@@ -0,0 +1,25 @@
+ALTER TABLE processed_events
+    ADD COLUMN slot           BIGINT,
+    ADD COLUMN commitment     TEXT NOT NULL DEFAULT 'confirmed',
+    ADD COLUMN finalized_at   TIMESTAMP;
+
+CREATE INDEX idx_processed_events_unfinalized
+    ON processed_events(slot)
+    WHERE finalized_at IS NULL;
+
+
+-- Balance effect of every event applied by the indexer, so a transaction from
+-- a forked-out slot can be reversed exactly.
+CREATE TABLE applied_events (
+    tx_signature        TEXT NOT NULL,
+    event_index         INTEGER NOT NULL,
+
+    event_type          TEXT NOT NULL,
+    vault_pda           TEXT NOT NULL,
+    counterparty_vault  TEXT,
+    amount              BIGINT NOT NULL,
+
+    applied_at          TIMESTAMP NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (tx_signature, event_index)
+);
@@ -0,0 +1,6 @@
+-- Commitment level each transaction was indexed at, so consumers can decide
+-- how far to trust rows that aren't finalized yet.
+ALTER TABLE transactions
+    ADD COLUMN commitment TEXT NOT NULL DEFAULT 'confirmed';
+
+CREATE INDEX idx_tx_commitment ON transactions(commitment) WHERE commitment <> 'finalized';
@@ -0,0 +1,16 @@
+-- Keep the slot with each applied event so replay ordering no longer depends
+-- on processed_events, which is now pruned.
+ALTER TABLE applied_events
+    ADD COLUMN slot BIGINT NOT NULL DEFAULT 0;
+
+UPDATE applied_events a
+SET slot = p.slot
+FROM processed_events p
+WHERE p.tx_signature = a.tx_signature
+  AND p.slot IS NOT NULL;
+
+CREATE INDEX idx_applied_events_order ON applied_events(slot, tx_signature, event_index);
+
+CREATE INDEX idx_processed_events_finalized_at
+    ON processed_events(finalized_at)
+    WHERE finalized_at IS NOT NULL;
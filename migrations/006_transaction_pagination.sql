@@ -0,0 +1,5 @@
+-- Serves keyset pagination of a user's history (`get_by_user_page`): the
+-- range scan follows the sort order, and INCLUDE makes it index-only.
+CREATE INDEX idx_tx_user_slot
+    ON transactions(user_pubkey, slot DESC, tx_signature DESC)
+    INCLUDE (vault_pda, tx_type, amount, block_time);
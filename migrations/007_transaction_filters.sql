@@ -0,0 +1,3 @@
+-- Type + time range scans used by filtered queries and compliance exports.
+CREATE INDEX idx_tx_type_block_time ON transactions(tx_type, block_time);
+CREATE INDEX idx_tx_block_time ON transactions(block_time);
@@ -0,0 +1,5 @@
+-- Keyset orderings and filters used by `list_vaults`.
+CREATE INDEX idx_vaults_created_at ON vaults(created_at, vault_pda);
+CREATE INDEX idx_vaults_total_balance ON vaults(total_balance DESC, vault_pda DESC);
+CREATE INDEX idx_vaults_last_synced_at ON vaults(last_synced_at, vault_pda);
+CREATE INDEX idx_vaults_mint ON vaults(mint);
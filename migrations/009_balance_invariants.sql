@@ -0,0 +1,6 @@
+-- Backstop for the guarded updates in vault_repo: balances can never go
+-- negative. NOT VALID so existing rows aren't rechecked; new writes are.
+ALTER TABLE vaults
+    ADD CONSTRAINT vaults_balances_non_negative
+    CHECK (total_balance >= 0 AND locked_balance >= 0 AND available_balance >= 0)
+    NOT VALID;
@@ -0,0 +1,41 @@
+-- Append-only history of every balance mutation. The vaults row holds the
+-- current state; this table is the audit trail that explains how it got there.
+CREATE TABLE IF NOT EXISTS balance_ledger (
+    id               BIGSERIAL PRIMARY KEY,
+    vault_pda        TEXT NOT NULL REFERENCES vaults(vault_pda),
+    entry_type       TEXT NOT NULL CHECK (entry_type IN (
+        'deposit',
+        'withdraw',
+        'lock',
+        'unlock',
+        'transfer_in',
+        'transfer_out',
+        'set_balance',
+        'revert_deposit',
+        'revert_withdraw',
+        'correction'
+    )),
+    amount           BIGINT NOT NULL,
+    total_before     BIGINT NOT NULL,
+    total_after      BIGINT NOT NULL,
+    available_before BIGINT NOT NULL,
+    available_after  BIGINT NOT NULL,
+    locked_before    BIGINT NOT NULL,
+    locked_after     BIGINT NOT NULL,
+    created_at       TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_balance_ledger_vault
+    ON balance_ledger (vault_pda, id DESC);
+
+-- Entries are immutable: reject any UPDATE or DELETE.
+CREATE OR REPLACE FUNCTION balance_ledger_immutable() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'balance_ledger is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS balance_ledger_no_mutation ON balance_ledger;
+CREATE TRIGGER balance_ledger_no_mutation
+    BEFORE UPDATE OR DELETE ON balance_ledger
+    FOR EACH ROW EXECUTE FUNCTION balance_ledger_immutable();
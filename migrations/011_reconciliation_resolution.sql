@@ -0,0 +1,13 @@
+-- Triage of reconciliation discrepancies: who resolved an entry, when, and why.
+UPDATE reconciliation_logs SET resolved = false WHERE resolved IS NULL;
+
+ALTER TABLE reconciliation_logs
+    ALTER COLUMN resolved SET NOT NULL,
+    ADD COLUMN IF NOT EXISTS resolved_at     TIMESTAMP,
+    ADD COLUMN IF NOT EXISTS resolved_by     TEXT,
+    ADD COLUMN IF NOT EXISTS resolution_note TEXT;
+
+-- Open items are what get queried; resolved ones only accumulate.
+CREATE INDEX IF NOT EXISTS idx_reconciliation_open
+    ON reconciliation_logs (detected_at)
+    WHERE NOT resolved;
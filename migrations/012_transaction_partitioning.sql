@@ -0,0 +1,107 @@
+-- Range-partition transactions by block_time, one partition per month.
+--
+-- Unique constraints on a partitioned table must include the partition key,
+-- so the primary key becomes (id, block_time) and signature uniqueness is
+-- (tx_signature, block_time). A signature always carries the same block
+-- time, so this still rejects duplicates.
+
+-- Free the global index names so the new table can reuse them.
+ALTER TABLE transactions RENAME CONSTRAINT transactions_pkey TO transactions_unpartitioned_pkey;
+ALTER TABLE transactions RENAME CONSTRAINT transactions_tx_signature_key TO transactions_unpartitioned_tx_signature_key;
+DROP INDEX IF EXISTS idx_tx_vault;
+DROP INDEX IF EXISTS idx_tx_user;
+DROP INDEX IF EXISTS idx_tx_network;
+DROP INDEX IF EXISTS idx_tx_commitment;
+DROP INDEX IF EXISTS idx_tx_user_slot;
+DROP INDEX IF EXISTS idx_tx_type_block_time;
+DROP INDEX IF EXISTS idx_tx_block_time;
+
+ALTER TABLE transactions RENAME TO transactions_unpartitioned;
+
+CREATE TABLE transactions (
+    id              UUID NOT NULL,
+
+    vault_pda       TEXT NOT NULL,
+    program_id      TEXT NOT NULL,
+    network         TEXT NOT NULL,
+
+    user_pubkey     TEXT,
+    tx_signature    TEXT NOT NULL,
+
+    tx_type         transaction_type NOT NULL,
+    amount          BIGINT NOT NULL,
+
+    slot            BIGINT NOT NULL,
+    block_time      TIMESTAMP NOT NULL,
+
+    created_at      TIMESTAMP DEFAULT now(),
+    commitment      TEXT NOT NULL DEFAULT 'confirmed',
+
+    PRIMARY KEY (id, block_time),
+    UNIQUE (tx_signature, block_time),
+
+    CONSTRAINT fk_transactions_vault
+        FOREIGN KEY (vault_pda)
+        REFERENCES vaults(vault_pda)
+        ON DELETE CASCADE
+) PARTITION BY RANGE (block_time);
+
+-- Catches rows outside every monthly partition, e.g. a backfill reaching
+-- further back than the oldest partition.
+CREATE TABLE transactions_default PARTITION OF transactions DEFAULT;
+
+-- Create the partition for the month containing `month_start`.
+-- Returns false if it already exists.
+CREATE OR REPLACE FUNCTION create_transaction_partition(month_start DATE)
+RETURNS BOOLEAN AS $$
+DECLARE
+    start_ts       TIMESTAMP := date_trunc('month', month_start)::timestamp;
+    end_ts         TIMESTAMP := start_ts + INTERVAL '1 month';
+    partition_name TEXT := 'transactions_' || to_char(start_ts, '"y"YYYY"m"MM');
+BEGIN
+    IF to_regclass(partition_name) IS NOT NULL THEN
+        RETURN false;
+    END IF;
+
+    EXECUTE format(
+        'CREATE TABLE %I PARTITION OF transactions FOR VALUES FROM (%L) TO (%L)',
+        partition_name, start_ts, end_ts
+    );
+
+    RETURN true;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Partitions for every month with existing rows, plus three months ahead.
+SELECT create_transaction_partition(month::date)
+FROM generate_series(
+    date_trunc('month', LEAST(
+        (SELECT MIN(block_time) FROM transactions_unpartitioned),
+        now()::timestamp
+    )),
+    date_trunc('month', now()) + INTERVAL '3 months',
+    INTERVAL '1 month'
+) AS month;
+
+INSERT INTO transactions (
+    id, vault_pda, program_id, network, user_pubkey, tx_signature,
+    tx_type, amount, slot, block_time, created_at, commitment
+)
+SELECT
+    id, vault_pda, program_id, network, user_pubkey, tx_signature,
+    tx_type, amount, slot, block_time, created_at, commitment
+FROM transactions_unpartitioned;
+
+DROP TABLE transactions_unpartitioned;
+
+-- Indexes on the parent are created on every partition, current and future.
+CREATE INDEX idx_tx_vault ON transactions(vault_pda);
+CREATE INDEX idx_tx_user ON transactions(user_pubkey);
+CREATE INDEX idx_tx_network ON transactions(network);
+CREATE INDEX idx_tx_signature ON transactions(tx_signature);
+CREATE INDEX idx_tx_commitment ON transactions(commitment) WHERE commitment <> 'finalized';
+CREATE INDEX idx_tx_user_slot
+    ON transactions(user_pubkey, slot DESC, tx_signature DESC)
+    INCLUDE (vault_pda, tx_type, amount, block_time);
+CREATE INDEX idx_tx_type_block_time ON transactions(tx_type, block_time);
+CREATE INDEX idx_tx_block_time ON transactions(block_time);
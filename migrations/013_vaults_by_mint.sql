@@ -0,0 +1,2 @@
+-- Per-mint keyset pages (`get_vaults_by_mint`) read in index order.
+CREATE INDEX idx_vaults_mint_created_at ON vaults(mint, created_at, vault_pda);
@@ -0,0 +1,3 @@
+-- Prefix lookups (`tx_signature LIKE 'abc%'`) from `get_by_signature`;
+-- pattern_ops makes the index usable regardless of the database collation.
+CREATE INDEX idx_tx_signature_prefix ON transactions(tx_signature text_pattern_ops);
@@ -0,0 +1,5 @@
+-- Full decoded event behind each transaction row, so fields added by program
+-- upgrades can be queried retroactively without re-indexing from the chain.
+ALTER TABLE transactions ADD COLUMN event_payload JSONB;
+
+CREATE INDEX idx_tx_event_payload ON transactions USING GIN (event_payload jsonb_path_ops);
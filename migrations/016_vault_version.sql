@@ -0,0 +1,3 @@
+-- Optimistic concurrency: bumped on every update of a vault row, so
+-- read-modify-write callers can detect that someone else wrote in between.
+ALTER TABLE vaults ADD COLUMN version BIGINT NOT NULL DEFAULT 0;
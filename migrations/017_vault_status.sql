@@ -0,0 +1,6 @@
+-- Vault lifecycle. Balance updates only apply to active vaults.
+ALTER TABLE vaults
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'active'
+    CHECK (status IN ('active', 'closing', 'closed', 'frozen'));
+
+CREATE INDEX idx_vaults_status ON vaults(status) WHERE status <> 'active';
@@ -0,0 +1,20 @@
+-- Per-user settings live here rather than on vaults. Rows are created lazily
+-- the first time the indexer sees a vault owned by the user.
+CREATE TABLE users (
+    pubkey                      TEXT PRIMARY KEY,
+    created_at                  TIMESTAMP NOT NULL DEFAULT now(),
+    metadata                    JSONB NOT NULL DEFAULT '{}',
+    notification_preferences    JSONB NOT NULL DEFAULT '{}'
+);
+
+INSERT INTO users (pubkey, created_at)
+SELECT owner_pubkey, MIN(created_at)
+FROM vaults
+GROUP BY owner_pubkey
+ON CONFLICT (pubkey) DO NOTHING;
+
+ALTER TABLE vaults
+    ADD CONSTRAINT fk_vaults_owner
+    FOREIGN KEY (owner_pubkey) REFERENCES users(pubkey);
+
+CREATE INDEX idx_vaults_owner ON vaults(owner_pubkey);
@@ -0,0 +1,20 @@
+-- API keys for the authentication middleware. Only a SHA-256 hash of each
+-- key is stored; `key_prefix` is the non-secret part used to look it up.
+CREATE TABLE api_keys (
+    id              UUID PRIMARY KEY,
+    key_prefix      TEXT NOT NULL UNIQUE,
+    key_hash        TEXT NOT NULL,
+
+    owner           TEXT NOT NULL,
+    scopes          TEXT[] NOT NULL DEFAULT '{}',
+
+    created_at      TIMESTAMP NOT NULL DEFAULT now(),
+    expires_at      TIMESTAMP,
+    last_used_at    TIMESTAMP,
+    revoked_at      TIMESTAMP,
+
+    -- Key this one replaced through `rotate`
+    rotated_from    UUID REFERENCES api_keys(id)
+);
+
+CREATE INDEX idx_api_keys_owner ON api_keys(owner);
@@ -0,0 +1,12 @@
+-- Which balance component a discrepancy is about. Existing rows all compared
+-- the vault's SPL token account against the indexed total, so they are
+-- backfilled as 'token_account'; new rows must name their component.
+ALTER TABLE reconciliation_logs
+    ADD COLUMN component TEXT NOT NULL DEFAULT 'token_account'
+    CHECK (component IN ('total', 'locked', 'available', 'token_account'));
+
+ALTER TABLE reconciliation_logs ALTER COLUMN component DROP DEFAULT;
+
+CREATE INDEX idx_reconciliation_open_component
+    ON reconciliation_logs (component, detected_at)
+    WHERE NOT resolved;
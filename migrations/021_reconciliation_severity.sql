@@ -0,0 +1,17 @@
+-- Severity tier of a discrepancy. Existing rows are classified with the
+-- default tiers (drift relative to the on-chain balance: 1% / 5% / 25%).
+ALTER TABLE reconciliation_logs ADD COLUMN severity TEXT;
+
+UPDATE reconciliation_logs
+SET severity = CASE
+    WHEN onchain_balance = 0 THEN 'critical'
+    WHEN abs(discrepancy::numeric) * 100 >= abs(onchain_balance::numeric) * 25 THEN 'critical'
+    WHEN abs(discrepancy::numeric) * 100 >= abs(onchain_balance::numeric) * 5 THEN 'high'
+    WHEN abs(discrepancy::numeric) * 100 >= abs(onchain_balance::numeric) THEN 'medium'
+    ELSE 'low'
+END;
+
+ALTER TABLE reconciliation_logs
+    ALTER COLUMN severity SET NOT NULL,
+    ADD CONSTRAINT reconciliation_logs_severity_check
+        CHECK (severity IN ('low', 'medium', 'high', 'critical'));
@@ -0,0 +1,16 @@
+-- One row per reconciliation pass. `finished_at` stays NULL while a pass is
+-- running, or if the reconciler died mid-pass.
+CREATE TABLE reconciliation_runs (
+    id                   UUID PRIMARY KEY,
+
+    started_at           TIMESTAMP NOT NULL,
+    finished_at          TIMESTAMP,
+
+    vaults_checked       BIGINT NOT NULL DEFAULT 0,
+    discrepancies_found  BIGINT NOT NULL DEFAULT 0,
+    -- Largest absolute drift among the discrepancies found
+    largest_drift        BIGINT NOT NULL DEFAULT 0,
+    error_count          BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE INDEX idx_reconciliation_runs_started ON reconciliation_runs(started_at DESC);
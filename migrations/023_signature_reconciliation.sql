@@ -0,0 +1,27 @@
+-- Signature-level reconciliation: on-chain transactions of a vault the
+-- indexer never processed ('missing'), and recorded transactions the chain
+-- doesn't have ('extra'). One open row per vault and signature.
+CREATE TABLE reconciliation_signature_gaps (
+    id              UUID PRIMARY KEY,
+
+    vault_pda       TEXT NOT NULL,
+    tx_signature    TEXT NOT NULL,
+    kind            TEXT NOT NULL CHECK (kind IN ('missing', 'extra')),
+    slot            BIGINT NOT NULL,
+
+    detected_at     TIMESTAMP NOT NULL DEFAULT now(),
+    resolved        BOOLEAN NOT NULL DEFAULT false,
+    resolved_at     TIMESTAMP,
+
+    CONSTRAINT fk_signature_gaps_vault
+        FOREIGN KEY (vault_pda)
+        REFERENCES vaults(vault_pda)
+        ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_signature_gaps_open
+    ON reconciliation_signature_gaps (vault_pda, tx_signature)
+    WHERE NOT resolved;
+
+ALTER TABLE reconciliation_runs
+    ADD COLUMN signature_gaps BIGINT NOT NULL DEFAULT 0;
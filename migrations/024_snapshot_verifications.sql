@@ -0,0 +1,26 @@
+-- Checks of historical balance_snapshots rows against the chain. Clean
+-- checks are kept too, as evidence that a snapshot used for statements was
+-- verified.
+CREATE TABLE snapshot_verifications (
+    id                  UUID PRIMARY KEY,
+
+    vault_pda           TEXT NOT NULL,
+    snapshot_time       TIMESTAMP NOT NULL,
+    -- Slot the verification was requested for
+    target_slot         BIGINT NOT NULL,
+
+    snapshot_balance    BIGINT NOT NULL,
+    onchain_balance     BIGINT NOT NULL,
+    matches             BOOLEAN NOT NULL,
+
+    verified_at         TIMESTAMP NOT NULL DEFAULT now(),
+
+    CONSTRAINT fk_snapshot_verifications_snapshot
+        FOREIGN KEY (vault_pda, snapshot_time)
+        REFERENCES balance_snapshots(vault_pda, snapshot_time)
+        ON DELETE CASCADE
+);
+
+CREATE INDEX idx_snapshot_verifications_mismatch
+    ON snapshot_verifications (verified_at)
+    WHERE NOT matches;
@@ -0,0 +1,3 @@
+-- Vaults a pass couldn't check because no token account is recorded for them.
+ALTER TABLE reconciliation_runs
+    ADD COLUMN vaults_skipped BIGINT NOT NULL DEFAULT 0;
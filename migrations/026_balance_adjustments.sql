@@ -0,0 +1,36 @@
+-- Corrections from resolved reconciliation discrepancies are written to the
+-- ledger as signed `adjustment` entries that say why, by whom, and for which
+-- discrepancy, so every balance change stays explainable.
+ALTER TABLE balance_ledger
+    DROP CONSTRAINT IF EXISTS balance_ledger_entry_type_check;
+
+ALTER TABLE balance_ledger
+    ADD CONSTRAINT balance_ledger_entry_type_check CHECK (entry_type IN (
+        'deposit',
+        'withdraw',
+        'lock',
+        'unlock',
+        'transfer_in',
+        'transfer_out',
+        'set_balance',
+        'revert_deposit',
+        'revert_withdraw',
+        'correction',
+        'adjustment'
+    ));
+
+ALTER TABLE balance_ledger
+    ADD COLUMN IF NOT EXISTS reason         TEXT,
+    ADD COLUMN IF NOT EXISTS operator       TEXT,
+    ADD COLUMN IF NOT EXISTS discrepancy_id UUID REFERENCES reconciliation_logs(id);
+
+ALTER TABLE balance_ledger
+    ADD CONSTRAINT balance_ledger_adjustment_explained CHECK (
+        entry_type <> 'adjustment'
+        OR (reason IS NOT NULL AND operator IS NOT NULL AND discrepancy_id IS NOT NULL)
+    );
+
+-- A discrepancy is corrected at most once.
+CREATE UNIQUE INDEX IF NOT EXISTS idx_balance_ledger_discrepancy
+    ON balance_ledger (discrepancy_id)
+    WHERE discrepancy_id IS NOT NULL;
@@ -0,0 +1,29 @@
+-- State of `AccessControlManager`, so it survives restarts and is shared by
+-- every replica instead of living in per-process maps.
+CREATE TABLE IF NOT EXISTS vault_authorizations (
+    vault       TEXT NOT NULL,
+    user_pubkey TEXT NOT NULL,
+    created_at  TIMESTAMP NOT NULL DEFAULT now(),
+    PRIMARY KEY (vault, user_pubkey)
+);
+
+CREATE TABLE IF NOT EXISTS security_events (
+    id          BIGSERIAL PRIMARY KEY,
+    event_type  TEXT NOT NULL,
+    -- Pubkey of the user involved, or the component that raised the event
+    actor       TEXT NOT NULL,
+    vault       TEXT NOT NULL,
+    details     TEXT NOT NULL,
+    -- `AlertSeverity` level: 1 low .. 4 critical, so it can be compared
+    severity    SMALLINT NOT NULL CHECK (severity BETWEEN 1 AND 4),
+    created_at  TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_security_events_severity
+    ON security_events (severity, id);
+
+CREATE TABLE IF NOT EXISTS failed_attempts (
+    user_pubkey     TEXT PRIMARY KEY,
+    attempts        INTEGER NOT NULL,
+    last_attempt_at TIMESTAMP NOT NULL DEFAULT now()
+);
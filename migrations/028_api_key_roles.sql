@@ -0,0 +1,5 @@
+-- What a key may do, enforced by the API's role middleware. Existing keys
+-- become read-only until an admin reissues them with a broader role.
+ALTER TABLE api_keys
+    ADD COLUMN IF NOT EXISTS role TEXT NOT NULL DEFAULT 'read_only'
+        CHECK (role IN ('admin', 'operator', 'read_only', 'service'));
@@ -0,0 +1,12 @@
+-- Withdrawals the API built transactions for, counted against the per-user
+-- velocity caps. Only recent rows matter; older ones are kept for audits.
+CREATE TABLE IF NOT EXISTS withdrawal_requests (
+    id           BIGSERIAL PRIMARY KEY,
+    user_pubkey  TEXT NOT NULL,
+    vault        TEXT NOT NULL,
+    amount       BIGINT NOT NULL CHECK (amount > 0),
+    requested_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_withdrawal_requests_user
+    ON withdrawal_requests (user_pubkey, requested_at);
@@ -0,0 +1,12 @@
+-- Nonces of signed API requests, so a captured request can't be replayed
+-- while its timestamp is still accepted. Rows past the signature max age
+-- are deleted as new nonces are claimed.
+CREATE TABLE IF NOT EXISTS request_nonces (
+    signer     TEXT NOT NULL,
+    nonce      TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    PRIMARY KEY (signer, nonce)
+);
+
+CREATE INDEX IF NOT EXISTS idx_request_nonces_created
+    ON request_nonces (created_at);
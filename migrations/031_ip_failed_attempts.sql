@@ -0,0 +1,7 @@
+-- Failed API requests (bad keys, bad signatures) per source IP. An IP with
+-- too many recent failures is banned until the ban policy's cooldown passes.
+CREATE TABLE IF NOT EXISTS ip_failed_attempts (
+    ip              TEXT PRIMARY KEY,
+    attempts        INTEGER NOT NULL,
+    last_attempt_at TIMESTAMP NOT NULL DEFAULT now()
+);
@@ -0,0 +1,25 @@
+-- Daily withdrawal caps per vault or per mint. A vault's own cap takes
+-- precedence over its mint's.
+CREATE TABLE IF NOT EXISTS withdrawal_caps (
+    scope      TEXT NOT NULL CHECK (scope IN ('vault', 'mint')),
+    -- Vault PDA or mint address, depending on `scope`
+    target     TEXT NOT NULL,
+    daily_cap  BIGINT NOT NULL CHECK (daily_cap >= 0),
+    updated_by TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT now(),
+    PRIMARY KEY (scope, target)
+);
+
+-- Indexed withdrawals that took a vault over its daily cap. The API refuses
+-- to build those, so they went to the program directly.
+CREATE TABLE IF NOT EXISTS withdrawal_cap_breaches (
+    id           BIGSERIAL PRIMARY KEY,
+    tx_signature TEXT NOT NULL,
+    vault_pda    TEXT NOT NULL,
+    amount       BIGINT NOT NULL,
+    -- Withdrawn from the vault in the 24 hours up to and including this one
+    daily_volume BIGINT NOT NULL,
+    daily_cap    BIGINT NOT NULL,
+    detected_at  TIMESTAMP NOT NULL DEFAULT now(),
+    UNIQUE (tx_signature, vault_pda)
+);
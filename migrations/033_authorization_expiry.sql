@@ -0,0 +1,7 @@
+-- Grants may expire; expired rows are ignored and swept periodically.
+ALTER TABLE vault_authorizations
+    ADD COLUMN IF NOT EXISTS expires_at TIMESTAMP;
+
+CREATE INDEX IF NOT EXISTS idx_vault_authorizations_expires
+    ON vault_authorizations (expires_at)
+    WHERE expires_at IS NOT NULL;
@@ -0,0 +1,18 @@
+-- Security events are queried by time range and actor, and moved here (or
+-- dropped) once older than the retention horizon.
+CREATE INDEX IF NOT EXISTS idx_security_events_created
+    ON security_events (created_at);
+
+CREATE INDEX IF NOT EXISTS idx_security_events_actor
+    ON security_events (actor, created_at);
+
+CREATE TABLE IF NOT EXISTS security_events_archive (
+    id          BIGINT PRIMARY KEY,
+    event_type  TEXT NOT NULL,
+    actor       TEXT NOT NULL,
+    vault       TEXT NOT NULL,
+    details     TEXT NOT NULL,
+    severity    SMALLINT NOT NULL,
+    created_at  TIMESTAMP NOT NULL,
+    archived_at TIMESTAMP NOT NULL DEFAULT now()
+);
@@ -0,0 +1,21 @@
+-- Per-caller-program CPI quotas. Unset limits don't apply.
+CREATE TABLE IF NOT EXISTS program_quotas (
+    program_id          TEXT PRIMARY KEY,
+    max_calls_per_hour  INTEGER,
+    max_locked_amount   BIGINT,
+    enforcement         TEXT NOT NULL DEFAULT 'reject',
+    updated_by          TEXT NOT NULL,
+    updated_at          TIMESTAMP NOT NULL DEFAULT now(),
+
+    CONSTRAINT program_quotas_enforcement_check
+        CHECK (enforcement IN ('reject', 'flag'))
+);
+
+-- What each caller program has locked through the CPI manager, net of
+-- unlocks, and its lock/unlock calls in the current hour.
+CREATE TABLE IF NOT EXISTS program_usage (
+    program_id      TEXT PRIMARY KEY,
+    locked_amount   BIGINT NOT NULL DEFAULT 0,
+    window_start    TIMESTAMP NOT NULL,
+    window_calls    INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,22 @@
+-- Short-lived sessions opened with an API key. Only SHA-256 hashes of the
+-- tokens are stored. A session ends when revoked, when its refresh token
+-- expires, or when its key is revoked or expires.
+CREATE TABLE IF NOT EXISTS sessions (
+    id                      UUID PRIMARY KEY,
+    api_key_id              UUID NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+
+    access_hash             TEXT NOT NULL UNIQUE,
+    access_expires_at       TIMESTAMP NOT NULL,
+    refresh_hash            TEXT NOT NULL UNIQUE,
+    refresh_expires_at      TIMESTAMP NOT NULL,
+    -- Refresh token replaced by the last refresh; presenting it again
+    -- means it leaked, and ends the session
+    previous_refresh_hash   TEXT,
+
+    created_at              TIMESTAMP NOT NULL DEFAULT now(),
+    refreshed_at            TIMESTAMP,
+    revoked_at              TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_sessions_api_key ON sessions (api_key_id);
+CREATE INDEX IF NOT EXISTS idx_sessions_previous_refresh ON sessions (previous_refresh_hash);
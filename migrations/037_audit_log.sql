@@ -0,0 +1,17 @@
+-- Durable copy of the high-value events logged through `Logger` (security
+-- events, balance changes, admin actions, CPI calls), kept for compliance
+-- independently of log shipping and rotation. Rows are only ever inserted.
+CREATE TABLE IF NOT EXISTS audit_log (
+    id          BIGSERIAL PRIMARY KEY,
+    -- security_event, balance_change, admin_action or cpi_call
+    category    TEXT NOT NULL,
+    -- Who acted: a user or program pubkey, an API key owner, or a service
+    actor       TEXT NOT NULL,
+    -- What was acted on (vault, user, route), if anything
+    subject     TEXT,
+    details     JSONB NOT NULL,
+    logged_at   TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_category_time ON audit_log (category, logged_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_actor_time ON audit_log (actor, logged_at);
@@ -0,0 +1,6 @@
+-- Signatures indexed at `finalized` commitment are final from the start, so
+-- they are eligible for pruning without waiting for the finality checker.
+UPDATE processed_events
+SET finalized_at = processed_at
+WHERE commitment = 'finalized'
+  AND finalized_at IS NULL;
@@ -0,0 +1,14 @@
+-- Status a vault had when its on-chain close event closed it, so rolling the
+-- close back restores it. NULL for vaults that aren't closed, or that an
+-- operator closed.
+ALTER TABLE vaults ADD COLUMN status_before_close TEXT
+    CHECK (status_before_close IN ('active', 'closing', 'closed', 'frozen'));
+
+-- Vaults closed by an indexed close event were reopened as active until now
+UPDATE vaults v
+SET status_before_close = 'active'
+WHERE v.status = 'closed'
+  AND EXISTS (
+      SELECT 1 FROM applied_events a
+      WHERE a.vault_pda = v.vault_pda AND a.event_type = 'close'
+  );
@@ -0,0 +1,25 @@
+-- Position of the event's transaction in its block, so a replay applies the
+-- transactions of a slot in execution order rather than by signature. NULL if
+-- the block wasn't available when the transaction was indexed.
+ALTER TABLE applied_events ADD COLUMN tx_index INTEGER;
+
+-- The indexer applies transactions oldest first, so for rows indexed before
+-- this column existed the order they were applied in is the best stand-in.
+UPDATE applied_events a
+SET tx_index = o.tx_index
+FROM (
+    SELECT
+        tx_signature,
+        (dense_rank() OVER (PARTITION BY slot ORDER BY first_applied_at, tx_signature) - 1)::int
+            AS tx_index
+    FROM (
+        SELECT tx_signature, slot, min(applied_at) AS first_applied_at
+        FROM applied_events
+        GROUP BY tx_signature, slot
+    ) t
+) o
+WHERE o.tx_signature = a.tx_signature;
+
+DROP INDEX idx_applied_events_order;
+CREATE INDEX idx_applied_events_order
+    ON applied_events(slot, COALESCE(tx_index, 2147483647), tx_signature, event_index);
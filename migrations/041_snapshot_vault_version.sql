@@ -0,0 +1,5 @@
+-- Vault version a snapshot was taken at. A vault needs a new snapshot once its
+-- version moves past it; comparing `last_synced_at` (a block time) with the
+-- wall-clock snapshot time missed changes indexed while catching up. NULL for
+-- older snapshots, so those vaults are snapshotted once more.
+ALTER TABLE balance_snapshots ADD COLUMN vault_version BIGINT;
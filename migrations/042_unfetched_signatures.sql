@@ -0,0 +1,14 @@
+-- Signatures the RPC node had no transaction for when the indexer fetched
+-- them. The gap audit fetches them again every pass until one is found.
+CREATE TABLE unfetched_signatures (
+    tx_signature    TEXT PRIMARY KEY,
+
+    -- NULL when only the signature was known
+    slot            BIGINT,
+
+    attempts        INTEGER NOT NULL DEFAULT 1,
+    first_seen_at   TIMESTAMP NOT NULL DEFAULT now(),
+    last_attempt_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_unfetched_signatures_slot ON unfetched_signatures(slot);
@@ -0,0 +1,43 @@
+-- Postgres refuses to create a partition while the default partition holds
+-- rows that belong in it, which happened for every month a backfill wrote
+-- before its partition existed. Build the partition as a standalone table,
+-- move that month's rows out of `transactions_default` into it, then attach.
+CREATE OR REPLACE FUNCTION create_transaction_partition(month_start DATE)
+RETURNS BOOLEAN AS $$
+DECLARE
+    start_ts       TIMESTAMP := date_trunc('month', month_start)::timestamp;
+    end_ts         TIMESTAMP := start_ts + INTERVAL '1 month';
+    partition_name TEXT := 'transactions_' || to_char(start_ts, '"y"YYYY"m"MM');
+BEGIN
+    IF to_regclass(partition_name) IS NOT NULL THEN
+        RETURN false;
+    END IF;
+
+    -- Blocks writes to the default partition until the function's
+    -- transaction ends, so no row for this month lands there meanwhile.
+    LOCK TABLE transactions_default IN SHARE ROW EXCLUSIVE MODE;
+
+    EXECUTE format(
+        'CREATE TABLE %I (LIKE transactions INCLUDING DEFAULTS INCLUDING CONSTRAINTS)',
+        partition_name
+    );
+
+    EXECUTE format(
+        'WITH moved AS (
+             DELETE FROM transactions_default
+             WHERE block_time >= %L AND block_time < %L
+             RETURNING *
+         )
+         INSERT INTO %I SELECT * FROM moved',
+        start_ts, end_ts, partition_name
+    );
+
+    -- Indexes, keys and the vault foreign key are added from the parent.
+    EXECUTE format(
+        'ALTER TABLE transactions ATTACH PARTITION %I FOR VALUES FROM (%L) TO (%L)',
+        partition_name, start_ts, end_ts
+    );
+
+    RETURN true;
+END;
+$$ LANGUAGE plpgsql;
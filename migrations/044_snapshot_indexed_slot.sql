@@ -0,0 +1,21 @@
+-- Highest slot the indexer had processed when a snapshot was taken. Historical
+-- verification compares the snapshot with the chain at this slot rather than
+-- at `snapshot_time`, which runs ahead of the indexed data whenever the
+-- indexer lags. NULL for older snapshots, which can't be verified.
+ALTER TABLE balance_snapshots ADD COLUMN indexed_slot BIGINT;
+
+CREATE INDEX idx_balance_snapshots_indexed_slot
+    ON balance_snapshots (vault_pda, indexed_slot);
+
+-- A check that couldn't read the chain balance is recorded with its error
+-- instead of a balance and a verdict.
+ALTER TABLE snapshot_verifications
+    ALTER COLUMN onchain_balance DROP NOT NULL,
+    ALTER COLUMN matches DROP NOT NULL,
+    ADD COLUMN error TEXT,
+    ADD CONSTRAINT snapshot_verifications_outcome
+        CHECK ((error IS NULL) = (matches IS NOT NULL AND onchain_balance IS NOT NULL));
+
+CREATE INDEX idx_snapshot_verifications_failed
+    ON snapshot_verifications (verified_at)
+    WHERE error IS NOT NULL;
@@ -0,0 +1,22 @@
+-- At most one open discrepancy per vault and component. The reconciler used
+-- to record the same drift again on every pass, and resolving two of those
+-- rows corrected the vault twice. Older open duplicates are closed in favour
+-- of the newest detection; later detections update the open row instead.
+UPDATE reconciliation_logs r
+SET resolved = true,
+    resolved_at = NOW(),
+    resolved_by = 'system',
+    resolution_note = 'superseded by a later detection'
+WHERE NOT r.resolved
+  AND EXISTS (
+        SELECT 1
+        FROM reconciliation_logs n
+        WHERE NOT n.resolved
+          AND n.vault_pda = r.vault_pda
+          AND n.component = r.component
+          AND (n.detected_at, n.id) > (r.detected_at, r.id)
+    );
+
+CREATE UNIQUE INDEX idx_reconciliation_open_per_component
+    ON reconciliation_logs (vault_pda, component)
+    WHERE NOT resolved;
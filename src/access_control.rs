@@ -1,17 +1,73 @@
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use sqlx::PgPool;
 use tracing::{warn, error};
 
+use crate::alerting::{AlertDispatcher, AlertRouting, AlertSink, AlertingConfig};
+use crate::anomaly::{self, AnomalyConfig, AnomalyScore, UserBaseline};
+use crate::escalation::{EscalationAction, EscalationRule};
+use crate::db::access_control_repo::{
+    AccessControlRepository, AuthorizationRow, FailedAttempts, SecurityEventQuery,
+    SecurityEventRow, WithdrawalVolume,
+};
+use crate::db::transaction_repo::{TransactionRepository, UserActivity};
+
+// Anomaly score at which a suspicious withdrawal is critical, e.g. one over
+// 10x the user's average
+const CRITICAL_ANOMALY_SCORE: f64 = 0.6;
+
 // Different types of security issues we monitor
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SecurityEventType {
     UnauthorizedAccessAttempt,
     SuspiciousWithdrawal,
     RapidTransactionSequence,
     LargeUnexpectedTransfer,
     AccountStateChange,
+    ReconciliationDrift,
+    IpBanned,
+    ProgramQuotaExceeded,
+    HandlerPanic,
+}
+
+impl SecurityEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SecurityEventType::UnauthorizedAccessAttempt => "unauthorized_access_attempt",
+            SecurityEventType::SuspiciousWithdrawal => "suspicious_withdrawal",
+            SecurityEventType::RapidTransactionSequence => "rapid_transaction_sequence",
+            SecurityEventType::LargeUnexpectedTransfer => "large_unexpected_transfer",
+            SecurityEventType::AccountStateChange => "account_state_change",
+            SecurityEventType::ReconciliationDrift => "reconciliation_drift",
+            SecurityEventType::IpBanned => "ip_banned",
+            SecurityEventType::ProgramQuotaExceeded => "program_quota_exceeded",
+            SecurityEventType::HandlerPanic => "handler_panic",
+        }
+    }
+}
+
+impl std::str::FromStr for SecurityEventType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "unauthorized_access_attempt" => Ok(SecurityEventType::UnauthorizedAccessAttempt),
+            "suspicious_withdrawal" => Ok(SecurityEventType::SuspiciousWithdrawal),
+            "rapid_transaction_sequence" => Ok(SecurityEventType::RapidTransactionSequence),
+            "large_unexpected_transfer" => Ok(SecurityEventType::LargeUnexpectedTransfer),
+            "account_state_change" => Ok(SecurityEventType::AccountStateChange),
+            "reconciliation_drift" => Ok(SecurityEventType::ReconciliationDrift),
+            "ip_banned" => Ok(SecurityEventType::IpBanned),
+            "program_quota_exceeded" => Ok(SecurityEventType::ProgramQuotaExceeded),
+            "handler_panic" => Ok(SecurityEventType::HandlerPanic),
+            other => anyhow::bail!("unknown security event type '{}'", other),
+        }
+    }
 }
 
 // Log entry for a security event
@@ -34,11 +90,419 @@ pub enum AlertSeverity {
     Critical = 4,
 }
 
-// Manages who can access which vaults and monitors for suspicious activity
+impl AlertSeverity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlertSeverity::Low => "low",
+            AlertSeverity::Medium => "medium",
+            AlertSeverity::High => "high",
+            AlertSeverity::Critical => "critical",
+        }
+    }
+
+    /// Numeric level (1 low .. 4 critical), as stored in `security_events`.
+    pub fn level(self) -> i16 {
+        self as i16
+    }
+
+    pub fn from_level(level: i16) -> Option<Self> {
+        match level {
+            1 => Some(AlertSeverity::Low),
+            2 => Some(AlertSeverity::Medium),
+            3 => Some(AlertSeverity::High),
+            4 => Some(AlertSeverity::Critical),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for AlertSeverity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "low" => Ok(AlertSeverity::Low),
+            "medium" => Ok(AlertSeverity::Medium),
+            "high" => Ok(AlertSeverity::High),
+            "critical" => Ok(AlertSeverity::Critical),
+            other => anyhow::bail!("unknown alert severity '{}'", other),
+        }
+    }
+}
+
+impl SecurityEvent {
+    fn to_row(&self) -> SecurityEventRow {
+        SecurityEventRow {
+            event_type: self.event_type.as_str().to_string(),
+            actor: self.user.clone(),
+            vault: self.vault.clone(),
+            details: self.details.clone(),
+            severity: self.severity.level(),
+            created_at: self.timestamp.naive_utc(),
+        }
+    }
+
+    fn from_row(row: SecurityEventRow) -> anyhow::Result<Self> {
+        Ok(Self {
+            event_type: row.event_type.parse()?,
+            user: row.actor,
+            vault: row.vault,
+            timestamp: row.created_at.and_utc(),
+            details: row.details,
+            severity: AlertSeverity::from_level(row.severity)
+                .ok_or_else(|| anyhow::anyhow!("invalid severity level {}", row.severity))?,
+        })
+    }
+}
+
+// What happens to a withdrawal that would exceed a velocity cap
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LimitEnforcement {
+    #[default]
+    Reject,
+    // Let it through, but still record the security event
+    Flag,
+}
+
+impl LimitEnforcement {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LimitEnforcement::Reject => "reject",
+            LimitEnforcement::Flag => "flag",
+        }
+    }
+}
+
+impl std::str::FromStr for LimitEnforcement {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "reject" => Ok(LimitEnforcement::Reject),
+            "flag" => Ok(LimitEnforcement::Flag),
+            other => anyhow::bail!("unknown limit enforcement '{}' (reject or flag)", other),
+        }
+    }
+}
+
+/// Per-user caps on withdrawal volume over sliding windows. A `None` cap
+/// disables that window.
+#[derive(Debug, Clone, Default)]
+pub struct WithdrawalLimits {
+    pub hourly_cap: Option<u64>,
+    pub daily_cap: Option<u64>,
+    pub enforcement: LimitEnforcement,
+}
+
+impl WithdrawalLimits {
+    pub fn is_enabled(&self) -> bool {
+        self.hourly_cap.is_some() || self.daily_cap.is_some()
+    }
+
+    /// The first window, as `(name, cap)`, whose cap `amount` on top of the
+    /// volume already withdrawn in it would exceed.
+    pub fn exceeded(
+        &self,
+        hourly_volume: u64,
+        daily_volume: u64,
+        amount: u64,
+    ) -> Option<(&'static str, u64)> {
+        [
+            ("hourly", self.hourly_cap, hourly_volume),
+            ("daily", self.daily_cap, daily_volume),
+        ]
+        .into_iter()
+        .find_map(|(window, cap, volume)| {
+            cap.filter(|cap| volume.saturating_add(amount) > *cap)
+                .map(|cap| (window, cap))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WithdrawalDecision {
+    Allowed,
+    // Over a cap with `LimitEnforcement::Flag`, or unusual for the user
+    Flagged,
+    Rejected,
+}
+
+/// When failed attempts block a user, and how they recover without an
+/// admin: attempts decay over time, and a block lifts after a cooldown.
+#[derive(Debug, Clone)]
+pub struct BlockPolicy {
+    /// Failed attempts at which a user is blocked.
+    pub max_attempts: u32,
+    /// Time after the last failed attempt at which a block lifts.
+    pub cooldown: std::time::Duration,
+    /// One failed attempt is forgiven per this much time without new ones.
+    pub decay_every: std::time::Duration,
+}
+
+impl Default for BlockPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            cooldown: std::time::Duration::from_secs(15 * 60),
+            decay_every: std::time::Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+impl BlockPolicy {
+    /// `stored` attempts minus the ones forgiven by `now`.
+    pub fn effective_attempts(&self, stored: FailedAttempts, now: DateTime<Utc>) -> u32 {
+        let forgiven = self.elapsed(stored, now).as_secs() / self.decay_every.as_secs().max(1);
+
+        stored
+            .attempts
+            .saturating_sub(u32::try_from(forgiven).unwrap_or(u32::MAX))
+    }
+
+    pub fn is_blocked(&self, stored: FailedAttempts, now: DateTime<Utc>) -> bool {
+        stored.attempts >= self.max_attempts && self.elapsed(stored, now) < self.cooldown
+    }
+
+    fn elapsed(&self, stored: FailedAttempts, now: DateTime<Utc>) -> std::time::Duration {
+        (now - stored.last_attempt_at.and_utc())
+            .to_std()
+            .unwrap_or_default()
+    }
+}
+
+/// Which security events to return. Unset fields don't filter.
+#[derive(Debug, Clone, Default)]
+pub struct SecurityEventFilter {
+    /// Events at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Events before this time.
+    pub until: Option<DateTime<Utc>>,
+    pub user: Option<String>,
+    pub event_type: Option<SecurityEventType>,
+    pub min_severity: Option<AlertSeverity>,
+    /// Only the newest `limit` matching events.
+    pub limit: Option<usize>,
+}
+
+impl SecurityEventFilter {
+    fn matches(&self, event: &SecurityEvent) -> bool {
+        self.since.is_none_or(|since| event.timestamp >= since)
+            && self.until.is_none_or(|until| event.timestamp < until)
+            && self.user.as_ref().is_none_or(|user| &event.user == user)
+            && self
+                .event_type
+                .as_ref()
+                .is_none_or(|t| &event.event_type == t)
+            && self.min_severity.is_none_or(|s| event.severity >= s)
+    }
+
+    fn to_query(&self) -> SecurityEventQuery {
+        SecurityEventQuery {
+            since: self.since.map(|t| t.naive_utc()),
+            until: self.until.map(|t| t.naive_utc()),
+            actor: self.user.clone(),
+            event_type: self.event_type.as_ref().map(|t| t.as_str().to_string()),
+            min_severity: self.min_severity.unwrap_or(AlertSeverity::Low).level(),
+            limit: self.limit.map(|n| i64::try_from(n).unwrap_or(i64::MAX)),
+        }
+    }
+}
+
+/// How long security events are kept, and whether older ones are moved to
+/// `security_events_archive` rather than dropped.
+#[derive(Debug, Clone)]
+pub struct EventRetention {
+    pub horizon: std::time::Duration,
+    pub archive: bool,
+}
+
+impl Default for EventRetention {
+    fn default() -> Self {
+        Self {
+            horizon: std::time::Duration::from_secs(90 * 24 * 60 * 60),
+            archive: true,
+        }
+    }
+}
+
+/// One vault grant, as exported and imported in bulk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthorizationEntry {
+    pub vault: String,
+    pub user: String,
+    /// Never expires if `None`.
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// What an import did with its entries.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ImportSummary {
+    pub imported: usize,
+    /// Repeats of a vault and user earlier in the same import.
+    pub duplicates: usize,
+    /// Entries whose expiry had already passed.
+    pub expired: usize,
+}
+
+/// Validate `entries` and reduce them to one per vault and user, keeping
+/// the longest-lived grant and dropping already expired ones. Fails on the
+/// first entry whose vault or user isn't a valid pubkey.
+pub fn normalize_authorizations(
+    entries: Vec<AuthorizationEntry>,
+    now: DateTime<Utc>,
+) -> anyhow::Result<(Vec<AuthorizationEntry>, ImportSummary)> {
+    let mut summary = ImportSummary::default();
+    let mut unique: HashMap<(String, String), Option<DateTime<Utc>>> = HashMap::new();
+
+    for (i, entry) in entries.into_iter().enumerate() {
+        let pubkey = |field: &str, value: &str| {
+            value
+                .trim()
+                .parse::<Pubkey>()
+                .map_err(|e| anyhow::anyhow!("entry {}: invalid {} '{}': {}", i, field, value, e))
+        };
+        let vault = pubkey("vault", &entry.vault)?;
+        let user = pubkey("user", &entry.user)?;
+
+        if entry.expires_at.is_some_and(|at| at <= now) {
+            summary.expired += 1;
+            continue;
+        }
+
+        match unique.entry((vault.to_string(), user.to_string())) {
+            std::collections::hash_map::Entry::Occupied(mut existing) => {
+                summary.duplicates += 1;
+                let longest = match (*existing.get(), entry.expires_at) {
+                    (Some(a), Some(b)) => Some(a.max(b)),
+                    _ => None,
+                };
+                existing.insert(longest);
+            }
+            std::collections::hash_map::Entry::Vacant(slot) => {
+                slot.insert(entry.expires_at);
+            }
+        }
+    }
+
+    let mut normalized: Vec<AuthorizationEntry> = unique
+        .into_iter()
+        .map(|((vault, user), expires_at)| AuthorizationEntry {
+            vault,
+            user,
+            expires_at,
+        })
+        .collect();
+    normalized.sort_by(|a, b| (&a.vault, &a.user).cmp(&(&b.vault, &b.user)));
+    summary.imported = normalized.len();
+
+    Ok((normalized, summary))
+}
+
+/// An IP address or CIDR block, e.g. `10.0.0.0/8` or `::1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let (network, ip, bits) = match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                (u32::from(network) as u128, u32::from(ip) as u128, 32)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(network), u128::from(ip), 128),
+            _ => return false,
+        };
+
+        // Only the first `prefix` bits have to match
+        let shift = bits - u32::from(self.prefix);
+        self.prefix == 0 || network >> shift == ip >> shift
+    }
+}
+
+impl std::str::FromStr for IpNetwork {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+
+        let addr: IpAddr = addr
+            .trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid IP address '{}'", addr))?;
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or_else(|| anyhow::anyhow!("invalid prefix length in '{}'", s))?,
+            None => max_prefix,
+        };
+
+        Ok(Self { addr, prefix })
+    }
+}
+
+/// Parse a comma separated list of IPs and CIDR blocks.
+pub fn parse_ip_allowlist(raw: &str) -> anyhow::Result<Vec<IpNetwork>> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// Which source IPs are screened, and when failed requests ban one.
+#[derive(Debug, Clone)]
+pub struct IpPolicy {
+    /// Internal services; never banned.
+    pub allowlist: Vec<IpNetwork>,
+    pub ban: BlockPolicy,
+}
+
+impl Default for IpPolicy {
+    fn default() -> Self {
+        Self {
+            allowlist: Vec::new(),
+            ban: BlockPolicy {
+                max_attempts: 20,
+                cooldown: std::time::Duration::from_secs(15 * 60),
+                decay_every: std::time::Duration::from_secs(60),
+            },
+        }
+    }
+}
+
+impl IpPolicy {
+    pub fn is_allowlisted(&self, ip: IpAddr) -> bool {
+        self.allowlist.iter().any(|network| network.contains(ip))
+    }
+}
+
+// Manages who can access which vaults and monitors for suspicious activity.
+// State lives in Postgres when a pool is set (see `with_pool`), otherwise in
+// memory for this process only.
 pub struct AccessControlManager {
-    authorized_users: Arc<RwLock<HashMap<String, Vec<String>>>>, // vault -> users
+    // vault -> user -> expiry (None: never expires)
+    authorized_users: Arc<RwLock<HashMap<String, HashMap<String, Option<DateTime<Utc>>>>>>,
     security_events: Arc<RwLock<Vec<SecurityEvent>>>,
-    failed_attempts: Arc<RwLock<HashMap<String, u32>>>, // user -> failed attempts
+    failed_attempts: Arc<RwLock<HashMap<String, FailedAttempts>>>, // user -> failed attempts
+    withdrawals: Arc<RwLock<HashMap<String, Vec<(DateTime<Utc>, u64)>>>>, // user -> requests
+    ip_failed_attempts: Arc<RwLock<HashMap<IpAddr, FailedAttempts>>>,
+    block_policy: BlockPolicy,
+    ip_policy: IpPolicy,
+    anomaly: AnomalyConfig,
+    escalation_rules: Vec<EscalationRule>,
+    pool: Option<PgPool>,
+    alert_dispatchers: Vec<AlertDispatcher>,
 }
 
 impl AccessControlManager {
@@ -47,28 +511,350 @@ impl AccessControlManager {
             authorized_users: Arc::new(RwLock::new(HashMap::new())),
             security_events: Arc::new(RwLock::new(Vec::new())),
             failed_attempts: Arc::new(RwLock::new(HashMap::new())),
+            withdrawals: Arc::new(RwLock::new(HashMap::new())),
+            ip_failed_attempts: Arc::new(RwLock::new(HashMap::new())),
+            block_policy: BlockPolicy::default(),
+            ip_policy: IpPolicy::default(),
+            anomaly: AnomalyConfig::default(),
+            escalation_rules: EscalationRule::defaults(),
+            pool: None,
+            alert_dispatchers: Vec::new(),
         }
     }
 
+    // Keep authorizations, events and failed attempts in Postgres, so they
+    // survive restarts and are shared across replicas
+    pub fn with_pool(mut self, pool: PgPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    // Replace the default thresholds for blocking users
+    pub fn with_block_policy(mut self, policy: BlockPolicy) -> Self {
+        self.block_policy = policy;
+        self
+    }
+
+    // Replace the default escalation rules; an empty list disables escalation
+    pub fn with_escalation_rules(mut self, rules: Vec<EscalationRule>) -> Self {
+        self.escalation_rules = rules;
+        self
+    }
+
+    // Replace the default IP allowlist and ban thresholds
+    pub fn with_ip_policy(mut self, policy: IpPolicy) -> Self {
+        self.ip_policy = policy;
+        self
+    }
+
+    // Replace the default baseline lookback and flagging threshold
+    pub fn with_anomaly_config(mut self, config: AnomalyConfig) -> Self {
+        self.anomaly = config;
+        self
+    }
+
+    // Forward recorded events to an alert sink as well. Needs a tokio
+    // runtime, since each sink is fed from its own task
+    pub fn with_alert_sink(mut self, sink: Arc<dyn AlertSink>, routing: AlertRouting) -> Self {
+        self.alert_dispatchers
+            .push(AlertDispatcher::spawn(sink, routing));
+        self
+    }
+
+    // Add every sink configured in `alerting`
+    pub fn with_alerting(self, alerting: &AlertingConfig) -> Self {
+        alerting.sinks().into_iter().fold(self, |manager, sink| {
+            manager.with_alert_sink(sink, alerting.routing.clone())
+        })
+    }
+
+    fn repo(&self) -> Option<AccessControlRepository<'_>> {
+        self.pool.as_ref().map(AccessControlRepository::new)
+    }
+
+    // Apply the escalation rules, store the event and hand it to the alert
+    // sinks
+    async fn record_event(&self, mut event: SecurityEvent) -> anyhow::Result<()> {
+        let block = self.escalate(&mut event).await;
+        let user = event.user.clone();
+
+        for dispatcher in &self.alert_dispatchers {
+            dispatcher.dispatch(&event);
+        }
+
+        match self.repo() {
+            Some(repo) => repo.insert_event(&event.to_row()).await?,
+            None => self.security_events.write().await.push(event),
+        }
+
+        if block {
+            self.block_user(&user).await?;
+        }
+
+        Ok(())
+    }
+
+    // Run `event` through the escalation rules in order, raising its
+    // severity for each one that fires. Returns whether one asked to block
+    // the user. A rule whose history can't be loaded is skipped.
+    async fn escalate(&self, event: &mut SecurityEvent) -> bool {
+        let mut block = false;
+
+        for rule in &self.escalation_rules {
+            if !rule.matches(&event.event_type, event.severity) {
+                continue;
+            }
+
+            if rule.count > 1 {
+                let filter = SecurityEventFilter {
+                    since: Some(event.timestamp - rule.window),
+                    user: Some(event.user.clone()),
+                    event_type: rule.event_type.clone(),
+                    min_severity: Some(rule.min_severity),
+                    limit: Some(rule.count - 1),
+                    ..Default::default()
+                };
+
+                match self.query_security_events(&filter).await {
+                    Ok(earlier) if earlier.len() + 1 >= rule.count => {}
+                    Ok(_) => continue,
+                    Err(e) => {
+                        error!("failed to evaluate escalation rule '{}': {}", rule, e);
+                        continue;
+                    }
+                }
+            }
+
+            let escalated = rule.escalated(event.severity);
+            if escalated != event.severity {
+                event.details = format!(
+                    "{} (escalated from {} by rule '{}')",
+                    event.details,
+                    event.severity.as_str(),
+                    rule
+                );
+                event.severity = escalated;
+            }
+            block |= rule.action == EscalationAction::Block;
+
+            error!(
+                "ALERT: rule '{}' fired for {} on {} event",
+                rule,
+                event.user,
+                event.event_type.as_str()
+            );
+        }
+
+        block
+    }
+
+    // Block `user` as if they had used up their failed attempts; the block
+    // lifts after the block policy's cooldown
+    async fn block_user(&self, user: &str) -> anyhow::Result<()> {
+        let max_attempts = self.block_policy.max_attempts;
+
+        match self.repo() {
+            Some(repo) => repo.block(user, max_attempts).await?,
+            None => {
+                let mut failed = self.failed_attempts.write().await;
+                let attempts = failed
+                    .get(user)
+                    .map_or(max_attempts, |stored| stored.attempts.max(max_attempts));
+                failed.insert(
+                    user.to_string(),
+                    FailedAttempts {
+                        attempts,
+                        last_attempt_at: Utc::now().naive_utc(),
+                    },
+                );
+            }
+        }
+
+        warn!("SECURITY: {} blocked by an escalation rule", user);
+        Ok(())
+    }
+
     // Allow a user to access a specific vault
     pub async fn authorize_user(&self, vault: &str, user: &str) -> anyhow::Result<()> {
-        let mut authorized = self.authorized_users.write().await;
-        authorized
-            .entry(vault.to_string())
-            .or_insert_with(Vec::new)
-            .push(user.to_string());
+        self.authorize_user_until(vault, user, None).await
+    }
+
+    // Allow a user to access a vault until `expires_at` (`None`: for good).
+    // Authorizing an already authorized user replaces the expiry.
+    pub async fn authorize_user_until(
+        &self,
+        vault: &str,
+        user: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()> {
+        match self.repo() {
+            Some(repo) => {
+                repo.authorize(vault, user, expires_at.map(|at| at.naive_utc()))
+                    .await?
+            }
+            None => {
+                let mut authorized = self.authorized_users.write().await;
+                authorized
+                    .entry(vault.to_string())
+                    .or_default()
+                    .insert(user.to_string(), expires_at);
+            }
+        }
 
         tracing::info!("User {} added to vault {}", user, vault);
         Ok(())
     }
 
-    // Check if a user is allowed to access a vault
+    // Take away a user's access to a vault. Returns false if they had none.
+    pub async fn revoke_user(&self, vault: &str, user: &str) -> anyhow::Result<bool> {
+        let revoked = match self.repo() {
+            Some(repo) => repo.revoke(vault, user).await?,
+            None => {
+                let mut authorized = self.authorized_users.write().await;
+                authorized
+                    .get_mut(vault)
+                    .map(|users| users.remove(user).is_some())
+                    .unwrap_or(false)
+            }
+        };
+
+        if revoked {
+            tracing::info!("User {} removed from vault {}", user, vault);
+        }
+        Ok(revoked)
+    }
+
+    // Delete expired authorizations; they are already ignored, this only
+    // keeps them from piling up. Returns how many were removed.
+    pub async fn sweep_expired_authorizations(&self) -> anyhow::Result<u64> {
+        if let Some(repo) = self.repo() {
+            return repo.delete_expired_authorizations().await;
+        }
+
+        let now = Utc::now();
+        let mut removed = 0;
+        let mut authorized = self.authorized_users.write().await;
+
+        for users in authorized.values_mut() {
+            let before = users.len();
+            users.retain(|_, expires_at| expires_at.is_none_or(|at| at > now));
+            removed += (before - users.len()) as u64;
+        }
+        authorized.retain(|_, users| !users.is_empty());
+
+        Ok(removed)
+    }
+
+    // Grant every entry, e.g. an allowlist exported from another environment.
+    // Nothing is written unless all entries are valid; see
+    // `normalize_authorizations` for how duplicates and expired ones are handled.
+    pub async fn import(&self, entries: Vec<AuthorizationEntry>) -> anyhow::Result<ImportSummary> {
+        let (entries, summary) = normalize_authorizations(entries, Utc::now())?;
+
+        match self.repo() {
+            Some(repo) => {
+                let rows: Vec<AuthorizationRow> = entries
+                    .into_iter()
+                    .map(|entry| AuthorizationRow {
+                        vault: entry.vault,
+                        user_pubkey: entry.user,
+                        expires_at: entry.expires_at.map(|at| at.naive_utc()),
+                    })
+                    .collect();
+                repo.authorize_all(&rows).await?;
+            }
+            None => {
+                let mut authorized = self.authorized_users.write().await;
+                for entry in entries {
+                    authorized
+                        .entry(entry.vault)
+                        .or_default()
+                        .insert(entry.user, entry.expires_at);
+                }
+            }
+        }
+
+        tracing::info!(
+            "Imported {} authorizations ({} duplicates, {} expired skipped)",
+            summary.imported,
+            summary.duplicates,
+            summary.expired
+        );
+        Ok(summary)
+    }
+
+    // All grants that haven't expired, by vault then user, in the form
+    // `import` takes
+    pub async fn export(&self) -> anyhow::Result<Vec<AuthorizationEntry>> {
+        if let Some(repo) = self.repo() {
+            let rows = repo.active_authorizations().await?;
+            return Ok(rows
+                .into_iter()
+                .map(|row| AuthorizationEntry {
+                    vault: row.vault,
+                    user: row.user_pubkey,
+                    expires_at: row.expires_at.map(|at| at.and_utc()),
+                })
+                .collect());
+        }
+
+        let now = Utc::now();
+        let authorized = self.authorized_users.read().await;
+        let mut entries: Vec<AuthorizationEntry> = authorized
+            .iter()
+            .flat_map(|(vault, users)| {
+                users
+                    .iter()
+                    .filter(|(_, expires_at)| expires_at.is_none_or(|at| at > now))
+                    .map(|(user, expires_at)| AuthorizationEntry {
+                        vault: vault.clone(),
+                        user: user.clone(),
+                        expires_at: *expires_at,
+                    })
+            })
+            .collect();
+        entries.sort_by(|a, b| (&a.vault, &a.user).cmp(&(&b.vault, &b.user)));
+
+        Ok(entries)
+    }
+
+    // Check if a user is allowed to access a vault. Fails closed if the
+    // database can't be reached.
     pub async fn is_authorized(&self, vault: &str, user: &str) -> bool {
+        if let Some(repo) = self.repo() {
+            return repo.is_authorized(vault, user).await.unwrap_or_else(|e| {
+                error!("failed to check authorization of {} on {}: {}", user, vault, e);
+                false
+            });
+        }
+
+        let now = Utc::now();
         let authorized = self.authorized_users.read().await;
         authorized
             .get(vault)
-            .map(|users| users.contains(&user.to_string()))
-            .unwrap_or(false)
+            .and_then(|users| users.get(user))
+            .is_some_and(|expires_at| expires_at.is_none_or(|at| at > now))
+    }
+
+    // Log a CPI caller program going over its quota. The program stands in
+    // for the user, since no wallet is behind the call.
+    pub async fn record_program_quota_breach(
+        &self,
+        program: &str,
+        vault: &str,
+        details: &str,
+    ) -> anyhow::Result<()> {
+        let event = SecurityEvent {
+            event_type: SecurityEventType::ProgramQuotaExceeded,
+            user: program.to_string(),
+            vault: vault.to_string(),
+            timestamp: Utc::now(),
+            details: details.to_string(),
+            severity: AlertSeverity::High,
+        };
+
+        warn!("CPI quota exceeded by program {}: {}", program, details);
+        self.record_event(event).await
     }
 
     // Log when someone tries to access a vault they're not authorized for
@@ -87,53 +873,81 @@ impl AccessControlManager {
             severity: AlertSeverity::High,
         };
 
-        self.security_events.write().await.push(event.clone());
+        self.record_event(event).await?;
+
+        let decay_secs = self.block_policy.decay_every.as_secs();
+        let attempt_count = match self.repo() {
+            Some(repo) => repo.increment_failed_attempts(user, decay_secs).await?,
+            None => {
+                let now = Utc::now();
+                let mut failed = self.failed_attempts.write().await;
+                let attempts = failed
+                    .get(user)
+                    .map(|stored| self.block_policy.effective_attempts(*stored, now))
+                    .unwrap_or(0)
+                    + 1;
 
-        let mut failed = self.failed_attempts.write().await;
-        let attempt_count = failed.entry(user.to_string()).or_insert(0);
-        *attempt_count += 1;
+                failed.insert(
+                    user.to_string(),
+                    FailedAttempts {
+                        attempts,
+                        last_attempt_at: now.naive_utc(),
+                    },
+                );
+                attempts
+            }
+        };
 
         warn!(
-            "SECURITY: {} tried to access {} unauthorized. Info: {}",
-            user, vault, details
+            "SECURITY: {} tried to access {} unauthorized ({} failed attempts). Info: {}",
+            user, vault, attempt_count, details
         );
 
-        // If someone tries too many times, that's a bigger issue
-        if *attempt_count >= 3 {
-            error!(
-                "ALERT: {} has made {} failed access attempts. Suspicious activity!",
-                user, attempt_count
-            );
-        }
-
         Ok(())
     }
 
-    // Log when a withdrawal looks unusual
+    // Log when a withdrawal looks unusual, scored against the user's own
+    // baseline
     pub async fn record_suspicious_withdrawal(
         &self,
         user: &str,
         vault: &str,
         amount: u64,
-        average_withdrawal: u64,
     ) -> anyhow::Result<()> {
+        let score = self.score_withdrawal(user, amount).await?;
+        self.record_scored_withdrawal(user, vault, amount, &score).await
+    }
+
+    async fn record_scored_withdrawal(
+        &self,
+        user: &str,
+        vault: &str,
+        amount: u64,
+        score: &AnomalyScore,
+    ) -> anyhow::Result<()> {
+        let reasons = if score.reasons.is_empty() {
+            "within the user's baseline".to_string()
+        } else {
+            score.reasons.join("; ")
+        };
+
         let event = SecurityEvent {
             event_type: SecurityEventType::SuspiciousWithdrawal,
             user: user.to_string(),
             vault: vault.to_string(),
             timestamp: Utc::now(),
             details: format!(
-                "Withdrawal: {} (usually around {})",
-                amount, average_withdrawal
+                "Withdrawal: {} (anomaly score {:.2}: {})",
+                amount, score.score, reasons
             ),
-            severity: if amount > average_withdrawal * 10 {
+            severity: if score.score >= CRITICAL_ANOMALY_SCORE {
                 AlertSeverity::Critical
             } else {
                 AlertSeverity::Medium
             },
         };
 
-        self.security_events.write().await.push(event);
+        self.record_event(event).await?;
 
         warn!(
             "SECURITY: Unusual withdrawal. User: {}, Vault: {}, Amount: {}",
@@ -143,6 +957,140 @@ impl AccessControlManager {
         Ok(())
     }
 
+    // Baseline of a user's activity: their indexed transactions with a
+    // pool, otherwise the withdrawal requests seen by this process
+    pub async fn user_baseline(&self, user: &str) -> anyhow::Result<UserBaseline> {
+        let now = Utc::now().naive_utc();
+
+        if let Some(pool) = &self.pool {
+            let repo = TransactionRepository::new(pool);
+            return anomaly::load_baseline(&repo, user, self.anomaly.lookback, now).await;
+        }
+
+        let withdrawals = self.withdrawals.read().await;
+        let activity: Vec<UserActivity> = withdrawals
+            .get(user)
+            .into_iter()
+            .flatten()
+            .map(|(at, amount)| UserActivity {
+                tx_type: "withdraw".to_string(),
+                amount: i64::try_from(*amount).unwrap_or(i64::MAX),
+                block_time: at.naive_utc(),
+            })
+            .collect();
+
+        Ok(UserBaseline::from_activity(&activity, now))
+    }
+
+    // How unusual a withdrawal of `amount` would be for the user right now
+    pub async fn score_withdrawal(&self, user: &str, amount: u64) -> anyhow::Result<AnomalyScore> {
+        let baseline = self.user_baseline(user).await?;
+        Ok(baseline.score_withdrawal(amount, Utc::now().naive_utc()))
+    }
+
+    // Check a withdrawal request against the velocity caps and the user's
+    // baseline. Anything over a cap or scoring at least the flagging
+    // threshold is recorded as a suspicious withdrawal; requests that aren't
+    // rejected count towards the caps from then on.
+    pub async fn check_withdrawal(
+        &self,
+        user: &str,
+        vault: &str,
+        amount: u64,
+        limits: &WithdrawalLimits,
+    ) -> anyhow::Result<WithdrawalDecision> {
+        let now = Utc::now();
+        let score = self.score_withdrawal(user, amount).await?;
+
+        let over_cap = if limits.is_enabled() {
+            let hourly = self.withdrawal_volume_since(user, now - Duration::hours(1)).await?;
+            let daily = self.withdrawal_volume_since(user, now - Duration::days(1)).await?;
+            limits.exceeded(hourly.total, daily.total, amount)
+        } else {
+            None
+        };
+
+        let decision = match over_cap {
+            Some((window, cap)) => {
+                warn!(
+                    "SECURITY: withdrawal of {} by {} exceeds the {} cap of {}",
+                    amount, user, window, cap
+                );
+
+                self.record_scored_withdrawal(user, vault, amount, &score)
+                    .await?;
+
+                match limits.enforcement {
+                    LimitEnforcement::Reject => WithdrawalDecision::Rejected,
+                    LimitEnforcement::Flag => WithdrawalDecision::Flagged,
+                }
+            }
+            None if score.score >= self.anomaly.flag_threshold => {
+                warn!(
+                    "SECURITY: withdrawal of {} by {} scores {:.2} against their baseline",
+                    amount, user, score.score
+                );
+
+                self.record_scored_withdrawal(user, vault, amount, &score)
+                    .await?;
+                WithdrawalDecision::Flagged
+            }
+            None => WithdrawalDecision::Allowed,
+        };
+
+        if decision != WithdrawalDecision::Rejected {
+            self.record_withdrawal_request(user, vault, amount, now).await?;
+        }
+
+        Ok(decision)
+    }
+
+    async fn withdrawal_volume_since(
+        &self,
+        user: &str,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<WithdrawalVolume> {
+        if let Some(repo) = self.repo() {
+            return repo.withdrawal_volume_since(user, since.naive_utc()).await;
+        }
+
+        let withdrawals = self.withdrawals.read().await;
+        let recent = withdrawals
+            .get(user)
+            .into_iter()
+            .flatten()
+            .filter(|(at, _)| *at >= since);
+
+        Ok(recent.fold(WithdrawalVolume::default(), |volume, (_, amount)| WithdrawalVolume {
+            total: volume.total.saturating_add(*amount),
+            count: volume.count + 1,
+        }))
+    }
+
+    async fn record_withdrawal_request(
+        &self,
+        user: &str,
+        vault: &str,
+        amount: u64,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        match self.repo() {
+            Some(repo) => repo.insert_withdrawal_request(user, vault, amount).await?,
+            None => {
+                let mut withdrawals = self.withdrawals.write().await;
+                let requests = withdrawals.entry(user.to_string()).or_default();
+
+                // Nothing older than the longest window or the baseline
+                // lookback is ever read
+                let horizon = self.anomaly.lookback.max(Duration::days(1));
+                requests.retain(|(requested_at, _)| *requested_at >= at - horizon);
+                requests.push((at, amount));
+            }
+        }
+
+        Ok(())
+    }
+
     // Log when someone does many transactions in a short time
     pub async fn record_rapid_transactions(
         &self,
@@ -163,7 +1111,7 @@ impl AccessControlManager {
             severity: AlertSeverity::High,
         };
 
-        self.security_events.write().await.push(event);
+        self.record_event(event).await?;
 
         warn!(
             "SECURITY: Rapid transaction sequence detected. User: {}, Count: {}",
@@ -173,41 +1121,253 @@ impl AccessControlManager {
         Ok(())
     }
 
+    // Log a reconciliation discrepancy too large to be rounding or a lagging
+    // indexer; it may be an exploit in progress
+    pub async fn record_reconciliation_drift(
+        &self,
+        vault: &str,
+        drift: i64,
+        details: &str,
+    ) -> anyhow::Result<()> {
+        let event = SecurityEvent {
+            event_type: SecurityEventType::ReconciliationDrift,
+            user: "reconciler".to_string(),
+            vault: vault.to_string(),
+            timestamp: Utc::now(),
+            details: format!("drift {}: {}", drift, details),
+            severity: AlertSeverity::Critical,
+        };
+
+        self.record_event(event).await?;
+
+        error!(
+            "ALERT: Vault {} drifted {} from the chain. {}",
+            vault, drift, details
+        );
+
+        Ok(())
+    }
+
+    // Log an API handler panicking. The request it panicked on is only known
+    // by the id its 500 response carries, so the API stands in for the user
+    pub async fn record_handler_panic(
+        &self,
+        request_id: &str,
+        details: &str,
+    ) -> anyhow::Result<()> {
+        let event = SecurityEvent {
+            event_type: SecurityEventType::HandlerPanic,
+            user: "api".to_string(),
+            vault: String::new(),
+            timestamp: Utc::now(),
+            details: format!("request {}: {}", request_id, details),
+            severity: AlertSeverity::Critical,
+        };
+
+        self.record_event(event).await?;
+
+        error!("ALERT: request {} panicked: {}", request_id, details);
+
+        Ok(())
+    }
+
     /// Get all security events
     pub async fn get_security_events(&self) -> Vec<SecurityEvent> {
-        self.security_events.read().await.clone()
+        self.get_alerts_by_severity(AlertSeverity::Low).await
     }
 
     /// Get security events for specific severity level or higher
     pub async fn get_alerts_by_severity(&self, min_severity: AlertSeverity) -> Vec<SecurityEvent> {
-        self.security_events
-            .read()
-            .await
-            .iter()
-            .filter(|e| e.severity >= min_severity)
-            .cloned()
-            .collect()
+        let filter = SecurityEventFilter {
+            min_severity: Some(min_severity),
+            ..Default::default()
+        };
+
+        self.query_security_events(&filter).await.unwrap_or_else(|e| {
+            error!("failed to load security events: {}", e);
+            Vec::new()
+        })
+    }
+
+    /// Security events matching `filter`, oldest first.
+    pub async fn query_security_events(
+        &self,
+        filter: &SecurityEventFilter,
+    ) -> anyhow::Result<Vec<SecurityEvent>> {
+        if let Some(repo) = self.repo() {
+            let rows = repo.events(&filter.to_query()).await?;
+
+            return Ok(rows
+                .into_iter()
+                .filter_map(|row| match SecurityEvent::from_row(row) {
+                    Ok(event) => Some(event),
+                    Err(e) => {
+                        warn!("skipping unreadable security event: {}", e);
+                        None
+                    }
+                })
+                .collect());
+        }
+
+        let events = self.security_events.read().await;
+        let mut matching: Vec<SecurityEvent> =
+            events.iter().filter(|e| filter.matches(e)).cloned().collect();
+        if let Some(limit) = filter.limit {
+            matching.drain(..matching.len().saturating_sub(limit));
+        }
+
+        Ok(matching)
+    }
+
+    /// Archive or drop security events older than the retention horizon.
+    /// In memory there is no archive, so old events are always dropped.
+    /// Returns how many events were removed.
+    pub async fn apply_event_retention(&self, retention: &EventRetention) -> anyhow::Result<u64> {
+        let cutoff = Utc::now() - Duration::from_std(retention.horizon)?;
+
+        if let Some(repo) = self.repo() {
+            return repo.expire_events(cutoff.naive_utc(), retention.archive).await;
+        }
+
+        let mut events = self.security_events.write().await;
+        let before = events.len();
+        events.retain(|e| e.timestamp >= cutoff);
+
+        Ok((before - events.len()) as u64)
     }
 
     /// Clear failed attempts for user (after successful action)
     pub async fn clear_failed_attempts(&self, user: &str) -> anyhow::Result<()> {
-        self.failed_attempts.write().await.remove(user);
+        match self.repo() {
+            Some(repo) => repo.clear_failed_attempts(user).await?,
+            None => {
+                self.failed_attempts.write().await.remove(user);
+            }
+        }
         Ok(())
     }
 
-    /// Get failed attempt count for user
+    /// Get failed attempt count for user, after decay
     pub async fn get_failed_attempts(&self, user: &str) -> u32 {
-        self.failed_attempts
-            .read()
-            .await
-            .get(user)
-            .copied()
-            .unwrap_or(0)
+        match self.stored_failed_attempts(user).await {
+            Ok(Some(stored)) => self.block_policy.effective_attempts(stored, Utc::now()),
+            Ok(None) => 0,
+            Err(e) => {
+                error!("failed to load failed attempts of {}: {}", user, e);
+                0
+            }
+        }
     }
 
-    /// Block user if too many failed attempts
+    /// Block user if too many failed attempts, until the cooldown passes.
+    /// Fails closed if the database can't be reached.
     pub async fn is_user_blocked(&self, user: &str) -> bool {
-        self.get_failed_attempts(user).await >= 5
+        match self.stored_failed_attempts(user).await {
+            Ok(Some(stored)) => self.block_policy.is_blocked(stored, Utc::now()),
+            Ok(None) => false,
+            Err(e) => {
+                error!("failed to load failed attempts of {}: {}", user, e);
+                true
+            }
+        }
+    }
+
+    /// Count a failed request (e.g. a bad API key) from `ip`, banning it once
+    /// the IP policy's limit is reached. Allowlisted IPs are not counted.
+    pub async fn record_failed_request(&self, ip: IpAddr, details: &str) -> anyhow::Result<()> {
+        if self.ip_policy.is_allowlisted(ip) {
+            return Ok(());
+        }
+
+        let ban = &self.ip_policy.ban;
+        let attempt_count = match self.repo() {
+            Some(repo) => {
+                repo.increment_ip_failed_attempts(&ip.to_string(), ban.decay_every.as_secs())
+                    .await?
+            }
+            None => {
+                let now = Utc::now();
+                let mut failed = self.ip_failed_attempts.write().await;
+                let attempts = failed
+                    .get(&ip)
+                    .map(|stored| ban.effective_attempts(*stored, now))
+                    .unwrap_or(0)
+                    + 1;
+
+                failed.insert(
+                    ip,
+                    FailedAttempts {
+                        attempts,
+                        last_attempt_at: now.naive_utc(),
+                    },
+                );
+                attempts
+            }
+        };
+
+        // Only the request that crosses the limit raises an event
+        if attempt_count == ban.max_attempts {
+            let event = SecurityEvent {
+                event_type: SecurityEventType::IpBanned,
+                user: ip.to_string(),
+                vault: String::new(),
+                timestamp: Utc::now(),
+                details: format!(
+                    "banned for {:?} after {} failed requests, last: {}",
+                    ban.cooldown, attempt_count, details
+                ),
+                severity: AlertSeverity::High,
+            };
+
+            self.record_event(event).await?;
+
+            warn!(
+                "SECURITY: banned IP {} after {} failed requests",
+                ip, attempt_count
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Whether `ip` is banned until its cooldown passes. Fails closed if the
+    /// database can't be reached.
+    pub async fn is_ip_banned(&self, ip: IpAddr) -> bool {
+        if self.ip_policy.is_allowlisted(ip) {
+            return false;
+        }
+
+        let stored = match self.repo() {
+            Some(repo) => repo.ip_failed_attempts(&ip.to_string()).await,
+            None => Ok(self.ip_failed_attempts.read().await.get(&ip).copied()),
+        };
+
+        match stored {
+            Ok(Some(stored)) => self.ip_policy.ban.is_blocked(stored, Utc::now()),
+            Ok(None) => false,
+            Err(e) => {
+                error!("failed to load failed requests of {}: {}", ip, e);
+                true
+            }
+        }
+    }
+
+    /// Lift a ban on `ip` early and forget its failed requests
+    pub async fn unban_ip(&self, ip: IpAddr) -> anyhow::Result<()> {
+        match self.repo() {
+            Some(repo) => repo.clear_ip_failed_attempts(&ip.to_string()).await?,
+            None => {
+                self.ip_failed_attempts.write().await.remove(&ip);
+            }
+        }
+        Ok(())
+    }
+
+    async fn stored_failed_attempts(&self, user: &str) -> anyhow::Result<Option<FailedAttempts>> {
+        match self.repo() {
+            Some(repo) => repo.failed_attempts(user).await,
+            None => Ok(self.failed_attempts.read().await.get(user).copied()),
+        }
     }
 }
 
@@ -230,6 +1390,71 @@ mod tests {
         assert!(!acm.is_authorized("vault1", "user2").await);
     }
 
+    #[tokio::test]
+    async fn test_revoke_and_expire_authorizations() {
+        let acm = AccessControlManager::new();
+        acm.authorize_user("vault1", "user1").await.unwrap();
+        acm.authorize_user("vault1", "user1").await.unwrap();
+
+        assert!(acm.revoke_user("vault1", "user1").await.unwrap());
+        assert!(!acm.is_authorized("vault1", "user1").await);
+        assert!(!acm.revoke_user("vault1", "user1").await.unwrap());
+
+        let expired = Utc::now() - Duration::minutes(1);
+        let later = Utc::now() + Duration::hours(1);
+        acm.authorize_user_until("vault1", "user2", Some(expired)).await.unwrap();
+        acm.authorize_user_until("vault1", "user3", Some(later)).await.unwrap();
+
+        assert!(!acm.is_authorized("vault1", "user2").await);
+        assert!(acm.is_authorized("vault1", "user3").await);
+
+        assert_eq!(acm.sweep_expired_authorizations().await.unwrap(), 1);
+        assert!(acm.is_authorized("vault1", "user3").await);
+    }
+
+    #[tokio::test]
+    async fn test_import_and_export_authorizations() {
+        let vault = Pubkey::new_unique().to_string();
+        let (user1, user2) = (Pubkey::new_unique().to_string(), Pubkey::new_unique().to_string());
+        let entry = |user: &str, expires_at| AuthorizationEntry {
+            vault: vault.clone(),
+            user: user.to_string(),
+            expires_at,
+        };
+        let later = Utc::now() + Duration::hours(1);
+
+        let acm = AccessControlManager::new();
+        let summary = acm
+            .import(vec![
+                entry(&user1, Some(later)),
+                entry(&user1, None),
+                entry(&user2, Some(Utc::now() - Duration::minutes(1))),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                imported: 1,
+                duplicates: 1,
+                expired: 1,
+            }
+        );
+        assert_eq!(acm.export().await.unwrap(), vec![entry(&user1, None)]);
+
+        // A bad pubkey rejects the whole import
+        assert!(acm
+            .import(vec![entry(&user2, None), entry("not-a-pubkey", None)])
+            .await
+            .is_err());
+        assert!(!acm.is_authorized(&vault, &user2).await);
+
+        // What one manager exports, another imports as is
+        let restored = AccessControlManager::new();
+        restored.import(acm.export().await.unwrap()).await.unwrap();
+        assert!(restored.is_authorized(&vault, &user1).await);
+    }
+
     #[tokio::test]
     async fn test_unauthorized_attempt_recording() {
         let acm = AccessControlManager::new();
@@ -242,6 +1467,19 @@ mod tests {
         assert_eq!(events[0].event_type, SecurityEventType::UnauthorizedAccessAttempt);
     }
 
+    #[tokio::test]
+    async fn test_handler_panic_recording() {
+        let acm = AccessControlManager::new();
+        acm.record_handler_panic("req-1", "index out of bounds")
+            .await
+            .unwrap();
+
+        let events = acm.get_alerts_by_severity(AlertSeverity::Critical).await;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, SecurityEventType::HandlerPanic);
+        assert_eq!(events[0].details, "request req-1: index out of bounds");
+    }
+
     #[tokio::test]
     async fn test_failed_attempts_tracking() {
         let acm = AccessControlManager::new();
@@ -264,12 +1502,46 @@ mod tests {
         assert!(acm.is_user_blocked("attacker").await);
     }
 
+    #[tokio::test]
+    async fn test_escalation_rules() {
+        let rules = crate::escalation::parse_rules(
+            "2 medium suspicious_withdrawal within 600 -> high; 1 critical -> block",
+        )
+        .unwrap();
+        let acm = AccessControlManager::new().with_escalation_rules(rules);
+        let event = |severity| SecurityEvent {
+            event_type: SecurityEventType::SuspiciousWithdrawal,
+            user: "user1".to_string(),
+            vault: "vault1".to_string(),
+            timestamp: Utc::now(),
+            details: String::new(),
+            severity,
+        };
+
+        acm.record_event(event(AlertSeverity::Medium)).await.unwrap();
+        acm.record_event(event(AlertSeverity::Medium)).await.unwrap();
+
+        let events = acm.get_security_events().await;
+        assert_eq!(events[0].severity, AlertSeverity::Medium);
+        assert_eq!(events[1].severity, AlertSeverity::High);
+        assert!(events[1].details.contains("escalated from medium"));
+        assert!(!acm.is_user_blocked("user1").await);
+
+        acm.record_event(event(AlertSeverity::Critical)).await.unwrap();
+        assert!(acm.is_user_blocked("user1").await);
+    }
+
     #[tokio::test]
     async fn test_suspicious_withdrawal_alert() {
         let acm = AccessControlManager::new();
-        // Use amount that's > 10x the average to trigger Critical severity
-        // 100_000_000 * 10 = 1_000_000_000, so we need > 1_000_000_000
-        acm.record_suspicious_withdrawal("user1", "vault1", 1_000_000_001, 100_000_000)
+        let now = Utc::now();
+        acm.withdrawals
+            .write()
+            .await
+            .insert("user1".to_string(), vec![(now, 100_000_000); 5]);
+
+        // Over 10x the user's average withdrawal is critical
+        acm.record_suspicious_withdrawal("user1", "vault1", 1_000_000_001)
             .await
             .unwrap();
 
@@ -281,6 +1553,21 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_withdrawal_flagged_against_baseline() {
+        let acm = AccessControlManager::new();
+        let limits = WithdrawalLimits::default();
+
+        for _ in 0..5 {
+            let decision = acm.check_withdrawal("user1", "vault1", 100, &limits).await.unwrap();
+            assert_eq!(decision, WithdrawalDecision::Allowed);
+        }
+
+        let decision = acm.check_withdrawal("user1", "vault1", 5_000, &limits).await.unwrap();
+        assert_eq!(decision, WithdrawalDecision::Flagged);
+        assert_eq!(acm.get_alerts_by_severity(AlertSeverity::Critical).await.len(), 1);
+    }
+
     #[tokio::test]
     async fn test_rapid_transaction_detection() {
         let acm = AccessControlManager::new();
@@ -296,6 +1583,224 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_alert_sink_receives_reconciliation_drift() {
+        use crate::alerting::AlertBatch;
+        use futures::future::BoxFuture;
+        use tokio::sync::mpsc;
+
+        struct ChannelSink(mpsc::UnboundedSender<AlertBatch>);
+
+        impl AlertSink for ChannelSink {
+            fn name(&self) -> &'static str {
+                "channel"
+            }
+
+            fn deliver<'a>(&'a self, batch: &'a AlertBatch) -> BoxFuture<'a, anyhow::Result<()>> {
+                let _ = self.0.send(batch.clone());
+                Box::pin(async { Ok(()) })
+            }
+        }
+
+        let (tx, mut delivered) = mpsc::unbounded_channel();
+        let routing = AlertRouting {
+            min_severity: AlertSeverity::Critical,
+            batch_window: std::time::Duration::from_millis(10),
+            ..AlertRouting::default()
+        };
+        let acm = AccessControlManager::new().with_alert_sink(Arc::new(ChannelSink(tx)), routing);
+
+        acm.record_rapid_transactions("user1", "vault1", 10, 5)
+            .await
+            .unwrap();
+        acm.record_reconciliation_drift("vault1", -5_000_000, "total")
+            .await
+            .unwrap();
+
+        let batch = delivered.recv().await.unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(
+            batch.events[0].event_type,
+            SecurityEventType::ReconciliationDrift
+        );
+
+        let critical_alerts = acm.get_alerts_by_severity(AlertSeverity::Critical).await;
+        assert_eq!(
+            critical_alerts[0].event_type,
+            SecurityEventType::ReconciliationDrift
+        );
+    }
+
+    #[test]
+    fn test_event_type_and_severity_round_trip() {
+        let event_type = SecurityEventType::ReconciliationDrift;
+        assert_eq!(event_type.as_str().parse::<SecurityEventType>().unwrap(), event_type);
+        assert!("unknown".parse::<SecurityEventType>().is_err());
+
+        for severity in [AlertSeverity::Low, AlertSeverity::High, AlertSeverity::Critical] {
+            assert_eq!(AlertSeverity::from_level(severity.level()), Some(severity));
+        }
+        assert_eq!(AlertSeverity::from_level(0), None);
+        assert_eq!("high".parse::<AlertSeverity>().unwrap(), AlertSeverity::High);
+    }
+
+    #[test]
+    fn test_ip_network_matching() {
+        let network: IpNetwork = "10.1.0.0/16".parse().unwrap();
+        assert!(network.contains("10.1.200.3".parse().unwrap()));
+        assert!(!network.contains("10.2.0.1".parse().unwrap()));
+        assert!(network.contains("::ffff:10.1.0.9".parse().unwrap()));
+
+        let single: IpNetwork = "::1".parse().unwrap();
+        assert!(single.contains("::1".parse().unwrap()));
+        assert!(!single.contains("127.0.0.1".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<IpNetwork>().is_err());
+        assert_eq!(parse_ip_allowlist(" 10.0.0.0/8, ::1 ,").unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_ip_ban_after_failed_requests() {
+        let mut ip_policy = IpPolicy {
+            allowlist: parse_ip_allowlist("10.0.0.0/8").unwrap(),
+            ..IpPolicy::default()
+        };
+        ip_policy.ban.max_attempts = 3;
+        let acm = AccessControlManager::new().with_ip_policy(ip_policy);
+
+        let attacker: IpAddr = "203.0.113.7".parse().unwrap();
+        let internal: IpAddr = "10.0.0.5".parse().unwrap();
+
+        for _ in 0..3 {
+            acm.record_failed_request(attacker, "invalid API key").await.unwrap();
+            acm.record_failed_request(internal, "invalid API key").await.unwrap();
+        }
+
+        assert!(acm.is_ip_banned(attacker).await);
+        assert!(!acm.is_ip_banned(internal).await);
+
+        let events = acm.get_security_events().await;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, SecurityEventType::IpBanned);
+
+        acm.unban_ip(attacker).await.unwrap();
+        assert!(!acm.is_ip_banned(attacker).await);
+    }
+
+    #[tokio::test]
+    async fn test_query_and_retention_of_security_events() {
+        let acm = AccessControlManager::new();
+        let event = |user: &str, event_type, days_ago, severity| SecurityEvent {
+            event_type,
+            user: user.to_string(),
+            vault: "vault1".to_string(),
+            timestamp: Utc::now() - Duration::days(days_ago),
+            details: String::new(),
+            severity,
+        };
+
+        for e in [
+            event("user1", SecurityEventType::IpBanned, 120, AlertSeverity::High),
+            event("user1", SecurityEventType::SuspiciousWithdrawal, 10, AlertSeverity::Low),
+            event("user2", SecurityEventType::SuspiciousWithdrawal, 5, AlertSeverity::Critical),
+            event("user1", SecurityEventType::SuspiciousWithdrawal, 1, AlertSeverity::High),
+        ] {
+            acm.record_event(e).await.unwrap();
+        }
+
+        let user1 = SecurityEventFilter {
+            user: Some("user1".to_string()),
+            event_type: Some(SecurityEventType::SuspiciousWithdrawal),
+            ..Default::default()
+        };
+        assert_eq!(acm.query_security_events(&user1).await.unwrap().len(), 2);
+
+        let window = SecurityEventFilter {
+            since: Some(Utc::now() - Duration::days(30)),
+            until: Some(Utc::now() - Duration::days(2)),
+            min_severity: Some(AlertSeverity::High),
+            ..Default::default()
+        };
+        let events = acm.query_security_events(&window).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].user, "user2");
+
+        let newest = SecurityEventFilter {
+            limit: Some(1),
+            ..Default::default()
+        };
+        let events = acm.query_security_events(&newest).await.unwrap();
+        assert_eq!(events[0].severity, AlertSeverity::High);
+        assert_eq!(events[0].user, "user1");
+
+        let removed = acm.apply_event_retention(&EventRetention::default()).await.unwrap();
+        assert_eq!(removed, 1);
+        assert_eq!(acm.get_security_events().await.len(), 3);
+    }
+
+    #[test]
+    fn test_withdrawal_limits_exceeded() {
+        let limits = WithdrawalLimits {
+            hourly_cap: Some(1_000),
+            daily_cap: Some(5_000),
+            enforcement: LimitEnforcement::Reject,
+        };
+
+        assert_eq!(limits.exceeded(400, 400, 600), None);
+        assert_eq!(limits.exceeded(400, 400, 601), Some(("hourly", 1_000)));
+        assert_eq!(limits.exceeded(0, 4_500, 600), Some(("daily", 5_000)));
+        assert_eq!(WithdrawalLimits::default().exceeded(0, u64::MAX, u64::MAX), None);
+    }
+
+    #[tokio::test]
+    async fn test_velocity_cap_rejects_and_records_event() {
+        let acm = AccessControlManager::new();
+        let limits = WithdrawalLimits {
+            hourly_cap: Some(1_000),
+            daily_cap: None,
+            enforcement: LimitEnforcement::Reject,
+        };
+
+        for _ in 0..2 {
+            let decision = acm.check_withdrawal("user1", "vault1", 500, &limits).await.unwrap();
+            assert_eq!(decision, WithdrawalDecision::Allowed);
+        }
+
+        let decision = acm.check_withdrawal("user1", "vault1", 1, &limits).await.unwrap();
+        assert_eq!(decision, WithdrawalDecision::Rejected);
+
+        let events = acm.get_security_events().await;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, SecurityEventType::SuspiciousWithdrawal);
+
+        // Flagged requests go through and keep counting
+        let flag = WithdrawalLimits {
+            enforcement: LimitEnforcement::Flag,
+            ..limits
+        };
+        let decision = acm.check_withdrawal("user1", "vault1", 1, &flag).await.unwrap();
+        assert_eq!(decision, WithdrawalDecision::Flagged);
+        assert_eq!(acm.get_security_events().await.len(), 2);
+    }
+
+    #[test]
+    fn test_block_policy_cooldown_and_decay() {
+        let policy = BlockPolicy::default();
+        let last = Utc::now();
+        let stored = FailedAttempts {
+            attempts: 5,
+            last_attempt_at: last.naive_utc(),
+        };
+
+        assert!(policy.is_blocked(stored, last));
+        assert!(policy.is_blocked(stored, last + Duration::minutes(14)));
+        assert!(!policy.is_blocked(stored, last + Duration::minutes(15)));
+
+        assert_eq!(policy.effective_attempts(stored, last + Duration::minutes(9)), 5);
+        assert_eq!(policy.effective_attempts(stored, last + Duration::minutes(25)), 3);
+        assert_eq!(policy.effective_attempts(stored, last + Duration::days(1)), 0);
+    }
+
     #[tokio::test]
     async fn test_clear_failed_attempts() {
         let acm = AccessControlManager::new();
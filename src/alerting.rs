@@ -0,0 +1,445 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{error, warn};
+
+use crate::access_control::{AlertSeverity, SecurityEvent};
+
+/// Events buffered per dispatcher before new ones are dropped.
+const QUEUE_CAPACITY: usize = 1024;
+
+/// Events listed in a single delivery; the rest are only counted.
+const MAX_BATCH_EVENTS: usize = 50;
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+/// Security events delivered to a sink in one go.
+#[derive(Debug, Clone, Default)]
+pub struct AlertBatch {
+    pub events: Vec<SecurityEvent>,
+    /// Events that arrived for this batch beyond `MAX_BATCH_EVENTS`.
+    pub suppressed: usize,
+}
+
+impl AlertBatch {
+    fn push(&mut self, event: SecurityEvent) {
+        if self.events.len() < MAX_BATCH_EVENTS {
+            self.events.push(event);
+        } else {
+            self.suppressed += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len() + self.suppressed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn max_severity(&self) -> AlertSeverity {
+        self.events
+            .iter()
+            .map(|event| event.severity)
+            .max()
+            .unwrap_or(AlertSeverity::Low)
+    }
+
+    /// One line describing the batch, e.g. for a page title.
+    pub fn summary(&self) -> String {
+        match self.events.as_slice() {
+            [event] if self.suppressed == 0 => format!(
+                "[{}] {} on {}: {}",
+                event.severity.as_str(),
+                event.event_type.as_str(),
+                event.vault,
+                event.details
+            ),
+            _ => format!(
+                "{} security events, highest severity {}",
+                self.len(),
+                self.max_severity().as_str()
+            ),
+        }
+    }
+}
+
+/// Destination for security alerts. Deliveries run on a background task
+/// owned by an `AlertDispatcher`, which does the severity filtering,
+/// batching and rate limiting.
+pub trait AlertSink: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn deliver<'a>(&'a self, batch: &'a AlertBatch) -> BoxFuture<'a, anyhow::Result<()>>;
+}
+
+fn event_json(event: &SecurityEvent) -> serde_json::Value {
+    serde_json::json!({
+        "event_type": event.event_type.as_str(),
+        "severity": event.severity.as_str(),
+        "user": event.user,
+        "vault": event.vault,
+        "details": event.details,
+        "timestamp": event.timestamp.to_rfc3339(),
+    })
+}
+
+async fn post_json(
+    http: &reqwest::Client,
+    url: &str,
+    body: &serde_json::Value,
+) -> anyhow::Result<()> {
+    http.post(url).json(body).send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// POSTs each batch to a URL as `{"events": [...], "suppressed": n}`.
+pub struct WebhookSink {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl WebhookSink {
+    pub fn new(url: String) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url,
+        }
+    }
+}
+
+impl AlertSink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn deliver<'a>(&'a self, batch: &'a AlertBatch) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let body = serde_json::json!({
+                "events": batch.events.iter().map(event_json).collect::<Vec<_>>(),
+                "suppressed": batch.suppressed,
+            });
+            post_json(&self.http, &self.url, &body).await
+        })
+    }
+}
+
+/// Posts each batch to a Slack incoming webhook as a single message.
+pub struct SlackSink {
+    http: reqwest::Client,
+    webhook_url: String,
+}
+
+impl SlackSink {
+    pub fn new(webhook_url: String) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            webhook_url,
+        }
+    }
+}
+
+impl AlertSink for SlackSink {
+    fn name(&self) -> &'static str {
+        "slack"
+    }
+
+    fn deliver<'a>(&'a self, batch: &'a AlertBatch) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let mut text = format!(":rotating_light: *{}*", batch.summary());
+
+            if batch.len() > 1 {
+                for event in &batch.events {
+                    text.push_str(&format!(
+                        "\n• `{}` {} user `{}` vault `{}`: {}",
+                        event.severity.as_str(),
+                        event.event_type.as_str(),
+                        event.user,
+                        event.vault,
+                        event.details
+                    ));
+                }
+                if batch.suppressed > 0 {
+                    text.push_str(&format!("\n…and {} more", batch.suppressed));
+                }
+            }
+
+            let body = serde_json::json!({ "text": text });
+            post_json(&self.http, &self.webhook_url, &body).await
+        })
+    }
+}
+
+/// Triggers one PagerDuty incident (Events API v2) per batch.
+pub struct PagerDutySink {
+    http: reqwest::Client,
+    routing_key: String,
+}
+
+impl PagerDutySink {
+    pub fn new(routing_key: String) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            routing_key,
+        }
+    }
+
+    fn pagerduty_severity(severity: AlertSeverity) -> &'static str {
+        match severity {
+            AlertSeverity::Low => "info",
+            AlertSeverity::Medium => "warning",
+            AlertSeverity::High => "error",
+            AlertSeverity::Critical => "critical",
+        }
+    }
+}
+
+impl AlertSink for PagerDutySink {
+    fn name(&self) -> &'static str {
+        "pagerduty"
+    }
+
+    fn deliver<'a>(&'a self, batch: &'a AlertBatch) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let body = serde_json::json!({
+                "routing_key": self.routing_key,
+                "event_action": "trigger",
+                "payload": {
+                    "summary": batch.summary(),
+                    "source": "vault-backend",
+                    "severity": Self::pagerduty_severity(batch.max_severity()),
+                    "custom_details": {
+                        "events": batch.events.iter().map(event_json).collect::<Vec<_>>(),
+                        "suppressed": batch.suppressed,
+                    },
+                },
+            });
+            post_json(&self.http, PAGERDUTY_EVENTS_URL, &body).await
+        })
+    }
+}
+
+/// Which events reach a sink, and how often.
+#[derive(Debug, Clone)]
+pub struct AlertRouting {
+    pub min_severity: AlertSeverity,
+    /// Events arriving within this long of the first are sent together.
+    pub batch_window: Duration,
+    /// Deliveries per minute; events beyond it wait for the next delivery.
+    pub max_batches_per_minute: usize,
+}
+
+impl Default for AlertRouting {
+    fn default() -> Self {
+        Self {
+            min_severity: AlertSeverity::High,
+            batch_window: Duration::from_secs(10),
+            max_batches_per_minute: 6,
+        }
+    }
+}
+
+/// Where alerts go, as configured; each sink is optional.
+#[derive(Debug, Clone, Default)]
+pub struct AlertingConfig {
+    pub webhook_url: Option<String>,
+    pub slack_webhook_url: Option<String>,
+    pub pagerduty_routing_key: Option<String>,
+    pub routing: AlertRouting,
+}
+
+impl AlertingConfig {
+    pub fn sinks(&self) -> Vec<Arc<dyn AlertSink>> {
+        let mut sinks: Vec<Arc<dyn AlertSink>> = Vec::new();
+
+        if let Some(url) = &self.webhook_url {
+            sinks.push(Arc::new(WebhookSink::new(url.clone())));
+        }
+        if let Some(url) = &self.slack_webhook_url {
+            sinks.push(Arc::new(SlackSink::new(url.clone())));
+        }
+        if let Some(key) = &self.pagerduty_routing_key {
+            sinks.push(Arc::new(PagerDutySink::new(key.clone())));
+        }
+
+        sinks
+    }
+}
+
+/// Feeds one sink from a background task. `dispatch` never blocks: events
+/// below the sink's severity are ignored and a full queue drops events.
+pub struct AlertDispatcher {
+    sink_name: &'static str,
+    min_severity: AlertSeverity,
+    queue: mpsc::Sender<SecurityEvent>,
+}
+
+impl AlertDispatcher {
+    /// Must be called within a tokio runtime.
+    pub fn spawn(sink: Arc<dyn AlertSink>, routing: AlertRouting) -> Self {
+        let (queue, events) = mpsc::channel(QUEUE_CAPACITY);
+        let dispatcher = Self {
+            sink_name: sink.name(),
+            min_severity: routing.min_severity,
+            queue,
+        };
+
+        tokio::spawn(deliver_batches(sink, routing, events));
+        dispatcher
+    }
+
+    pub fn dispatch(&self, event: &SecurityEvent) {
+        if event.severity < self.min_severity {
+            return;
+        }
+
+        if let Err(e) = self.queue.try_send(event.clone()) {
+            warn!("dropping {} alert: {}", self.sink_name, e);
+        }
+    }
+}
+
+/// Deliveries made within the last minute, to rate limit a sink.
+struct DeliveryBudget {
+    max_per_window: usize,
+    delivered_at: VecDeque<Instant>,
+}
+
+impl DeliveryBudget {
+    fn new(max_per_window: usize) -> Self {
+        Self {
+            max_per_window: max_per_window.max(1),
+            delivered_at: VecDeque::new(),
+        }
+    }
+
+    /// Earliest time from `now` at which another delivery is allowed.
+    fn next_slot(&mut self, now: Instant) -> Instant {
+        while self
+            .delivered_at
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= RATE_WINDOW)
+        {
+            self.delivered_at.pop_front();
+        }
+
+        match self.delivered_at.front() {
+            Some(oldest) if self.delivered_at.len() >= self.max_per_window => *oldest + RATE_WINDOW,
+            _ => now,
+        }
+    }
+
+    fn record(&mut self, at: Instant) {
+        self.delivered_at.push_back(at);
+    }
+}
+
+async fn deliver_batches(
+    sink: Arc<dyn AlertSink>,
+    routing: AlertRouting,
+    mut events: mpsc::Receiver<SecurityEvent>,
+) {
+    let mut budget = DeliveryBudget::new(routing.max_batches_per_minute);
+
+    while let Some(first) = events.recv().await {
+        let mut batch = AlertBatch::default();
+        batch.push(first);
+
+        // Over the rate limit, events keep collecting into this batch until
+        // a delivery is allowed again
+        let now = Instant::now();
+        let send_at = budget.next_slot(now).max(now + routing.batch_window);
+
+        while let Ok(Some(event)) = tokio::time::timeout_at(send_at, events.recv()).await {
+            batch.push(event);
+        }
+
+        budget.record(Instant::now());
+        if let Err(e) = sink.deliver(&batch).await {
+            error!(
+                "failed to deliver {} security alerts to {}: {}",
+                batch.len(),
+                sink.name(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access_control::SecurityEventType;
+    use chrono::Utc;
+
+    struct ChannelSink(mpsc::UnboundedSender<AlertBatch>);
+
+    impl AlertSink for ChannelSink {
+        fn name(&self) -> &'static str {
+            "channel"
+        }
+
+        fn deliver<'a>(&'a self, batch: &'a AlertBatch) -> BoxFuture<'a, anyhow::Result<()>> {
+            let _ = self.0.send(batch.clone());
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    fn event(severity: AlertSeverity) -> SecurityEvent {
+        SecurityEvent {
+            event_type: SecurityEventType::UnauthorizedAccessAttempt,
+            user: "attacker".to_string(),
+            vault: "vault1".to_string(),
+            timestamp: Utc::now(),
+            details: "denied".to_string(),
+            severity,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatcher_filters_and_batches() {
+        let (tx, mut delivered) = mpsc::unbounded_channel();
+        let dispatcher = AlertDispatcher::spawn(
+            Arc::new(ChannelSink(tx)),
+            AlertRouting {
+                min_severity: AlertSeverity::High,
+                batch_window: Duration::from_millis(50),
+                max_batches_per_minute: 10,
+            },
+        );
+
+        dispatcher.dispatch(&event(AlertSeverity::Low));
+        for _ in 0..MAX_BATCH_EVENTS + 5 {
+            dispatcher.dispatch(&event(AlertSeverity::Critical));
+        }
+
+        let batch = delivered.recv().await.unwrap();
+        assert_eq!(batch.events.len(), MAX_BATCH_EVENTS);
+        assert_eq!(batch.suppressed, 5);
+        assert_eq!(batch.max_severity(), AlertSeverity::Critical);
+    }
+
+    #[test]
+    fn test_delivery_budget_waits_for_the_window() {
+        let start = Instant::now();
+        let mut budget = DeliveryBudget::new(2);
+
+        assert_eq!(budget.next_slot(start), start);
+        budget.record(start);
+        budget.record(start + Duration::from_secs(5));
+
+        let later = start + Duration::from_secs(10);
+        assert_eq!(budget.next_slot(later), start + RATE_WINDOW);
+
+        // Once the oldest delivery leaves the window, one slot frees up
+        let after_window = start + RATE_WINDOW;
+        assert_eq!(budget.next_slot(after_window), after_window);
+    }
+}
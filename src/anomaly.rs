@@ -0,0 +1,247 @@
+//! Behavioural baselines per user, derived from their indexed transactions,
+//! and scoring of new operations against them.
+//!
+//! A baseline covers withdrawal sizes (average and 95th percentile), the
+//! hours of the day the user is usually active, and how many transactions
+//! they make per day. `UserBaseline::score_withdrawal` adds up how far a new
+//! withdrawal departs from each of these.
+
+use chrono::{NaiveDateTime, Timelike};
+
+use crate::db::transaction_repo::{TransactionRepository, UserActivity};
+
+/// Withdrawals (for sizes) or transactions (for hours and frequency) a
+/// baseline needs before it is trusted.
+pub const MIN_HISTORY: usize = 5;
+
+/// Most transactions read per baseline.
+const ACTIVITY_LIMIT: i64 = 10_000;
+
+/// A withdrawal more than this many times the average is a size outlier.
+const SIZE_OUTLIER_FACTOR: u64 = 10;
+
+/// Hours holding less than this share of the user's activity are unusual.
+const UNUSUAL_HOUR_SHARE: f64 = 0.02;
+
+/// More than this many times the usual daily count in a day is a burst.
+const BURST_FACTOR: f64 = 3.0;
+
+#[derive(Debug, Clone)]
+pub struct AnomalyConfig {
+    /// How far back baselines look.
+    pub lookback: chrono::Duration,
+    /// Score at which an operation is flagged.
+    pub flag_threshold: f64,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            lookback: chrono::Duration::days(30),
+            flag_threshold: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UserBaseline {
+    pub transactions: usize,
+    pub withdrawals: usize,
+    pub average_withdrawal: u64,
+    pub p95_withdrawal: u64,
+    /// Transactions per UTC hour of day.
+    pub hourly_activity: [u32; 24],
+    pub transactions_per_day: f64,
+    /// Transactions in the 24 hours before the baseline was computed.
+    pub last_day_transactions: usize,
+}
+
+/// How unusual an operation is: 0 is typical, 1 (the cap) is as unusual as
+/// this engine can tell. `reasons` says what contributed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AnomalyScore {
+    pub score: f64,
+    pub reasons: Vec<String>,
+}
+
+impl AnomalyScore {
+    fn add(&mut self, weight: f64, reason: String) {
+        self.score = (self.score + weight).min(1.0);
+        self.reasons.push(reason);
+    }
+}
+
+impl UserBaseline {
+    /// Baseline over `activity`, as of `now`.
+    pub fn from_activity(activity: &[UserActivity], now: NaiveDateTime) -> Self {
+        let mut withdrawals: Vec<u64> = activity
+            .iter()
+            .filter(|a| a.tx_type == "withdraw")
+            .map(|a| a.amount.max(0) as u64)
+            .collect();
+        withdrawals.sort_unstable();
+
+        let mut hourly_activity = [0u32; 24];
+        for a in activity {
+            hourly_activity[a.block_time.hour() as usize] += 1;
+        }
+
+        let days_observed = activity
+            .iter()
+            .map(|a| a.block_time)
+            .min()
+            .map(|oldest| (now - oldest).num_seconds() as f64 / 86_400.0)
+            .unwrap_or(0.0)
+            .max(1.0);
+
+        let day_ago = now - chrono::Duration::days(1);
+
+        Self {
+            transactions: activity.len(),
+            withdrawals: withdrawals.len(),
+            average_withdrawal: match withdrawals.len() {
+                0 => 0,
+                n => withdrawals.iter().sum::<u64>() / n as u64,
+            },
+            p95_withdrawal: percentile(&withdrawals, 0.95),
+            hourly_activity,
+            transactions_per_day: activity.len() as f64 / days_observed,
+            last_day_transactions: activity.iter().filter(|a| a.block_time >= day_ago).count(),
+        }
+    }
+
+    /// Score a withdrawal of `amount` made at `at` against this baseline.
+    pub fn score_withdrawal(&self, amount: u64, at: NaiveDateTime) -> AnomalyScore {
+        let mut score = AnomalyScore::default();
+
+        if self.withdrawals >= MIN_HISTORY {
+            if amount > self.average_withdrawal.saturating_mul(SIZE_OUTLIER_FACTOR) {
+                score.add(
+                    0.6,
+                    format!(
+                        "{} is over {}x the average withdrawal of {}",
+                        amount, SIZE_OUTLIER_FACTOR, self.average_withdrawal
+                    ),
+                );
+            } else if amount > self.p95_withdrawal {
+                score.add(
+                    0.3,
+                    format!(
+                        "{} is above the 95th percentile withdrawal of {}",
+                        amount, self.p95_withdrawal
+                    ),
+                );
+            }
+        }
+
+        if self.transactions >= MIN_HISTORY {
+            let hour = at.hour() as usize;
+            let share = self.hourly_activity[hour] as f64 / self.transactions as f64;
+            if share < UNUSUAL_HOUR_SHARE {
+                score.add(0.2, format!("unusual hour of activity ({:02}:00 UTC)", hour));
+            }
+
+            let usual = (self.transactions_per_day * BURST_FACTOR).max(BURST_FACTOR);
+            if (self.last_day_transactions + 1) as f64 > usual {
+                score.add(
+                    0.2,
+                    format!(
+                        "{} transactions in the last day, usually {:.1}",
+                        self.last_day_transactions + 1,
+                        self.transactions_per_day
+                    ),
+                );
+            }
+        }
+
+        score
+    }
+}
+
+/// Nearest-rank percentile of sorted `values`; 0 when empty.
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Baseline of `user` from their indexed transactions within `lookback`.
+pub async fn load_baseline(
+    repo: &TransactionRepository<'_>,
+    user: &str,
+    lookback: chrono::Duration,
+    now: NaiveDateTime,
+) -> anyhow::Result<UserBaseline> {
+    let activity = repo
+        .user_activity_since(user, now - lookback, ACTIVITY_LIMIT)
+        .await?;
+
+    Ok(UserBaseline::from_activity(&activity, now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 5, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn activity(tx_type: &str, amount: i64, block_time: NaiveDateTime) -> UserActivity {
+        UserActivity {
+            tx_type: tx_type.to_string(),
+            amount,
+            block_time,
+        }
+    }
+
+    #[test]
+    fn test_percentile() {
+        assert_eq!(percentile(&[], 0.95), 0);
+        assert_eq!(percentile(&[7], 0.95), 7);
+
+        let values: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&values, 0.95), 95);
+    }
+
+    #[test]
+    fn test_baseline_and_scoring() {
+        // One withdrawal of ~100 a day around 14:00 for ten days
+        let history: Vec<UserActivity> = (1..=10)
+            .map(|day| activity("withdraw", 90 + day as i64 * 2, at(day, 14)))
+            .chain([activity("deposit", 5_000, at(1, 13))])
+            .collect();
+
+        let now = at(11, 12);
+        let baseline = UserBaseline::from_activity(&history, now);
+        assert_eq!(baseline.withdrawals, 10);
+        assert_eq!(baseline.average_withdrawal, 101);
+        assert_eq!(baseline.p95_withdrawal, 110);
+        assert_eq!(baseline.hourly_activity[14], 10);
+
+        // Typical size, usual hour
+        assert_eq!(baseline.score_withdrawal(100, at(11, 14)).score, 0.0);
+
+        // Somewhat large
+        let large = baseline.score_withdrawal(150, at(11, 14));
+        assert!((large.score - 0.3).abs() < 1e-9);
+
+        // Huge, at 03:00
+        let huge = baseline.score_withdrawal(5_000, at(11, 3));
+        assert!((huge.score - 0.8).abs() < 1e-9);
+        assert_eq!(huge.reasons.len(), 2);
+    }
+
+    #[test]
+    fn test_no_score_without_history() {
+        let baseline = UserBaseline::from_activity(&[], at(1, 0));
+        assert_eq!(baseline.score_withdrawal(u64::MAX, at(1, 3)), AnomalyScore::default());
+    }
+}
@@ -1,40 +1,85 @@
-use std::net::SocketAddr; // here we import the SocketAddr struct this includes the network address and port number
+use std::net::{IpAddr, SocketAddr}; // here we import the SocketAddr struct this includes the network address and port number
 use std::sync::Arc; // here we import the arc struct (the shared state between multiple threads)
 
 use anyhow::Context;
 use axum::{ // we are using the axum framework for the web server
-    extract::{Path, State, WebSocketUpgrade},
+    extract::{Path, Query, State, WebSocketUpgrade},
     http::StatusCode,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post, put},
     Json, Router,
 };
+use axum::extract::{FromRequestParts, MatchedPath, Request};
+use axum::http::request::Parts;
+use axum::middleware::{self, Next};
 use axum::response::Response;
 use axum::extract::ws::{Message as WsMessage, WebSocket};
+use tower_http::catch_panic::{CatchPanicLayer, ResponseForPanic};
 use serde::{Deserialize, Serialize};
+use tracing::Instrument;
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
+    commitment_config::CommitmentConfig,
     message::Message,
     pubkey::Pubkey,
     transaction::Transaction,
 };
-use sqlx::PgPool;
 
-use crate::config::Config;
-use crate::db::{pool::create_pg_pool, transaction_repo::TransactionRepository, vault_repo::VaultRepository};
+use crate::access_control::{
+    normalize_authorizations, AccessControlManager, AlertSeverity, AuthorizationEntry,
+    ImportSummary, LimitEnforcement, SecurityEventFilter, SecurityEventType, WithdrawalDecision,
+    WithdrawalLimits,
+};
+use crate::auth::{
+    self, Caller, RequestSigning, SessionSettings, SignedBy, SignedJson, ADMINS, OPERATORS,
+    TRANSACTION_BUILDERS,
+};
+use crate::config::{Config, Subsystems};
+use crate::audit::{self, AuditCategory};
+use crate::db::{
+    api_key_repo::{ApiKeyRepository, Role},
+    audit_log_repo::{AuditLogQuery, AuditLogRepository},
+    migrate::run_migrations,
+    pool::{create_db_pools, follow_database_url, DbPools},
+    reconciliation_repo::ReconciliationRepository,
+    transaction_repo::TransactionRepository,
+    vault_repo::{ChainBalances, VaultRepository},
+    program_quota_repo::{ProgramQuotaRepository, ProgramQuotaRow},
+    session_repo::{IssuedSession, RefreshOutcome, SessionRepository},
+    withdrawal_cap_repo::{CapScope, WithdrawalCapRepository, WithdrawalCapRow},
+};
+use crate::error_handling::{
+    Deadline, OperationClass, TimeoutPolicy, VaultContextExt, VaultError, VaultResult,
+};
+use crate::logging::Logger;
+use crate::metrics::MetricsRegistry;
+use crate::network::TokenProgram;
+use crate::shutdown::shutdown_signal;
+use crate::telemetry;
 use crate::transaction_builder::TransactionBuilder;
+use crate::{indexer, reconciliation};
 
 #[derive(Clone)]
 pub struct AppState { // this is the state of the application (this includes the rpc client, the program id, and the database pool)
     pub rpc: Arc<RpcClient>, // this is the rpc client (this is used to interact with the solana blockchain)
     pub program_id: Pubkey, // this is the program id (this is used to identify the program)
-    pub pool: PgPool, // this is the database pool (this is used to interact with the database)
+    pub token_program: TokenProgram, // token program of the network profile (vault token accounts belong to it)
+    pub pools: DbPools, // primary pool for writes plus read replicas for queries
+    pub access_control: Arc<AccessControlManager>, // security events and withdrawal velocity tracking
+    pub withdrawal_limits: WithdrawalLimits, // per-user withdrawal caps checked before building a withdrawal
+    pub request_signing: RequestSigning, // whether withdrawals must be signed by the user's wallet
+    pub trust_forwarded_for: bool, // take the client IP from X-Forwarded-For (only behind our own proxy)
+    pub sessions: SessionSettings, // lifetimes of session tokens opened with an API key
+    pub ws_broadcast_interval: std::time::Duration, // how often /ws/vaults pushes TVL to each client
+    pub subsystems: Subsystems, // which optional endpoint groups (WebSocket, admin) are served
+    pub timeouts: TimeoutPolicy, // how long handlers wait on RPC calls and database queries
+    pub request_timeout: std::time::Duration, // overall budget of a request, see `Deadline`
 }
 
 impl AppState { // this is the implementation of the app state (this includes the transaction builder)
 
     pub fn tx_builder(&self) -> TransactionBuilder {
-        TransactionBuilder::new(self.program_id)
+        TransactionBuilder::new(self.program_id).with_token_program(self.token_program)
     }
 
 }
@@ -54,9 +99,32 @@ pub struct DepositRequest { // this is the request body for the deposit endpoint
 
 #[derive(Deserialize)]
 pub struct WithdrawRequest { // this is the request body for the withdraw endpoint
-    pub user_pubkey: String,
+    pub user_pubkey: String, // wallet withdrawing, which must sign the request
     pub mint: String,
     pub amount: u64, // amount to be withdrawn
+    #[serde(default)]
+    pub vault_pda: Option<String>, // vault to withdraw from (the user's own if omitted)
+}
+
+impl SignedBy for WithdrawRequest { // withdrawals must be signed by the wallet they withdraw for
+    fn signer(&self) -> &str {
+        &self.user_pubkey
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LockRequest { // this is the request body for the lock and unlock endpoints
+    pub user_pubkey: String, // wallet asking for the lock, which must sign the request
+    pub caller_program: String, // program the collateral is locked for
+    pub amount: u64,
+    #[serde(default)]
+    pub vault_pda: Option<String>, // vault to lock in (the user's own if omitted)
+}
+
+impl SignedBy for LockRequest { // locks must be signed by the wallet that asks for them
+    fn signer(&self) -> &str {
+        &self.user_pubkey
+    }
 }
 
 #[derive(Serialize)]
@@ -85,23 +153,281 @@ pub struct TransactionSummary { // this is the response body for the transaction
     pub slot: i64, // slot of the transaction 
 }
 
+#[derive(Serialize)]
+pub struct TransactionLookupResponse { // this is the response body for the signature lookup endpoint
+    pub transactions: Vec<TransactionDetail>, // one entry for an exact match, possibly several for a prefix
+}
+
+#[derive(Serialize)]
+pub struct TransactionDetail { // everything the backend recorded for a transaction
+    pub tx_signature: String,
+    pub vault_pda: String,
+    pub user_pubkey: Option<String>,
+    pub tx_type: String,
+    pub amount: i64,
+    pub slot: i64,
+    pub block_time: chrono::NaiveDateTime,
+    pub commitment: String, // commitment the indexer saw the transaction at
+}
+
 #[derive(Serialize)]
 pub struct TvlResponse { // this is the response body for the tvl endpoint
     pub tvl: i64, // total value locked (TVL) of all vaults (this is the total value of all the vaults in the database)
 }
 
+#[derive(Deserialize)]
+pub struct ReconciliationRunsQuery { // query string for the reconciliation runs endpoint
+    pub limit: Option<i64>, // number of runs to return, newest first (default 20, at most 100)
+}
+
+#[derive(Serialize)]
+pub struct ReconciliationRunsResponse { // this is the response body for the reconciliation runs endpoint
+    pub runs: Vec<ReconciliationRunSummary>,
+}
+
+#[derive(Serialize)]
+pub struct ReconciliationRunSummary { // one reconciliation pass
+    pub id: String,
+    pub started_at: chrono::NaiveDateTime,
+    pub finished_at: Option<chrono::NaiveDateTime>, // null while running or if the pass never finished
+    pub vaults_checked: i64,
+    pub vaults_skipped: i64, // vaults without a recorded token account
+    pub discrepancies_found: i64,
+    pub largest_drift: i64, // largest absolute drift among the discrepancies found
+    pub error_count: i64,
+    pub signature_gaps: i64, // signatures only the chain or only the indexed history has
+    pub clean: bool, // finished with no discrepancies, signature gaps, errors or skipped vaults
+}
+
+#[derive(Deserialize)]
+pub struct ResolveDiscrepancyRequest { // this is the request body for the resolve discrepancy endpoint
+    pub note: String, // reason recorded with the resolution and the ledger adjustment
+}
+
+#[derive(Serialize)]
+pub struct ResolveDiscrepancyResponse { // this is the response body for the resolve discrepancy endpoint
+    pub adjustment: i64, // signed change made to the drifted component (0 if it already matched the chain)
+    pub resolved: u64, // discrepancies closed for the vault, this one included
+}
+
+#[derive(Deserialize)]
+pub struct IssueApiKeyRequest { // this is the request body for the issue api key endpoint
+    pub owner: String, // who the key is for
+    pub role: String, // admin | operator | read_only | service
+    pub scopes: Option<Vec<String>>,
+    pub expires_at: Option<chrono::NaiveDateTime>, // never expires if omitted
+}
+
+#[derive(Serialize)]
+pub struct IssueApiKeyResponse { // this is the response body for the issue api key endpoint
+    pub id: String,
+    pub key: String, // only ever shown here, store it now
+    pub role: String,
+    pub expires_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+pub struct UnblockUserResponse { // this is the response body for the unblock user endpoint
+    pub was_blocked: bool, // false if the user wasn't blocked (their failed attempts are cleared anyway)
+}
+
+#[derive(Serialize)]
+pub struct UnbanIpResponse { // this is the response body for the unban ip endpoint
+    pub was_banned: bool, // false if the ip wasn't banned (its failed requests are cleared anyway)
+}
+
+#[derive(Deserialize)]
+pub struct AuthorizeUserRequest { // this is the request body for the authorize user endpoint
+    pub user: String, // pubkey of the user to grant access to the vault
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>, // never expires if omitted
+}
+
+#[derive(Serialize)]
+pub struct AuthorizeUserResponse { // this is the response body for the authorize user endpoint
+    pub vault: String,
+    pub user: String,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct RevokeUserResponse { // this is the response body for the revoke user endpoint
+    pub revoked: bool, // false if the user had no access to the vault
+}
+
+#[derive(Serialize)]
+pub struct SweepAuthorizationsResponse { // this is the response body for the sweep authorizations endpoint
+    pub removed: u64, // expired authorizations deleted
+}
+
+#[derive(Deserialize)]
+pub struct SecurityEventsQuery { // query string for the security events endpoint
+    pub since: Option<chrono::DateTime<chrono::Utc>>, // inclusive, RFC 3339
+    pub until: Option<chrono::DateTime<chrono::Utc>>, // exclusive, RFC 3339
+    pub user: Option<String>,
+    pub event_type: Option<String>, // e.g. suspicious_withdrawal
+    pub min_severity: Option<String>, // low | medium | high | critical
+    pub limit: Option<usize>, // newest events to return (default 100, at most 1000)
+}
+
+#[derive(Serialize)]
+pub struct SecurityEventResponse { // one security event, as returned by the security events endpoint
+    pub event_type: String,
+    pub user: String,
+    pub vault: String,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub details: String,
+    pub severity: String,
+}
+
+#[derive(Deserialize)]
+pub struct AuditLogParams { // query string for the audit log endpoint
+    pub since: Option<chrono::DateTime<chrono::Utc>>, // inclusive, RFC 3339
+    pub until: Option<chrono::DateTime<chrono::Utc>>, // exclusive, RFC 3339
+    pub category: Option<String>, // security_event | balance_change | admin_action | cpi_call
+    pub actor: Option<String>,
+    pub subject: Option<String>,
+    pub limit: Option<i64>, // newest entries to return (default 100, at most 1000)
+}
+
+#[derive(Serialize)]
+pub struct AuditLogEntryResponse { // one audit log entry, as returned by the audit log endpoint
+    pub category: String,
+    pub actor: String,
+    pub subject: Option<String>,
+    pub details: serde_json::Value,
+    pub logged_at: chrono::NaiveDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct SetWithdrawalCapRequest { // this is the request body for the set withdrawal cap endpoint
+    pub daily_cap: u64, // most that may be withdrawn in any 24 hours, in base units
+}
+
+#[derive(Serialize)]
+pub struct WithdrawalCapResponse { // this is one withdrawal cap, as returned by the withdrawal cap endpoints
+    pub scope: String, // vault | mint (a vault's own cap takes precedence over its mint's)
+    pub target: String, // vault pda or mint address
+    pub daily_cap: i64,
+    pub updated_by: String, // owner of the admin key that last set it
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl From<WithdrawalCapRow> for WithdrawalCapResponse {
+    fn from(row: WithdrawalCapRow) -> Self {
+        Self {
+            scope: row.scope,
+            target: row.target,
+            daily_cap: row.daily_cap,
+            updated_by: row.updated_by,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct RemoveWithdrawalCapResponse { // this is the response body for the remove withdrawal cap endpoint
+    pub removed: bool, // false if there was no such cap
+}
+
+#[derive(Deserialize)]
+pub struct SetProgramQuotaRequest { // this is the request body for the set program quota endpoint
+    pub max_calls_per_hour: Option<u32>, // lock/unlock calls per clock hour (no limit if omitted)
+    pub max_locked_amount: Option<u64>, // most the program may hold locked, net of unlocks (no limit if omitted)
+    pub enforcement: Option<String>, // reject (default) | flag: allow, but record a security event
+}
+
+#[derive(Serialize)]
+pub struct ProgramQuotaResponse { // this is one program quota, as returned by the program quota endpoints
+    pub program_id: String,
+    pub max_calls_per_hour: Option<i32>,
+    pub max_locked_amount: Option<i64>,
+    pub enforcement: String,
+    pub updated_by: String, // owner of the admin key that last set it
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl From<ProgramQuotaRow> for ProgramQuotaResponse {
+    fn from(row: ProgramQuotaRow) -> Self {
+        Self {
+            program_id: row.program_id,
+            max_calls_per_hour: row.max_calls_per_hour,
+            max_locked_amount: row.max_locked_amount,
+            enforcement: row.enforcement,
+            updated_by: row.updated_by,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct RemoveProgramQuotaResponse { // this is the response body for the remove program quota endpoint
+    pub removed: bool, // false if the program had no quota
+}
+
+#[derive(Serialize)]
+pub struct RevokeApiKeyResponse { // this is the response body for the revoke api key endpoint
+    pub revoked: bool, // false if the key was unknown or already revoked
+}
+
+#[derive(Serialize)]
+pub struct SessionResponse { // this is the response body for the open and refresh session endpoints
+    pub session_id: String,
+    pub access_token: String, // send as `Authorization: Bearer <token>` in place of the API key
+    pub access_expires_at: chrono::NaiveDateTime,
+    pub refresh_token: String, // single use: each refresh returns a new one
+    pub refresh_expires_at: chrono::NaiveDateTime, // the session can't be refreshed past this
+}
+
+impl From<IssuedSession> for SessionResponse {
+    fn from(session: IssuedSession) -> Self {
+        Self {
+            session_id: session.id.to_string(),
+            access_token: session.access_token,
+            access_expires_at: session.access_expires_at,
+            refresh_token: session.refresh_token,
+            refresh_expires_at: session.refresh_expires_at,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RefreshSessionRequest { // this is the request body for the refresh session endpoint
+    pub refresh_token: String,
+}
+
+#[derive(Serialize)]
+pub struct RevokeSessionsResponse { // this is the response body for the revoke session endpoints
+    pub revoked: u64, // sessions ended (0 if already ended or unknown)
+}
+
 async fn build_tx_response( // this is the function to build the transaction response and return it unsigned for external signing
-    rpc: &RpcClient,
+    state: &AppState,   // the rpc client and the time it may take
+    deadline: Deadline, // when the caller stops waiting
     payer: &Pubkey,
     ix: solana_sdk::instruction::Instruction, // this is the instruction to be executed
-) -> anyhow::Result<BuildTransactionResponse> {
-    let recent_blockhash = rpc.get_latest_blockhash()?; // getting the latest blockhash from the rpc client
+) -> VaultResult<BuildTransactionResponse> {
+    // The client blocks, so the call runs on a blocking thread; a hung connection times out
+    // the request instead of stalling the handler (the thread finishes on its own)
+    let rpc = state.rpc.clone();
+    let recent_blockhash = state
+        .timeouts
+        .run_within(OperationClass::RpcRead, deadline, async move {
+            // Blocking threads don't inherit the span; carry it over for the trace
+            let span = tracing::Span::current();
+            tokio::task::spawn_blocking(move || span.in_scope(|| rpc.get_latest_blockhash()))
+                .await
+                .context("blockhash lookup failed")?
+                .map_err(VaultError::from)
+        })
+        .await?; // getting the latest blockhash from the rpc client
 
     let message = Message::new(&[ix], Some(payer)); // creating a new message with the instruction and the payer
     let mut tx = Transaction::new_unsigned(message); // creating a new transaction with the message
     tx.message.recent_blockhash = recent_blockhash; // setting the recent blockhash to the recent blockhash
 
-    let bytes = bincode::serialize(&tx)?; // serializing the transaction
+    let bytes = bincode::serialize(&tx).map_err(|e| VaultError::SerializationError {
+        reason: e.to_string(),
+    })?; // serializing the transaction
     use base64::engine::general_purpose::STANDARD; // using the standard base64 engine  
     use base64::Engine; // using the base64 engine
     let encoded = STANDARD.encode(bytes); // encoding the transaction   
@@ -110,14 +436,102 @@ async fn build_tx_response( // this is the function to build the transaction res
 }
 
 pub fn router(state: AppState) -> Router { // this is the router for the api
-    Router::new()
-        .route("/vault/initialize", post(initialize_vault))
-        .route("/vault/deposit", post(deposit))
-        .route("/vault/withdraw", post(withdraw))
+    // Every role may query
+    let read = Router::new()
         .route("/vault/balance/{user}", get(get_balance))
         .route("/vault/transactions/{user}", get(get_transactions))
         .route("/vault/tvl", get(get_tvl))
-        .route("/ws/vaults", get(ws_vaults))
+        .route("/tx/{signature}", get(get_transaction_by_signature))
+        .route("/reconciliation/runs", get(get_reconciliation_runs));
+    let read = if state.subsystems.ws {
+        read.route("/ws/vaults", get(ws_vaults))
+    } else {
+        read
+    };
+
+    // Any caller may open and end sessions of their own
+    let sessions = Router::new()
+        .route("/auth/sessions", post(open_session))
+        .route("/auth/sessions/current", delete(close_session));
+
+    let build = Router::new()
+        .route("/vault/initialize", post(initialize_vault))
+        .route("/vault/deposit", post(deposit))
+        .route("/vault/withdraw", post(withdraw))
+        .route("/vault/lock", post(lock_collateral))
+        .route("/vault/unlock", post(unlock_collateral))
+        .route_layer(middleware::from_fn(|req: Request, next: Next| {
+            auth::require_roles(TRANSACTION_BUILDERS, req, next)
+        }));
+
+    let operate = Router::new()
+        .route(
+            "/reconciliation/discrepancies/{id}/resolve",
+            post(resolve_discrepancy),
+        )
+        .route_layer(middleware::from_fn(audit_admin_action))
+        .route_layer(middleware::from_fn(|req: Request, next: Next| {
+            auth::require_roles(OPERATORS, req, next)
+        }));
+
+    let admin = Router::new()
+        .route("/admin/api-keys", post(issue_api_key))
+        .route("/admin/api-keys/{id}/revoke", post(revoke_api_key))
+        .route("/admin/api-keys/{id}/sessions/revoke", post(revoke_key_sessions))
+        .route("/admin/sessions/{id}/revoke", post(revoke_session))
+        .route("/admin/users/{user}/unblock", post(unblock_user))
+        .route("/admin/ips/{ip}/unban", post(unban_ip))
+        .route("/admin/vaults/{vault}/authorizations", post(authorize_user))
+        .route(
+            "/admin/vaults/{vault}/authorizations/{user}",
+            delete(revoke_user),
+        )
+        .route("/admin/authorizations/sweep", post(sweep_authorizations))
+        .route("/admin/authorizations/export", get(export_authorizations))
+        .route("/admin/authorizations/import", post(import_authorizations))
+        .route("/admin/security-events", get(list_security_events))
+        .route("/admin/audit-log", get(list_audit_log))
+        .route("/admin/withdrawal-caps", get(list_withdrawal_caps))
+        .route(
+            "/admin/withdrawal-caps/{scope}/{target}",
+            put(set_withdrawal_cap).delete(remove_withdrawal_cap),
+        )
+        .route("/admin/program-quotas", get(list_program_quotas))
+        .route(
+            "/admin/program-quotas/{program}",
+            put(set_program_quota).delete(remove_program_quota),
+        )
+        // Inside the role check, so only actions the caller was allowed to try
+        .route_layer(middleware::from_fn(audit_admin_action))
+        .route_layer(middleware::from_fn(|req: Request, next: Next| {
+            auth::require_roles(ADMINS, req, next)
+        }));
+
+    let routes = Router::new()
+        .merge(read)
+        .merge(sessions)
+        .merge(build)
+        .merge(operate);
+    let routes = if state.subsystems.admin_api {
+        routes.merge(admin)
+    } else {
+        routes
+    };
+
+    routes
+        // Added last so they run first: IP screening, then authentication,
+        // then the role checks above
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::authenticate))
+        // The refresh token is the credential here, so only IP screening applies
+        .route("/auth/sessions/refresh", post(refresh_session))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::screen_ip))
+        .layer(middleware::from_fn(telemetry::trace_request))
+        // So a panic anywhere below still gets an answer
+        .layer(CatchPanicLayer::custom(PanicResponse {
+            access_control: state.access_control.clone(),
+        }))
+        // Outermost, so every request is logged, panicked ones included
+        .layer(middleware::from_fn(log_requests))
         .with_state(state) // passing the state to the router  
 }
 
@@ -130,10 +544,10 @@ async fn ws_vaults( // this is the websocket endpoint for the api
 
 async fn handle_ws(mut socket: WebSocket, state: AppState) {
     
-    use tokio::time::{sleep, Duration};
+    use tokio::time::sleep;
 
     loop {
-        let repo = VaultRepository::new(&state.pool);
+        let repo = VaultRepository::from_pools(&state.pools);
         match repo.get_tvl().await {
             Ok(tvl) => {
                 let msg = serde_json::to_string(&TvlResponse { tvl }).unwrap_or_default();
@@ -147,12 +561,13 @@ async fn handle_ws(mut socket: WebSocket, state: AppState) {
         }
 
         // Throttle updates to avoid spamming clients.
-        sleep(Duration::from_secs(5)).await;
+        sleep(state.ws_broadcast_interval).await;
     }
 }
 
 async fn initialize_vault(
     State(state): State<AppState>,
+    deadline: Deadline,
     Json(body): Json<InitializeVaultRequest>,
 ) -> impl IntoResponse {
     (|| async {
@@ -166,7 +581,7 @@ async fn initialize_vault(
             .tx_builder()
             .build_initialize_vault_ix(&user_pubkey, &mint)?;
 
-        let resp = build_tx_response(&state.rpc, &user_pubkey, ix).await?;
+        let resp = build_tx_response(&state, deadline, &user_pubkey, ix).await?;
         Ok::<_, anyhow::Error>(Json(resp))
     })()
     .await
@@ -175,6 +590,7 @@ async fn initialize_vault(
 
 async fn deposit(
     State(state): State<AppState>,
+    deadline: Deadline,
     Json(body): Json<DepositRequest>,
 ) -> impl IntoResponse {
     (|| async {
@@ -188,7 +604,7 @@ async fn deposit(
             .tx_builder()
             .build_deposit_ix(&user_pubkey, &mint, body.amount)?;
 
-        let resp = build_tx_response(&state.rpc, &user_pubkey, ix).await?;
+        let resp = build_tx_response(&state, deadline, &user_pubkey, ix).await?;
         Ok::<_, anyhow::Error>(Json(resp))
     })()
     .await
@@ -197,50 +613,255 @@ async fn deposit(
 
 async fn withdraw(
     State(state): State<AppState>,
-    Json(body): Json<WithdrawRequest>,
-) -> impl IntoResponse {
-    (|| async {
-        let user_pubkey = body
-            .user_pubkey
-            .parse::<Pubkey>()
-            .context("invalid user_pubkey")?;
-        let mint = body.mint.parse::<Pubkey>().context("invalid mint")?;
+    deadline: Deadline,
+    SignedJson(body): SignedJson<WithdrawRequest>,
+) -> Result<Json<BuildTransactionResponse>, (StatusCode, String)> {
+    let user_pubkey = body
+        .user_pubkey
+        .parse::<Pubkey>()
+        .context("invalid user_pubkey")
+        .map_err(internal_error)?;
+    let mint = body
+        .mint
+        .parse::<Pubkey>()
+        .context("invalid mint")
+        .map_err(internal_error)?;
 
-        let ix = state
-            .tx_builder()
-            .build_withdraw_ix(&user_pubkey, &mint, body.amount)?;
+    let vault = target_vault(&state, &user_pubkey, body.vault_pda.as_deref())?;
 
-        let resp = build_tx_response(&state.rpc, &user_pubkey, ix).await?;
-        Ok::<_, anyhow::Error>(Json(resp))
-    })()
+    // Access and velocity caps are checked before anything is built, so a
+    // rejected request never yields a signable transaction
+    let owner = ensure_vault_access(&state, deadline, &vault, &user_pubkey).await?;
+
+    let amount = i64::try_from(body.amount).unwrap_or(i64::MAX);
+    let cap = WithdrawalCapRepository::new(state.pools.primary())
+        .daily_cap_status(&vault, &body.mint, chrono::Utc::now().naive_utc())
+        .await
+        .map_err(internal_error)?;
+
+    if let Some(cap) = cap.filter(|cap| !cap.allows(amount)) {
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            format!(
+                "withdrawal exceeds the vault's daily cap of {} ({} withdrawn in the last 24h)",
+                cap.daily_cap, cap.withdrawn
+            ),
+        ));
+    }
+
+    let decision = state
+        .access_control
+        .check_withdrawal(&body.user_pubkey, &vault, body.amount, &state.withdrawal_limits)
+        .await
+        .map_err(internal_error)?;
+
+    if decision == WithdrawalDecision::Rejected {
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            "withdrawal exceeds the velocity limit".to_string(),
+        ));
+    }
+
+    // The instruction names the vault's owner; the requesting wallet pays
+    let ix = state
+        .tx_builder()
+        .build_withdraw_ix(&owner, &mint, body.amount)
+        .map_err(internal_error)?;
+
+    let resp = build_tx_response(&state, deadline, &user_pubkey, ix)
+        .await
+        .with_vault_context("withdraw", &vault, &body.user_pubkey)?;
+
+    state
+        .access_control
+        .clear_failed_attempts(&body.user_pubkey)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(resp))
+}
+
+async fn lock_collateral(
+    State(state): State<AppState>,
+    deadline: Deadline,
+    SignedJson(body): SignedJson<LockRequest>,
+) -> Result<Json<BuildTransactionResponse>, (StatusCode, String)> {
+    build_lock_tx(
+        &state,
+        deadline,
+        &body,
+        "lock",
+        TransactionBuilder::build_lock_collateral_ix,
+    )
     .await
-    .map_err(internal_error)
+    .map(Json)
+}
+
+async fn unlock_collateral(
+    State(state): State<AppState>,
+    deadline: Deadline,
+    SignedJson(body): SignedJson<LockRequest>,
+) -> Result<Json<BuildTransactionResponse>, (StatusCode, String)> {
+    build_lock_tx(
+        &state,
+        deadline,
+        &body,
+        "unlock",
+        TransactionBuilder::build_unlock_collateral_ix,
+    )
+    .await
+    .map(Json)
+}
+
+/// Build a lock or unlock of `body.amount` for `body.caller_program`, once
+/// the requesting wallet is cleared to act on the vault.
+async fn build_lock_tx(
+    state: &AppState,
+    deadline: Deadline,
+    body: &LockRequest,
+    operation: &'static str,
+    build_ix: fn(
+        &TransactionBuilder,
+        &Pubkey,
+        &Pubkey,
+        u64,
+    ) -> anyhow::Result<solana_sdk::instruction::Instruction>,
+) -> Result<BuildTransactionResponse, (StatusCode, String)> {
+    let bad_request = |e: anyhow::Error| (StatusCode::BAD_REQUEST, e.to_string());
+    let user_pubkey = body
+        .user_pubkey
+        .parse::<Pubkey>()
+        .context("invalid user_pubkey")
+        .map_err(bad_request)?;
+    let caller_program = body
+        .caller_program
+        .parse::<Pubkey>()
+        .context("invalid caller_program")
+        .map_err(bad_request)?;
+
+    let vault = target_vault(state, &user_pubkey, body.vault_pda.as_deref())?;
+    let owner = ensure_vault_access(state, deadline, &vault, &user_pubkey).await?;
+
+    let ix = build_ix(&state.tx_builder(), &caller_program, &owner, body.amount)
+        .map_err(internal_error)?;
+
+    let resp = build_tx_response(state, deadline, &user_pubkey, ix)
+        .await
+        .with_vault_context(operation, &vault, &body.user_pubkey)?;
+
+    state
+        .access_control
+        .clear_failed_attempts(&body.user_pubkey)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(resp)
+}
+
+/// The vault a request names, or the user's own vault if it names none.
+fn target_vault(
+    state: &AppState,
+    user: &Pubkey,
+    vault_pda: Option<&str>,
+) -> Result<String, (StatusCode, String)> {
+    match vault_pda {
+        Some(vault_pda) => vault_pda
+            .parse::<Pubkey>()
+            .map(|vault| vault.to_string())
+            .map_err(|_| (StatusCode::BAD_REQUEST, "invalid vault_pda".to_string())),
+        None => Ok(state.tx_builder().derive_vault_pda(user).0.to_string()),
+    }
+}
+
+/// Reject blocked users, and users who neither own `vault` nor were granted
+/// access to it. The latter is recorded as an unauthorized attempt, so
+/// repeated tries block the user. `user` is the wallet that signed the
+/// request; returns the vault's owner.
+async fn ensure_vault_access(
+    state: &AppState,
+    deadline: Deadline,
+    vault: &str,
+    user: &Pubkey,
+) -> Result<Pubkey, (StatusCode, String)> {
+    let access_control = &state.access_control;
+    let user_str = user.to_string();
+
+    if access_control.is_user_blocked(&user_str).await {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "user is temporarily blocked after repeated unauthorized attempts".to_string(),
+        ));
+    }
+
+    let repo = VaultRepository::from_pools(&state.pools);
+    let owner = state
+        .timeouts
+        .run_within(OperationClass::DbQuery, deadline, repo.get_vault(vault))
+        .await
+        .with_vault_context("check vault access", vault, &user_str)?
+        .map(|row| row.owner_pubkey);
+
+    // A vault that isn't indexed yet is only known to be the user's own if
+    // its PDA is derived from their pubkey
+    let (own_vault, _) = state.tx_builder().derive_vault_pda(user);
+    let owner = match owner {
+        Some(owner) => owner,
+        None if own_vault.to_string() == vault => user_str.clone(),
+        None => {
+            return Err(VaultError::AccountNotFound {
+                account: vault.to_string(),
+            }
+            .into())
+        }
+    };
+
+    let allowed = owner == user_str || access_control.is_authorized(vault, &user_str).await;
+
+    if !allowed {
+        access_control
+            .record_unauthorized_attempt(&user_str, vault, "not the owner or an authorized user")
+            .await
+            .map_err(internal_error)?;
+
+        return Err((StatusCode::FORBIDDEN, "user may not access this vault".to_string()));
+    }
+
+    owner
+        .parse::<Pubkey>()
+        .context("invalid vault owner")
+        .map_err(internal_error)
 }
 
 async fn get_balance(
     State(state): State<AppState>,
+    deadline: Deadline,
     Path(user): Path<String>,
-) -> impl IntoResponse {
-    (|| async {
-        let user_pubkey = user.parse::<Pubkey>().context("invalid user pubkey")?;
-
-        let (vault_pda, _) = state.tx_builder().derive_vault_pda(&user_pubkey);
-
-        let repo = VaultRepository::new(&state.pool);
-        if let Some(vault) = repo.get_vault(&vault_pda.to_string()).await? {
-            let resp = BalanceResponse {
-                vault_pda: vault.vault_pda,
-                total_balance: vault.total_balance,
-                available_balance: vault.available_balance,
-                locked_balance: vault.locked_balance,
-            };
-            Ok::<_, anyhow::Error>(Json(resp))
-        } else {
-            Err(anyhow::anyhow!("vault not found"))
-        }
-    })()
-    .await
-    .map_err(internal_error)
+) -> Result<Json<BalanceResponse>, (StatusCode, String)> {
+    let user_pubkey = user
+        .parse::<Pubkey>()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "invalid user pubkey".to_string()))?;
+
+    let (vault_pda, _) = state.tx_builder().derive_vault_pda(&user_pubkey);
+    let vault_pda = vault_pda.to_string();
+
+    let repo = VaultRepository::from_pools(&state.pools);
+    let vault = state
+        .timeouts
+        .run_within(
+            OperationClass::DbQuery,
+            deadline,
+            repo.get_vault(&vault_pda),
+        )
+        .await
+        .with_vault_context("get balance", &vault_pda, &user)?
+        .ok_or(VaultError::AccountNotFound { account: vault_pda })?;
+
+    Ok(Json(BalanceResponse {
+        vault_pda: vault.vault_pda,
+        total_balance: vault.total_balance,
+        available_balance: vault.available_balance,
+        locked_balance: vault.locked_balance,
+    }))
 }
 
 async fn get_transactions(
@@ -248,7 +869,7 @@ async fn get_transactions(
     Path(user): Path<String>,
 ) -> impl IntoResponse {
     (|| async {
-        let repo = TransactionRepository::new(&state.pool);
+        let repo = TransactionRepository::from_pools(&state.pools);
         let rows = repo.get_by_user(&user).await?;
 
         let txs = rows
@@ -267,17 +888,865 @@ async fn get_transactions(
     .map_err(internal_error)
 }
 
-async fn get_tvl(State(state): State<AppState>) -> impl IntoResponse {
-    (|| async {
-        let repo = VaultRepository::new(&state.pool);
-        let tvl = repo.get_tvl().await?;
-        Ok::<_, anyhow::Error>(Json(TvlResponse { tvl }))
-    })()
+async fn get_transaction_by_signature(
+    State(state): State<AppState>,
+    Path(signature): Path<String>,
+) -> Result<Json<TransactionLookupResponse>, (StatusCode, String)> {
+    let repo = TransactionRepository::from_pools(&state.pools);
+    let rows = repo
+        .get_by_signature(signature.trim())
+        .await
+        .map_err(internal_error)?;
+
+    if rows.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "transaction not found".to_string()));
+    }
+
+    let transactions = rows
+        .into_iter()
+        .map(|row| TransactionDetail {
+            tx_signature: row.tx_signature,
+            vault_pda: row.vault_pda,
+            user_pubkey: row.user_pubkey,
+            tx_type: row.tx_type,
+            amount: row.amount,
+            slot: row.slot,
+            block_time: row.block_time,
+            commitment: row.commitment,
+        })
+        .collect();
+
+    Ok(Json(TransactionLookupResponse { transactions }))
+}
+
+async fn get_tvl(
+    State(state): State<AppState>,
+    deadline: Deadline,
+) -> Result<Json<TvlResponse>, (StatusCode, String)> {
+    let repo = VaultRepository::from_pools(&state.pools);
+    let tvl = state
+        .timeouts
+        .run_within(OperationClass::DbQuery, deadline, repo.get_tvl())
+        .await?;
+
+    Ok(Json(TvlResponse { tvl }))
+}
+
+async fn get_reconciliation_runs(
+    State(state): State<AppState>,
+    Query(query): Query<ReconciliationRunsQuery>,
+) -> Result<Json<ReconciliationRunsResponse>, (StatusCode, String)> {
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+
+    let repo = ReconciliationRepository::new(state.pools.read());
+    let rows = repo.recent_runs(limit).await.map_err(internal_error)?;
+
+    let runs = rows
+        .into_iter()
+        .map(|row| ReconciliationRunSummary {
+            clean: row.finished_at.is_some()
+                && row.discrepancies_found == 0
+                && row.signature_gaps == 0
+                && row.error_count == 0
+                && row.vaults_skipped == 0,
+            id: row.id.to_string(),
+            started_at: row.started_at,
+            finished_at: row.finished_at,
+            vaults_checked: row.vaults_checked,
+            vaults_skipped: row.vaults_skipped,
+            discrepancies_found: row.discrepancies_found,
+            largest_drift: row.largest_drift,
+            error_count: row.error_count,
+            signature_gaps: row.signature_gaps,
+        })
+        .collect();
+
+    Ok(Json(ReconciliationRunsResponse { runs }))
+}
+
+async fn resolve_discrepancy(
+    State(state): State<AppState>,
+    caller: Caller,
+    deadline: Deadline,
+    Path(id): Path<String>,
+    Json(req): Json<ResolveDiscrepancyRequest>,
+) -> Result<Json<ResolveDiscrepancyResponse>, (StatusCode, String)> {
+    let id = uuid::Uuid::parse_str(&id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "invalid discrepancy id".to_string()))?;
+
+    if req.note.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "note is required".to_string()));
+    }
+
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            "discrepancy not found or already resolved".to_string(),
+        )
+    };
+
+    let repo = ReconciliationRepository::new(state.pools.primary());
+    let vault_pda = repo
+        .open_discrepancy_vault(id)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(not_found)?;
+
+    let vault = VaultRepository::from_pools(&state.pools)
+        .get_vault(&vault_pda)
+        .await?
+        .ok_or_else(not_found)?;
+
+    // The correction is what the chain holds now, read at finalized like the
+    // reconciler does, so a balance that may still roll back is never written
+    let url = state.rpc.url();
+    let (onchain, token_balance) = state
+        .timeouts
+        .run_within(OperationClass::RpcRead, deadline, async move {
+            let span = tracing::Span::current();
+            tokio::task::spawn_blocking(move || {
+                span.in_scope(|| -> anyhow::Result<_> {
+                    let rpc = RpcClient::new_with_commitment(url, CommitmentConfig::finalized());
+                    let vault_state =
+                        reconciliation::onchain::fetch_vault_state(&rpc, &vault_pda.parse()?)?;
+                    let token_balance = reconciliation::onchain::fetch_token_balance(
+                        &rpc,
+                        &vault.vault_token_account.parse()?,
+                    )?;
+                    Ok((vault_state, token_balance))
+                })
+            })
+            .await
+            .context("on-chain balance lookup failed")?
+            .map_err(VaultError::from)
+        })
+        .await?;
+
+    let to_i64 = |amount: u64| {
+        i64::try_from(amount)
+            .context("on-chain balance is too large")
+            .map_err(internal_error)
+    };
+    let onchain = ChainBalances {
+        total: to_i64(onchain.total_balance)?,
+        available: to_i64(onchain.available_balance)?,
+        locked: to_i64(onchain.locked_balance)?,
+    };
+
+    // The operator on record is whoever the key belongs to
+    let resolution = repo
+        .resolve_with_adjustment(
+            id,
+            &onchain,
+            to_i64(token_balance)?,
+            req.note.trim(),
+            &caller.owner,
+        )
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(not_found)?;
+
+    Ok(Json(ResolveDiscrepancyResponse {
+        adjustment: resolution.adjustment,
+        resolved: resolution.resolved,
+    }))
+}
+
+async fn issue_api_key(
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(req): Json<IssueApiKeyRequest>,
+) -> Result<Json<IssueApiKeyResponse>, (StatusCode, String)> {
+    let role: Role = req
+        .role
+        .parse()
+        .map_err(|e: anyhow::Error| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    if req.owner.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "owner is required".to_string()));
+    }
+
+    let repo = ApiKeyRepository::new(state.pools.primary());
+    let issued = repo
+        .create(
+            req.owner.trim(),
+            role,
+            &req.scopes.unwrap_or_default(),
+            req.expires_at,
+        )
+        .await
+        .map_err(internal_error)?;
+
+    tracing::info!(
+        "{} issued {} key {} for {}",
+        caller.owner,
+        role.as_str(),
+        issued.row.key_prefix,
+        issued.row.owner
+    );
+
+    Ok(Json(IssueApiKeyResponse {
+        id: issued.row.id.to_string(),
+        key: issued.plaintext,
+        role: issued.row.role,
+        expires_at: issued.row.expires_at,
+    }))
+}
+
+async fn revoke_api_key(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<RevokeApiKeyResponse>, (StatusCode, String)> {
+    let id = uuid::Uuid::parse_str(&id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "invalid key id".to_string()))?;
+
+    let repo = ApiKeyRepository::new(state.pools.primary());
+    let revoked = repo.revoke(id).await.map_err(internal_error)?;
+
+    Ok(Json(RevokeApiKeyResponse { revoked }))
+}
+
+async fn open_session(
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Result<Json<SessionResponse>, (StatusCode, String)> {
+    if caller.session_id.is_some() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "sessions are opened with an API key".to_string(),
+        ));
+    }
+
+    let to_chrono = |d| chrono::Duration::from_std(d).map_err(|e| internal_error(e.into()));
+    let session = SessionRepository::new(state.pools.primary())
+        .create(
+            caller.key_id,
+            to_chrono(state.sessions.access_ttl)?,
+            to_chrono(state.sessions.max_age)?,
+        )
+        .await
+        .map_err(internal_error)?;
+
+    tracing::info!("{} opened session {}", caller.owner, session.id);
+
+    Ok(Json(SessionResponse::from(session)))
+}
+
+async fn refresh_session(
+    State(state): State<AppState>,
+    Json(req): Json<RefreshSessionRequest>,
+) -> Result<Json<SessionResponse>, (StatusCode, String)> {
+    let access_ttl = chrono::Duration::from_std(state.sessions.access_ttl)
+        .map_err(|e| internal_error(e.into()))?;
+
+    let outcome = SessionRepository::new(state.pools.primary())
+        .refresh(req.refresh_token.trim(), access_ttl)
+        .await
+        .map_err(internal_error)?;
+
+    match outcome {
+        RefreshOutcome::Refreshed(session) => Ok(Json(SessionResponse::from(session))),
+        RefreshOutcome::Reused(id) => {
+            tracing::warn!("refresh token of session {} was reused; session revoked", id);
+            Err((StatusCode::UNAUTHORIZED, "invalid refresh token".to_string()))
+        }
+        RefreshOutcome::Invalid => {
+            Err((StatusCode::UNAUTHORIZED, "invalid refresh token".to_string()))
+        }
+    }
+}
+
+async fn close_session(
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Result<Json<RevokeSessionsResponse>, (StatusCode, String)> {
+    let id = caller.session_id.ok_or_else(|| {
+        (StatusCode::BAD_REQUEST, "not authenticated with a session".to_string())
+    })?;
+
+    let revoked = SessionRepository::new(state.pools.primary())
+        .revoke(id)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(RevokeSessionsResponse {
+        revoked: u64::from(revoked),
+    }))
+}
+
+async fn revoke_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<RevokeSessionsResponse>, (StatusCode, String)> {
+    let id = uuid::Uuid::parse_str(&id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "invalid session id".to_string()))?;
+
+    let revoked = SessionRepository::new(state.pools.primary())
+        .revoke(id)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(RevokeSessionsResponse {
+        revoked: u64::from(revoked),
+    }))
+}
+
+async fn revoke_key_sessions(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(id): Path<String>,
+) -> Result<Json<RevokeSessionsResponse>, (StatusCode, String)> {
+    let id = uuid::Uuid::parse_str(&id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "invalid key id".to_string()))?;
+
+    let revoked = SessionRepository::new(state.pools.primary())
+        .revoke_for_key(id)
+        .await
+        .map_err(internal_error)?;
+
+    tracing::info!("{} revoked {} sessions of key {}", caller.owner, revoked, id);
+
+    Ok(Json(RevokeSessionsResponse { revoked }))
+}
+
+async fn unblock_user(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(user): Path<String>,
+) -> Result<Json<UnblockUserResponse>, (StatusCode, String)> {
+    let was_blocked = state.access_control.is_user_blocked(&user).await;
+
+    state
+        .access_control
+        .clear_failed_attempts(&user)
+        .await
+        .map_err(internal_error)?;
+
+    tracing::info!("{} unblocked {} (was blocked: {})", caller.owner, user, was_blocked);
+
+    Ok(Json(UnblockUserResponse { was_blocked }))
+}
+
+async fn unban_ip(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(ip): Path<IpAddr>,
+) -> Result<Json<UnbanIpResponse>, (StatusCode, String)> {
+    let was_banned = state.access_control.is_ip_banned(ip).await;
+
+    state.access_control.unban_ip(ip).await.map_err(internal_error)?;
+
+    tracing::info!("{} unbanned {} (was banned: {})", caller.owner, ip, was_banned);
+
+    Ok(Json(UnbanIpResponse { was_banned }))
+}
+
+async fn authorize_user(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(vault): Path<String>,
+    Json(req): Json<AuthorizeUserRequest>,
+) -> Result<Json<AuthorizeUserResponse>, (StatusCode, String)> {
+    for (name, key) in [("vault", &vault), ("user", &req.user)] {
+        key.parse::<Pubkey>()
+            .map_err(|_| (StatusCode::BAD_REQUEST, format!("invalid {} pubkey", name)))?;
+    }
+
+    if req.expires_at.is_some_and(|at| at <= chrono::Utc::now()) {
+        return Err((StatusCode::BAD_REQUEST, "expires_at is in the past".to_string()));
+    }
+
+    state
+        .access_control
+        .authorize_user_until(&vault, &req.user, req.expires_at)
+        .await
+        .map_err(internal_error)?;
+
+    tracing::info!("{} authorized {} on {}", caller.owner, req.user, vault);
+
+    Ok(Json(AuthorizeUserResponse {
+        vault,
+        user: req.user,
+        expires_at: req.expires_at,
+    }))
+}
+
+async fn revoke_user(
+    State(state): State<AppState>,
+    Path((vault, user)): Path<(String, String)>,
+) -> Result<Json<RevokeUserResponse>, (StatusCode, String)> {
+    let revoked = state
+        .access_control
+        .revoke_user(&vault, &user)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(RevokeUserResponse { revoked }))
+}
+
+async fn sweep_authorizations(
+    State(state): State<AppState>,
+) -> Result<Json<SweepAuthorizationsResponse>, (StatusCode, String)> {
+    let removed = state
+        .access_control
+        .sweep_expired_authorizations()
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(SweepAuthorizationsResponse { removed }))
+}
+
+async fn export_authorizations(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AuthorizationEntry>>, (StatusCode, String)> {
+    let entries = state.access_control.export().await.map_err(internal_error)?;
+
+    Ok(Json(entries))
+}
+
+async fn import_authorizations(
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(entries): Json<Vec<AuthorizationEntry>>,
+) -> Result<Json<ImportSummary>, (StatusCode, String)> {
+    // Reject bad pubkeys as the caller's mistake, before anything is written
+    normalize_authorizations(entries.clone(), chrono::Utc::now())
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let summary = state
+        .access_control
+        .import(entries)
+        .await
+        .map_err(internal_error)?;
+
+    tracing::info!(
+        "{} imported {} authorizations",
+        caller.owner,
+        summary.imported
+    );
+
+    Ok(Json(summary))
+}
+
+async fn list_audit_log(
+    State(state): State<AppState>,
+    Query(query): Query<AuditLogParams>,
+) -> Result<Json<Vec<AuditLogEntryResponse>>, (StatusCode, String)> {
+    if let Some(category) = &query.category {
+        category
+            .parse::<AuditCategory>()
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    }
+
+    let filter = AuditLogQuery {
+        since: query.since.map(|t| t.naive_utc()),
+        until: query.until.map(|t| t.naive_utc()),
+        category: query.category,
+        actor: query.actor,
+        subject: query.subject,
+        limit: Some(query.limit.unwrap_or(100).clamp(1, 1000)),
+    };
+
+    let entries = AuditLogRepository::new(state.pools.read())
+        .entries(&filter)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(
+        entries
+            .into_iter()
+            .map(|e| AuditLogEntryResponse {
+                category: e.category,
+                actor: e.actor,
+                subject: e.subject,
+                details: e.details,
+                logged_at: e.logged_at,
+            })
+            .collect(),
+    ))
+}
+
+// Records every state-changing admin or operator request in the audit log,
+// whatever it answered
+async fn audit_admin_action(caller: Caller, req: Request, next: Next) -> Response {
+    if req.method() == axum::http::Method::GET {
+        return next.run(req).await;
+    }
+
+    let action = match req.extensions().get::<MatchedPath>() {
+        Some(route) => format!("{} {}", req.method(), route.as_str()),
+        None => req.method().to_string(),
+    };
+    let target = req.uri().path().to_string();
+
+    let response = next.run(req).await;
+    Logger::log_admin_action(&caller.owner, &action, &target, response.status().as_u16());
+
+    response
+}
+
+async fn list_security_events(
+    State(state): State<AppState>,
+    Query(query): Query<SecurityEventsQuery>,
+) -> Result<Json<Vec<SecurityEventResponse>>, (StatusCode, String)> {
+    let bad_request = |e: anyhow::Error| (StatusCode::BAD_REQUEST, e.to_string());
+
+    let filter = SecurityEventFilter {
+        since: query.since,
+        until: query.until,
+        user: query.user,
+        event_type: query
+            .event_type
+            .map(|t| t.parse::<SecurityEventType>())
+            .transpose()
+            .map_err(bad_request)?,
+        min_severity: query
+            .min_severity
+            .map(|s| s.parse::<AlertSeverity>())
+            .transpose()
+            .map_err(bad_request)?,
+        limit: Some(query.limit.unwrap_or(100).clamp(1, 1000)),
+    };
+
+    let events = state
+        .access_control
+        .query_security_events(&filter)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(
+        events
+            .into_iter()
+            .map(|e| SecurityEventResponse {
+                event_type: e.event_type.as_str().to_string(),
+                user: e.user,
+                vault: e.vault,
+                timestamp: e.timestamp,
+                details: e.details,
+                severity: e.severity.as_str().to_string(),
+            })
+            .collect(),
+    ))
+}
+
+async fn list_withdrawal_caps(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<WithdrawalCapResponse>>, (StatusCode, String)> {
+    let caps = WithdrawalCapRepository::new(state.pools.primary())
+        .list()
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(caps.into_iter().map(WithdrawalCapResponse::from).collect()))
+}
+
+async fn set_withdrawal_cap(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path((scope, target)): Path<(String, String)>,
+    Json(req): Json<SetWithdrawalCapRequest>,
+) -> Result<Json<WithdrawalCapResponse>, (StatusCode, String)> {
+    let scope = parse_cap_scope(&scope, &target)?;
+    let daily_cap = i64::try_from(req.daily_cap)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "daily_cap is too large".to_string()))?;
+
+    let repo = WithdrawalCapRepository::new(state.pools.primary());
+    repo.set_cap(scope, &target, daily_cap, &caller.owner)
+        .await
+        .map_err(internal_error)?;
+
+    tracing::info!(
+        "{} set the daily withdrawal cap of {} {} to {}",
+        caller.owner,
+        scope.as_str(),
+        target,
+        daily_cap
+    );
+
+    Ok(Json(WithdrawalCapResponse {
+        scope: scope.as_str().to_string(),
+        target,
+        daily_cap,
+        updated_by: caller.owner,
+        updated_at: chrono::Utc::now().naive_utc(),
+    }))
+}
+
+async fn remove_withdrawal_cap(
+    State(state): State<AppState>,
+    Path((scope, target)): Path<(String, String)>,
+) -> Result<Json<RemoveWithdrawalCapResponse>, (StatusCode, String)> {
+    let scope = parse_cap_scope(&scope, &target)?;
+
+    let removed = WithdrawalCapRepository::new(state.pools.primary())
+        .remove_cap(scope, &target)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(RemoveWithdrawalCapResponse { removed }))
+}
+
+async fn list_program_quotas(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ProgramQuotaResponse>>, (StatusCode, String)> {
+    let quotas = ProgramQuotaRepository::new(state.pools.primary())
+        .list()
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(quotas.into_iter().map(ProgramQuotaResponse::from).collect()))
+}
+
+async fn set_program_quota(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(program): Path<String>,
+    Json(req): Json<SetProgramQuotaRequest>,
+) -> Result<Json<ProgramQuotaResponse>, (StatusCode, String)> {
+    let bad_request = |e: anyhow::Error| (StatusCode::BAD_REQUEST, e.to_string());
+
+    program
+        .parse::<Pubkey>()
+        .context("invalid program id")
+        .map_err(bad_request)?;
+    let enforcement = req
+        .enforcement
+        .as_deref()
+        .map(|e| e.parse::<LimitEnforcement>())
+        .transpose()
+        .map_err(bad_request)?
+        .unwrap_or_default();
+    let max_calls_per_hour = req
+        .max_calls_per_hour
+        .map(i32::try_from)
+        .transpose()
+        .context("max_calls_per_hour is too large")
+        .map_err(bad_request)?;
+    let max_locked_amount = req
+        .max_locked_amount
+        .map(i64::try_from)
+        .transpose()
+        .context("max_locked_amount is too large")
+        .map_err(bad_request)?;
+
+    let repo = ProgramQuotaRepository::new(state.pools.primary());
+    repo.set_quota(
+        &program,
+        max_calls_per_hour,
+        max_locked_amount,
+        enforcement.as_str(),
+        &caller.owner,
+    )
     .await
-    .map_err(internal_error)
+    .map_err(internal_error)?;
+
+    tracing::info!(
+        "{} set the CPI quota of {} to {:?} calls/hour, {:?} locked ({})",
+        caller.owner,
+        program,
+        max_calls_per_hour,
+        max_locked_amount,
+        enforcement.as_str()
+    );
+
+    Ok(Json(ProgramQuotaResponse {
+        program_id: program,
+        max_calls_per_hour,
+        max_locked_amount,
+        enforcement: enforcement.as_str().to_string(),
+        updated_by: caller.owner,
+        updated_at: chrono::Utc::now().naive_utc(),
+    }))
+}
+
+async fn remove_program_quota(
+    State(state): State<AppState>,
+    Path(program): Path<String>,
+) -> Result<Json<RemoveProgramQuotaResponse>, (StatusCode, String)> {
+    let removed = ProgramQuotaRepository::new(state.pools.primary())
+        .remove_quota(&program)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(RemoveProgramQuotaResponse { removed }))
+}
+
+/// Caps are keyed by vault PDA or mint address, both pubkeys.
+fn parse_cap_scope(scope: &str, target: &str) -> Result<CapScope, (StatusCode, String)> {
+    let bad_request = |e: anyhow::Error| (StatusCode::BAD_REQUEST, e.to_string());
+
+    target
+        .parse::<Pubkey>()
+        .context("invalid target pubkey")
+        .map_err(bad_request)?;
+    scope.parse().map_err(bad_request)
+}
+
+/// The request's deadline: `REQUEST_TIMEOUT_SECS` after it arrived, or sooner
+/// if the client says in `X-Request-Timeout-Ms` that it gives up earlier.
+impl FromRequestParts<AppState> for Deadline {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let budget = parts
+            .headers
+            .get("x-request-timeout-ms")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(std::time::Duration::from_millis)
+            .map_or(state.request_timeout, |client| {
+                client.min(state.request_timeout)
+            });
+
+        Ok(Deadline::after(budget))
+    }
+}
+
+/// Rejections map to the status the client can act on. Internal failures are
+/// logged with their source chain, which the response leaves out.
+impl From<VaultError> for (StatusCode, String) {
+    fn from(err: VaultError) -> Self {
+        let status = match err.root() {
+            VaultError::InvalidAmount { .. } | VaultError::InsufficientBalance { .. } => {
+                StatusCode::BAD_REQUEST
+            }
+            VaultError::UnauthorizedAccess { .. } => StatusCode::FORBIDDEN,
+            VaultError::AccountNotFound { .. } => StatusCode::NOT_FOUND,
+            VaultError::VersionConflict { .. }
+            | VaultError::VaultNotActive { .. }
+            | VaultError::InvalidStatusTransition { .. }
+            | VaultError::UniqueViolation { .. }
+            | VaultError::ForeignKeyViolation { .. }
+            | VaultError::SerializationFailure(_) => StatusCode::CONFLICT,
+            VaultError::PoolTimeout => StatusCode::SERVICE_UNAVAILABLE,
+            VaultError::RpcConnectionError { .. } | VaultError::Rpc(_) => StatusCode::BAD_GATEWAY,
+            VaultError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        // The client gets what went wrong; the context is for the logs
+        let message = err.root().to_string();
+        if status.is_server_error() {
+            Logger::log_vault_error(&err);
+        }
+
+        (status, message)
+    }
+}
+
+/// Turns a handler panic into a JSON 500 carrying a fresh request id, and
+/// records it as a critical security event under that id. Without it the
+/// panic would drop the connection with no response and no trace.
+#[derive(Clone)]
+struct PanicResponse {
+    access_control: Arc<AccessControlManager>,
+}
+
+impl ResponseForPanic for PanicResponse {
+    type ResponseBody = axum::body::Body;
+
+    fn response_for_panic(
+        &mut self,
+        err: Box<dyn std::any::Any + Send + 'static>,
+    ) -> axum::http::Response<Self::ResponseBody> {
+        let details = if let Some(message) = err.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = err.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "panic with a non-string payload".to_string()
+        };
+        let request_id = REQUEST_ID
+            .try_with(Clone::clone)
+            .unwrap_or_else(|_| uuid::Uuid::new_v4().to_string());
+
+        // The event is stored off the request path; the response can't wait on it
+        let access_control = self.access_control.clone();
+        let event_request_id = request_id.clone();
+        tokio::spawn(async move {
+            if let Err(e) = access_control
+                .record_handler_panic(&event_request_id, &details)
+                .await
+            {
+                tracing::error!(
+                    "failed to record panic of request {}: {}",
+                    event_request_id,
+                    e
+                );
+            }
+        });
+
+        let body = Json(serde_json::json!({
+            "error": "Internal Server Error",
+            "request_id": request_id,
+        }));
+        (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+    }
+}
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    // Id of the request being handled, for the panic response
+    static REQUEST_ID: String;
+}
+
+// Logs every request and its response under one request id: the caller's
+// `x-request-id` if it sent a usable one, else a new one. The id is echoed
+// back in the same header
+async fn log_requests(req: Request, next: Next) -> Response {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let method = req.method().to_string();
+    let path = req.uri().path().to_string();
+    let started = std::time::Instant::now();
+
+    Logger::log_api_request(&request_id, &method, &path);
+
+    // Everything logged while handling the request carries its id
+    let span = Logger::request_span(&request_id, &method, &path);
+    let mut response = REQUEST_ID
+        .scope(request_id.clone(), next.run(req))
+        .instrument(span)
+        .await;
+
+    // Set by authentication; requests that never got that far are anonymous
+    let principal = response
+        .extensions()
+        .get::<Caller>()
+        .map(|caller| caller.owner.clone())
+        .unwrap_or_else(|| "anonymous".to_string());
+    Logger::log_api_response(
+        &request_id,
+        &method,
+        &path,
+        &principal,
+        response.status().as_u16(),
+        started.elapsed().as_millis(),
+    );
+
+    if let Ok(value) = axum::http::HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    response
 }
 
 fn internal_error(err: anyhow::Error) -> (StatusCode, String) {
+    // Database conflicts from repos that still return anyhow get their 409 too
+    let err = match err.downcast::<sqlx::Error>() {
+        Ok(db_err) => return VaultError::from(db_err).into(),
+        Err(err) => err,
+    };
+
     // In a production system you'd log this with `tracing` and return a structured body.
     (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
 }
@@ -286,28 +1755,157 @@ pub async fn run_server() -> anyhow::Result<()> {
 
     dotenvy::dotenv().ok();
 
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
+    // Loaded before logging starts, since it says where logs go; the
+    // startup checks run after, so what they log is kept
+    let config = Config::from_env().await?;
+    let _telemetry = telemetry::init(
+        "vault-backend",
+        config.log_file.as_ref(),
+        &config.log_sampling,
+    )?;
+    config.check_startup().await?;
+
+    let pools = create_db_pools(
+        &config.database_url,
+        &config.database_replica_urls,
+        &config.db_pool,
+    )
+    .await?;
+
+    if let Some(secrets) = config.secrets.clone() {
+        tokio::spawn(follow_database_url(
+            secrets,
+            pools.primary().clone(),
+            config.db_pool.clone(),
+        ));
+    }
+
+    if config.run_migrations {
+        run_migrations(pools.primary()).await?;
+    }
+
+    if let Some(queue_size) = config.audit_log_queue_size {
+        audit::install(pools.primary().clone(), queue_size);
+    }
+
+    let subsystems = config.subsystems;
+    tracing::info!(
+        "running api: {}, indexer: {}, reconciliation: {}",
+        subsystems.api,
+        subsystems.indexer,
+        subsystems.reconciliation
+    );
+
+    // The first subsystem to stop takes the others down with it, so a
+    // supervisor restarts the whole process rather than leaving it half up
+    let pool = pools.primary().clone();
+    let result = tokio::select! {
+        result = serve_api(&config, pools), if subsystems.api => result,
+        result = indexer::service::run(&config, pool.clone()), if subsystems.indexer => result,
+        result = reconciliation::service::run(&config, pool.clone()),
+            if subsystems.reconciliation =>
+        {
+            result.context("reconciliation worker stopped")
+        }
+        _ = shutdown_signal() => {
+            tracing::info!("shutdown signal received, stopping");
+            Ok(())
+        }
+    };
+
+    pool.close().await;
+
+    result
+}
 
-    let config = Config::from_env()?;
+async fn serve_api(config: &Config, pools: DbPools) -> anyhow::Result<()> {
+    let rpc = Arc::new(RpcClient::new_with_commitment(
+        config.rpc_url.clone(),
+        config.commitment_config(),
+    ));
 
-    let rpc = Arc::new(RpcClient::new(config.rpc_url));
-    let pool = create_pg_pool(&config.database_url).await?;
+    match config.signing.pubkey() {
+        Some(payer) => tracing::info!("signing transactions with payer {}", payer),
+        None => tracing::info!("no payer configured; transactions are returned unsigned"),
+    }
+
+    let access_control = AccessControlManager::new()
+        .with_pool(pools.primary().clone())
+        .with_block_policy(config.block_policy.clone())
+        .with_ip_policy(config.ip_policy.clone())
+        .with_anomaly_config(config.anomaly.clone())
+        .with_escalation_rules(config.escalation_rules.clone())
+        .with_alerting(&config.alerting);
 
     let state = AppState {
         rpc,
         program_id: config.program_id,
-        pool,
+        token_program: config.token_program,
+        pools,
+        access_control: Arc::new(access_control),
+        withdrawal_limits: config.withdrawal_limits.clone(),
+        request_signing: config.request_signing.clone(),
+        trust_forwarded_for: config.trust_forwarded_for,
+        sessions: config.sessions.clone(),
+        ws_broadcast_interval: std::time::Duration::from_secs(config.ws_broadcast_interval_secs),
+        subsystems: config.subsystems,
+        timeouts: config.timeouts.clone(),
+        request_timeout: std::time::Duration::from_secs(config.request_timeout_secs),
     };
 
+    // Expired authorizations and ended sessions are already ignored; sweeping
+    // only keeps them from piling up. Security events past their retention go
+    // on the same tick.
+    let access_control = state.access_control.clone();
+    let sweep_interval = std::time::Duration::from_secs(config.authorization_sweep_interval_secs);
+    let event_retention = config.event_retention.clone();
+    let pool = state.pools.primary().clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(sweep_interval);
+        loop {
+            ticker.tick().await;
+            match access_control.sweep_expired_authorizations().await {
+                Ok(0) => {}
+                Ok(removed) => tracing::info!("swept {} expired authorizations", removed),
+                Err(e) => tracing::error!("failed to sweep expired authorizations: {}", e),
+            }
+            match access_control.apply_event_retention(&event_retention).await {
+                Ok(0) => {}
+                Ok(removed) => tracing::info!("expired {} security events", removed),
+                Err(e) => tracing::error!("failed to expire security events: {}", e),
+            }
+            match SessionRepository::new(&pool).delete_expired().await {
+                Ok(0) => {}
+                Ok(removed) => tracing::info!("deleted {} ended sessions", removed),
+                Err(e) => tracing::error!("failed to delete ended sessions: {}", e),
+            }
+        }
+    });
+
+    // Kept off the API listener, so scrapes need no API key
+    if let Some(metrics_addr) = &config.api_metrics_addr {
+        let metrics_addr: SocketAddr = metrics_addr.parse()?;
+        let listener = tokio::net::TcpListener::bind(metrics_addr).await?;
+        tracing::info!("api metrics listening on {}", metrics_addr);
+
+        let metrics = Router::new().route(
+            "/metrics",
+            get(|| async { MetricsRegistry::global().render() }),
+        );
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, metrics).await {
+                tracing::error!("metrics server stopped: {}", e);
+            }
+        });
+    }
+
     let app = router(state);
 
     let addr: SocketAddr = config.server_addr.parse()?;
     tracing::info!("listening on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app.into_make_service())
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .context("server error")
         
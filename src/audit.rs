@@ -0,0 +1,121 @@
+//! Second sink for the high-value events logged through `Logger`.
+//!
+//! Once `install` has run (`AUDIT_LOG_ENABLED`), security events, balance
+//! changes, admin actions and CPI calls are also queued on a bounded channel
+//! and written to `audit_log` by a background task, so they outlive log
+//! rotation and can be queried. Logging never waits on the database: when the
+//! queue is full the entry is dropped and counted in
+//! `audit_log_dropped_total`.
+
+use std::sync::OnceLock;
+
+use chrono::Utc;
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::db::audit_log_repo::{AuditLogRepository, AuditLogRow};
+
+static AUDIT_QUEUE: OnceLock<mpsc::Sender<AuditLogRow>> = OnceLock::new();
+
+/// What kind of event an `audit_log` entry records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditCategory {
+    SecurityEvent,
+    BalanceChange,
+    AdminAction,
+    CpiCall,
+}
+
+impl AuditCategory {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditCategory::SecurityEvent => "security_event",
+            AuditCategory::BalanceChange => "balance_change",
+            AuditCategory::AdminAction => "admin_action",
+            AuditCategory::CpiCall => "cpi_call",
+        }
+    }
+}
+
+impl std::str::FromStr for AuditCategory {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "security_event" => Ok(AuditCategory::SecurityEvent),
+            "balance_change" => Ok(AuditCategory::BalanceChange),
+            "admin_action" => Ok(AuditCategory::AdminAction),
+            "cpi_call" => Ok(AuditCategory::CpiCall),
+            other => anyhow::bail!("unknown audit category '{}'", other),
+        }
+    }
+}
+
+/// Start writing audit entries to `pool`, queueing up to `capacity` of them.
+/// Only the first call in a process installs a writer; later ones return
+/// `None`.
+pub fn install(pool: PgPool, capacity: usize) -> Option<JoinHandle<()>> {
+    let (tx, rx) = mpsc::channel(capacity.max(1));
+    AUDIT_QUEUE.set(tx).ok()?;
+
+    Some(tokio::spawn(write_entries(pool, rx)))
+}
+
+/// Queue an entry, if the audit sink is installed.
+pub fn record(
+    category: AuditCategory,
+    actor: &str,
+    subject: Option<&str>,
+    details: serde_json::Value,
+) {
+    let Some(queue) = AUDIT_QUEUE.get() else {
+        return;
+    };
+
+    let row = AuditLogRow {
+        category: category.as_str().to_string(),
+        actor: actor.to_string(),
+        subject: subject.map(str::to_string),
+        details,
+        logged_at: Utc::now().naive_utc(),
+    };
+
+    if queue.try_send(row).is_err() {
+        metrics::counter!("audit_log_dropped_total").increment(1);
+    }
+}
+
+async fn write_entries(pool: PgPool, mut rx: mpsc::Receiver<AuditLogRow>) {
+    let repo = AuditLogRepository::new(&pool);
+
+    while let Some(row) = rx.recv().await {
+        if let Err(e) = repo.insert(&row).await {
+            metrics::counter!("audit_log_write_failures_total").increment(1);
+            warn!("failed to write {} audit entry: {}", row.category, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_category_round_trip() {
+        for category in [
+            AuditCategory::SecurityEvent,
+            AuditCategory::BalanceChange,
+            AuditCategory::AdminAction,
+            AuditCategory::CpiCall,
+        ] {
+            assert_eq!(
+                category.as_str().parse::<AuditCategory>().unwrap(),
+                category
+            );
+        }
+
+        assert!("login".parse::<AuditCategory>().is_err());
+    }
+}
@@ -0,0 +1,432 @@
+//! API key authentication and role checks for the HTTP API.
+//!
+//! `screen_ip` runs first on every route and turns away banned source IPs.
+//! `authenticate` then resolves the presented API key or session token to a
+//! `Caller`, and `require_roles` gates route groups by the caller's role.
+//! Sessions are opened with an API key and carry its owner and role.
+//! Sensitive endpoints additionally take a `SignedJson` body, signed by the
+//! wallet the request acts for.
+
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use axum::{
+    body::Bytes,
+    extract::{ConnectInfo, FromRequest, FromRequestParts, Request, State},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::de::DeserializeOwned;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::api::AppState;
+use crate::db::api_key_repo::{ApiKeyRepository, Role};
+use crate::db::request_nonce_repo;
+use crate::db::session_repo::{SessionRepository, ACCESS_PREFIX};
+
+/// Roles that may build deposit / withdraw / initialize transactions.
+pub const TRANSACTION_BUILDERS: &[Role] = &[Role::Operator, Role::Service];
+
+/// Roles that may correct off-chain state, e.g. resolve discrepancies.
+pub const OPERATORS: &[Role] = &[Role::Operator];
+
+/// Admin-only routes: `require_roles` always lets admins through.
+pub const ADMINS: &[Role] = &[];
+
+/// The key a request was authenticated with, set by `authenticate`.
+#[derive(Debug, Clone)]
+pub struct Caller {
+    pub key_id: Uuid,
+    pub owner: String,
+    pub role: Role,
+    /// Set when the request used a session token rather than the key itself.
+    pub session_id: Option<Uuid>,
+}
+
+impl Caller {
+    pub fn has_any_role(&self, roles: &[Role]) -> bool {
+        self.role == Role::Admin || roles.contains(&self.role)
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Caller {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Caller>()
+            .cloned()
+            .ok_or_else(|| (StatusCode::UNAUTHORIZED, "not authenticated".to_string()))
+    }
+}
+
+/// Source IP of the request, set by `screen_ip`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// The peer address, or with `trust_forwarded_for` the last hop in
+/// `X-Forwarded-For` (the one appended by our own proxy).
+fn client_ip(headers: &HeaderMap, peer: SocketAddr, trust_forwarded_for: bool) -> IpAddr {
+    let forwarded = headers
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit(',').next())
+        .and_then(|hop| hop.trim().parse::<IpAddr>().ok())
+        .filter(|_| trust_forwarded_for);
+
+    forwarded.unwrap_or(peer.ip()).to_canonical()
+}
+
+/// Turn away banned IPs, and count requests that fail authentication (401)
+/// against their source IP. Must run before `authenticate`.
+pub async fn screen_ip(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let ip = client_ip(req.headers(), peer, state.trust_forwarded_for);
+
+    if state.access_control.is_ip_banned(ip).await {
+        return (StatusCode::FORBIDDEN, "IP temporarily banned").into_response();
+    }
+
+    req.extensions_mut().insert(ClientIp(ip));
+    let path = req.uri().path().to_string();
+    let response = next.run(req).await;
+
+    if response.status() == StatusCode::UNAUTHORIZED {
+        let details = format!("unauthorized request to {}", path);
+        if let Err(e) = state.access_control.record_failed_request(ip, &details).await {
+            error!("failed to record failed request from {}: {}", ip, e);
+        }
+    }
+
+    response
+}
+
+/// Key from `X-API-Key` or `Authorization: Bearer <key>`.
+fn presented_key(headers: &HeaderMap) -> Option<&str> {
+    let header_value = |name| headers.get(name).and_then(|v| v.to_str().ok());
+
+    header_value(header::HeaderName::from_static("x-api-key"))
+        .or_else(|| header_value(header::AUTHORIZATION)?.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+}
+
+/// Reject requests without a valid API key or session token, otherwise
+/// attach its `Caller`.
+pub async fn authenticate(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let key = match presented_key(req.headers()) {
+        Some(key) => key.to_string(),
+        None => return (StatusCode::UNAUTHORIZED, "missing API key").into_response(),
+    };
+
+    let caller = if key.starts_with(ACCESS_PREFIX) {
+        session_caller(&state, &key).await
+    } else {
+        key_caller(&state, &key).await
+    };
+
+    match caller {
+        Ok(caller) => {
+            req.extensions_mut().insert(caller.clone());
+            let mut response = next.run(req).await;
+            // For the request log, which runs before authentication
+            response.extensions_mut().insert(caller);
+            response
+        }
+        Err(response) => response,
+    }
+}
+
+async fn key_caller(state: &AppState, key: &str) -> Result<Caller, Response> {
+    // `verify` records the use, so it goes to the primary
+    let row = match ApiKeyRepository::new(state.pools.primary()).verify(key).await {
+        Ok(Some(row)) => row,
+        Ok(None) => return Err((StatusCode::UNAUTHORIZED, "invalid API key").into_response()),
+        Err(e) => {
+            error!("failed to verify API key: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        }
+    };
+
+    Ok(Caller {
+        key_id: row.id,
+        owner: row.owner,
+        role: parse_role(row.id, &row.role)?,
+        session_id: None,
+    })
+}
+
+async fn session_caller(state: &AppState, token: &str) -> Result<Caller, Response> {
+    // On the primary, so a revocation takes effect on the next request
+    let session = match SessionRepository::new(state.pools.primary()).verify_access(token).await {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            return Err((StatusCode::UNAUTHORIZED, "invalid or expired session").into_response())
+        }
+        Err(e) => {
+            error!("failed to verify session token: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        }
+    };
+
+    Ok(Caller {
+        key_id: session.api_key_id,
+        owner: session.owner,
+        role: parse_role(session.api_key_id, &session.role)?,
+        session_id: Some(session.session_id),
+    })
+}
+
+fn parse_role(key_id: Uuid, role: &str) -> Result<Role, Response> {
+    role.parse().map_err(|e| {
+        error!("API key {} has an invalid role: {}", key_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })
+}
+
+/// Let the request through only if its caller is an admin or has one of
+/// `roles`. Must run after `authenticate`.
+pub async fn require_roles(roles: &'static [Role], req: Request, next: Next) -> Response {
+    match req.extensions().get::<Caller>() {
+        Some(caller) if caller.has_any_role(roles) => next.run(req).await,
+        Some(caller) => (
+            StatusCode::FORBIDDEN,
+            format!("role {} may not call this endpoint", caller.role.as_str()),
+        )
+            .into_response(),
+        None => (StatusCode::UNAUTHORIZED, "not authenticated").into_response(),
+    }
+}
+
+/// Whether sensitive endpoints require a wallet signature over the request.
+#[derive(Debug, Clone)]
+pub struct RequestSigning {
+    pub required: bool,
+    /// How far `X-Timestamp` may be from the server's clock, either way.
+    pub max_age_secs: u64,
+}
+
+impl Default for RequestSigning {
+    fn default() -> Self {
+        Self {
+            required: true,
+            max_age_secs: 60,
+        }
+    }
+}
+
+/// Lifetimes of sessions opened with an API key.
+#[derive(Debug, Clone)]
+pub struct SessionSettings {
+    /// How long an access token works before it must be refreshed.
+    pub access_ttl: std::time::Duration,
+    /// How long after opening a session it can still be refreshed.
+    pub max_age: std::time::Duration,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            access_ttl: std::time::Duration::from_secs(15 * 60),
+            max_age: std::time::Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+/// Request bodies that name the wallet they act for.
+pub trait SignedBy {
+    /// Base58 pubkey expected to have signed the request.
+    fn signer(&self) -> &str;
+}
+
+/// The bytes a client signs: `<timestamp>.<nonce>.<raw body>`.
+pub fn signed_message(timestamp: i64, nonce: &str, body: &[u8]) -> Vec<u8> {
+    let mut message = format!("{}.{}.", timestamp, nonce).into_bytes();
+    message.extend_from_slice(body);
+    message
+}
+
+/// Check the Ed25519 `signature` (base58) of `signer` over the request, and
+/// that `timestamp` is within `max_age_secs` of `now`. Nonce reuse is checked
+/// separately, against the database.
+pub fn verify_request_signature(
+    signer: &str,
+    signature: &str,
+    timestamp: i64,
+    nonce: &str,
+    body: &[u8],
+    now: i64,
+    max_age_secs: u64,
+) -> Result<(), String> {
+    if timestamp.abs_diff(now) > max_age_secs {
+        return Err("request timestamp is too far from the server time".to_string());
+    }
+    if nonce.is_empty() || nonce.len() > 64 {
+        return Err("nonce must be 1-64 characters".to_string());
+    }
+
+    let signer = Pubkey::from_str(signer).map_err(|_| "invalid signer pubkey".to_string())?;
+    let signature = Signature::from_str(signature).map_err(|_| "invalid signature".to_string())?;
+
+    if !signature.verify(signer.as_ref(), &signed_message(timestamp, nonce, body)) {
+        return Err("signature does not match the request".to_string());
+    }
+
+    Ok(())
+}
+
+/// JSON body that, with `RequestSigning::required`, must carry a signature
+/// by its `SignedBy::signer` in `X-Signature`, plus `X-Timestamp` (unix
+/// seconds) and a single-use `X-Nonce`. Without it, this is plain `Json`.
+pub struct SignedJson<T>(pub T);
+
+impl<T> FromRequest<AppState> for SignedJson<T>
+where
+    T: DeserializeOwned + SignedBy,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request(req: Request, state: &AppState) -> Result<Self, Self::Rejection> {
+        let headers = req.headers().clone();
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+        let value: T = serde_json::from_slice(&body)
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("invalid JSON body: {}", e)))?;
+
+        if !state.request_signing.required {
+            return Ok(SignedJson(value));
+        }
+
+        let unauthorized = |message: &str| (StatusCode::UNAUTHORIZED, message.to_string());
+        let header_value = |name| headers.get(name).and_then(|v| v.to_str().ok());
+
+        let signature =
+            header_value("x-signature").ok_or_else(|| unauthorized("missing X-Signature"))?;
+        let nonce = header_value("x-nonce").ok_or_else(|| unauthorized("missing X-Nonce"))?;
+        let timestamp = header_value("x-timestamp")
+            .and_then(|v| v.parse::<i64>().ok())
+            .ok_or_else(|| unauthorized("missing or invalid X-Timestamp"))?;
+
+        let verified = verify_request_signature(
+            value.signer(),
+            signature,
+            timestamp,
+            nonce,
+            &body,
+            chrono::Utc::now().timestamp(),
+            state.request_signing.max_age_secs,
+        );
+
+        // The signer is only a claim until the signature checks out, so a
+        // bad one isn't held against that wallet (anyone could name it to
+        // block its owner); `screen_ip` counts the 401 against the source IP
+        if let Err(message) = verified {
+            return Err(unauthorized(&message));
+        }
+
+        let fresh = request_nonce_repo::claim_nonce(
+            state.pools.primary(),
+            value.signer(),
+            nonce,
+            state.request_signing.max_age_secs,
+        )
+        .await
+        .map_err(|e| {
+            error!("failed to record request nonce: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "internal error".to_string())
+        })?;
+
+        if !fresh {
+            return Err(unauthorized("nonce already used"));
+        }
+
+        Ok(SignedJson(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+
+    fn caller(role: Role) -> Caller {
+        Caller {
+            key_id: Uuid::new_v4(),
+            owner: "ops".to_string(),
+            role,
+            session_id: None,
+        }
+    }
+
+    #[test]
+    fn test_role_checks() {
+        assert!(caller(Role::Admin).has_any_role(ADMINS));
+        assert!(!caller(Role::Operator).has_any_role(ADMINS));
+        assert!(caller(Role::Service).has_any_role(TRANSACTION_BUILDERS));
+        assert!(!caller(Role::ReadOnly).has_any_role(TRANSACTION_BUILDERS));
+        assert!(!caller(Role::Service).has_any_role(OPERATORS));
+    }
+
+    #[test]
+    fn test_presented_key_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(presented_key(&headers), None);
+
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer vk_a_b"));
+        assert_eq!(presented_key(&headers), Some("vk_a_b"));
+
+        headers.insert("x-api-key", HeaderValue::from_static("vk_c_d"));
+        assert_eq!(presented_key(&headers), Some("vk_c_d"));
+    }
+
+    #[test]
+    fn test_client_ip_honours_forwarded_for_only_when_trusted() {
+        let peer: SocketAddr = "[::ffff:10.0.0.2]:4000".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("198.51.100.1, 203.0.113.9"),
+        );
+
+        assert_eq!(client_ip(&headers, peer, false), "10.0.0.2".parse::<IpAddr>().unwrap());
+        assert_eq!(client_ip(&headers, peer, true), "203.0.113.9".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn test_request_signature_verification() {
+        let wallet = Keypair::new();
+        let signer = wallet.pubkey().to_string();
+        let body = br#"{"amount":5}"#;
+        let signature = wallet
+            .sign_message(&signed_message(1_000, "n1", body))
+            .to_string();
+
+        let verify = |body: &[u8], nonce, now| {
+            verify_request_signature(&signer, &signature, 1_000, nonce, body, now, 60)
+        };
+
+        assert!(verify(body, "n1", 1_030).is_ok());
+        // Tampered body, different nonce, stale timestamp
+        assert!(verify(br#"{"amount":6}"#, "n1", 1_030).is_err());
+        assert!(verify(body, "n2", 1_030).is_err());
+        assert!(verify(body, "n1", 1_061).is_err());
+
+        let other = Keypair::new().pubkey().to_string();
+        assert!(
+            verify_request_signature(&other, &signature, 1_000, "n1", body, 1_000, 60).is_err()
+        );
+    }
+}
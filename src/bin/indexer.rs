@@ -1,99 +1,165 @@
-use base64::engine::general_purpose::STANDARD;
-use base64::Engine;
-use borsh::BorshDeserialize;
-use solana_transaction_status::EncodedTransactionWithStatusMeta;
-use solana_transaction_status::option_serializer::OptionSerializer;
-
-use vault_backend::idl;
-
-#[derive(Debug)]
-pub enum VaultEvent {
-    Initialize {
-        vault: String,
-        owner: String,
-    },
-    Deposit {
-        vault: String,
-        user: String,
-        amount: u64,
-    },
-    Withdraw {
-        vault: String,
-        user: String,
-        amount: u64,
-    },
-    Lock {
-        vault: String,
-        amount: u64,
-    },
-    Unlock {
-        vault: String,
-        amount: u64,
-    },
-    Transfer {
-        from: String,
-        to: String,
-        amount: u64,
-    },
+use std::net::SocketAddr;
+
+use anyhow::Context;
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+use solana_client::rpc_config::CommitmentConfig;
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use vault_backend::config::Config;
+use vault_backend::db::health;
+use vault_backend::db::migrate::run_migrations;
+use vault_backend::db::pool::{create_pg_pool, follow_database_url};
+use vault_backend::indexer::{replay, service, vault_indexer::BackfillFrom};
+use vault_backend::metrics::MetricsRegistry;
+use vault_backend::rpc_endpoints::RpcRole;
+use vault_backend::shutdown::shutdown_signal;
+use vault_backend::telemetry;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        [] => run().await,
+        ["replay"] => replay().await,
+        ["backfill", from] => backfill(from.parse()?).await,
+        _ => anyhow::bail!("usage: indexer [replay | backfill <genesis|slot:<slot>|signature>]"),
+    }
 }
 
-fn parse_event(data: &[u8]) -> anyhow::Result<Option<VaultEvent>> {
-    if data.len() < 8 {
-        return Ok(None);
+/// `indexer backfill <from>`: index the program's history from `from` and
+/// exit. Running it again with the same `from` resumes where it stopped.
+async fn backfill(from: BackfillFrom) -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+
+    let config = Config::from_env().await?;
+    let _telemetry = telemetry::init(
+        "vault-indexer",
+        config.log_file.as_ref(),
+        &config.log_sampling,
+    )?;
+
+    let pool = create_pg_pool(&config.database_url, &config.db_pool).await?;
+
+    if config.run_migrations {
+        run_migrations(&pool).await?;
     }
 
-    match &data[..8] {
-        // DepositEvent
-        [120, 248, 61, 83, 31, 142, 107, 144] => {
-            let ev = idl::DepositEvent::try_from_slice(&data[8..])?;
-            Ok(Some(VaultEvent::Deposit {
-                vault: ev.user.to_string(),
-                user: ev.user.to_string(),
-                amount: ev.amount,
-            }))
-        }
+    let result = service::backfill(&config, pool.clone(), from).await;
 
-        // CollateralWithdrawn
-        [51, 224, 133, 106, 74, 173, 72, 82] => {
-            let ev = idl::CollateralWithdrawn::try_from_slice(&data[8..])?;
-            Ok(Some(VaultEvent::Withdraw {
-                vault: ev.vault.to_string(),
-                user: ev.user.to_string(),
-                amount: ev.amount,
-            }))
-        }
+    pool.close().await;
+
+    result
+}
+
+/// `indexer replay`: rebuild vault balances from the stored event history
+/// and exit. Stop the running indexer first. The RPC is only asked for the
+/// order of transactions that share a slot.
+async fn replay() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+
+    let config = Config::from_env().await?;
+    let _telemetry = telemetry::init(
+        "vault-indexer",
+        config.log_file.as_ref(),
+        &config.log_sampling,
+    )?;
+
+    let pool = create_pg_pool(&config.database_url, &config.db_pool).await?;
+
+    if config.run_migrations {
+        run_migrations(&pool).await?;
+    }
+
+    let rpc = config
+        .rpc_endpoints
+        .failover(RpcRole::Read, CommitmentConfig::finalized());
+
+    // The replay logs its own summary
+    let result = async {
+        let resolved = replay::resolve_block_positions(&pool, &rpc).await?;
+        info!("resolved block positions in {} slots", resolved);
 
-        _ => Ok(None),
+        replay::replay(&pool).await
     }
+    .await;
+
+    pool.close().await;
+
+    result.map(|_| ())
 }
 
-pub fn decode_events(tx: &EncodedTransactionWithStatusMeta) -> anyhow::Result<Vec<VaultEvent>> {
-    let mut events = vec![];
-
-    let meta = match &tx.meta {
-        Some(m) => m,
-        None => return Ok(events),
-    };
-
-    let logs = match &meta.log_messages {
-        OptionSerializer::Some(l) => l,
-        _ => return Ok(events),
-    };
-
-    for log in logs {
-        if let Some(payload) = log.strip_prefix("Program log: ") {
-            if let Ok(bytes) = STANDARD.decode(payload) {
-                if let Some(ev) = parse_event(&bytes)? {
-                    events.push(ev);
-                }
+async fn run() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+
+    let config = Config::from_env().await?;
+    let _telemetry = telemetry::init(
+        "vault-indexer",
+        config.log_file.as_ref(),
+        &config.log_sampling,
+    )?;
+    config.check_startup().await?;
+
+    let pool = create_pg_pool(&config.database_url, &config.db_pool).await?;
+
+    if let Some(secrets) = config.secrets.clone() {
+        tokio::spawn(follow_database_url(secrets, pool.clone(), config.db_pool.clone()));
+    }
+
+    if config.run_migrations {
+        run_migrations(&pool).await?;
+    }
+
+    let health_addr: SocketAddr = config
+        .indexer_health_addr
+        .parse()
+        .context("invalid INDEXER_HEALTH_ADDR")?;
+    let listener = tokio::net::TcpListener::bind(health_addr).await?;
+    info!("indexer health/metrics listening on {}", health_addr);
+
+    // Dropping the indexer future mid-transaction rolls back the open DB
+    // transaction, so shutting down at any point leaves no partial state.
+    tokio::select! {
+        result = service::run(&config, pool.clone()) => {
+            if let Err(e) = result {
+                error!("{:#}", e);
             }
         }
+        result = axum::serve(listener, health_router(pool.clone())) => {
+            if let Err(e) = result {
+                error!("health server stopped: {}", e);
+            }
+        }
+        _ = shutdown_signal() => {
+            info!("shutdown signal received, stopping indexer");
+        }
     }
 
-    Ok(events)
+    pool.close().await;
+
+    Ok(())
 }
 
-fn main() {
-    println!("Indexer binary");
+fn health_router(pool: PgPool) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/metrics", get(metrics))
+        .with_state(pool)
 }
 
+async fn health(State(pool): State<PgPool>) -> (StatusCode, &'static str) {
+    if health::check(&pool).await.healthy {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "database unavailable")
+    }
+}
+
+async fn metrics(State(pool): State<PgPool>) -> String {
+    // Refresh the db_* gauges so every scrape sees current pool stats
+    health::check(&pool).await;
+
+    MetricsRegistry::global().render()
+}
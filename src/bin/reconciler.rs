@@ -0,0 +1,127 @@
+use std::net::SocketAddr;
+
+use anyhow::Context;
+use axum::{routing::get, Router};
+use solana_client::rpc_client::RpcClient;
+use tracing::{error, info};
+
+use vault_backend::audit;
+use vault_backend::config::Config;
+use vault_backend::db::migrate::run_migrations;
+use vault_backend::db::pool::{create_pg_pool, follow_database_url};
+use vault_backend::metrics::MetricsRegistry;
+use vault_backend::reconciliation::historical::SnapshotVerifier;
+use vault_backend::reconciliation::service;
+use vault_backend::shutdown::shutdown_signal;
+use vault_backend::telemetry;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        [] => run().await,
+        ["verify-snapshots", slot] => {
+            verify_snapshots(slot.parse().context("invalid slot")?).await
+        }
+        _ => anyhow::bail!("usage: reconciler [verify-snapshots <slot>]"),
+    }
+}
+
+/// `reconciler verify-snapshots <slot>`: check the snapshots at or before
+/// `slot` against the chain once and exit.
+async fn verify_snapshots(slot: u64) -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+
+    let config = Config::from_env().await?;
+    let _telemetry = telemetry::init(
+        "vault-reconciler",
+        config.log_file.as_ref(),
+        &config.log_sampling,
+    )?;
+
+    let pool = create_pg_pool(&config.database_url, &config.db_pool).await?;
+
+    if config.run_migrations {
+        run_migrations(&pool).await?;
+    }
+
+    let rpc = RpcClient::new(config.archive_rpc_url.clone());
+    let verifier = SnapshotVerifier::new(rpc, pool.clone());
+    let report = verifier.verify_at_slot(slot).await;
+
+    pool.close().await;
+
+    let report = report?;
+    anyhow::ensure!(
+        report.mismatched == 0 && report.failed == 0,
+        "{} of {} snapshots disagree with the chain, {} could not be verified",
+        report.mismatched,
+        report.checked,
+        report.failed
+    );
+
+    Ok(())
+}
+
+async fn run() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+
+    let config = Config::from_env().await?;
+    let _telemetry = telemetry::init(
+        "vault-reconciler",
+        config.log_file.as_ref(),
+        &config.log_sampling,
+    )?;
+    config.check_startup().await?;
+
+    let pool = create_pg_pool(&config.database_url, &config.db_pool).await?;
+
+    if let Some(secrets) = config.secrets.clone() {
+        tokio::spawn(follow_database_url(secrets, pool.clone(), config.db_pool.clone()));
+    }
+
+    if config.run_migrations {
+        run_migrations(&pool).await?;
+    }
+
+    if let Some(queue_size) = config.audit_log_queue_size {
+        audit::install(pool.clone(), queue_size);
+    }
+
+    let metrics_addr: SocketAddr = config
+        .reconciler_metrics_addr
+        .parse()
+        .context("invalid RECONCILER_METRICS_ADDR")?;
+    let listener = tokio::net::TcpListener::bind(metrics_addr).await?;
+    info!("reconciler metrics listening on {}", metrics_addr);
+
+    tokio::select! {
+        result = service::run(&config, pool.clone()) => {
+            if let Err(e) = result {
+                error!("reconciliation worker stopped: {:#}", e);
+            }
+        }
+        result = axum::serve(listener, metrics_router()) => {
+            if let Err(e) = result {
+                error!("metrics server stopped: {}", e);
+            }
+        }
+        _ = shutdown_signal() => {
+            info!("shutdown signal received, stopping reconciler");
+        }
+    }
+
+    pool.close().await;
+
+    Ok(())
+}
+
+fn metrics_router() -> Router {
+    Router::new().route("/metrics", get(metrics))
+}
+
+async fn metrics() -> String {
+    MetricsRegistry::global().render()
+}
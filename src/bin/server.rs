@@ -1,7 +1,64 @@
+use anyhow::Context;
+
 use vault_backend::api;
+use vault_backend::config::ConfigSource;
+use vault_backend::db::{
+    api_key_repo::{ApiKeyRepository, Role},
+    migrate::run_migrations,
+    pool::{create_pg_pool, PoolSettings},
+};
+use vault_backend::telemetry;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    api::run_server().await
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        [] => api::run_server().await,
+        ["migrate"] => migrate().await,
+        ["issue-key", owner, role] => issue_key(owner, role.parse()?).await,
+        _ => anyhow::bail!("usage: server [migrate | issue-key <owner> <role>]"),
+    }
 }
 
+/// `server issue-key <owner> <role>`: print a new API key, e.g. the first
+/// admin key, which can't be issued through the API.
+async fn issue_key(owner: &str, role: Role) -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+
+    let database_url = ConfigSource::load_resolved()
+        .await?
+        .var("DATABASE_URL")
+        .context("DATABASE_URL not set in the environment or config file")?;
+
+    let pool = create_pg_pool(&database_url, &PoolSettings::default()).await?;
+    let issued = ApiKeyRepository::new(&pool)
+        .create(owner, role, &[], None)
+        .await?;
+    pool.close().await;
+
+    println!("{}", issued.plaintext);
+
+    Ok(())
+}
+
+/// `server migrate`: apply pending migrations and exit.
+async fn migrate() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+
+    let _telemetry = telemetry::init("vault-migrate", None, &[])?;
+
+    let database_url = ConfigSource::load_resolved()
+        .await?
+        .var("DATABASE_URL")
+        .context("DATABASE_URL not set in the environment or config file")?;
+
+    // Defaults on purpose: a configured statement_timeout could cut off a
+    // long-running migration
+    let pool = create_pg_pool(&database_url, &PoolSettings::default()).await?;
+    run_migrations(&pool).await?;
+    pool.close().await;
+
+    Ok(())
+}
@@ -1,35 +1,1458 @@
 use anyhow::{Context, Result};
+use figment::providers::{Format, Toml};
+use figment::Figment;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::access_control::{
+    parse_ip_allowlist, AlertSeverity, BlockPolicy, EventRetention, IpPolicy, WithdrawalLimits,
+};
+use crate::alerting::{AlertRouting, AlertingConfig};
+use crate::anomaly::AnomalyConfig;
+use crate::escalation::{parse_rules, EscalationRule};
+use crate::auth::{RequestSigning, SessionSettings};
+use crate::db::migrate::pending_migrations;
+use crate::db::pool::{create_pg_pool, PoolSettings};
+use crate::error_handling::TimeoutPolicy;
+use crate::indexer::batch_fetch::{DEFAULT_BATCH_SIZE, DEFAULT_CONCURRENCY};
+use crate::indexer::block_ingest::IngestionMode;
+use crate::indexer::event_filter::{parse_list, EventFilter};
+use crate::indexer::pruning::RetentionPolicy;
+use crate::indexer::rate_limit::{parse_endpoint_limits, RateLimitConfig};
+use crate::log_file::{LogFileConfig, LogRotation};
+use crate::log_sampling::{self, SamplingRule};
+use crate::network::{NetworkProfile, TokenProgram, PROFILES};
+use crate::reconciliation::schedule::ReconciliationSchedule;
+use crate::reconciliation::tolerance::{
+    parse_mint_tolerances, parse_severity_thresholds, parse_tolerance, ToleranceConfig,
+};
+use crate::rpc_endpoints::RpcEndpoints;
+use crate::secrets::{SecretRef, SecretStore, SecretsBackend, DEFAULT_CACHE_TTL, SECRET_SETTINGS};
+
+/// How long each startup check waits for the RPC endpoint.
+const STARTUP_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct Config {
     pub rpc_url: String,
+    pub ws_url: String,
+    pub rpc_endpoints: RpcEndpoints,
     pub program_id: Pubkey,
+    pub network: String,
+    pub token_program: TokenProgram,
+    pub commitment: CommitmentLevel,
     pub database_url: String,
+    pub database_replica_urls: Vec<String>,
+    pub db_pool: PoolSettings,
     pub server_addr: String,
+    /// Where the API serves Prometheus `/metrics`; none unless set.
+    pub api_metrics_addr: Option<String>,
+    pub ws_broadcast_interval_secs: u64,
+    pub timeouts: TimeoutPolicy,
+    pub request_timeout_secs: u64,
+    pub indexer_lag_alert_slots: u64,
+    pub snapshot_interval_secs: u64,
+    pub idl_path: Option<String>,
+    pub rpc_rate_limits: HashMap<String, RateLimitConfig>,
+    pub indexer_health_addr: String,
+    pub indexer_poll_interval_secs: u64,
+    pub indexer_batch_size: usize,
+    pub indexer_backfill_concurrency: usize,
+    pub event_filter: EventFilter,
+    pub indexer_commitment: CommitmentLevel,
+    pub gap_audit_interval_secs: u64,
+    pub gap_audit_window: usize,
+    /// How often indexed signatures are checked for finality or rollback.
+    pub finality_check_interval_secs: u64,
+    pub stale_vault_minutes: u64,
+    pub indexer_mode: IngestionMode,
+    pub retention: RetentionPolicy,
+    pub prune_interval_secs: u64,
+    pub run_migrations: bool,
+    pub transaction_partitions_ahead: u32,
+    /// Commitment the reconciler reads chain state at; `finalized` unless
+    /// `RECONCILIATION_COMMITMENT` says otherwise, whatever `COMMITMENT` is.
+    pub reconciliation_commitment: CommitmentLevel,
+    pub reconciliation_interval_secs: u64,
+    pub reconciliation_batch_size: Option<usize>,
+    pub reconciliation_tolerance: ToleranceConfig,
+    pub reconciliation_schedule: ReconciliationSchedule,
+    pub reconciliation_alert_drift: Option<u64>,
+    pub alerting: AlertingConfig,
+    pub reconciliation_signature_window: Option<usize>,
+    pub reconciler_metrics_addr: String,
+    pub archive_rpc_url: String,
+    pub partition_maintenance_interval_secs: u64,
+    pub withdrawal_limits: WithdrawalLimits,
+    pub request_signing: RequestSigning,
+    pub block_policy: BlockPolicy,
+    pub ip_policy: IpPolicy,
+    pub trust_forwarded_for: bool,
+    pub anomaly: AnomalyConfig,
+    pub authorization_sweep_interval_secs: u64,
+    pub event_retention: EventRetention,
+    pub sessions: SessionSettings,
+    pub escalation_rules: Vec<EscalationRule>,
+    pub signing: Signing,
+    /// Queue size of the `audit_log` writer; `None` unless `AUDIT_LOG_ENABLED`.
+    pub audit_log_queue_size: Option<usize>,
+    /// Rolling log files, besides stdout; `None` unless `LOG_FILE_DIR` is set.
+    pub log_file: Option<LogFileConfig>,
+    /// Share of each high-volume target's events below `warn` to log.
+    pub log_sampling: Vec<SamplingRule>,
+    pub subsystems: Subsystems,
+    /// Where `secret:` settings were read from, to follow rotations.
+    pub secrets: Option<Arc<SecretStore>>,
 }
 
 impl Config {
-    pub fn from_env() -> Result<Self> {
-        let rpc_url = env::var("RPC_URL")
-            .unwrap_or_else(|_| "http://127.0.0.1:8899".to_string());
+    /// Settings from `config.toml` (or `CONFIG_FILE`), with environment
+    /// variables taking precedence and `secret:` references resolved.
+    pub async fn from_env() -> Result<Self> {
+        Self::from_source(&ConfigSource::load_resolved().await?)
+    }
+
+    /// Every setting that is invalid is reported in one error, rather than
+    /// only the first.
+    pub fn from_source(source: &ConfigSource) -> Result<Self> {
+        let settings = Settings::new(source);
+
+        // Cluster profile; its defaults apply unless set explicitly, for this
+        // network only (DEVNET_RPC_URL) or for any network (RPC_URL)
+        let profile = settings
+            .parse_with("NETWORK", NetworkProfile::named)
+            .unwrap_or(&PROFILES[0]);
+        let network_var = |name: &str| {
+            settings
+                .var(&profile.scoped(name))
+                .or_else(|| settings.var(name))
+        };
+
+        // Label stored with indexed rows, e.g. "devnet" or "mainnet-beta"
+        let network = profile.name.to_string();
+
+        let rpc_url = network_var("RPC_URL").unwrap_or_else(|| profile.rpc_url.to_string());
+
+        let ws_url = network_var("WS_URL").unwrap_or_else(|| profile.ws_url.to_string());
+
+        // Weighted providers per role, e.g. "https://a 3 read, https://b send";
+        // roles left out are served by RPC_URL / WS_URL
+        let rpc_endpoints = network_var("RPC_URLS")
+            .and_then(|raw| {
+                settings
+                    .check(RpcEndpoints::parse(&raw, &rpc_url, &ws_url).context("Invalid RPC_URLS"))
+            })
+            .unwrap_or_else(|| RpcEndpoints::single(&rpc_url, &ws_url));
+
+        let program_id = match network_var("PROGRAM_ID") {
+            Some(raw) => settings
+                .check(raw.parse::<Pubkey>().context("Invalid PROGRAM_ID format"))
+                .unwrap_or_default(),
+            None => {
+                settings.report(format!(
+                    "PROGRAM_ID (or {}) not set in the environment or config file",
+                    profile.scoped("PROGRAM_ID")
+                ));
+                Pubkey::default()
+            }
+        };
+
+        // "spl-token" or "token-2022"
+        let token_program = network_var("TOKEN_PROGRAM")
+            .and_then(|raw| {
+                settings.check(raw.parse::<TokenProgram>().context("Invalid TOKEN_PROGRAM"))
+            })
+            .unwrap_or(profile.token_program);
+
+        // Commitment every RPC client reads at: the API, VaultManager, the
+        // indexer and the reconciliation worker
+        let commitment = network_var("COMMITMENT")
+            .and_then(|raw| settings.check(parse_commitment(&raw).context("Invalid COMMITMENT")))
+            .unwrap_or(profile.commitment);
+
+        let database_url = settings.var("DATABASE_URL").unwrap_or_else(|| {
+            settings.report("DATABASE_URL not set in the environment or config file");
+            String::new()
+        });
+
+        // Optional comma separated read-replica DSNs
+        let database_replica_urls = settings
+            .var("DATABASE_REPLICA_URLS")
+            .map(|v| {
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let db_pool = pool_settings_from_env(&settings);
+
+        let server_addr = settings
+            .var("SERVER_ADDR")
+            .unwrap_or_else(|| "0.0.0.0:8080".to_string());
+        let api_metrics_addr = settings.var("API_METRICS_ADDR");
+
+        // How often /ws/vaults pushes TVL to each client
+        let ws_broadcast_interval_secs = settings.parse("WS_BROADCAST_INTERVAL_SECS").unwrap_or(5);
+        settings.ensure(
+            ws_broadcast_interval_secs > 0,
+            "WS_BROADCAST_INTERVAL_SECS must be at least 1",
+        );
+
+        // How long API handlers wait on the chain and the database
+        let timeout_defaults = TimeoutPolicy::default();
+        let timeouts = TimeoutPolicy {
+            rpc_read: settings.secs("RPC_READ_TIMEOUT_SECS", timeout_defaults.rpc_read),
+            rpc_send: settings.secs("RPC_SEND_TIMEOUT_SECS", timeout_defaults.rpc_send),
+            db_query: settings.secs("DB_QUERY_TIMEOUT_SECS", timeout_defaults.db_query),
+        };
+        settings.ensure(
+            [timeouts.rpc_read, timeouts.rpc_send, timeouts.db_query]
+                .iter()
+                .all(|limit| !limit.is_zero()),
+            "RPC_READ_TIMEOUT_SECS, RPC_SEND_TIMEOUT_SECS and DB_QUERY_TIMEOUT_SECS \
+             must be at least 1",
+        );
+
+        // Overall budget of an API request; nested timeouts and retries stay within it
+        let request_timeout_secs = settings.parse("REQUEST_TIMEOUT_SECS").unwrap_or(30);
+        settings.ensure(
+            request_timeout_secs > 0,
+            "REQUEST_TIMEOUT_SECS must be at least 1",
+        );
+
+        let indexer_lag_alert_slots = settings.parse("INDEXER_LAG_ALERT_SLOTS").unwrap_or(150);
+
+        let snapshot_interval_secs = settings.parse("SNAPSHOT_INTERVAL_SECS").unwrap_or(3600);
+
+        let idl_path = settings.var("IDL_PATH");
+
+        // Per-endpoint limits, e.g. "https://api.devnet.solana.com=5:10"
+        let rpc_rate_limits = settings
+            .parse_with("RPC_RATE_LIMITS", parse_endpoint_limits)
+            .unwrap_or_default();
+
+        let indexer_health_addr = settings
+            .var("INDEXER_HEALTH_ADDR")
+            .unwrap_or_else(|| "0.0.0.0:9100".to_string());
+
+        let indexer_poll_interval_secs = settings.parse("INDEXER_POLL_INTERVAL_SECS").unwrap_or(10);
+
+        // getTransaction calls per JSON-RPC batch, and batches in flight at
+        // once while catching up or backfilling
+        let indexer_batch_size = settings
+            .parse("INDEXER_BATCH_SIZE")
+            .unwrap_or(DEFAULT_BATCH_SIZE);
+        let indexer_backfill_concurrency = settings
+            .parse("INDEXER_BACKFILL_CONCURRENCY")
+            .unwrap_or(DEFAULT_CONCURRENCY);
+        settings.ensure(
+            indexer_batch_size > 0 && indexer_backfill_concurrency > 0,
+            "INDEXER_BATCH_SIZE and INDEXER_BACKFILL_CONCURRENCY must be at least 1",
+        );
+
+        // Comma separated lists; unset means no restriction
+        let list = |key: &str| {
+            settings
+                .var(key)
+                .map(|v| parse_list(&v))
+                .unwrap_or_default()
+        };
+
+        let event_filter = EventFilter {
+            include_mints: list("INDEXER_INCLUDE_MINTS"),
+            exclude_mints: list("INDEXER_EXCLUDE_MINTS"),
+            include_vaults: list("INDEXER_INCLUDE_VAULTS"),
+            exclude_vaults: list("INDEXER_EXCLUDE_VAULTS"),
+            include_event_types: list("INDEXER_INCLUDE_EVENT_TYPES"),
+            exclude_event_types: list("INDEXER_EXCLUDE_EVENT_TYPES"),
+        };
+
+        let indexer_commitment = settings
+            .parse_with("INDEXER_COMMITMENT", parse_commitment)
+            .unwrap_or(commitment);
+
+        let gap_audit_interval_secs = settings.parse("GAP_AUDIT_INTERVAL_SECS").unwrap_or(300);
+
+        let gap_audit_window = settings.parse("GAP_AUDIT_WINDOW").unwrap_or(1000);
+
+        let finality_check_interval_secs =
+            settings.parse("FINALITY_CHECK_INTERVAL_SECS").unwrap_or(30);
+
+        // Minutes without a sync, despite on-chain activity, before a vault
+        // is reported as stale ("0" disables the check)
+        let stale_vault_minutes = settings.parse("STALE_VAULT_MINUTES").unwrap_or(30);
+
+        // "signatures" (default) or "blocks"
+        let indexer_mode = settings
+            .parse("INDEXER_MODE")
+            .unwrap_or(IngestionMode::Signatures);
+
+        // Age in days ("0" disables) and/or row count for processed_events
+        let retention_days: u64 = settings
+            .parse("PROCESSED_EVENTS_RETENTION_DAYS")
+            .unwrap_or(30);
+
+        let retention = RetentionPolicy {
+            max_age: (retention_days > 0).then(|| Duration::from_secs(retention_days * 24 * 3600)),
+            max_rows: settings.parse("PROCESSED_EVENTS_MAX_ROWS"),
+        };
+
+        let prune_interval_secs = settings.parse("PRUNE_INTERVAL_SECS").unwrap_or(3600);
+
+        // Apply embedded migrations at startup when set to "true" / "1"
+        let run_migrations = settings.flag("RUN_MIGRATIONS").unwrap_or(false);
+
+        // Monthly transactions partitions to keep created beyond the current one
+        let transaction_partitions_ahead =
+            settings.parse("TRANSACTION_PARTITIONS_AHEAD").unwrap_or(3);
+
+        let partition_maintenance_interval_secs = settings
+            .parse("PARTITION_MAINTENANCE_INTERVAL_SECS")
+            .unwrap_or(86400);
+
+        // Balances are only compared against state that can't roll back,
+        // unless explicitly relaxed
+        let reconciliation_commitment = settings
+            .parse_with("RECONCILIATION_COMMITMENT", parse_commitment)
+            .unwrap_or(CommitmentLevel::Finalized);
+
+        let reconciliation_interval_secs = settings
+            .parse("RECONCILIATION_INTERVAL_SECS")
+            .unwrap_or(300);
+
+        // Most vaults checked per pass; unset checks every due vault
+        let reconciliation_batch_size: Option<usize> = settings.parse("RECONCILIATION_BATCH_SIZE");
+        settings.ensure(
+            reconciliation_batch_size != Some(0),
+            "RECONCILIATION_BATCH_SIZE must be at least 1",
+        );
+
+        // Drift ignored by reconciliation, as "absolute:percent" by default
+        // and "mint=absolute:percent,..." per mint; severity tiers are
+        // "medium:high:critical" percents of the on-chain balance
+        let reconciliation_tolerance = ToleranceConfig {
+            default: settings
+                .parse_with("RECONCILIATION_TOLERANCE", parse_tolerance)
+                .unwrap_or_default(),
+            per_mint: settings
+                .parse_with("RECONCILIATION_MINT_TOLERANCES", parse_mint_tolerances)
+                .unwrap_or_default(),
+            severity: settings
+                .parse_with("RECONCILIATION_SEVERITY_TIERS", parse_severity_thresholds)
+                .unwrap_or_default(),
+        };
+
+        // Vaults active within the window are reconciled every pass, dormant
+        // ones once every RECONCILIATION_DORMANT_EVERY passes
+        let reconciliation_schedule = ReconciliationSchedule {
+            active_window: settings.secs(
+                "RECONCILIATION_ACTIVE_WINDOW_SECS",
+                Duration::from_secs(3600),
+            ),
+            dormant_every: settings.parse("RECONCILIATION_DORMANT_EVERY").unwrap_or(1),
+        };
+
+        settings.ensure(
+            reconciliation_schedule.dormant_every > 0,
+            "RECONCILIATION_DORMANT_EVERY must be at least 1",
+        );
+
+        // Absolute drift (base units) raised as a critical security event;
+        // unset disables these alerts
+        let reconciliation_alert_drift = settings.parse("RECONCILIATION_ALERT_DRIFT");
+
+        // Security events of at least ALERT_MIN_SEVERITY go to each sink
+        // that is set, batched over ALERT_BATCH_WINDOW_SECS and sent at most
+        // ALERT_MAX_BATCHES_PER_MINUTE times a minute
+        let alert_defaults = AlertRouting::default();
+        let alerting = AlertingConfig {
+            webhook_url: settings.var("ALERT_WEBHOOK_URL"),
+            slack_webhook_url: settings.var("SLACK_WEBHOOK_URL"),
+            pagerduty_routing_key: settings.var("PAGERDUTY_ROUTING_KEY"),
+            routing: AlertRouting {
+                min_severity: settings
+                    .parse("ALERT_MIN_SEVERITY")
+                    .unwrap_or(alert_defaults.min_severity),
+                batch_window: settings.secs("ALERT_BATCH_WINDOW_SECS", alert_defaults.batch_window),
+                max_batches_per_minute: settings
+                    .parse("ALERT_MAX_BATCHES_PER_MINUTE")
+                    .unwrap_or(alert_defaults.max_batches_per_minute),
+            },
+        };
+
+        // Newest signatures per vault compared against the indexed history;
+        // unset skips the signature check
+        let reconciliation_signature_window = settings.parse("RECONCILIATION_SIGNATURE_WINDOW");
+
+        let reconciler_metrics_addr = settings
+            .var("RECONCILER_METRICS_ADDR")
+            .unwrap_or_else(|| "0.0.0.0:9101".to_string());
+
+        // Historical snapshot verification needs old transactions; defaults
+        // to RPC_URL, which only works for recent slots on most providers
+        let archive_rpc_url = settings
+            .var("ARCHIVE_RPC_URL")
+            .unwrap_or_else(|| rpc_url.clone());
+
+        // Per-user withdrawal caps (base units) over the last hour / day;
+        // unset caps are not enforced
+        let withdrawal_limits = WithdrawalLimits {
+            hourly_cap: settings.parse("WITHDRAWAL_HOURLY_CAP"),
+            daily_cap: settings.parse("WITHDRAWAL_DAILY_CAP"),
+            enforcement: settings.parse("WITHDRAWAL_LIMIT_MODE").unwrap_or_default(),
+        };
+
+        // Withdrawals must be signed by the user's wallet unless explicitly
+        // opted out with REQUIRE_SIGNED_REQUESTS=false
+        let request_signing = RequestSigning {
+            required: settings
+                .flag("REQUIRE_SIGNED_REQUESTS")
+                .unwrap_or(RequestSigning::default().required),
+            max_age_secs: settings
+                .parse("REQUEST_SIGNATURE_MAX_AGE_SECS")
+                .unwrap_or(RequestSigning::default().max_age_secs),
+        };
+
+        // Users are blocked after BLOCK_MAX_ATTEMPTS failed attempts until
+        // BLOCK_COOLDOWN_SECS pass; one attempt decays per
+        // FAILED_ATTEMPT_DECAY_SECS
+        let block_defaults = BlockPolicy::default();
+        let block_policy = BlockPolicy {
+            max_attempts: settings
+                .parse("BLOCK_MAX_ATTEMPTS")
+                .unwrap_or(block_defaults.max_attempts),
+            cooldown: settings.secs("BLOCK_COOLDOWN_SECS", block_defaults.cooldown),
+            decay_every: settings.secs("FAILED_ATTEMPT_DECAY_SECS", block_defaults.decay_every),
+        };
 
-        let program_id = env::var("PROGRAM_ID")
-            .context("PROGRAM_ID environment variable not set")?
-            .parse::<Pubkey>()
-            .context("Invalid PROGRAM_ID format")?;
+        settings.ensure(
+            !block_policy.decay_every.is_zero(),
+            "FAILED_ATTEMPT_DECAY_SECS must be at least 1",
+        );
 
-        let database_url = env::var("DATABASE_URL")
-            .context("DATABASE_URL environment variable not set")?;
+        // Source IPs are banned for IP_BAN_SECS after IP_BAN_MAX_FAILURES
+        // failed requests, one decaying per IP_FAILURE_DECAY_SECS. IPs and
+        // CIDR blocks in IP_ALLOWLIST (internal services) are never banned
+        let ip_defaults = IpPolicy::default();
+        let ip_policy = IpPolicy {
+            allowlist: settings
+                .parse_with("IP_ALLOWLIST", parse_ip_allowlist)
+                .unwrap_or_default(),
+            ban: BlockPolicy {
+                max_attempts: settings
+                    .parse("IP_BAN_MAX_FAILURES")
+                    .unwrap_or(ip_defaults.ban.max_attempts),
+                cooldown: settings.secs("IP_BAN_SECS", ip_defaults.ban.cooldown),
+                decay_every: settings.secs("IP_FAILURE_DECAY_SECS", ip_defaults.ban.decay_every),
+            },
+        };
 
-        let server_addr = env::var("SERVER_ADDR")
-            .unwrap_or_else(|_| "0.0.0.0:8080".to_string());
+        settings.ensure(
+            !ip_policy.ban.decay_every.is_zero(),
+            "IP_FAILURE_DECAY_SECS must be at least 1",
+        );
+
+        // Only enable behind a proxy that sets X-Forwarded-For itself
+        let trust_forwarded_for = settings.flag("TRUST_X_FORWARDED_FOR").unwrap_or(false);
+
+        // Session access tokens last SESSION_TTL_SECS; a session can be
+        // refreshed until SESSION_MAX_AGE_SECS after it was opened
+        let session_defaults = SessionSettings::default();
+        let sessions = SessionSettings {
+            access_ttl: settings.secs("SESSION_TTL_SECS", session_defaults.access_ttl),
+            max_age: settings.secs("SESSION_MAX_AGE_SECS", session_defaults.max_age),
+        };
+
+        settings.ensure(
+            !sessions.access_ttl.is_zero() && sessions.access_ttl <= sessions.max_age,
+            "SESSION_TTL_SECS must be at least 1 and at most SESSION_MAX_AGE_SECS",
+        );
+
+        // Withdrawals are scored against the user's last ANOMALY_LOOKBACK_DAYS
+        // of transactions and flagged from ANOMALY_FLAG_SCORE (0..1)
+        let anomaly_defaults = AnomalyConfig::default();
+        let anomaly = AnomalyConfig {
+            lookback: settings
+                .parse("ANOMALY_LOOKBACK_DAYS")
+                .map(chrono::Duration::days)
+                .unwrap_or(anomaly_defaults.lookback),
+            flag_threshold: settings
+                .parse("ANOMALY_FLAG_SCORE")
+                .unwrap_or(anomaly_defaults.flag_threshold),
+        };
+
+        // High-value Logger events are also queued for `audit_log`; entries
+        // beyond AUDIT_LOG_QUEUE_SIZE waiting to be written are dropped
+        let audit_log_queue_size = settings.parse("AUDIT_LOG_QUEUE_SIZE").unwrap_or(1024);
+        settings.ensure(
+            audit_log_queue_size > 0,
+            "AUDIT_LOG_QUEUE_SIZE must be at least 1",
+        );
+        let audit_log_queue_size = settings
+            .flag("AUDIT_LOG_ENABLED")
+            .unwrap_or(false)
+            .then_some(audit_log_queue_size);
+
+        // Logs also go to files under LOG_FILE_DIR, rotated hourly, daily or
+        // at LOG_FILE_MAX_BYTES, keeping the newest LOG_FILE_MAX_FILES
+        let log_file_max_bytes = settings
+            .parse("LOG_FILE_MAX_BYTES")
+            .unwrap_or(100 * 1024 * 1024);
+        let log_file_max_files = settings.parse("LOG_FILE_MAX_FILES").unwrap_or(7);
+        settings.ensure(
+            log_file_max_bytes > 0,
+            "LOG_FILE_MAX_BYTES must be at least 1",
+        );
+        settings.ensure(
+            log_file_max_files > 0,
+            "LOG_FILE_MAX_FILES must be at least 1",
+        );
+        let log_file = settings.var("LOG_FILE_DIR").map(|directory| LogFileConfig {
+            directory: PathBuf::from(directory),
+            name: settings.var("LOG_FILE_NAME"),
+            rotation: settings.parse("LOG_ROTATION").unwrap_or(LogRotation::Daily),
+            max_bytes: log_file_max_bytes,
+            max_files: log_file_max_files,
+        });
+        let log_sampling = settings
+            .parse_with("LOG_SAMPLING", log_sampling::parse_rules)
+            .unwrap_or_default();
+
+        let signing = settings
+            .check(signing_from_env(source))
+            .unwrap_or(Signing::Disabled);
+
+        // What the `server` process runs besides, or instead of, the API
+        let subsystem_defaults = Subsystems::default();
+        let subsystems = Subsystems {
+            api: settings
+                .flag("ENABLE_API")
+                .unwrap_or(subsystem_defaults.api),
+            indexer: settings
+                .flag("ENABLE_INDEXER")
+                .unwrap_or(subsystem_defaults.indexer),
+            reconciliation: settings
+                .flag("ENABLE_RECONCILIATION")
+                .unwrap_or(subsystem_defaults.reconciliation),
+            ws: settings.flag("ENABLE_WS").unwrap_or(subsystem_defaults.ws),
+            admin_api: settings
+                .flag("ENABLE_ADMIN_API")
+                .unwrap_or(subsystem_defaults.admin_api),
+        };
+        settings.ensure(
+            subsystems.api || subsystems.indexer || subsystems.reconciliation,
+            "ENABLE_API, ENABLE_INDEXER and ENABLE_RECONCILIATION are all off",
+        );
+
+        // Only `ConfigSource::load_resolved` looks `secret:` references up
+        for setting in SECRET_SETTINGS {
+            if settings
+                .var(setting)
+                .is_some_and(|raw| SecretRef::parse(&raw).is_some())
+                && source
+                    .secrets()
+                    .and_then(|s| s.reference(setting))
+                    .is_none()
+            {
+                settings.report(format!(
+                    "{} refers to a secret that was not resolved",
+                    setting
+                ));
+            }
+        }
+
+        // Escalation rules separated by ';', e.g.
+        // "3 medium within 600 -> high; 1 critical -> block"
+        let escalation_rules = settings
+            .parse_with("ESCALATION_RULES", parse_rules)
+            .unwrap_or_else(EscalationRule::defaults);
+
+        // How often the server deletes expired vault authorizations
+        let authorization_sweep_interval_secs = settings
+            .parse("AUTHORIZATION_SWEEP_INTERVAL_SECS")
+            .unwrap_or(3600);
+
+        settings.ensure(
+            authorization_sweep_interval_secs > 0,
+            "AUTHORIZATION_SWEEP_INTERVAL_SECS must be at least 1",
+        );
+
+        // Security events older than SECURITY_EVENT_RETENTION_DAYS are moved to
+        // security_events_archive, or dropped with SECURITY_EVENT_ARCHIVE=false
+        let event_retention_defaults = EventRetention::default();
+        let event_retention = EventRetention {
+            horizon: settings
+                .parse::<u64>("SECURITY_EVENT_RETENTION_DAYS")
+                .map(|days| Duration::from_secs(days * 24 * 60 * 60))
+                .unwrap_or(event_retention_defaults.horizon),
+            archive: settings
+                .flag("SECURITY_EVENT_ARCHIVE")
+                .unwrap_or(event_retention_defaults.archive),
+        };
+
+        settings.ensure(
+            !event_retention.horizon.is_zero(),
+            "SECURITY_EVENT_RETENTION_DAYS must be at least 1",
+        );
+
+        // Listen addresses must be host:port, e.g. "0.0.0.0:8080"
+        for (name, addr) in [
+            ("SERVER_ADDR", Some(&server_addr)),
+            ("INDEXER_HEALTH_ADDR", Some(&indexer_health_addr)),
+            ("RECONCILER_METRICS_ADDR", Some(&reconciler_metrics_addr)),
+            ("API_METRICS_ADDR", api_metrics_addr.as_ref()),
+        ] {
+            let Some(addr) = addr else {
+                continue;
+            };
+            settings.ensure(
+                addr.to_socket_addrs()
+                    .is_ok_and(|mut addrs| addrs.next().is_some()),
+                format!("Invalid {}: '{}' is not a host:port address", name, addr),
+            );
+        }
+
+        settings.finish()?;
 
         Ok(Self {
             rpc_url,
+            ws_url,
+            rpc_endpoints,
             program_id,
+            network,
+            token_program,
+            commitment,
             database_url,
+            database_replica_urls,
+            db_pool,
             server_addr,
+            api_metrics_addr,
+            ws_broadcast_interval_secs,
+            timeouts,
+            request_timeout_secs,
+            indexer_lag_alert_slots,
+            snapshot_interval_secs,
+            idl_path,
+            rpc_rate_limits,
+            indexer_health_addr,
+            indexer_poll_interval_secs,
+            indexer_batch_size,
+            indexer_backfill_concurrency,
+            event_filter,
+            indexer_commitment,
+            gap_audit_interval_secs,
+            gap_audit_window,
+            finality_check_interval_secs,
+            stale_vault_minutes,
+            indexer_mode,
+            retention,
+            prune_interval_secs,
+            run_migrations,
+            transaction_partitions_ahead,
+            reconciliation_commitment,
+            reconciliation_interval_secs,
+            reconciliation_batch_size,
+            reconciliation_tolerance,
+            reconciliation_schedule,
+            reconciliation_alert_drift,
+            alerting,
+            reconciliation_signature_window,
+            reconciler_metrics_addr,
+            archive_rpc_url,
+            partition_maintenance_interval_secs,
+            withdrawal_limits,
+            request_signing,
+            block_policy,
+            ip_policy,
+            trust_forwarded_for,
+            anomaly,
+            authorization_sweep_interval_secs,
+            event_retention,
+            sessions,
+            escalation_rules,
+            signing,
+            audit_log_queue_size,
+            log_file,
+            log_sampling,
+            subsystems,
+            secrets: source.secrets().cloned(),
+        })
+    }
+
+    /// `from_env`, then `check_startup`.
+    pub async fn load_checked() -> Result<Self> {
+        let config = Self::from_env().await?;
+        config.check_startup().await?;
+        Ok(config)
+    }
+
+    /// Check the settings against what they point at: the RPC endpoint
+    /// answers, the program is deployed and executable on that cluster, and
+    /// the database has every migration applied (unless RUN_MIGRATIONS will
+    /// apply them). Everything that fails is reported in one error.
+    pub async fn check_startup(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        let rpc = RpcClient::new_with_timeout(self.rpc_url.clone(), STARTUP_CHECK_TIMEOUT);
+        match rpc.get_version().await {
+            Ok(_) => {
+                let account = rpc
+                    .get_account_with_commitment(&self.program_id, self.commitment_config())
+                    .await;
+                match account.map(|response| response.value) {
+                    Ok(Some(account)) if account.executable => {}
+                    Ok(Some(_)) => problems.push(format!(
+                        "PROGRAM_ID {} on {} is not an executable program",
+                        self.program_id, self.network
+                    )),
+                    Ok(None) => problems.push(format!(
+                        "PROGRAM_ID {} does not exist on {} ({})",
+                        self.program_id, self.network, self.rpc_url
+                    )),
+                    Err(e) => problems.push(format!(
+                        "could not look up PROGRAM_ID {}: {}",
+                        self.program_id, e
+                    )),
+                }
+            }
+            Err(e) => problems.push(format!("RPC_URL {} is not reachable: {}", self.rpc_url, e)),
+        }
+
+        if !self.run_migrations {
+            match create_pg_pool(&self.database_url, &self.db_pool).await {
+                Ok(pool) => {
+                    match pending_migrations(&pool).await {
+                        Ok(pending) if pending.is_empty() => {}
+                        Ok(pending) => problems.push(format!(
+                            "{} database migrations are not applied (first {}); \
+                             run `server migrate` or set RUN_MIGRATIONS=true",
+                            pending.len(),
+                            pending[0]
+                        )),
+                        Err(e) => problems.push(format!("could not check migrations: {:#}", e)),
+                    }
+                    pool.close().await;
+                }
+                Err(e) => problems.push(format!("DATABASE_URL is not reachable: {:#}", e)),
+            }
+        }
+
+        anyhow::ensure!(
+            problems.is_empty(),
+            "startup checks failed:\n  - {}",
+            problems.join("\n  - ")
+        );
+
+        Ok(())
+    }
+
+    /// Rate limit configured for an RPC endpoint, or the default.
+    pub fn rate_limit_for(&self, endpoint: &str) -> RateLimitConfig {
+        self.rpc_rate_limits
+            .get(endpoint)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// `COMMITMENT` as an RPC client setting.
+    pub fn commitment_config(&self) -> CommitmentConfig {
+        CommitmentConfig {
+            commitment: self.commitment,
+        }
+    }
+
+    /// `RECONCILIATION_COMMITMENT` as an RPC client setting.
+    pub fn reconciliation_commitment_config(&self) -> CommitmentConfig {
+        CommitmentConfig {
+            commitment: self.reconciliation_commitment,
+        }
+    }
+}
+
+/// Reads settings from a `ConfigSource`, collecting every invalid one
+/// instead of stopping at the first.
+struct Settings<'a> {
+    source: &'a ConfigSource,
+    problems: RefCell<Vec<String>>,
+}
+
+impl<'a> Settings<'a> {
+    fn new(source: &'a ConfigSource) -> Self {
+        Self {
+            source,
+            problems: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn var(&self, name: &str) -> Option<String> {
+        self.source.var(name).ok()
+    }
+
+    /// `name` parsed, or `None` if it is unset or invalid; invalid values
+    /// are recorded.
+    fn parse<T>(&self, name: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Into<anyhow::Error>,
+    {
+        self.parse_with(name, |raw| raw.parse::<T>().map_err(Into::into))
+    }
+
+    fn parse_with<T>(&self, name: &str, parse: impl FnOnce(&str) -> Result<T>) -> Option<T> {
+        let raw = self.var(name)?;
+        self.check(parse(&raw).with_context(|| format!("Invalid {}", name)))
+    }
+
+    /// Whole seconds, or `default` if unset or invalid.
+    fn secs(&self, name: &str, default: Duration) -> Duration {
+        self.parse(name).map(Duration::from_secs).unwrap_or(default)
+    }
+
+    /// "true" / "1" enable a flag; anything else disables it.
+    fn flag(&self, name: &str) -> Option<bool> {
+        self.var(name)
+            .map(|v| matches!(v.trim().to_lowercase().as_str(), "true" | "1"))
+    }
+
+    fn check<T>(&self, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.report(format!("{:#}", e));
+                None
+            }
+        }
+    }
+
+    fn ensure(&self, ok: bool, problem: impl Into<String>) {
+        if !ok {
+            self.report(problem);
+        }
+    }
+
+    fn report(&self, problem: impl Into<String>) {
+        self.problems.borrow_mut().push(problem.into());
+    }
+
+    /// Fails with everything recorded, one problem per line.
+    fn finish(self) -> Result<()> {
+        let problems = self.problems.into_inner();
+        anyhow::ensure!(
+            problems.is_empty(),
+            "invalid configuration:\n  - {}",
+            problems.join("\n  - ")
+        );
+        Ok(())
+    }
+}
+
+/// Sections a config file may have. They only group settings; each key is
+/// the lower-case name of the environment variable it stands in for.
+const CONFIG_SECTIONS: &[&str] = &["api", "indexer", "reconciliation", "database", "security"];
+
+/// Where settings are read from: the environment, then the config file.
+///
+/// ```toml
+/// [database]
+/// database_url = "postgres://vault@localhost/vault_backend"
+/// db_max_connections = 20
+///
+/// [indexer]
+/// indexer_include_mints = ["So11111111111111111111111111111111111111112"]
+/// ```
+#[derive(Debug, Default)]
+pub struct ConfigSource {
+    file: HashMap<String, String>,
+    /// Values of the secret settings given as `secret:` references.
+    resolved: HashMap<String, String>,
+    secrets: Option<Arc<SecretStore>>,
+}
+
+impl ConfigSource {
+    /// Read `CONFIG_FILE`, which must exist if set, or `config.toml` in the
+    /// working directory if there is one.
+    pub fn load() -> Result<Self> {
+        let path = match env::var("CONFIG_FILE") {
+            Ok(path) => {
+                anyhow::ensure!(
+                    Path::new(&path).is_file(),
+                    "CONFIG_FILE {} does not exist",
+                    path
+                );
+                PathBuf::from(path)
+            }
+            Err(_) => {
+                let default = PathBuf::from("config.toml");
+                if !default.is_file() {
+                    return Ok(Self::default());
+                }
+                default
+            }
+        };
+
+        Self::from_figment(Figment::from(Toml::file(&path)))
+            .with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn from_toml_str(toml: &str) -> Result<Self> {
+        Self::from_figment(Figment::from(Toml::string(toml)))
+    }
+
+    fn from_figment(figment: Figment) -> Result<Self> {
+        let sections: BTreeMap<String, BTreeMap<String, serde_json::Value>> = figment.extract()?;
+
+        let mut file = HashMap::new();
+        for (section, values) in sections {
+            anyhow::ensure!(
+                CONFIG_SECTIONS.contains(&section.as_str()),
+                "unknown section [{}], expected one of {}",
+                section,
+                CONFIG_SECTIONS.join(", ")
+            );
+            for (key, value) in values {
+                let value = match value {
+                    serde_json::Value::String(s) => s,
+                    serde_json::Value::Array(items) => items
+                        .iter()
+                        .map(|item| match item {
+                            serde_json::Value::String(s) => s.clone(),
+                            other => other.to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    serde_json::Value::Null | serde_json::Value::Object(_) => anyhow::bail!(
+                        "[{}] {} must be a string, number, bool or list",
+                        section,
+                        key
+                    ),
+                    other => other.to_string(),
+                };
+                file.insert(key.to_uppercase(), value);
+            }
+        }
+
+        Ok(Self {
+            file,
+            ..Self::default()
         })
     }
+
+    /// `load`, then `resolve_secrets`.
+    pub async fn load_resolved() -> Result<Self> {
+        Self::load()?.resolve_secrets().await
+    }
+
+    /// Look up the secret settings given as `secret:` references in the
+    /// backend selected by `SECRETS_BACKEND`.
+    pub async fn resolve_secrets(mut self) -> Result<Self> {
+        let mut references = Vec::new();
+        for setting in SECRET_SETTINGS {
+            if let Some(reference) = self.var(setting).ok().as_deref().and_then(SecretRef::parse) {
+                references.push((
+                    *setting,
+                    reference.with_context(|| format!("Invalid {}", setting))?,
+                ));
+            }
+        }
+
+        if references.is_empty() {
+            return Ok(self);
+        }
+
+        let backend =
+            SecretsBackend::from_settings(|name| self.var(name).ok())?.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{} refers to a secret but SECRETS_BACKEND is not set",
+                    references[0].0
+                )
+            })?;
+
+        let ttl = match self.var("SECRETS_CACHE_TTL_SECS") {
+            Ok(raw) => Duration::from_secs(
+                raw.trim()
+                    .parse()
+                    .context("Invalid SECRETS_CACHE_TTL_SECS")?,
+            ),
+            Err(_) => DEFAULT_CACHE_TTL,
+        };
+        anyhow::ensure!(!ttl.is_zero(), "SECRETS_CACHE_TTL_SECS must be at least 1");
+
+        let mut store = SecretStore::new(backend, ttl);
+        for (setting, reference) in references {
+            let value = store
+                .get(&reference)
+                .await
+                .with_context(|| format!("could not resolve {}", setting))?;
+            self.resolved.insert(setting.to_string(), value);
+            store = store.with_reference(setting, reference);
+        }
+
+        self.secrets = Some(Arc::new(store));
+        Ok(self)
+    }
+
+    /// The store `resolve_secrets` read secrets from, if any.
+    pub fn secrets(&self) -> Option<&Arc<SecretStore>> {
+        self.secrets.as_ref()
+    }
+
+    /// The environment variable `name` if set, else its config file value;
+    /// for a resolved `secret:` reference, the secret.
+    pub fn var(&self, name: &str) -> Result<String, env::VarError> {
+        if let Some(value) = self.resolved.get(name) {
+            return Ok(value.clone());
+        }
+        env::var(name).or_else(|err| self.file.get(name).cloned().ok_or(err))
+    }
+}
+
+/// Parts of the backend the `server` binary runs. The `indexer` and
+/// `reconciler` binaries always run their own part.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subsystems {
+    /// The HTTP API.
+    pub api: bool,
+    /// The indexer and its background jobs, in-process.
+    pub indexer: bool,
+    /// The reconciliation worker, in-process.
+    pub reconciliation: bool,
+    /// The `/ws/vaults` WebSocket endpoint.
+    pub ws: bool,
+    /// The `/admin` endpoints.
+    pub admin_api: bool,
+}
+
+impl Default for Subsystems {
+    /// API-only, with every endpoint.
+    fn default() -> Self {
+        Self {
+            api: true,
+            indexer: false,
+            reconciliation: false,
+            ws: true,
+            admin_api: true,
+        }
+    }
+}
+
+/// Whether this process can sign transactions, and as whom.
+pub enum Signing {
+    /// API-only deployments: transactions are built for the user to sign.
+    Disabled,
+    /// Fee payer for transactions the backend sends itself.
+    Payer(Keypair),
+}
+
+impl Signing {
+    pub fn pubkey(&self) -> Option<Pubkey> {
+        match self {
+            Signing::Disabled => None,
+            Signing::Payer(payer) => Some(payer.pubkey()),
+        }
+    }
+
+    /// A copy of the payer, for `VaultManager` or `CPIManager::new_with_payer`.
+    pub fn payer(&self) -> Result<Keypair> {
+        match self {
+            Signing::Disabled => anyhow::bail!(
+                "signing is disabled; set PAYER_KEYPAIR_PATH or PAYER_KEYPAIR to send transactions"
+            ),
+            Signing::Payer(payer) => Ok(payer.insecure_clone()),
+        }
+    }
+}
+
+/// The payer from `PAYER_KEYPAIR_PATH` (a Solana CLI keypair file) or
+/// `PAYER_KEYPAIR` (base58), checked against `SIGNING_MODE` ("payer" or
+/// "none"; by default whichever matches what is configured).
+fn signing_from_env(source: &ConfigSource) -> Result<Signing> {
+    let payer = match (
+        source.var("PAYER_KEYPAIR_PATH"),
+        source.var("PAYER_KEYPAIR"),
+    ) {
+        (Ok(_), Ok(_)) => anyhow::bail!("set only one of PAYER_KEYPAIR_PATH and PAYER_KEYPAIR"),
+        (Ok(path), Err(_)) => Some(
+            read_keypair_file(&path)
+                .map_err(|e| anyhow::anyhow!("Invalid PAYER_KEYPAIR_PATH {}: {}", path, e))?,
+        ),
+        (Err(_), Ok(raw)) => {
+            let bytes = bs58::decode(raw.trim())
+                .into_vec()
+                .context("Invalid PAYER_KEYPAIR: not base58")?;
+            Some(
+                Keypair::try_from(bytes.as_slice())
+                    .map_err(|e| anyhow::anyhow!("Invalid PAYER_KEYPAIR: {}", e))?,
+            )
+        }
+        (Err(_), Err(_)) => None,
+    };
+
+    let mode = source.var("SIGNING_MODE").ok();
+    match (mode.as_deref().map(str::trim), payer) {
+        (None | Some("payer"), Some(payer)) => Ok(Signing::Payer(payer)),
+        (None | Some("none"), None) => Ok(Signing::Disabled),
+        (Some("payer"), None) => anyhow::bail!(
+            "SIGNING_MODE is payer but neither PAYER_KEYPAIR_PATH nor PAYER_KEYPAIR is set"
+        ),
+        (Some("none"), Some(_)) => {
+            anyhow::bail!("SIGNING_MODE is none but a payer keypair is configured")
+        }
+        (Some(other), _) => {
+            anyhow::bail!("Invalid SIGNING_MODE '{}', expected payer or none", other)
+        }
+    }
+}
+
+/// Pool settings from `DB_*` variables; unset ones keep their defaults.
+fn pool_settings_from_env(settings: &Settings) -> PoolSettings {
+    let defaults = PoolSettings::default();
+
+    let max_connections = settings
+        .parse("DB_MAX_CONNECTIONS")
+        .unwrap_or(defaults.max_connections);
+
+    let min_connections = settings
+        .parse("DB_MIN_CONNECTIONS")
+        .unwrap_or(defaults.min_connections);
+
+    let acquire_timeout = settings.secs("DB_ACQUIRE_TIMEOUT_SECS", defaults.acquire_timeout);
+
+    let idle_timeout = settings
+        .parse("DB_IDLE_TIMEOUT_SECS")
+        .map(Duration::from_secs);
+
+    let statement_timeout = settings
+        .parse("DB_STATEMENT_TIMEOUT_MS")
+        .map(Duration::from_millis);
+
+    let application_name = settings
+        .var("DB_APPLICATION_NAME")
+        .unwrap_or(defaults.application_name);
+
+    settings.ensure(
+        min_connections <= max_connections,
+        format!(
+            "DB_MIN_CONNECTIONS ({}) exceeds DB_MAX_CONNECTIONS ({})",
+            min_connections, max_connections
+        ),
+    );
+
+    PoolSettings {
+        max_connections,
+        min_connections,
+        acquire_timeout,
+        idle_timeout,
+        statement_timeout,
+        application_name,
+    }
+}
+
+/// Parse "processed" / "confirmed" / "finalized".
+pub fn parse_commitment(raw: &str) -> Result<CommitmentLevel> {
+    match raw.trim().to_lowercase().as_str() {
+        "processed" => Ok(CommitmentLevel::Processed),
+        "confirmed" => Ok(CommitmentLevel::Confirmed),
+        "finalized" => Ok(CommitmentLevel::Finalized),
+        other => anyhow::bail!("unknown commitment level '{}'", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_file_values() {
+        let source = ConfigSource::from_toml_str(
+            r#"
+            [database]
+            db_max_connections = 20
+
+            [indexer]
+            indexer_include_mints = ["mint_a", "mint_b"]
+            indexer_mode = "blocks"
+
+            [security]
+            session_ttl_secs = 600
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(source.var("DB_MAX_CONNECTIONS").unwrap(), "20");
+        assert_eq!(
+            source.var("INDEXER_INCLUDE_MINTS").unwrap(),
+            "mint_a,mint_b"
+        );
+        assert_eq!(source.var("INDEXER_MODE").unwrap(), "blocks");
+        assert_eq!(source.var("SESSION_TTL_SECS").unwrap(), "600");
+        assert!(source.var("VAULT_BACKEND_UNSET_TEST_KEY").is_err());
+    }
+
+    #[test]
+    fn test_environment_overrides_config_file() {
+        let source =
+            ConfigSource::from_toml_str("[api]\nvault_backend_config_test_addr = \"0.0.0.0:1\"")
+                .unwrap();
+        assert_eq!(
+            source.var("VAULT_BACKEND_CONFIG_TEST_ADDR").unwrap(),
+            "0.0.0.0:1"
+        );
+
+        env::set_var("VAULT_BACKEND_CONFIG_TEST_ADDR", "0.0.0.0:2");
+        assert_eq!(
+            source.var("VAULT_BACKEND_CONFIG_TEST_ADDR").unwrap(),
+            "0.0.0.0:2"
+        );
+        env::remove_var("VAULT_BACKEND_CONFIG_TEST_ADDR");
+    }
+
+    #[test]
+    fn test_unknown_section_is_rejected() {
+        assert!(ConfigSource::from_toml_str("[indexr]\nindexer_mode = \"blocks\"").is_err());
+        assert!(ConfigSource::from_toml_str("[api]\nserver = { addr = \"x\" }").is_err());
+    }
+
+    #[test]
+    fn test_payer_keypair_and_signing_mode() {
+        let payer = Keypair::new();
+        let with_payer = |extra: &str| {
+            ConfigSource::from_toml_str(&format!(
+                "[security]\npayer_keypair = \"{}\"\n{}",
+                payer.to_base58_string(),
+                extra
+            ))
+            .unwrap()
+        };
+
+        let signing = signing_from_env(&with_payer("")).unwrap();
+        assert_eq!(signing.pubkey(), Some(payer.pubkey()));
+        assert_eq!(signing.payer().unwrap().pubkey(), payer.pubkey());
+        assert!(signing_from_env(&with_payer("signing_mode = \"none\"")).is_err());
+
+        let disabled = signing_from_env(&ConfigSource::default()).unwrap();
+        assert!(disabled.pubkey().is_none());
+        assert!(disabled.payer().is_err());
+
+        let payer_required = ConfigSource::from_toml_str("[security]\nsigning_mode = \"payer\"");
+        assert!(signing_from_env(&payer_required.unwrap()).is_err());
+
+        let garbled = ConfigSource::from_toml_str("[security]\npayer_keypair = \"not-a-key\"");
+        assert!(signing_from_env(&garbled.unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_commitment_applies_to_indexer_by_default() {
+        let config = |extra: &str| {
+            let source = ConfigSource::from_toml_str(&format!(
+                "[indexer]\nnetwork = \"mainnet\"\nprogram_id = \"{}\"\n{}\n\
+                 [database]\ndatabase_url = \"postgres://localhost/vault\"",
+                Pubkey::new_unique(),
+                extra
+            ))
+            .unwrap();
+            Config::from_source(&source).unwrap()
+        };
+
+        let defaults = config("");
+        assert_eq!(defaults.commitment, CommitmentLevel::Finalized);
+        assert_eq!(defaults.indexer_commitment, CommitmentLevel::Finalized);
+
+        let processed = config("commitment = \"processed\"");
+        assert_eq!(processed.commitment_config(), CommitmentConfig::processed());
+        assert_eq!(processed.indexer_commitment, CommitmentLevel::Processed);
+
+        let split = config("commitment = \"confirmed\"\nindexer_commitment = \"finalized\"");
+        assert_eq!(split.commitment, CommitmentLevel::Confirmed);
+        assert_eq!(split.indexer_commitment, CommitmentLevel::Finalized);
+
+        // The reconciler stays on finalized unless told otherwise
+        assert_eq!(split.reconciliation_commitment, CommitmentLevel::Finalized);
+        assert_eq!(
+            processed.reconciliation_commitment_config(),
+            CommitmentConfig::finalized()
+        );
+        let relaxed = config("reconciliation_commitment = \"confirmed\"");
+        assert_eq!(
+            relaxed.reconciliation_commitment,
+            CommitmentLevel::Confirmed
+        );
+    }
+
+    #[test]
+    fn test_signed_requests_required_by_default() {
+        let config = |extra: &str| {
+            let source = ConfigSource::from_toml_str(&format!(
+                "[indexer]\nnetwork = \"mainnet\"\nprogram_id = \"{}\"\n\
+                 [database]\ndatabase_url = \"postgres://localhost/vault\"\n\
+                 [security]\n{}",
+                Pubkey::new_unique(),
+                extra
+            ))
+            .unwrap();
+            Config::from_source(&source).unwrap()
+        };
+
+        assert!(config("").request_signing.required);
+        assert!(
+            !config("require_signed_requests = false")
+                .request_signing
+                .required
+        );
+    }
+
+    #[test]
+    fn test_subsystem_flags() {
+        let config = |api: &str| {
+            let source = ConfigSource::from_toml_str(&format!(
+                "[api]\n{}\n\
+                 [indexer]\nnetwork = \"mainnet\"\nprogram_id = \"{}\"\n\
+                 [database]\ndatabase_url = \"postgres://localhost/vault\"",
+                api,
+                Pubkey::new_unique()
+            ))
+            .unwrap();
+            Config::from_source(&source)
+        };
+
+        assert_eq!(config("").unwrap().subsystems, Subsystems::default());
+
+        let indexer_only = config("enable_api = false\nenable_indexer = true")
+            .unwrap()
+            .subsystems;
+        assert!(!indexer_only.api);
+        assert!(indexer_only.indexer);
+        assert!(!indexer_only.reconciliation);
+
+        assert!(config("enable_api = false").is_err());
+    }
+
+    #[test]
+    fn test_audit_log_settings() {
+        let config = |security: &str| {
+            let source = ConfigSource::from_toml_str(&format!(
+                "[security]\n{}\n\
+                 [indexer]\nnetwork = \"mainnet\"\nprogram_id = \"{}\"\n\
+                 [database]\ndatabase_url = \"postgres://localhost/vault\"",
+                security,
+                Pubkey::new_unique()
+            ))
+            .unwrap();
+            Config::from_source(&source)
+        };
+
+        assert_eq!(config("").unwrap().audit_log_queue_size, None);
+        assert_eq!(
+            config("audit_log_enabled = true")
+                .unwrap()
+                .audit_log_queue_size,
+            Some(1024)
+        );
+        assert_eq!(
+            config("audit_log_enabled = true\naudit_log_queue_size = 64")
+                .unwrap()
+                .audit_log_queue_size,
+            Some(64)
+        );
+
+        assert!(config("audit_log_enabled = true\naudit_log_queue_size = 0").is_err());
+    }
+
+    #[test]
+    fn test_log_file_settings() {
+        let config = |api: &str| {
+            let source = ConfigSource::from_toml_str(&format!(
+                "[api]\n{}\n\
+                 [indexer]\nnetwork = \"mainnet\"\nprogram_id = \"{}\"\n\
+                 [database]\ndatabase_url = \"postgres://localhost/vault\"",
+                api,
+                Pubkey::new_unique()
+            ))
+            .unwrap();
+            Config::from_source(&source)
+        };
+
+        assert!(config("").unwrap().log_file.is_none());
+
+        let log_file = config("log_file_dir = \"/var/log/vault\"\nlog_rotation = \"size\"")
+            .unwrap()
+            .log_file
+            .unwrap();
+        assert_eq!(log_file.directory, PathBuf::from("/var/log/vault"));
+        assert_eq!(log_file.rotation, LogRotation::Size);
+        assert_eq!(log_file.max_files, 7);
+
+        assert!(config("log_file_dir = \"logs\"\nlog_rotation = \"weekly\"").is_err());
+        assert!(config("log_file_max_files = 0").is_err());
+
+        let sampling = config("log_sampling = \"rpc=0.01,database=0.01\"")
+            .unwrap()
+            .log_sampling;
+        assert_eq!(sampling.len(), 2);
+        assert_eq!(sampling[0].target, "rpc");
+        assert_eq!(sampling[0].rate, 0.01);
+        assert!(config("log_sampling = \"rpc=5\"").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_secret_references_need_a_backend() {
+        let source = || {
+            ConfigSource::from_toml_str(
+                "[database]\ndatabase_url = \"secret:vault-backend#database_url\"",
+            )
+            .unwrap()
+        };
+
+        let report = format!("{:#}", Config::from_source(&source()).err().unwrap());
+        assert!(report.contains("DATABASE_URL refers to a secret that was not resolved"));
+
+        let unresolved = source().resolve_secrets().await.err().unwrap();
+        assert!(unresolved
+            .to_string()
+            .contains("SECRETS_BACKEND is not set"));
+    }
+
+    #[test]
+    fn test_invalid_settings_are_reported_together() {
+        let source = ConfigSource::from_toml_str(
+            r#"
+            [api]
+            server_addr = "8080"
+
+            [indexer]
+            indexer_poll_interval_secs = "soon"
+            indexer_commitment = "final"
+
+            [security]
+            session_ttl_secs = 0
+            "#,
+        )
+        .unwrap();
+
+        let report = format!("{:#}", Config::from_source(&source).err().unwrap());
+        for problem in [
+            "SERVER_ADDR",
+            "INDEXER_POLL_INTERVAL_SECS",
+            "INDEXER_COMMITMENT",
+            "SESSION_TTL_SECS",
+        ] {
+            assert!(
+                report.contains(problem),
+                "{} missing from {}",
+                problem,
+                report
+            );
+        }
+    }
 }
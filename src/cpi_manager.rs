@@ -9,9 +9,52 @@ use solana_sdk::{
 };
 use sqlx::PgPool;
 
+use crate::access_control::{AccessControlManager, LimitEnforcement};
+use crate::db::program_quota_repo::{ProgramQuotaRepository, ProgramQuotaRow, ProgramUsage};
 use crate::db::program_repo::ProgramRepository;
+use crate::logging::Logger;
 use crate::transaction_builder::TransactionBuilder;
 
+/// Limits on one caller program's use of the CPI manager. `None` limits
+/// don't apply.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramQuota {
+    pub max_calls_per_hour: Option<u32>,
+    /// Most the program may hold locked at once, net of its unlocks.
+    pub max_locked_amount: Option<u64>,
+    pub enforcement: LimitEnforcement,
+}
+
+impl ProgramQuota {
+    pub fn from_row(row: &ProgramQuotaRow) -> anyhow::Result<Self> {
+        Ok(Self {
+            max_calls_per_hour: row.max_calls_per_hour.map(|n| n.max(0) as u32),
+            max_locked_amount: row.max_locked_amount.map(|n| n.max(0) as u64),
+            enforcement: row.enforcement.parse()?,
+        })
+    }
+
+    /// Why one more call, changing the locked amount by `locked_delta`,
+    /// would go over this quota; `None` if it wouldn't.
+    pub fn exceeded(&self, usage: ProgramUsage, locked_delta: i64) -> Option<String> {
+        if let Some(max) = self.max_calls_per_hour {
+            if usage.calls_this_hour + 1 > i64::from(max) {
+                let calls = usage.calls_this_hour + 1;
+                return Some(format!("{} calls this hour, quota {}", calls, max));
+            }
+        }
+
+        if let Some(max) = self.max_locked_amount {
+            let locked = usage.locked_amount.saturating_add(locked_delta);
+            if locked_delta > 0 && locked > i64::try_from(max).unwrap_or(i64::MAX) {
+                return Some(format!("{} locked, quota {}", locked, max));
+            }
+        }
+
+        None
+    }
+}
+
 /// CPIManager is the  abstraction layer  for other services (position manager,
 /// liquidation engine, settlement relayer) it locks/unlocks collateral
 /// in the on-chain vault program.
@@ -21,17 +64,24 @@ pub struct CPIManager<'a> {
     pub program_id: Pubkey,
     pub pool: &'a PgPool,
     pub payer: Option<Keypair>,
+    /// Records quota breaches as security events when set.
+    pub access_control: Option<Arc<AccessControlManager>>,
 }
 
 impl<'a> CPIManager<'a> {
 
     pub fn new(rpc: Arc<RpcClient>, program_id: Pubkey, pool: &'a PgPool) -> Self {
-        Self { rpc, program_id, pool, payer: None }
+        Self { rpc, program_id, pool, payer: None, access_control: None }
     }
 
     /// Create CPIManager with a payer keypair for sending transactions
     pub fn new_with_payer(rpc: Arc<RpcClient>, program_id: Pubkey, pool: &'a PgPool, payer: Keypair) -> Self {
-        Self { rpc, program_id, pool, payer: Some(payer) }
+        Self { rpc, program_id, pool, payer: Some(payer), access_control: None }
+    }
+
+    pub fn with_access_control(mut self, access_control: Arc<AccessControlManager>) -> Self {
+        self.access_control = Some(access_control);
+        self
     }
 
     fn tx_builder(&self) -> TransactionBuilder {
@@ -54,7 +104,51 @@ impl<'a> CPIManager<'a> {
         Ok(())
     }
 
+    /// Check one more call by `program_id`, changing what it holds locked by
+    /// `locked_delta`, against its quota. Over the quota the call is
+    /// recorded as a security event, and refused unless the quota only flags.
+    #[tracing::instrument(level = "debug", skip_all, fields(caller = %program_id, locked_delta = locked_delta))]
+    async fn enforce_quota(
+        &self,
+        program_id: &Pubkey,
+        vault_pda: &Pubkey,
+        locked_delta: i64,
+    ) -> anyhow::Result<()> {
+        let repo = ProgramQuotaRepository::new(self.pool);
+        let program = program_id.to_string();
+
+        let quota = match repo.quota(&program).await? {
+            Some(row) => ProgramQuota::from_row(&row)?,
+            None => return Ok(()),
+        };
+
+        let usage = repo.usage(&program).await?;
+        let Some(reason) = quota.exceeded(usage, locked_delta) else {
+            return Ok(());
+        };
+
+        if let Some(access_control) = &self.access_control {
+            access_control
+                .record_program_quota_breach(&program, &vault_pda.to_string(), &reason)
+                .await?;
+        }
+
+        if quota.enforcement == LimitEnforcement::Reject {
+            anyhow::bail!("CPI quota exceeded by {}: {}", program_id, reason);
+        }
 
+        Ok(())
+    }
+
+    /// Count a call against `program_id`'s quota.
+    async fn record_usage(&self, program_id: &Pubkey, locked_delta: i64) -> anyhow::Result<()> {
+        ProgramQuotaRepository::new(self.pool)
+            .record_call(&program_id.to_string(), locked_delta)
+            .await
+    }
+
+
+    #[tracing::instrument(name = "cpi_operation", skip_all, fields(operation = "build_lock_collateral", caller = %caller_program, user = %user_pubkey, vault = %vault_pda, amount = amount))]
     pub async fn build_lock_collateral_tx(
         &self,
         caller_program: &Pubkey,
@@ -67,6 +161,8 @@ impl<'a> CPIManager<'a> {
     ) -> anyhow::Result<String> {
         // Verify the caller program is authorized to make CPI calls
         self.ensure_authorized_program(caller_program).await?;
+        let locked_delta = i64::try_from(amount).unwrap_or(i64::MAX);
+        self.enforce_quota(caller_program, vault_pda, locked_delta).await?;
 
         // Build the actual lock_collateral instruction
         let tx_builder = self.tx_builder();
@@ -94,6 +190,7 @@ impl<'a> CPIManager<'a> {
                 block_time.naive_utc(),
             )
             .await?;
+        self.record_usage(caller_program, locked_delta).await?;
 
         // Serialize and base64 encode the transaction
         use base64::engine::general_purpose::STANDARD;
@@ -104,6 +201,7 @@ impl<'a> CPIManager<'a> {
 
     /// Build an unlock-collateral transaction with actual on-chain instruction
     /// This unlocks locked balance back to available balance on the blockchain
+    #[tracing::instrument(name = "cpi_operation", skip_all, fields(operation = "build_unlock_collateral", caller = %caller_program, user = %user_pubkey, vault = %vault_pda, amount = amount))]
     pub async fn build_unlock_collateral_tx(
         &self,
         caller_program: &Pubkey,
@@ -116,6 +214,8 @@ impl<'a> CPIManager<'a> {
     ) -> anyhow::Result<String> {
         // Verify the caller program is authorized to make CPI calls
         self.ensure_authorized_program(caller_program).await?;
+        let locked_delta = -i64::try_from(amount).unwrap_or(i64::MAX);
+        self.enforce_quota(caller_program, vault_pda, locked_delta).await?;
 
         // Build the actual unlock_collateral instruction
         let tx_builder = self.tx_builder();
@@ -143,6 +243,7 @@ impl<'a> CPIManager<'a> {
                 block_time.naive_utc(),
             )
             .await?;
+        self.record_usage(caller_program, locked_delta).await?;
 
         // Serialize and base64 encode the transaction
         use base64::engine::general_purpose::STANDARD;
@@ -153,6 +254,7 @@ impl<'a> CPIManager<'a> {
 
     /// Lock collateral and send the transaction to the blockchain
     /// This method requires a payer to be set via `new_with_payer`
+    #[tracing::instrument(name = "cpi_operation", skip_all, fields(operation = "lock_collateral", caller = %caller_program, user = %user_pubkey, vault, amount = amount))]
     pub async fn lock_collateral(
         &self,
         caller_program: &Pubkey,
@@ -164,11 +266,15 @@ impl<'a> CPIManager<'a> {
         let payer = self.payer.as_ref()
             .ok_or_else(|| anyhow::anyhow!("CPIManager must be created with payer to send transactions"))?;
 
-        // Verify authorization
+        // Verify authorization and quota
         self.ensure_authorized_program(caller_program).await?;
+        let tx_builder = self.tx_builder();
+        let (vault_pda, _) = tx_builder.derive_vault_pda(user_pubkey);
+        tracing::Span::current().record("vault", tracing::field::display(&vault_pda));
+        let locked_delta = i64::try_from(amount).unwrap_or(i64::MAX);
+        self.enforce_quota(caller_program, &vault_pda, locked_delta).await?;
 
         // Build the lock instruction
-        let tx_builder = self.tx_builder();
         let lock_ix = tx_builder.build_lock_collateral_ix(caller_program, user_pubkey, amount)?;
 
         // Build and send transaction
@@ -179,7 +285,6 @@ impl<'a> CPIManager<'a> {
         let signature = self.rpc.send_and_confirm_transaction(&tx)?;
 
         // Record in database for audit trail
-        let (vault_pda, _) = tx_builder.derive_vault_pda(user_pubkey);
         let repo = ProgramRepository::new(self.pool);
         repo
             .insert_program_call(
@@ -192,12 +297,20 @@ impl<'a> CPIManager<'a> {
                 block_time.naive_utc(),
             )
             .await?;
+        self.record_usage(caller_program, locked_delta).await?;
+
+        Logger::log_cpi_call(
+            &caller_program.to_string(),
+            &self.program_id.to_string(),
+            "lock_collateral",
+        );
 
         Ok(signature)
     }
 
     /// Unlock collateral and send the transaction to the blockchain
     /// This method requires a payer to be set via `new_with_payer`
+    #[tracing::instrument(name = "cpi_operation", skip_all, fields(operation = "unlock_collateral", caller = %caller_program, user = %user_pubkey, vault, amount = amount))]
     pub async fn unlock_collateral(
         &self,
         caller_program: &Pubkey,
@@ -209,11 +322,15 @@ impl<'a> CPIManager<'a> {
         let payer = self.payer.as_ref()
             .ok_or_else(|| anyhow::anyhow!("CPIManager must be created with payer to send transactions"))?;
 
-        // Verify authorization
+        // Verify authorization and quota
         self.ensure_authorized_program(caller_program).await?;
+        let tx_builder = self.tx_builder();
+        let (vault_pda, _) = tx_builder.derive_vault_pda(user_pubkey);
+        tracing::Span::current().record("vault", tracing::field::display(&vault_pda));
+        let locked_delta = -i64::try_from(amount).unwrap_or(i64::MAX);
+        self.enforce_quota(caller_program, &vault_pda, locked_delta).await?;
 
         // Build the unlock instruction
-        let tx_builder = self.tx_builder();
         let unlock_ix = tx_builder.build_unlock_collateral_ix(caller_program, user_pubkey, amount)?;
 
         // Build and send transaction
@@ -224,7 +341,6 @@ impl<'a> CPIManager<'a> {
         let signature = self.rpc.send_and_confirm_transaction(&tx)?;
 
         // Record in database for audit trail
-        let (vault_pda, _) = tx_builder.derive_vault_pda(user_pubkey);
         let repo = ProgramRepository::new(self.pool);
         repo
             .insert_program_call(
@@ -237,6 +353,13 @@ impl<'a> CPIManager<'a> {
                 block_time.naive_utc(),
             )
             .await?;
+        self.record_usage(caller_program, locked_delta).await?;
+
+        Logger::log_cpi_call(
+            &caller_program.to_string(),
+            &self.program_id.to_string(),
+            "unlock_collateral",
+        );
 
         Ok(signature)
     }
@@ -244,3 +367,29 @@ impl<'a> CPIManager<'a> {
     
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_program_quota_exceeded() {
+        let quota = ProgramQuota {
+            max_calls_per_hour: Some(10),
+            max_locked_amount: Some(1_000),
+            enforcement: LimitEnforcement::Reject,
+        };
+        let usage = |calls_this_hour, locked_amount| ProgramUsage {
+            calls_this_hour,
+            locked_amount,
+        };
+
+        assert_eq!(quota.exceeded(usage(9, 400), 600), None);
+        assert!(quota.exceeded(usage(10, 0), 1).is_some());
+        assert!(quota.exceeded(usage(0, 400), 601).is_some());
+
+        // Unlocks are never held back by the locked amount
+        assert_eq!(quota.exceeded(usage(0, 5_000), -100), None);
+        assert_eq!(ProgramQuota::default().exceeded(usage(i64::MAX - 1, i64::MAX), 1), None);
+    }
+}
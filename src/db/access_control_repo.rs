@@ -0,0 +1,448 @@
+use chrono::NaiveDateTime;
+use sqlx::{PgPool, Row};
+
+/// One `security_events` row. `severity` is the `AlertSeverity` level.
+#[derive(Debug)]
+pub struct SecurityEventRow {
+    pub event_type: String,
+    pub actor: String,
+    pub vault: String,
+    pub details: String,
+    pub severity: i16,
+    pub created_at: NaiveDateTime,
+}
+
+/// One `vault_authorizations` row.
+#[derive(Debug, Clone)]
+pub struct AuthorizationRow {
+    pub vault: String,
+    pub user_pubkey: String,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// Criteria for `AccessControlRepository::events`. Unset fields don't filter.
+#[derive(Debug, Clone, Default)]
+pub struct SecurityEventQuery {
+    /// Inclusive lower bound on `created_at`.
+    pub since: Option<NaiveDateTime>,
+    /// Exclusive upper bound on `created_at`.
+    pub until: Option<NaiveDateTime>,
+    pub actor: Option<String>,
+    pub event_type: Option<String>,
+    /// `AlertSeverity` level; 1 (low) matches everything.
+    pub min_severity: i16,
+    /// Only the newest `limit` matches.
+    pub limit: Option<i64>,
+}
+
+/// Stored failed-attempt counter of one user, before any decay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FailedAttempts {
+    pub attempts: u32,
+    pub last_attempt_at: NaiveDateTime,
+}
+
+/// Withdrawal volume of one user over some window.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WithdrawalVolume {
+    pub total: u64,
+    pub count: u64,
+}
+
+pub struct AccessControlRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> AccessControlRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Grant `user` access to `vault` until `expires_at` (`None`: for good).
+    /// Granting again replaces the expiry.
+    pub async fn authorize(
+        &self,
+        vault: &str,
+        user: &str,
+        expires_at: Option<NaiveDateTime>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO vault_authorizations (vault, user_pubkey, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (vault, user_pubkey) DO UPDATE
+            SET expires_at = EXCLUDED.expires_at
+            "#,
+        )
+        .bind(vault)
+        .bind(user)
+        .bind(expires_at)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Grant all of `rows` in one transaction, replacing existing expiries.
+    pub async fn authorize_all(&self, rows: &[AuthorizationRow]) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for row in rows {
+            sqlx::query(
+                r#"
+                INSERT INTO vault_authorizations (vault, user_pubkey, expires_at)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (vault, user_pubkey) DO UPDATE
+                SET expires_at = EXCLUDED.expires_at
+                "#,
+            )
+            .bind(&row.vault)
+            .bind(&row.user_pubkey)
+            .bind(row.expires_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Grants that haven't expired, by vault then user.
+    pub async fn active_authorizations(&self) -> anyhow::Result<Vec<AuthorizationRow>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT vault, user_pubkey, expires_at
+            FROM vault_authorizations
+            WHERE expires_at IS NULL OR expires_at > now()
+            ORDER BY vault, user_pubkey
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| AuthorizationRow {
+                vault: row.get("vault"),
+                user_pubkey: row.get("user_pubkey"),
+                expires_at: row.get("expires_at"),
+            })
+            .collect())
+    }
+
+    pub async fn is_authorized(&self, vault: &str, user: &str) -> anyhow::Result<bool> {
+        let row = sqlx::query(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM vault_authorizations
+                WHERE vault = $1
+                  AND user_pubkey = $2
+                  AND (expires_at IS NULL OR expires_at > now())
+            ) AS authorized
+            "#,
+        )
+        .bind(vault)
+        .bind(user)
+        .fetch_one(self.pool)
+        .await?;
+
+        Ok(row.get("authorized"))
+    }
+
+    /// `false` if `user` had no grant on `vault`.
+    pub async fn revoke(&self, vault: &str, user: &str) -> anyhow::Result<bool> {
+        let result =
+            sqlx::query("DELETE FROM vault_authorizations WHERE vault = $1 AND user_pubkey = $2")
+                .bind(vault)
+                .bind(user)
+                .execute(self.pool)
+                .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete expired grants; returns how many.
+    pub async fn delete_expired_authorizations(&self) -> anyhow::Result<u64> {
+        let result = sqlx::query("DELETE FROM vault_authorizations WHERE expires_at <= now()")
+            .execute(self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn insert_event(&self, event: &SecurityEventRow) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO security_events (
+                event_type,
+                actor,
+                vault,
+                details,
+                severity,
+                created_at
+            )
+            VALUES ($1,$2,$3,$4,$5,$6)
+            "#,
+        )
+        .bind(&event.event_type)
+        .bind(&event.actor)
+        .bind(&event.vault)
+        .bind(&event.details)
+        .bind(event.severity)
+        .bind(event.created_at)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Events matching `query`, oldest first.
+    pub async fn events(
+        &self,
+        query: &SecurityEventQuery,
+    ) -> anyhow::Result<Vec<SecurityEventRow>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT event_type, actor, vault, details, severity, created_at
+            FROM (
+                SELECT id, event_type, actor, vault, details, severity, created_at
+                FROM security_events
+                WHERE severity >= $1
+                  AND ($2::timestamp IS NULL OR created_at >= $2)
+                  AND ($3::timestamp IS NULL OR created_at < $3)
+                  AND ($4::text IS NULL OR actor = $4)
+                  AND ($5::text IS NULL OR event_type = $5)
+                ORDER BY id DESC
+                LIMIT $6
+            ) newest
+            ORDER BY id ASC
+            "#,
+        )
+        .bind(query.min_severity)
+        .bind(query.since)
+        .bind(query.until)
+        .bind(&query.actor)
+        .bind(&query.event_type)
+        .bind(query.limit)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| SecurityEventRow {
+                event_type: row.get("event_type"),
+                actor: row.get("actor"),
+                vault: row.get("vault"),
+                details: row.get("details"),
+                severity: row.get("severity"),
+                created_at: row.get("created_at"),
+            })
+            .collect())
+    }
+
+    /// Remove events created before `cutoff`, moving them to
+    /// `security_events_archive` first when `archive` is set. Returns how many.
+    pub async fn expire_events(&self, cutoff: NaiveDateTime, archive: bool) -> anyhow::Result<u64> {
+        let sql = if archive {
+            r#"
+            WITH expired AS (
+                DELETE FROM security_events
+                WHERE created_at < $1
+                RETURNING id, event_type, actor, vault, details, severity, created_at
+            )
+            INSERT INTO security_events_archive
+                (id, event_type, actor, vault, details, severity, created_at)
+            SELECT id, event_type, actor, vault, details, severity, created_at
+            FROM expired
+            ON CONFLICT (id) DO NOTHING
+            "#
+        } else {
+            "DELETE FROM security_events WHERE created_at < $1"
+        };
+
+        let result = sqlx::query(sql).bind(cutoff).execute(self.pool).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Count one more failed attempt by `user` and return the new total.
+    /// One earlier attempt is forgiven per `decay_secs` since the last one.
+    pub async fn increment_failed_attempts(
+        &self,
+        user: &str,
+        decay_secs: u64,
+    ) -> anyhow::Result<u32> {
+        let row = sqlx::query(
+            r#"
+            INSERT INTO failed_attempts (user_pubkey, attempts)
+            VALUES ($1, 1)
+            ON CONFLICT (user_pubkey) DO UPDATE
+            SET attempts = GREATEST(
+                    failed_attempts.attempts - FLOOR(
+                        EXTRACT(EPOCH FROM now() - failed_attempts.last_attempt_at) / $2
+                    )::INTEGER,
+                    0
+                ) + 1,
+                last_attempt_at = now()
+            RETURNING attempts
+            "#,
+        )
+        .bind(user)
+        .bind(decay_secs.max(1) as f64)
+        .fetch_one(self.pool)
+        .await?;
+
+        Ok(row.get::<i32, _>("attempts") as u32)
+    }
+
+    /// Raise `user`'s failed attempts to at least `attempts`, as of now.
+    pub async fn block(&self, user: &str, attempts: u32) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO failed_attempts (user_pubkey, attempts)
+            VALUES ($1, $2)
+            ON CONFLICT (user_pubkey) DO UPDATE
+            SET attempts = GREATEST(failed_attempts.attempts, EXCLUDED.attempts),
+                last_attempt_at = now()
+            "#,
+        )
+        .bind(user)
+        .bind(attempts as i32)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn failed_attempts(&self, user: &str) -> anyhow::Result<Option<FailedAttempts>> {
+        let row = sqlx::query(
+            r#"
+            SELECT attempts, last_attempt_at
+            FROM failed_attempts
+            WHERE user_pubkey = $1
+            "#,
+        )
+        .bind(user)
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(row.map(|row| FailedAttempts {
+            attempts: row.get::<i32, _>("attempts") as u32,
+            last_attempt_at: row.get("last_attempt_at"),
+        }))
+    }
+
+    pub async fn clear_failed_attempts(&self, user: &str) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM failed_attempts WHERE user_pubkey = $1")
+            .bind(user)
+            .execute(self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Count one more failed request from `ip` and return the new total,
+    /// decayed like `increment_failed_attempts`.
+    pub async fn increment_ip_failed_attempts(
+        &self,
+        ip: &str,
+        decay_secs: u64,
+    ) -> anyhow::Result<u32> {
+        let row = sqlx::query(
+            r#"
+            INSERT INTO ip_failed_attempts (ip, attempts)
+            VALUES ($1, 1)
+            ON CONFLICT (ip) DO UPDATE
+            SET attempts = GREATEST(
+                    ip_failed_attempts.attempts - FLOOR(
+                        EXTRACT(EPOCH FROM now() - ip_failed_attempts.last_attempt_at) / $2
+                    )::INTEGER,
+                    0
+                ) + 1,
+                last_attempt_at = now()
+            RETURNING attempts
+            "#,
+        )
+        .bind(ip)
+        .bind(decay_secs.max(1) as f64)
+        .fetch_one(self.pool)
+        .await?;
+
+        Ok(row.get::<i32, _>("attempts") as u32)
+    }
+
+    pub async fn ip_failed_attempts(&self, ip: &str) -> anyhow::Result<Option<FailedAttempts>> {
+        let row = sqlx::query(
+            r#"
+            SELECT attempts, last_attempt_at
+            FROM ip_failed_attempts
+            WHERE ip = $1
+            "#,
+        )
+        .bind(ip)
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(row.map(|row| FailedAttempts {
+            attempts: row.get::<i32, _>("attempts") as u32,
+            last_attempt_at: row.get("last_attempt_at"),
+        }))
+    }
+
+    pub async fn clear_ip_failed_attempts(&self, ip: &str) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM ip_failed_attempts WHERE ip = $1")
+            .bind(ip)
+            .execute(self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn insert_withdrawal_request(
+        &self,
+        user: &str,
+        vault: &str,
+        amount: u64,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO withdrawal_requests (user_pubkey, vault, amount)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(user)
+        .bind(vault)
+        .bind(i64::try_from(amount)?)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Withdrawals requested by `user` at or after `since`.
+    pub async fn withdrawal_volume_since(
+        &self,
+        user: &str,
+        since: NaiveDateTime,
+    ) -> anyhow::Result<WithdrawalVolume> {
+        let row = sqlx::query(
+            r#"
+            SELECT COALESCE(SUM(amount), 0)::BIGINT AS total, COUNT(*) AS count
+            FROM withdrawal_requests
+            WHERE user_pubkey = $1
+              AND requested_at >= $2
+            "#,
+        )
+        .bind(user)
+        .bind(since)
+        .fetch_one(self.pool)
+        .await?;
+
+        Ok(WithdrawalVolume {
+            total: row.get::<i64, _>("total") as u64,
+            count: row.get::<i64, _>("count") as u64,
+        })
+    }
+}
@@ -0,0 +1,300 @@
+use chrono::{NaiveDateTime, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+/// Every key starts with this, so leaked keys are easy to grep for.
+const KEY_PREFIX: &str = "vk";
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ApiKeyRow {
+    pub id: Uuid,
+    /// Public part of the key, safe to show in listings and logs.
+    pub key_prefix: String,
+    pub key_hash: String,
+    pub owner: String,
+    pub scopes: Vec<String>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub rotated_from: Option<Uuid>,
+    /// One of the `Role` names.
+    pub role: String,
+}
+
+impl ApiKeyRow {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
+/// What a key is allowed to do. `Admin` may do everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Manages keys and everything else under `/admin`.
+    Admin,
+    /// Triage and corrections, e.g. resolving reconciliation discrepancies.
+    Operator,
+    /// Queries only; can't build transactions.
+    ReadOnly,
+    /// Other backends, e.g. programs integrating through CPI.
+    Service,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Operator => "operator",
+            Role::ReadOnly => "read_only",
+            Role::Service => "service",
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "admin" => Ok(Role::Admin),
+            "operator" => Ok(Role::Operator),
+            "read_only" => Ok(Role::ReadOnly),
+            "service" => Ok(Role::Service),
+            other => anyhow::bail!("unknown role '{}'", other),
+        }
+    }
+}
+
+/// A freshly issued key. `plaintext` is only available here; store it now,
+/// it can't be recovered later.
+#[derive(Debug)]
+pub struct IssuedApiKey {
+    pub row: ApiKeyRow,
+    pub plaintext: String,
+}
+
+pub struct ApiKeyRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> ApiKeyRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Issue a new key for `owner`.
+    pub async fn create(
+        &self,
+        owner: &str,
+        role: Role,
+        scopes: &[String],
+        expires_at: Option<NaiveDateTime>,
+    ) -> anyhow::Result<IssuedApiKey> {
+        let mut conn = self.pool.acquire().await?;
+
+        insert_key(&mut *conn, owner, role.as_str(), scopes, expires_at, None).await
+    }
+
+    /// Replace a key with a new one carrying the same owner, role, scopes
+    /// and expiry; the old key is revoked in the same transaction. Returns `None`
+    /// if `id` doesn't name an active key.
+    pub async fn rotate(&self, id: Uuid) -> anyhow::Result<Option<IssuedApiKey>> {
+        let mut tx = self.pool.begin().await?;
+
+        let old = sqlx::query_as::<_, ApiKeyRow>(
+            r#"
+            UPDATE api_keys
+            SET revoked_at = now()
+            WHERE id = $1 AND revoked_at IS NULL
+            RETURNING *
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let old = match old {
+            Some(old) => old,
+            None => return Ok(None),
+        };
+
+        let issued = insert_key(
+            &mut *tx,
+            &old.owner,
+            &old.role,
+            &old.scopes,
+            old.expires_at,
+            Some(old.id),
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(issued))
+    }
+
+    /// Revoke a key. Returns `false` if it was unknown or already revoked.
+    pub async fn revoke(&self, id: Uuid) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            r#"UPDATE api_keys SET revoked_at = now() WHERE id = $1 AND revoked_at IS NULL"#,
+        )
+        .bind(id)
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Resolve a presented key to its row if it is valid: known, matching
+    /// hash, not revoked and not expired. Records the use on success.
+    pub async fn verify(&self, plaintext: &str) -> anyhow::Result<Option<ApiKeyRow>> {
+        let prefix = match key_prefix(plaintext) {
+            Some(prefix) => prefix,
+            None => return Ok(None),
+        };
+
+        let row = sqlx::query_as::<_, ApiKeyRow>(r#"SELECT * FROM api_keys WHERE key_prefix = $1"#)
+            .bind(prefix)
+            .fetch_optional(self.pool)
+            .await?;
+
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let now = Utc::now().naive_utc();
+
+        if !constant_time_eq(hash_key(plaintext).as_bytes(), row.key_hash.as_bytes())
+            || row.revoked_at.is_some()
+            || row.expires_at.is_some_and(|exp| exp <= now)
+        {
+            return Ok(None);
+        }
+
+        sqlx::query(r#"UPDATE api_keys SET last_used_at = now() WHERE id = $1"#)
+            .bind(row.id)
+            .execute(self.pool)
+            .await?;
+
+        Ok(Some(row))
+    }
+
+    /// Keys issued to `owner`, newest first, including revoked ones.
+    pub async fn list_by_owner(&self, owner: &str) -> anyhow::Result<Vec<ApiKeyRow>> {
+        let rows = sqlx::query_as::<_, ApiKeyRow>(
+            r#"SELECT * FROM api_keys WHERE owner = $1 ORDER BY created_at DESC"#,
+        )
+        .bind(owner)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(rows)
+    }
+}
+
+async fn insert_key(
+    conn: &mut PgConnection,
+    owner: &str,
+    role: &str,
+    scopes: &[String],
+    expires_at: Option<NaiveDateTime>,
+    rotated_from: Option<Uuid>,
+) -> anyhow::Result<IssuedApiKey> {
+    let id = Uuid::new_v4();
+    let (prefix, plaintext) = generate_key();
+
+    let row = sqlx::query_as::<_, ApiKeyRow>(
+        r#"
+        INSERT INTO api_keys (
+            id, key_prefix, key_hash, owner, role, scopes, expires_at, rotated_from
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING *
+        "#,
+    )
+    .bind(id)
+    .bind(prefix)
+    .bind(hash_key(&plaintext))
+    .bind(owner)
+    .bind(role)
+    .bind(scopes)
+    .bind(expires_at)
+    .bind(rotated_from)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(IssuedApiKey { row, plaintext })
+}
+
+/// `(prefix, full key)` where the key is `vk_<prefix>_<secret>`. Both parts
+/// come from v4 UUIDs (OS randomness), giving 244 random bits in the secret.
+fn generate_key() -> (String, String) {
+    let prefix = Uuid::new_v4().simple().to_string()[..12].to_string();
+    let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+
+    let key = format!("{}_{}_{}", KEY_PREFIX, prefix, secret);
+
+    (prefix, key)
+}
+
+/// The lookup prefix of a well-formed key.
+fn key_prefix(key: &str) -> Option<&str> {
+    let mut parts = key.splitn(3, '_');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(KEY_PREFIX), Some(prefix), Some(secret)) if !secret.is_empty() => {
+            (!prefix.is_empty()).then_some(prefix)
+        }
+        _ => None,
+    }
+}
+
+/// Hex SHA-256 of a key. Keys are random, so no salt or slow hash is needed.
+pub(crate) fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_key_round_trips_prefix() {
+        let (prefix, key) = generate_key();
+
+        assert!(key.starts_with("vk_"));
+        assert_eq!(key_prefix(&key), Some(prefix.as_str()));
+    }
+
+    #[test]
+    fn test_malformed_keys_have_no_prefix() {
+        assert_eq!(key_prefix("vk_abc"), None);
+        assert_eq!(key_prefix("xx_abc_def"), None);
+        assert_eq!(key_prefix("vk__def"), None);
+    }
+
+    #[test]
+    fn test_role_round_trips() {
+        for role in [Role::Admin, Role::Operator, Role::ReadOnly, Role::Service] {
+            assert_eq!(role.as_str().parse::<Role>().unwrap(), role);
+        }
+        assert!("root".parse::<Role>().is_err());
+    }
+
+    #[test]
+    fn test_hash_is_stable_and_distinct() {
+        assert_eq!(hash_key("vk_a_b"), hash_key("vk_a_b"));
+        assert_ne!(hash_key("vk_a_b"), hash_key("vk_a_c"));
+        assert_eq!(hash_key("vk_a_b").len(), 64);
+    }
+}
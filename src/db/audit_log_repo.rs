@@ -0,0 +1,95 @@
+use chrono::NaiveDateTime;
+use sqlx::{PgPool, Row};
+
+/// One `audit_log` row.
+#[derive(Debug, Clone)]
+pub struct AuditLogRow {
+    /// One of the `AuditCategory` names.
+    pub category: String,
+    pub actor: String,
+    pub subject: Option<String>,
+    pub details: serde_json::Value,
+    pub logged_at: NaiveDateTime,
+}
+
+/// Criteria for `AuditLogRepository::entries`. Unset fields don't filter.
+#[derive(Debug, Clone, Default)]
+pub struct AuditLogQuery {
+    /// Inclusive lower bound on `logged_at`.
+    pub since: Option<NaiveDateTime>,
+    /// Exclusive upper bound on `logged_at`.
+    pub until: Option<NaiveDateTime>,
+    pub category: Option<String>,
+    pub actor: Option<String>,
+    pub subject: Option<String>,
+    /// Only the newest `limit` matches.
+    pub limit: Option<i64>,
+}
+
+pub struct AuditLogRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> AuditLogRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn insert(&self, row: &AuditLogRow) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log (category, actor, subject, details, logged_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(&row.category)
+        .bind(&row.actor)
+        .bind(&row.subject)
+        .bind(&row.details)
+        .bind(row.logged_at)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Entries matching `query`, oldest first.
+    pub async fn entries(&self, query: &AuditLogQuery) -> anyhow::Result<Vec<AuditLogRow>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT category, actor, subject, details, logged_at
+            FROM (
+                SELECT id, category, actor, subject, details, logged_at
+                FROM audit_log
+                WHERE ($1::timestamp IS NULL OR logged_at >= $1)
+                  AND ($2::timestamp IS NULL OR logged_at < $2)
+                  AND ($3::text IS NULL OR category = $3)
+                  AND ($4::text IS NULL OR actor = $4)
+                  AND ($5::text IS NULL OR subject = $5)
+                ORDER BY id DESC
+                LIMIT $6
+            ) newest
+            ORDER BY id ASC
+            "#,
+        )
+        .bind(query.since)
+        .bind(query.until)
+        .bind(&query.category)
+        .bind(&query.actor)
+        .bind(&query.subject)
+        .bind(query.limit)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| AuditLogRow {
+                category: row.get("category"),
+                actor: row.get("actor"),
+                subject: row.get("subject"),
+                details: row.get("details"),
+                logged_at: row.get("logged_at"),
+            })
+            .collect())
+    }
+}
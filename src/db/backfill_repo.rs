@@ -0,0 +1,296 @@
+use chrono::NaiveDateTime;
+use sqlx::{PgPool, Row};
+
+#[derive(Debug)]
+pub struct BackfillProgressRow {
+    pub job_name: String,
+    pub program_id: String,
+    pub discovery_cursor: Option<String>,
+    pub discovery_complete: bool,
+    pub last_processed_slot: Option<i64>,
+    pub last_processed_signature: Option<String>,
+    pub started_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug)]
+pub struct PendingSignatureRow {
+    pub tx_signature: String,
+    pub slot: i64,
+}
+
+pub struct BackfillRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> BackfillRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Fetch the progress row for a job, creating it if this is the first run.
+    pub async fn get_or_create_job(
+        &self,
+        job_name: &str,
+        program_id: &str,
+    ) -> anyhow::Result<BackfillProgressRow> {
+        sqlx::query(
+            r#"
+            INSERT INTO backfill_progress (job_name, program_id)
+            VALUES ($1, $2)
+            ON CONFLICT (job_name) DO NOTHING
+            "#,
+        )
+        .bind(job_name)
+        .bind(program_id)
+        .execute(self.pool)
+        .await?;
+
+        let row = sqlx::query(
+            r#"
+            SELECT
+                job_name,
+                program_id,
+                discovery_cursor,
+                discovery_complete,
+                last_processed_slot,
+                last_processed_signature,
+                started_at,
+                updated_at
+            FROM backfill_progress
+            WHERE job_name = $1
+            "#,
+        )
+        .bind(job_name)
+        .fetch_one(self.pool)
+        .await?;
+
+        Ok(BackfillProgressRow {
+            job_name: row.get("job_name"),
+            program_id: row.get("program_id"),
+            discovery_cursor: row.get("discovery_cursor"),
+            discovery_complete: row.get("discovery_complete"),
+            last_processed_slot: row.get("last_processed_slot"),
+            last_processed_signature: row.get("last_processed_signature"),
+            started_at: row.get("started_at"),
+            updated_at: row.get("updated_at"),
+        })
+    }
+
+    /// Store one page of discovered signatures (newest-first, as returned by
+    /// the RPC) and advance the discovery cursor in the same transaction.
+    pub async fn record_discovered_page(
+        &self,
+        job_name: &str,
+        signatures: &[(String, i64)],
+        cursor: Option<&str>,
+        complete: bool,
+    ) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for (signature, slot) in signatures {
+            sqlx::query(
+                r#"
+                INSERT INTO backfill_signatures (tx_signature, job_name, slot)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (tx_signature) DO NOTHING
+                "#,
+            )
+            .bind(signature)
+            .bind(job_name)
+            .bind(slot)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE backfill_progress
+            SET
+                discovery_cursor   = COALESCE($2, discovery_cursor),
+                discovery_complete = $3,
+                updated_at         = now()
+            WHERE job_name = $1
+            "#,
+        )
+        .bind(job_name)
+        .bind(cursor)
+        .bind(complete)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Queue signatures for (re)processing under `job_name`, even if another
+    /// job already recorded them as processed.
+    pub async fn enqueue_signatures(
+        &self,
+        job_name: &str,
+        signatures: &[(String, i64)],
+    ) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for (signature, slot) in signatures {
+            sqlx::query(
+                r#"
+                INSERT INTO backfill_signatures (tx_signature, job_name, slot)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (tx_signature) DO UPDATE
+                SET job_name = EXCLUDED.job_name, processed = false
+                "#,
+            )
+            .bind(signature)
+            .bind(job_name)
+            .bind(slot)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Next batch of unprocessed signatures in chronological order.
+    pub async fn next_pending(
+        &self,
+        job_name: &str,
+        limit: i64,
+    ) -> anyhow::Result<Vec<PendingSignatureRow>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT tx_signature, slot
+            FROM backfill_signatures
+            WHERE job_name = $1 AND processed = false
+            ORDER BY slot ASC, seq DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(job_name)
+        .bind(limit)
+        .fetch_all(self.pool)
+        .await?;
+
+        let rows = rows
+            .into_iter()
+            .map(|row: sqlx::postgres::PgRow| PendingSignatureRow {
+                tx_signature: row.get("tx_signature"),
+                slot: row.get("slot"),
+            })
+            .collect();
+
+        Ok(rows)
+    }
+
+    /// Mark a signature as processed and record it as the job's high-water mark.
+    pub async fn mark_signature_processed(
+        &self,
+        job_name: &str,
+        signature: &str,
+        slot: i64,
+    ) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            UPDATE backfill_signatures
+            SET processed = true
+            WHERE tx_signature = $1
+            "#,
+        )
+        .bind(signature)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE backfill_progress
+            SET
+                last_processed_slot      = $2,
+                last_processed_signature = $3,
+                updated_at               = now()
+            WHERE job_name = $1
+            "#,
+        )
+        .bind(job_name)
+        .bind(slot)
+        .bind(signature)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Remember a signature the RPC node had no transaction for, or count
+    /// another failed attempt if it is already recorded.
+    pub async fn record_unfetched(&self, signature: &str, slot: Option<i64>) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO unfetched_signatures (tx_signature, slot)
+            VALUES ($1, $2)
+            ON CONFLICT (tx_signature) DO UPDATE
+            SET
+                slot            = COALESCE(EXCLUDED.slot, unfetched_signatures.slot),
+                attempts        = unfetched_signatures.attempts + 1,
+                last_attempt_at = now()
+            "#,
+        )
+        .bind(signature)
+        .bind(slot)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Unfetched signatures to retry, oldest slot first.
+    pub async fn unfetched(&self, limit: i64) -> anyhow::Result<Vec<String>> {
+        let signatures = sqlx::query_scalar(
+            r#"
+            SELECT tx_signature
+            FROM unfetched_signatures
+            ORDER BY slot ASC NULLS LAST, first_seen_at ASC
+            LIMIT $1
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(signatures)
+    }
+
+    /// Forget an unfetched signature once its transaction was found.
+    pub async fn clear_unfetched(&self, signature: &str) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM unfetched_signatures WHERE tx_signature = $1")
+            .bind(signature)
+            .execute(self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Advance a slot-driven job (e.g. block ingestion) past `slot`.
+    pub async fn record_slot_progress(&self, job_name: &str, slot: i64) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE backfill_progress
+            SET
+                last_processed_slot = $2,
+                updated_at          = now()
+            WHERE job_name = $1
+            "#,
+        )
+        .bind(job_name)
+        .bind(slot)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use sqlx::error::ErrorKind;
+
+use crate::error_handling::VaultError;
+
+/// `serialization_failure`: a concurrent transaction won; retrying succeeds.
+const SERIALIZATION_FAILURE: &str = "40001";
+/// `deadlock_detected`: Postgres aborted one side of a deadlock; also safe to retry.
+const DEADLOCK_DETECTED: &str = "40P01";
+
+/// Every `?` on a sqlx error in code returning `VaultResult` goes through
+/// here, so conflicts and transient failures get variants callers can match
+/// on instead of a generic `VaultError::Database`.
+impl From<sqlx::Error> for VaultError {
+    fn from(error: sqlx::Error) -> Self {
+        if matches!(error, sqlx::Error::PoolTimedOut) {
+            return VaultError::PoolTimeout;
+        }
+
+        let Some(db_error) = error.as_database_error() else {
+            return VaultError::Database(error);
+        };
+
+        let constraint = db_error.constraint().unwrap_or("unknown").to_string();
+
+        if is_serialization_code(db_error.code().as_deref()) {
+            return VaultError::SerializationFailure(error);
+        }
+
+        match db_error.kind() {
+            ErrorKind::UniqueViolation => VaultError::UniqueViolation {
+                constraint,
+                source: error,
+            },
+            ErrorKind::ForeignKeyViolation => VaultError::ForeignKeyViolation {
+                constraint,
+                source: error,
+            },
+            _ => VaultError::Database(error),
+        }
+    }
+}
+
+fn is_serialization_code(code: Option<&str>) -> bool {
+    matches!(code, Some(SERIALIZATION_FAILURE | DEADLOCK_DETECTED))
+}
+
+/// The transaction lost to a concurrent one and can be run again as is.
+/// Looks through the whole chain, whether the failure was mapped to a
+/// `VaultError` or is still a plain `sqlx::Error`.
+pub fn is_serialization_failure(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(VaultError::SerializationFailure(_)) =
+            cause.downcast_ref::<VaultError>().map(VaultError::root)
+        {
+            return true;
+        }
+
+        cause
+            .downcast_ref::<sqlx::Error>()
+            .and_then(|e| e.as_database_error())
+            .is_some_and(|e| is_serialization_code(e.code().as_deref()))
+    })
+}
+
+/// Failures that say nothing about the query itself: a lost serialization
+/// race or no free connection in time.
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    is_serialization_failure(error)
+        || error.chain().any(|cause| {
+            matches!(
+                cause.downcast_ref::<VaultError>().map(VaultError::root),
+                Some(VaultError::PoolTimeout)
+            ) || matches!(
+                cause.downcast_ref::<sqlx::Error>(),
+                Some(sqlx::Error::PoolTimedOut)
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use sqlx::error::DatabaseError;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct FakeDbError {
+        code: &'static str,
+        constraint: Option<&'static str>,
+    }
+
+    impl std::fmt::Display for FakeDbError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "database error {}", self.code)
+        }
+    }
+
+    impl std::error::Error for FakeDbError {}
+
+    impl DatabaseError for FakeDbError {
+        fn message(&self) -> &str {
+            "fake"
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.code))
+        }
+
+        fn constraint(&self) -> Option<&str> {
+            self.constraint
+        }
+
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+
+        // As Postgres classifies its SQLSTATEs
+        fn kind(&self) -> ErrorKind {
+            match self.code {
+                "23505" => ErrorKind::UniqueViolation,
+                "23503" => ErrorKind::ForeignKeyViolation,
+                _ => ErrorKind::Other,
+            }
+        }
+    }
+
+    fn db_error(code: &'static str, constraint: Option<&'static str>) -> sqlx::Error {
+        sqlx::Error::Database(Box::new(FakeDbError { code, constraint }))
+    }
+
+    #[test]
+    fn test_maps_sqlx_errors_to_variants() {
+        let unique = db_error("23505", Some("api_keys_key_hash_key"));
+        assert!(matches!(
+            VaultError::from(unique),
+            VaultError::UniqueViolation { constraint, .. } if constraint == "api_keys_key_hash_key"
+        ));
+
+        let foreign_key = db_error("23503", None);
+        assert!(matches!(
+            VaultError::from(foreign_key),
+            VaultError::ForeignKeyViolation { constraint, .. } if constraint == "unknown"
+        ));
+
+        for code in ["40001", "40P01"] {
+            let err = VaultError::from(db_error(code, None));
+            assert!(
+                matches!(err, VaultError::SerializationFailure(_)),
+                "{}",
+                code
+            );
+        }
+
+        assert!(matches!(
+            VaultError::from(sqlx::Error::PoolTimedOut),
+            VaultError::PoolTimeout
+        ));
+        assert!(matches!(
+            VaultError::from(sqlx::Error::RowNotFound),
+            VaultError::Database(_)
+        ));
+    }
+
+    #[test]
+    fn test_transient_failures() {
+        let raw = anyhow::Error::from(db_error("40001", None));
+        assert!(is_serialization_failure(&raw));
+        assert!(is_transient(&raw));
+
+        let mapped = anyhow::Error::from(VaultError::from(db_error("40P01", None)))
+            .context("applying events");
+        assert!(is_serialization_failure(&mapped));
+
+        let pool = anyhow::Error::from(VaultError::PoolTimeout);
+        assert!(!is_serialization_failure(&pool));
+        assert!(is_transient(&pool));
+
+        let unique = anyhow::Error::from(db_error("23505", None));
+        assert!(!is_transient(&unique));
+    }
+}
@@ -0,0 +1,65 @@
+use std::time::{Duration, Instant};
+
+use sqlx::PgPool;
+
+/// How long a health check may take before the database counts as down.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Result of one `check`.
+#[derive(Debug, Clone)]
+pub struct HealthReport {
+    pub healthy: bool,
+    /// Why the check failed, if it did.
+    pub error: Option<String>,
+    /// Time spent waiting for a pooled connection.
+    pub acquire_wait: Duration,
+    /// Round trip of `SELECT 1` once a connection was acquired.
+    pub query_latency: Duration,
+    /// Open connections, idle or in use.
+    pub size: u32,
+    pub idle: usize,
+}
+
+/// Run `SELECT 1` with a short timeout and report the pool's state.
+///
+/// Never fails itself: errors and timeouts are reported as unhealthy. The
+/// numbers are also exported as `db_*` gauges.
+pub async fn check(pool: &PgPool) -> HealthReport {
+    let mut acquire_wait = Duration::ZERO;
+    let mut query_latency = Duration::ZERO;
+
+    let probe = async {
+        let started = Instant::now();
+        let mut conn = pool.acquire().await?;
+        acquire_wait = started.elapsed();
+
+        let started = Instant::now();
+        sqlx::query("SELECT 1").execute(&mut *conn).await?;
+        query_latency = started.elapsed();
+
+        Ok::<_, sqlx::Error>(())
+    };
+
+    let error = match tokio::time::timeout(CHECK_TIMEOUT, probe).await {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(_) => Some(format!("no response within {:?}", CHECK_TIMEOUT)),
+    };
+
+    let report = HealthReport {
+        healthy: error.is_none(),
+        error,
+        acquire_wait,
+        query_latency,
+        size: pool.size(),
+        idle: pool.num_idle(),
+    };
+
+    metrics::gauge!("db_healthy").set(if report.healthy { 1.0 } else { 0.0 });
+    metrics::gauge!("db_pool_size").set(report.size as f64);
+    metrics::gauge!("db_pool_idle").set(report.idle as f64);
+    metrics::gauge!("db_acquire_wait_ms").set(report.acquire_wait.as_millis() as f64);
+    metrics::gauge!("db_query_latency_ms").set(report.query_latency.as_millis() as f64);
+
+    report
+}
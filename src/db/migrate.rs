@@ -0,0 +1,38 @@
+use std::collections::HashSet;
+
+use sqlx::migrate::Migrator;
+use sqlx::PgPool;
+use tracing::info;
+
+/// Migrations from `migrations/`, embedded into the binary at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Apply all pending migrations. Already applied ones are skipped, so this is
+/// safe to run on every startup.
+pub async fn run_migrations(pool: &PgPool) -> anyhow::Result<()> {
+    MIGRATOR.run(pool).await?;
+
+    info!("database migrations up to date ({} known)", MIGRATOR.iter().count());
+
+    Ok(())
+}
+
+/// Versions of embedded migrations the database hasn't applied yet.
+pub async fn pending_migrations(pool: &PgPool) -> anyhow::Result<Vec<i64>> {
+    let applied: HashSet<i64> =
+        match sqlx::query_scalar::<_, i64>("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await
+        {
+            Ok(versions) => versions.into_iter().collect(),
+            // undefined_table: nothing was ever migrated
+            Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("42P01") => HashSet::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration() && !applied.contains(&m.version))
+        .map(|m| m.version)
+        .collect())
+}
@@ -1,7 +1,20 @@
 pub mod pool;
+pub mod error;
 pub mod vault_repo;
 pub mod transaction_repo;
 pub mod snapshot_repo;
 pub mod reconciliation_repo;
 pub mod processed_events;
-pub mod program_repo;
\ No newline at end of file
+pub mod program_repo;
+pub mod backfill_repo;
+pub mod migrate;
+pub mod partitions;
+pub mod health;
+pub mod user_repo;
+pub mod api_key_repo;
+pub mod access_control_repo;
+pub mod request_nonce_repo;
+pub mod withdrawal_cap_repo;
+pub mod program_quota_repo;
+pub mod session_repo;
+pub mod audit_log_repo;
\ No newline at end of file
@@ -0,0 +1,63 @@
+use chrono::{Datelike, Months, NaiveDate};
+use sqlx::PgPool;
+
+/// Create the monthly `transactions` partition containing `month`, moving
+/// that month's rows out of the default partition into it.
+/// Returns `false` if it already existed.
+pub async fn create_transaction_partition(pool: &PgPool, month: NaiveDate) -> anyhow::Result<bool> {
+    let created: bool = sqlx::query_scalar("SELECT create_transaction_partition($1)")
+        .bind(month)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(created)
+}
+
+/// Make sure partitions exist for the month of `today` and the `ahead`
+/// months after it. Returns how many were created.
+pub async fn ensure_transaction_partitions(
+    pool: &PgPool,
+    today: NaiveDate,
+    ahead: u32,
+) -> anyhow::Result<u32> {
+    let mut created = 0;
+
+    for month in upcoming_months(today, ahead) {
+        if create_transaction_partition(pool, month).await? {
+            created += 1;
+        }
+    }
+
+    Ok(created)
+}
+
+/// First day of the month of `from`, followed by the first day of each of
+/// the next `ahead` months.
+pub fn upcoming_months(from: NaiveDate, ahead: u32) -> Vec<NaiveDate> {
+    let first = from.with_day(1).expect("day 1 exists in every month");
+
+    (0..=ahead)
+        .filter_map(|i| first.checked_add_months(Months::new(i)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upcoming_months_starts_at_current_month_and_crosses_years() {
+        let from = NaiveDate::from_ymd_opt(2026, 11, 17).unwrap();
+
+        let months = upcoming_months(from, 2);
+
+        assert_eq!(
+            months,
+            vec![
+                NaiveDate::from_ymd_opt(2026, 11, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2026, 12, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2027, 1, 1).unwrap(),
+            ]
+        );
+    }
+}
@@ -1,13 +1,147 @@
-use sqlx::{PgPool, postgres::PgPoolOptions};
+use sqlx::{PgPool, postgres::{PgConnectOptions, PgPoolOptions}};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
-pub async fn create_pg_pool(database_url: &str) -> anyhow::Result<PgPool> {
+use crate::secrets::SecretStore;
+
+/// Sizing and per-connection settings of a Postgres pool.
+///
+/// The API wants many short-lived connections with a tight statement
+/// timeout, the indexer a few long-running ones, so each process sets its
+/// own through `Config`.
+#[derive(Debug, Clone)]
+pub struct PoolSettings {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout: Duration,
+    /// Close connections idle for longer than this.
+    pub idle_timeout: Option<Duration>,
+    /// Server-side `statement_timeout` for every connection.
+    pub statement_timeout: Option<Duration>,
+    /// Shown in `pg_stat_activity`, to tell the services apart.
+    pub application_name: String,
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        Self {
+            max_connections: 10,
+            min_connections: 2,
+            acquire_timeout: Duration::from_secs(5),
+            idle_timeout: None,
+            statement_timeout: None,
+            application_name: "vault_backend".to_string(),
+        }
+    }
+}
+
+/// Connection options for `database_url` with the per-connection settings.
+pub fn connect_options(
+    database_url: &str,
+    settings: &PoolSettings,
+) -> anyhow::Result<PgConnectOptions> {
+    let mut connect_options =
+        PgConnectOptions::from_str(database_url)?.application_name(&settings.application_name);
+
+    if let Some(timeout) = settings.statement_timeout {
+        let millis = timeout.as_millis().to_string();
+        connect_options = connect_options.options([("statement_timeout", millis.as_str())]);
+    }
+
+    Ok(connect_options)
+}
+
+pub async fn create_pg_pool(
+    database_url: &str,
+    settings: &PoolSettings,
+) -> anyhow::Result<PgPool> {
+    let connect_options = connect_options(database_url, settings)?;
+
     let pool = PgPoolOptions::new()
-        .max_connections(10)
-        .min_connections(2)
-        .acquire_timeout(Duration::from_secs(5))
-        .connect(database_url)
+        .max_connections(settings.max_connections)
+        .min_connections(settings.min_connections)
+        .acquire_timeout(settings.acquire_timeout)
+        .idle_timeout(settings.idle_timeout)
+        .connect_with(connect_options)
         .await?;
 
     Ok(pool)
 }
+
+/// Open new connections with the rotated `DATABASE_URL` whenever its secret
+/// changes. Connections already open keep working until they are recycled.
+pub async fn follow_database_url(secrets: Arc<SecretStore>, pool: PgPool, settings: PoolSettings) {
+    secrets
+        .watch("DATABASE_URL", |database_url| {
+            pool.set_connect_options(connect_options(database_url, &settings)?);
+            Ok(())
+        })
+        .await
+}
+
+/// Primary pool for writes plus optional read replicas.
+///
+/// Read-only queries (balances, TVL, history) go to `read()`, which rotates
+/// over the replicas and falls back to the primary when none are configured,
+/// keeping the primary free for indexer writes. Cheap to clone.
+#[derive(Clone)]
+pub struct DbPools {
+    primary: PgPool,
+    replicas: Arc<Vec<PgPool>>,
+    next_replica: Arc<AtomicUsize>,
+}
+
+impl DbPools {
+    pub fn new(primary: PgPool, replicas: Vec<PgPool>) -> Self {
+        Self {
+            primary,
+            replicas: Arc::new(replicas),
+            next_replica: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Everything on one pool (no replicas).
+    pub fn single(pool: PgPool) -> Self {
+        Self::new(pool, vec![])
+    }
+
+    /// Pool for writes and reads that must see the latest writes.
+    pub fn primary(&self) -> &PgPool {
+        &self.primary
+    }
+
+    /// Pool for read-only queries that tolerate replication lag.
+    pub fn read(&self) -> &PgPool {
+        if self.replicas.is_empty() {
+            return &self.primary;
+        }
+
+        let i = self.next_replica.fetch_add(1, Ordering::Relaxed) % self.replicas.len();
+        &self.replicas[i]
+    }
+
+    pub async fn close(&self) {
+        self.primary.close().await;
+        for replica in self.replicas.iter() {
+            replica.close().await;
+        }
+    }
+}
+
+/// Connect to the primary and every replica DSN.
+pub async fn create_db_pools(
+    primary_url: &str,
+    replica_urls: &[String],
+    settings: &PoolSettings,
+) -> anyhow::Result<DbPools> {
+    let primary = create_pg_pool(primary_url, settings).await?;
+
+    let mut replicas = Vec::with_capacity(replica_urls.len());
+    for url in replica_urls {
+        replicas.push(create_pg_pool(url, settings).await?);
+    }
+
+    Ok(DbPools::new(primary, replicas))
+}
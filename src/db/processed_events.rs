@@ -1,4 +1,27 @@
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgExecutor, PgPool, Row};
+
+/// A processed signature that has not been confirmed finalized yet.
+#[derive(Debug)]
+pub struct UnfinalizedRow {
+    pub tx_signature: String,
+    pub slot: i64,
+    pub commitment: String,
+}
+
+/// Balance effect recorded for one event of an indexed transaction.
+#[derive(Debug)]
+pub struct AppliedEventRow {
+    pub tx_signature: String,
+    pub event_index: i32,
+    pub event_type: String,
+    pub vault_pda: String,
+    pub counterparty_vault: Option<String>,
+    pub amount: i64,
+    pub slot: i64,
+    /// Position of the transaction in its block, `None` if the block wasn't
+    /// available when it was indexed.
+    pub tx_index: Option<i32>,
+}
 
 /// Struct wrapper used by the indexer; internally just calls the free
 /// functions below.
@@ -18,6 +41,19 @@ impl<'a> ProcessedEventsRepo<'a> {
     pub async fn mark_processed(&self, sig: &str) -> anyhow::Result<()> {
         mark_processed(self.pool, sig).await
     }
+
+    pub async fn mark_processed_at(
+        &self,
+        sig: &str,
+        slot: i64,
+        commitment: &str,
+    ) -> anyhow::Result<()> {
+        mark_processed_at(self.pool, sig, slot, commitment).await
+    }
+
+    pub async fn record_applied_event(&self, event: &AppliedEventRow) -> anyhow::Result<bool> {
+        record_applied_event(self.pool, event).await
+    }
 }
 
 pub async fn is_processed(pool: &PgPool, sig: &str) -> anyhow::Result<bool> {
@@ -43,3 +79,242 @@ pub async fn mark_processed(pool: &PgPool, sig: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Mark a signature processed, remembering the slot and the commitment level
+/// it was observed at so it can be re-verified against finalized slots.
+/// Signatures observed at `finalized` are final already and skip the check.
+pub async fn mark_processed_at<'e, E: PgExecutor<'e>>(
+    executor: E,
+    sig: &str,
+    slot: i64,
+    commitment: &str,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO processed_events (tx_signature, slot, commitment, finalized_at)
+        VALUES ($1, $2, $3, CASE WHEN $3 = 'finalized' THEN now() END)
+        ON CONFLICT (tx_signature) DO NOTHING
+        "#,
+    )
+    .bind(sig)
+    .bind(slot)
+    .bind(commitment)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Highest slot the indexer has processed, if any.
+pub async fn last_processed_slot<'e, E: PgExecutor<'e>>(
+    executor: E,
+) -> anyhow::Result<Option<i64>> {
+    let slot: Option<i64> = sqlx::query_scalar("SELECT MAX(slot) FROM processed_events")
+        .fetch_one(executor)
+        .await?;
+
+    Ok(slot)
+}
+
+/// Subset of `signatures` that has no `processed_events` row.
+pub async fn find_unprocessed(pool: &PgPool, signatures: &[String]) -> anyhow::Result<Vec<String>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT s.sig AS tx_signature
+        FROM unnest($1::text[]) AS s(sig)
+        WHERE NOT EXISTS (
+            SELECT 1 FROM processed_events p WHERE p.tx_signature = s.sig
+        )
+        "#,
+    )
+    .bind(signatures)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| row.get("tx_signature")).collect())
+}
+
+/// Oldest processed signatures that have not been finalized yet.
+pub async fn get_unfinalized(pool: &PgPool, limit: i64) -> anyhow::Result<Vec<UnfinalizedRow>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT tx_signature, slot, commitment
+        FROM processed_events
+        WHERE finalized_at IS NULL AND slot IS NOT NULL
+        ORDER BY slot ASC
+        LIMIT $1
+        "#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    let rows = rows
+        .into_iter()
+        .map(|row: sqlx::postgres::PgRow| UnfinalizedRow {
+            tx_signature: row.get("tx_signature"),
+            slot: row.get("slot"),
+            commitment: row.get("commitment"),
+        })
+        .collect();
+
+    Ok(rows)
+}
+
+pub async fn mark_finalized(pool: &PgPool, sig: &str) -> anyhow::Result<()> {
+    let mut db_tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        UPDATE processed_events
+        SET commitment = 'finalized', finalized_at = now()
+        WHERE tx_signature = $1
+        "#,
+    )
+    .bind(sig)
+    .execute(&mut *db_tx)
+    .await?;
+
+    sqlx::query(r#"UPDATE transactions SET commitment = 'finalized' WHERE tx_signature = $1"#)
+        .bind(sig)
+        .execute(&mut *db_tx)
+        .await?;
+
+    db_tx.commit().await?;
+
+    Ok(())
+}
+
+/// Record the idempotency key `(signature, event_index)` for an event.
+///
+/// Returns `false` if the key already exists, i.e. the event was applied
+/// before and must not be applied again.
+pub async fn record_applied_event<'e, E: PgExecutor<'e>>(
+    executor: E,
+    event: &AppliedEventRow,
+) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO applied_events (
+            tx_signature,
+            event_index,
+            event_type,
+            vault_pda,
+            counterparty_vault,
+            amount,
+            slot,
+            tx_index
+        )
+        VALUES ($1,$2,$3,$4,$5,$6,$7,$8)
+        ON CONFLICT (tx_signature, event_index) DO NOTHING
+        "#,
+    )
+    .bind(&event.tx_signature)
+    .bind(event.event_index)
+    .bind(&event.event_type)
+    .bind(&event.vault_pda)
+    .bind(&event.counterparty_vault)
+    .bind(event.amount)
+    .bind(event.slot)
+    .bind(event.tx_index)
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn get_applied_events<'e, E: PgExecutor<'e>>(
+    executor: E,
+    sig: &str,
+) -> anyhow::Result<Vec<AppliedEventRow>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            tx_signature,
+            event_index,
+            event_type,
+            vault_pda,
+            counterparty_vault,
+            amount,
+            slot,
+            tx_index
+        FROM applied_events
+        WHERE tx_signature = $1
+        ORDER BY event_index ASC
+        "#,
+    )
+    .bind(sig)
+    .fetch_all(executor)
+    .await?;
+
+    let rows = rows
+        .into_iter()
+        .map(|row: sqlx::postgres::PgRow| AppliedEventRow {
+            tx_signature: row.get("tx_signature"),
+            event_index: row.get("event_index"),
+            event_type: row.get("event_type"),
+            vault_pda: row.get("vault_pda"),
+            counterparty_vault: row.get("counterparty_vault"),
+            amount: row.get("amount"),
+            slot: row.get("slot"),
+            tx_index: row.get("tx_index"),
+        })
+        .collect();
+
+    Ok(rows)
+}
+
+/// Forget a signature entirely (used when rolling back a forked-out
+/// transaction, inside the same DB transaction as the balance reverts).
+pub async fn delete_processed(conn: &mut PgConnection, sig: &str) -> anyhow::Result<()> {
+    sqlx::query("DELETE FROM applied_events WHERE tx_signature = $1")
+        .bind(sig)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query("DELETE FROM transactions WHERE tx_signature = $1")
+        .bind(sig)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query("DELETE FROM processed_events WHERE tx_signature = $1")
+        .bind(sig)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Delete finalized `processed_events` rows outside the retention policy and
+/// return how many were removed.
+///
+/// Rows are only eligible once finalized, so the reorg window tracked by the
+/// finality checker is never pruned. A row goes if it was finalized before
+/// `finalized_before`, or if it falls outside the newest `keep_latest` rows.
+/// `applied_events` is kept: it stays the idempotency record for replays.
+pub async fn prune(
+    pool: &PgPool,
+    finalized_before: Option<chrono::NaiveDateTime>,
+    keep_latest: Option<i64>,
+) -> anyhow::Result<u64> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM processed_events
+        WHERE finalized_at IS NOT NULL
+          AND (
+                ($1::timestamp IS NOT NULL AND finalized_at < $1)
+             OR ($2::bigint IS NOT NULL AND tx_signature NOT IN (
+                    SELECT tx_signature
+                    FROM processed_events
+                    ORDER BY slot DESC NULLS LAST
+                    LIMIT $2
+                ))
+          )
+        "#,
+    )
+    .bind(finalized_before)
+    .bind(keep_latest)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
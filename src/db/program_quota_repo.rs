@@ -0,0 +1,164 @@
+use chrono::NaiveDateTime;
+use sqlx::{PgPool, Row};
+
+#[derive(Debug, Clone)]
+pub struct ProgramQuotaRow {
+    pub program_id: String,
+    pub max_calls_per_hour: Option<i32>,
+    pub max_locked_amount: Option<i64>,
+    /// `reject` or `flag`, as in `LimitEnforcement`.
+    pub enforcement: String,
+    pub updated_by: String,
+    pub updated_at: NaiveDateTime,
+}
+
+/// A program's lock/unlock calls in the current hour and what it holds
+/// locked. Zero for programs that never called.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProgramUsage {
+    pub calls_this_hour: i64,
+    pub locked_amount: i64,
+}
+
+pub struct ProgramQuotaRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> ProgramQuotaRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn set_quota(
+        &self,
+        program_id: &str,
+        max_calls_per_hour: Option<i32>,
+        max_locked_amount: Option<i64>,
+        enforcement: &str,
+        updated_by: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO program_quotas
+                (program_id, max_calls_per_hour, max_locked_amount, enforcement, updated_by)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (program_id) DO UPDATE
+            SET max_calls_per_hour = EXCLUDED.max_calls_per_hour,
+                max_locked_amount = EXCLUDED.max_locked_amount,
+                enforcement = EXCLUDED.enforcement,
+                updated_by = EXCLUDED.updated_by,
+                updated_at = now()
+            "#,
+        )
+        .bind(program_id)
+        .bind(max_calls_per_hour)
+        .bind(max_locked_amount)
+        .bind(enforcement)
+        .bind(updated_by)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// `false` if the program had no quota.
+    pub async fn remove_quota(&self, program_id: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM program_quotas WHERE program_id = $1")
+            .bind(program_id)
+            .execute(self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list(&self) -> anyhow::Result<Vec<ProgramQuotaRow>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT program_id, max_calls_per_hour, max_locked_amount, enforcement,
+                   updated_by, updated_at
+            FROM program_quotas
+            ORDER BY program_id
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(quota_from_row).collect())
+    }
+
+    pub async fn quota(&self, program_id: &str) -> anyhow::Result<Option<ProgramQuotaRow>> {
+        let row = sqlx::query(
+            r#"
+            SELECT program_id, max_calls_per_hour, max_locked_amount, enforcement,
+                   updated_by, updated_at
+            FROM program_quotas
+            WHERE program_id = $1
+            "#,
+        )
+        .bind(program_id)
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(row.map(quota_from_row))
+    }
+
+    pub async fn usage(&self, program_id: &str) -> anyhow::Result<ProgramUsage> {
+        let row = sqlx::query(
+            r#"
+            SELECT
+                CASE WHEN window_start = date_trunc('hour', now()::timestamp)
+                     THEN window_calls ELSE 0 END::BIGINT AS calls_this_hour,
+                locked_amount
+            FROM program_usage
+            WHERE program_id = $1
+            "#,
+        )
+        .bind(program_id)
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(row
+            .map(|row| ProgramUsage {
+                calls_this_hour: row.get("calls_this_hour"),
+                locked_amount: row.get("locked_amount"),
+            })
+            .unwrap_or_default())
+    }
+
+    /// Count one call by `program_id` that changed its locked amount by
+    /// `locked_delta` (negative for unlocks). The locked amount never drops
+    /// below zero.
+    pub async fn record_call(&self, program_id: &str, locked_delta: i64) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO program_usage (program_id, locked_amount, window_start, window_calls)
+            VALUES ($1, GREATEST($2, 0), date_trunc('hour', now()::timestamp), 1)
+            ON CONFLICT (program_id) DO UPDATE
+            SET locked_amount = GREATEST(program_usage.locked_amount + $2, 0),
+                window_calls = CASE
+                    WHEN program_usage.window_start = EXCLUDED.window_start
+                    THEN program_usage.window_calls + 1
+                    ELSE 1
+                END,
+                window_start = EXCLUDED.window_start
+            "#,
+        )
+        .bind(program_id)
+        .bind(locked_delta)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+fn quota_from_row(row: sqlx::postgres::PgRow) -> ProgramQuotaRow {
+    ProgramQuotaRow {
+        program_id: row.get("program_id"),
+        max_calls_per_hour: row.get("max_calls_per_hour"),
+        max_locked_amount: row.get("max_locked_amount"),
+        enforcement: row.get("enforcement"),
+        updated_by: row.get("updated_by"),
+        updated_at: row.get("updated_at"),
+    }
+}
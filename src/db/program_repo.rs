@@ -73,6 +73,7 @@ impl<'a> ProgramRepository<'a> {
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip_all, fields(vault = vault_pda, caller = caller_program, operation = instruction, amount = amount))]
     pub async fn insert_program_call(
         &self,
         tx_signature: &str,
@@ -1,18 +1,194 @@
 use chrono::NaiveDateTime;
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool, Row};
 use uuid::Uuid;
 
+use crate::db::vault_repo::{self, Adjustment, ChainBalances};
+
 #[derive(Debug)]
 pub struct ReconciliationRow {
     pub id: Uuid,
     pub vault_pda: String,
     pub program_id: String,
     pub network: String,
+    /// One of the `DiscrepancyComponent` names. The balances below are the
+    /// on-chain and off-chain values of that component only.
+    pub component: String,
+    /// One of the `DiscrepancySeverity` names.
+    pub severity: String,
     pub onchain_balance: i64,
     pub offchain_balance: i64,
     pub discrepancy: i64,
     pub detected_at: NaiveDateTime,
     pub resolved: bool,
+    pub resolved_at: Option<NaiveDateTime>,
+    pub resolved_by: Option<String>,
+    pub resolution_note: Option<String>,
+}
+
+/// Which balance diverged. Drift in `Locked` alone usually points at lock /
+/// unlock event handling, drift in `Total` and `Available` at deposits and
+/// withdrawals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscrepancyComponent {
+    /// `total_balance` of the vault account vs the indexed row.
+    Total,
+    /// `locked_balance` of the vault account vs the indexed row.
+    Locked,
+    /// `available_balance` of the vault account vs the indexed row.
+    Available,
+    /// SPL balance of the vault's token account vs the indexed total.
+    TokenAccount,
+}
+
+impl DiscrepancyComponent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DiscrepancyComponent::Total => "total",
+            DiscrepancyComponent::Locked => "locked",
+            DiscrepancyComponent::Available => "available",
+            DiscrepancyComponent::TokenAccount => "token_account",
+        }
+    }
+}
+
+impl std::str::FromStr for DiscrepancyComponent {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "total" => Ok(DiscrepancyComponent::Total),
+            "locked" => Ok(DiscrepancyComponent::Locked),
+            "available" => Ok(DiscrepancyComponent::Available),
+            "token_account" => Ok(DiscrepancyComponent::TokenAccount),
+            other => anyhow::bail!("unknown discrepancy component '{}'", other),
+        }
+    }
+}
+
+/// How bad a recorded discrepancy is, which decides how loudly it is alerted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DiscrepancySeverity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl DiscrepancySeverity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DiscrepancySeverity::Low => "low",
+            DiscrepancySeverity::Medium => "medium",
+            DiscrepancySeverity::High => "high",
+            DiscrepancySeverity::Critical => "critical",
+        }
+    }
+}
+
+impl std::str::FromStr for DiscrepancySeverity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "low" => Ok(DiscrepancySeverity::Low),
+            "medium" => Ok(DiscrepancySeverity::Medium),
+            "high" => Ok(DiscrepancySeverity::High),
+            "critical" => Ok(DiscrepancySeverity::Critical),
+            other => anyhow::bail!("unknown discrepancy severity '{}'", other),
+        }
+    }
+}
+
+/// A discrepancy about to be recorded.
+#[derive(Debug)]
+pub struct NewDiscrepancy<'a> {
+    pub id: Uuid,
+    pub vault_pda: &'a str,
+    pub program_id: &'a str,
+    pub network: &'a str,
+    pub component: DiscrepancyComponent,
+    pub severity: DiscrepancySeverity,
+    pub onchain_balance: i64,
+    pub offchain_balance: i64,
+    pub discrepancy: i64,
+}
+
+/// Number of unresolved discrepancies recorded for one vault.
+#[derive(Debug)]
+pub struct OpenDiscrepancyCount {
+    pub vault_pda: String,
+    pub open: i64,
+}
+
+/// Outcome of resolving a discrepancy with an adjustment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resolution {
+    /// Signed change made to the discrepancy's component (0 if it already
+    /// matched the chain).
+    pub adjustment: i64,
+    /// Discrepancies closed, the requested one included.
+    pub resolved: u64,
+}
+
+/// One reconciliation pass. `finished_at` is `None` while it runs, or if
+/// the reconciler died before finishing it.
+#[derive(Debug)]
+pub struct ReconciliationRunRow {
+    pub id: Uuid,
+    pub started_at: NaiveDateTime,
+    pub finished_at: Option<NaiveDateTime>,
+    pub vaults_checked: i64,
+    pub vaults_skipped: i64,
+    pub discrepancies_found: i64,
+    pub largest_drift: i64,
+    pub error_count: i64,
+    pub signature_gaps: i64,
+}
+
+/// Tallies of a pass, accumulated by the worker and stored on completion.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReconciliationRunStats {
+    pub vaults_checked: i64,
+    /// Vaults without a recorded token account, which can't be checked.
+    pub vaults_skipped: i64,
+    pub discrepancies_found: i64,
+    pub largest_drift: i64,
+    pub error_count: i64,
+    /// Missing plus extra signatures found.
+    pub signature_gaps: i64,
+}
+
+impl ReconciliationRunStats {
+    pub fn record_discrepancy(&mut self, discrepancy: i64) {
+        self.discrepancies_found += 1;
+        self.largest_drift = self.largest_drift.max(discrepancy.saturating_abs());
+    }
+
+    /// Whether the pass found nothing, hit no errors and skipped no vaults.
+    pub fn is_clean(&self) -> bool {
+        self.discrepancies_found == 0
+            && self.error_count == 0
+            && self.signature_gaps == 0
+            && self.vaults_skipped == 0
+    }
+}
+
+/// Which side of a signature comparison has a transaction the other lacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureGapKind {
+    /// On-chain, never processed by the indexer.
+    Missing,
+    /// Recorded in `transactions`, not on-chain.
+    Extra,
+}
+
+impl SignatureGapKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SignatureGapKind::Missing => "missing",
+            SignatureGapKind::Extra => "extra",
+        }
+    }
 }
 
 pub struct ReconciliationRepository<'a> {
@@ -32,19 +208,23 @@ impl<'a> ReconciliationRepository<'a> {
                 vault_pda,
                 program_id,
                 network,
+                component,
+                severity,
                 onchain_balance,
                 offchain_balance,
                 discrepancy,
                 detected_at,
                 resolved
             )
-            VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9)
+            VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11)
             "#,
         )
         .bind(entry.id)
         .bind(&entry.vault_pda)
         .bind(&entry.program_id)
         .bind(&entry.network)
+        .bind(&entry.component)
+        .bind(&entry.severity)
         .bind(entry.onchain_balance)
         .bind(entry.offchain_balance)
         .bind(entry.discrepancy)
@@ -56,42 +236,437 @@ impl<'a> ReconciliationRepository<'a> {
         Ok(())
     }
 
-    pub async fn insert_discrepancy(
-        &self,
-        id: Uuid,
-        vault_pda: &str,
-        program_id: &str,
-        network: &str,
-        onchain_balance: i64,
-        offchain_balance: i64,
-        discrepancy: i64,
-    ) -> anyhow::Result<()> {
-        sqlx::query(
+    pub async fn insert_discrepancy(&self, entry: &NewDiscrepancy<'_>) -> anyhow::Result<()> {
+        insert_discrepancy(self.pool, entry).await
+    }
+
+    /// Oldest unresolved discrepancies first, so triage works through the
+    /// backlog in the order it was detected.
+    pub async fn get_unresolved(&self, limit: i64) -> anyhow::Result<Vec<ReconciliationRow>> {
+        let rows = sqlx::query(
             r#"
-            INSERT INTO reconciliation_logs (
+            SELECT
                 id,
                 vault_pda,
                 program_id,
                 network,
+                component,
+                severity,
                 onchain_balance,
                 offchain_balance,
                 discrepancy,
-                detected_at
-            )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, NOW())
+                detected_at,
+                resolved,
+                resolved_at,
+                resolved_by,
+                resolution_note
+            FROM reconciliation_logs
+            WHERE NOT resolved
+            ORDER BY detected_at ASC
+            LIMIT $1
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ReconciliationRow {
+                id: row.get("id"),
+                vault_pda: row.get("vault_pda"),
+                program_id: row.get("program_id"),
+                network: row.get("network"),
+                component: row.get("component"),
+                severity: row.get("severity"),
+                onchain_balance: row.get("onchain_balance"),
+                offchain_balance: row.get("offchain_balance"),
+                discrepancy: row.get("discrepancy"),
+                detected_at: row.get("detected_at"),
+                resolved: row.get("resolved"),
+                resolved_at: row.get("resolved_at"),
+                resolved_by: row.get("resolved_by"),
+                resolution_note: row.get("resolution_note"),
+            })
+            .collect())
+    }
+
+    /// Close a discrepancy. Returns `false` if it doesn't exist or was
+    /// already resolved, so the first resolution is never overwritten.
+    pub async fn mark_resolved(
+        &self,
+        id: Uuid,
+        resolution_note: &str,
+        resolved_by: &str,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE reconciliation_logs
+            SET resolved = true,
+                resolved_at = NOW(),
+                resolved_by = $2,
+                resolution_note = $3
+            WHERE id = $1
+              AND NOT resolved
             "#,
         )
         .bind(id)
-        .bind(vault_pda)
-        .bind(program_id)
-        .bind(network)
-        .bind(onchain_balance)
-        .bind(offchain_balance)
-        .bind(discrepancy)
+        .bind(resolved_by)
+        .bind(resolution_note)
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Vault an open discrepancy was recorded for, or `None` if it doesn't
+    /// exist or was already resolved.
+    pub async fn open_discrepancy_vault(&self, id: Uuid) -> anyhow::Result<Option<String>> {
+        let vault_pda = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT vault_pda
+            FROM reconciliation_logs
+            WHERE id = $1
+              AND NOT resolved
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(vault_pda)
+    }
+
+    /// Close a discrepancy and bring its vault to the balances just read from
+    /// the chain through an `adjustment` ledger entry, in one transaction.
+    ///
+    /// The correction is worked out from the vault row as it is when the
+    /// transaction locks it, not from the values recorded at detection, so
+    /// indexing that happened since is never applied twice. Every other open
+    /// discrepancy of the vault is closed with it, since all balances now
+    /// match; token account drift stays open unless `token_balance` agrees
+    /// with the on-chain total. Returns `None` if the discrepancy doesn't
+    /// exist or was already resolved.
+    pub async fn resolve_with_adjustment(
+        &self,
+        id: Uuid,
+        onchain: &ChainBalances,
+        token_balance: i64,
+        resolution_note: &str,
+        resolved_by: &str,
+    ) -> anyhow::Result<Option<Resolution>> {
+        let mut tx = self.pool.begin().await?;
+
+        let row = sqlx::query(
+            r#"
+            SELECT vault_pda, component
+            FROM reconciliation_logs
+            WHERE id = $1
+              AND NOT resolved
+            FOR UPDATE
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let vault_pda: String = row.get("vault_pda");
+        let component: DiscrepancyComponent = row.get::<String, _>("component").parse()?;
+
+        let applied = vault_repo::apply_adjustment(
+            &mut *tx,
+            &vault_pda,
+            onchain,
+            &Adjustment {
+                reason: resolution_note,
+                operator: resolved_by,
+                discrepancy_id: id,
+            },
+        )
+        .await?;
+
+        let resolved = sqlx::query(
+            r#"
+            UPDATE reconciliation_logs
+            SET resolved = true,
+                resolved_at = NOW(),
+                resolved_by = $3,
+                resolution_note = $4
+            WHERE id = $1
+               OR (vault_pda = $2
+                   AND NOT resolved
+                   AND (component <> 'token_account' OR $5))
+            "#,
+        )
+        .bind(id)
+        .bind(&vault_pda)
+        .bind(resolved_by)
+        .bind(resolution_note)
+        .bind(token_balance == onchain.total)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        tx.commit().await?;
+
+        let adjustment = match component {
+            DiscrepancyComponent::Total | DiscrepancyComponent::TokenAccount => applied.total,
+            DiscrepancyComponent::Locked => applied.locked,
+            DiscrepancyComponent::Available => applied.available,
+        };
+
+        Ok(Some(Resolution {
+            adjustment,
+            resolved,
+        }))
+    }
+
+    /// Record the start of a pass and return its id.
+    pub async fn start_run(&self) -> anyhow::Result<Uuid> {
+        let id = Uuid::new_v4();
+
+        sqlx::query(
+            r#"
+            INSERT INTO reconciliation_runs (id, started_at)
+            VALUES ($1, NOW())
+            "#,
+        )
+        .bind(id)
+        .execute(self.pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    pub async fn finish_run(&self, id: Uuid, stats: &ReconciliationRunStats) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE reconciliation_runs
+            SET finished_at = NOW(),
+                vaults_checked = $2,
+                discrepancies_found = $3,
+                largest_drift = $4,
+                error_count = $5,
+                signature_gaps = $6,
+                vaults_skipped = $7
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(stats.vaults_checked)
+        .bind(stats.discrepancies_found)
+        .bind(stats.largest_drift)
+        .bind(stats.error_count)
+        .bind(stats.signature_gaps)
+        .bind(stats.vaults_skipped)
         .execute(self.pool)
         .await?;
 
         Ok(())
     }
+
+    /// Most recent passes first.
+    pub async fn recent_runs(&self, limit: i64) -> anyhow::Result<Vec<ReconciliationRunRow>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                id,
+                started_at,
+                finished_at,
+                vaults_checked,
+                vaults_skipped,
+                discrepancies_found,
+                largest_drift,
+                error_count,
+                signature_gaps
+            FROM reconciliation_runs
+            ORDER BY started_at DESC
+            LIMIT $1
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ReconciliationRunRow {
+                id: row.get("id"),
+                started_at: row.get("started_at"),
+                finished_at: row.get("finished_at"),
+                vaults_checked: row.get("vaults_checked"),
+                vaults_skipped: row.get("vaults_skipped"),
+                discrepancies_found: row.get("discrepancies_found"),
+                largest_drift: row.get("largest_drift"),
+                error_count: row.get("error_count"),
+                signature_gaps: row.get("signature_gaps"),
+            })
+            .collect())
+    }
+
+    /// Record `(signature, slot)` gaps of one kind for a vault. Signatures
+    /// that already have an open gap are skipped; returns how many were new.
+    pub async fn insert_signature_gaps(
+        &self,
+        vault_pda: &str,
+        kind: SignatureGapKind,
+        gaps: &[(String, i64)],
+    ) -> anyhow::Result<u64> {
+        if gaps.is_empty() {
+            return Ok(0);
+        }
+
+        let ids: Vec<Uuid> = gaps.iter().map(|_| Uuid::new_v4()).collect();
+        let (signatures, slots): (Vec<String>, Vec<i64>) = gaps.iter().cloned().unzip();
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO reconciliation_signature_gaps (id, vault_pda, tx_signature, kind, slot)
+            SELECT g.id, $1, g.sig, $2, g.slot
+            FROM unnest($3::uuid[], $4::text[], $5::bigint[]) AS g(id, sig, slot)
+            ON CONFLICT (vault_pda, tx_signature) WHERE NOT resolved DO NOTHING
+            "#,
+        )
+        .bind(vault_pda)
+        .bind(kind.as_str())
+        .bind(&ids)
+        .bind(&signatures)
+        .bind(&slots)
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Total number of unresolved discrepancies.
+    pub async fn count_open(&self) -> anyhow::Result<i64> {
+        let open: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM reconciliation_logs WHERE NOT resolved")
+                .fetch_one(self.pool)
+                .await?;
+
+        Ok(open)
+    }
+
+    /// Unresolved discrepancy counts per vault, most affected vaults first.
+    pub async fn count_open_by_vault(&self) -> anyhow::Result<Vec<OpenDiscrepancyCount>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT vault_pda, COUNT(*) AS open
+            FROM reconciliation_logs
+            WHERE NOT resolved
+            GROUP BY vault_pda
+            ORDER BY open DESC, vault_pda ASC
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| OpenDiscrepancyCount {
+                vault_pda: row.get("vault_pda"),
+                open: row.get("open"),
+            })
+            .collect())
+    }
+}
+
+/// Record a discrepancy, e.g. inside the indexer's transaction so it commits
+/// with the balance changes it is about.
+///
+/// A vault has at most one open discrepancy per component: if one is open
+/// already, it is updated to the latest balances and keeps its id and
+/// detection time.
+pub async fn insert_discrepancy<'e, E: PgExecutor<'e>>(
+    executor: E,
+    entry: &NewDiscrepancy<'_>,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO reconciliation_logs (
+            id,
+            vault_pda,
+            program_id,
+            network,
+            component,
+            severity,
+            onchain_balance,
+            offchain_balance,
+            discrepancy,
+            detected_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, NOW())
+        ON CONFLICT (vault_pda, component) WHERE NOT resolved DO UPDATE
+        SET severity = EXCLUDED.severity,
+            onchain_balance = EXCLUDED.onchain_balance,
+            offchain_balance = EXCLUDED.offchain_balance,
+            discrepancy = EXCLUDED.discrepancy
+        "#,
+    )
+    .bind(entry.id)
+    .bind(entry.vault_pda)
+    .bind(entry.program_id)
+    .bind(entry.network)
+    .bind(entry.component.as_str())
+    .bind(entry.severity.as_str())
+    .bind(entry.onchain_balance)
+    .bind(entry.offchain_balance)
+    .bind(entry.discrepancy)
+    .execute(executor)
+    .await?;
+
+    Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_component_round_trip() {
+        use DiscrepancyComponent::*;
+
+        for component in [Total, Locked, Available, TokenAccount] {
+            assert_eq!(
+                component.as_str().parse::<DiscrepancyComponent>().unwrap(),
+                component
+            );
+        }
+
+        assert!("balance".parse::<DiscrepancyComponent>().is_err());
+    }
+
+    #[test]
+    fn test_severity_round_trip_and_order() {
+        use DiscrepancySeverity::*;
+
+        for severity in [Low, Medium, High, Critical] {
+            assert_eq!(
+                severity.as_str().parse::<DiscrepancySeverity>().unwrap(),
+                severity
+            );
+        }
+
+        assert!(Low < Medium && High < Critical);
+        assert!("urgent".parse::<DiscrepancySeverity>().is_err());
+    }
+
+    #[test]
+    fn test_run_stats_track_largest_drift() {
+        let mut stats = ReconciliationRunStats::default();
+        assert!(stats.is_clean());
+
+        stats.record_discrepancy(40);
+        stats.record_discrepancy(-75);
+        stats.record_discrepancy(10);
+
+        assert_eq!(stats.discrepancies_found, 3);
+        assert_eq!(stats.largest_drift, 75);
+        assert!(!stats.is_clean());
+    }
+}
@@ -0,0 +1,36 @@
+use sqlx::PgPool;
+
+/// Record `nonce` as used by `signer`. Returns `false` if it already was,
+/// i.e. the request is a replay. A timestamp is accepted for `max_age_secs`
+/// either side of now, so nonces are kept for twice that and then forgotten:
+/// their requests are rejected by timestamp anyway.
+pub async fn claim_nonce(
+    pool: &PgPool,
+    signer: &str,
+    nonce: &str,
+    max_age_secs: u64,
+) -> anyhow::Result<bool> {
+    sqlx::query(
+        r#"
+        DELETE FROM request_nonces
+        WHERE created_at < now() - make_interval(secs => $1)
+        "#,
+    )
+    .bind(max_age_secs.saturating_mul(2) as f64)
+    .execute(pool)
+    .await?;
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO request_nonces (signer, nonce)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(signer)
+    .bind(nonce)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,248 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+use crate::db::api_key_repo::hash_key;
+
+/// Access tokens start with this, refresh tokens with `REFRESH_PREFIX`, so
+/// `authenticate` can tell them from API keys.
+pub const ACCESS_PREFIX: &str = "vs_";
+const REFRESH_PREFIX: &str = "vr_";
+
+/// A freshly opened or refreshed session. The tokens are only available
+/// here; the database keeps their hashes.
+#[derive(Debug)]
+pub struct IssuedSession {
+    pub id: Uuid,
+    pub access_token: String,
+    pub access_expires_at: NaiveDateTime,
+    pub refresh_token: String,
+    /// The session can't be refreshed past this.
+    pub refresh_expires_at: NaiveDateTime,
+}
+
+/// A valid access token, resolved to the key its session was opened with.
+#[derive(Debug, Clone)]
+pub struct SessionKey {
+    pub session_id: Uuid,
+    pub api_key_id: Uuid,
+    pub owner: String,
+    /// One of the `Role` names.
+    pub role: String,
+}
+
+#[derive(Debug)]
+pub enum RefreshOutcome {
+    Refreshed(IssuedSession),
+    /// A refresh token that was already exchanged; the session is now revoked.
+    Reused(Uuid),
+    /// Unknown, expired or revoked.
+    Invalid,
+}
+
+pub struct SessionRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> SessionRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Open a session for `api_key_id`. Access tokens last `access_ttl`; the
+    /// session can be refreshed until `max_age` after it was opened.
+    pub async fn create(
+        &self,
+        api_key_id: Uuid,
+        access_ttl: Duration,
+        max_age: Duration,
+    ) -> anyhow::Result<IssuedSession> {
+        let now = Utc::now().naive_utc();
+        let refresh_expires_at = now + max_age;
+        let access_expires_at = (now + access_ttl).min(refresh_expires_at);
+        let access_token = generate_token(ACCESS_PREFIX);
+        let refresh_token = generate_token(REFRESH_PREFIX);
+        let id = Uuid::new_v4();
+
+        sqlx::query(
+            r#"
+            INSERT INTO sessions (
+                id, api_key_id, access_hash, access_expires_at, refresh_hash, refresh_expires_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(id)
+        .bind(api_key_id)
+        .bind(hash_key(&access_token))
+        .bind(access_expires_at)
+        .bind(hash_key(&refresh_token))
+        .bind(refresh_expires_at)
+        .execute(self.pool)
+        .await?;
+
+        Ok(IssuedSession {
+            id,
+            access_token,
+            access_expires_at,
+            refresh_token,
+            refresh_expires_at,
+        })
+    }
+
+    /// Resolve an access token if it and its session are live and the key
+    /// behind it is still valid.
+    pub async fn verify_access(&self, token: &str) -> anyhow::Result<Option<SessionKey>> {
+        if !token.starts_with(ACCESS_PREFIX) {
+            return Ok(None);
+        }
+
+        let row = sqlx::query(
+            r#"
+            SELECT s.id, k.id AS api_key_id, k.owner, k.role
+            FROM sessions s
+            JOIN api_keys k ON k.id = s.api_key_id
+            WHERE s.access_hash = $1
+              AND s.access_expires_at > now()
+              AND s.revoked_at IS NULL
+              AND k.revoked_at IS NULL
+              AND (k.expires_at IS NULL OR k.expires_at > now())
+            "#,
+        )
+        .bind(hash_key(token))
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(row.map(|row| SessionKey {
+            session_id: row.get("id"),
+            api_key_id: row.get("api_key_id"),
+            owner: row.get("owner"),
+            role: row.get("role"),
+        }))
+    }
+
+    /// Exchange a refresh token for a new pair of tokens. The old refresh
+    /// token stops working; presenting it again revokes the session.
+    pub async fn refresh(
+        &self,
+        refresh_token: &str,
+        access_ttl: Duration,
+    ) -> anyhow::Result<RefreshOutcome> {
+        if !refresh_token.starts_with(REFRESH_PREFIX) {
+            return Ok(RefreshOutcome::Invalid);
+        }
+
+        let presented = hash_key(refresh_token);
+        let access_token = generate_token(ACCESS_PREFIX);
+        let new_refresh_token = generate_token(REFRESH_PREFIX);
+
+        let row = sqlx::query(
+            r#"
+            UPDATE sessions s
+            SET access_hash = $2,
+                access_expires_at = LEAST(now()::timestamp + $3 * INTERVAL '1 second',
+                                          s.refresh_expires_at),
+                refresh_hash = $4,
+                previous_refresh_hash = s.refresh_hash,
+                refreshed_at = now()
+            FROM api_keys k
+            WHERE s.refresh_hash = $1
+              AND s.refresh_expires_at > now()
+              AND s.revoked_at IS NULL
+              AND k.id = s.api_key_id
+              AND k.revoked_at IS NULL
+              AND (k.expires_at IS NULL OR k.expires_at > now())
+            RETURNING s.id, s.access_expires_at, s.refresh_expires_at
+            "#,
+        )
+        .bind(&presented)
+        .bind(hash_key(&access_token))
+        .bind(access_ttl.num_seconds() as f64)
+        .bind(hash_key(&new_refresh_token))
+        .fetch_optional(self.pool)
+        .await?;
+
+        if let Some(row) = row {
+            return Ok(RefreshOutcome::Refreshed(IssuedSession {
+                id: row.get("id"),
+                access_token,
+                access_expires_at: row.get("access_expires_at"),
+                refresh_token: new_refresh_token,
+                refresh_expires_at: row.get("refresh_expires_at"),
+            }));
+        }
+
+        let reused = sqlx::query(
+            r#"
+            UPDATE sessions
+            SET revoked_at = now()
+            WHERE previous_refresh_hash = $1 AND revoked_at IS NULL
+            RETURNING id
+            "#,
+        )
+        .bind(&presented)
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(match reused {
+            Some(row) => RefreshOutcome::Reused(row.get("id")),
+            None => RefreshOutcome::Invalid,
+        })
+    }
+
+    /// End a session right away. `false` if it was unknown or already revoked.
+    pub async fn revoke(&self, id: Uuid) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            "UPDATE sessions SET revoked_at = now() WHERE id = $1 AND revoked_at IS NULL",
+        )
+        .bind(id)
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// End every live session opened with `api_key_id`; returns how many.
+    pub async fn revoke_for_key(&self, api_key_id: Uuid) -> anyhow::Result<u64> {
+        let result = sqlx::query(
+            "UPDATE sessions SET revoked_at = now() WHERE api_key_id = $1 AND revoked_at IS NULL",
+        )
+        .bind(api_key_id)
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Delete sessions that can no longer be used or refreshed; returns how many.
+    pub async fn delete_expired(&self) -> anyhow::Result<u64> {
+        let result = sqlx::query(
+            "DELETE FROM sessions WHERE refresh_expires_at <= now() OR revoked_at IS NOT NULL",
+        )
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// `<prefix><secret>`, with 244 random bits from two v4 UUIDs like API keys.
+fn generate_token(prefix: &str) -> String {
+    format!("{}{}{}", prefix, Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_are_prefixed_and_distinct() {
+        let access = generate_token(ACCESS_PREFIX);
+        let refresh = generate_token(REFRESH_PREFIX);
+
+        assert!(access.starts_with("vs_"));
+        assert!(refresh.starts_with("vr_"));
+        assert_eq!(access.len(), 3 + 64);
+        assert_ne!(access, generate_token(ACCESS_PREFIX));
+    }
+}
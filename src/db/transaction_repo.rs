@@ -1,7 +1,9 @@
-use sqlx::{PgPool, Row};
+use sqlx::{PgConnection, PgPool, Row};
 use uuid::Uuid;
 use chrono::NaiveDateTime;
 
+use crate::db::pool::DbPools;
+
 #[derive(Debug)]
 pub struct TransactionRow {
     pub id: Uuid,
@@ -14,50 +16,116 @@ pub struct TransactionRow {
     pub amount: i64,
     pub slot: i64,
     pub block_time: NaiveDateTime,
+    /// Commitment the indexer observed the transaction at.
+    pub commitment: String,
+    /// The full decoded event, including fields without a column of their own.
+    pub event_payload: Option<serde_json::Value>,
+}
+
+/// Position after the last row of a page: rows strictly older are next.
+#[derive(Debug, Clone)]
+pub struct TransactionCursor {
+    pub slot: i64,
+    pub tx_signature: String,
+}
+
+#[derive(Debug)]
+pub struct TransactionPage {
+    pub rows: Vec<TransactionRow>,
+    pub next_cursor: Option<TransactionCursor>,
+}
+
+impl TransactionPage {
+    fn from_rows(rows: Vec<TransactionRow>, limit: i64) -> Self {
+        // A short page means there is nothing left
+        let next_cursor = if rows.len() as i64 == limit {
+            rows.last().map(|row| TransactionCursor {
+                slot: row.slot,
+                tx_signature: row.tx_signature.clone(),
+            })
+        } else {
+            None
+        };
+
+        Self { rows, next_cursor }
+    }
 }
 
+/// Criteria for `TransactionRepository::find`. Unset fields don't filter.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionFilter {
+    pub user_pubkey: Option<String>,
+    pub vault_pda: Option<String>,
+    /// Any of these types, e.g. `["withdraw"]`.
+    pub tx_types: Vec<String>,
+    /// Inclusive lower bound on `block_time`.
+    pub from: Option<NaiveDateTime>,
+    /// Exclusive upper bound on `block_time`.
+    pub to: Option<NaiveDateTime>,
+    pub min_amount: Option<i64>,
+    pub max_amount: Option<i64>,
+}
+
+/// Lifetime deposited and withdrawn amounts of one vault.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FlowTotals {
+    pub deposited: i64,
+    pub withdrawn: i64,
+}
+
+/// Lifetime aggregates of one user's transactions.
+#[derive(Debug, Default)]
+pub struct UserTotals {
+    pub deposited: i64,
+    pub withdrawn: i64,
+    /// `deposited - withdrawn`.
+    pub net: i64,
+    pub transaction_count: i64,
+    /// `None` when the user has no transactions.
+    pub first_activity: Option<NaiveDateTime>,
+    pub last_activity: Option<NaiveDateTime>,
+}
+
+/// One transaction of a user, as used to build behavioural baselines.
+#[derive(Debug, Clone)]
+pub struct UserActivity {
+    pub tx_type: String,
+    pub amount: i64,
+    pub block_time: NaiveDateTime,
+}
+
+/// Shortest prefix `get_by_signature` will search for, so a stray character
+/// doesn't scan a large slice of the index.
+pub const MIN_SIGNATURE_PREFIX: usize = 8;
+
+/// Most rows returned for a signature prefix.
+const SIGNATURE_PREFIX_LIMIT: i64 = 20;
+
 pub struct TransactionRepository<'a> {
     pool: &'a PgPool,
+    read_pool: &'a PgPool,
 }
 
 impl<'a> TransactionRepository<'a> {
     pub fn new(pool: &'a PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            read_pool: pool,
+        }
+    }
+
+    /// Route history queries to a replica, inserts to the primary.
+    pub fn from_pools(pools: &'a DbPools) -> Self {
+        Self {
+            pool: pools.primary(),
+            read_pool: pools.read(),
+        }
     }
 
     pub async fn insert_transaction(&self, tx: &TransactionRow) -> anyhow::Result<()> {
-        sqlx::query(
-            r#"
-            INSERT INTO transactions (
-                id,
-                vault_pda,
-                program_id,
-                network,
-                user_pubkey,
-                tx_signature,
-                tx_type,
-                amount,
-                slot,
-                block_time
-            )
-            VALUES ($1,$2,$3,$4,$5,$6,$7::transaction_type,$8,$9,$10)
-            ON CONFLICT (tx_signature) DO NOTHING
-            "#,
-        )
-        .bind(tx.id)
-        .bind(&tx.vault_pda)
-        .bind(&tx.program_id)
-        .bind(&tx.network)
-        .bind(&tx.user_pubkey)
-        .bind(&tx.tx_signature)
-        .bind(&tx.tx_type)
-        .bind(tx.amount)
-        .bind(tx.slot)
-        .bind(tx.block_time)
-        .execute(self.pool)
-        .await?;
+        let mut conn = self.pool.acquire().await?;
 
-        Ok(())
+        insert_transaction(&mut *conn, tx).await
     }
 
     /// Convenience helper used by the indexer to persist a transaction.
@@ -70,27 +138,24 @@ impl<'a> TransactionRepository<'a> {
         amount: i64,
         slot: i64,
         block_time: i64,
+        commitment: &str,
+        event_payload: Option<&serde_json::Value>,
     ) -> anyhow::Result<()> {
-        let row = TransactionRow {
-            id: Uuid::new_v4(),
-            vault_pda: vault_pda.to_string(),
-            program_id: "".to_string(),
-            network: "localnet".to_string(),
-            user_pubkey: user_pubkey.map(|s| s.to_string()),
-            tx_signature: tx_signature.to_string(),
-            tx_type: tx_type.to_string(),
+        let mut conn = self.pool.acquire().await?;
+
+        insert_simple(
+            &mut *conn,
+            vault_pda,
+            user_pubkey,
+            tx_signature,
+            tx_type,
             amount,
             slot,
-            // Interpret `block_time` as unix timestamp seconds.
-            block_time: {
-                use chrono::{DateTime, Utc};
-                let utc_dt = DateTime::<Utc>::from_timestamp(block_time, 0)
-                    .unwrap_or_else(|| Utc::now());
-                utc_dt.naive_utc()
-            },
-        };
-
-        self.insert_transaction(&row).await
+            block_time,
+            commitment,
+            event_payload,
+        )
+        .await
     }
 
     /// Fetch all transactions for a given user public key.
@@ -98,45 +163,385 @@ impl<'a> TransactionRepository<'a> {
         &self,
         user_pubkey: &str,
     ) -> anyhow::Result<Vec<TransactionRow>> {
-        let rows = sqlx::query(
+        let rows = sqlx::query(&format!(
             r#"
-            SELECT
-                id,
-                vault_pda,
-                program_id,
-                network,
-                user_pubkey,
-                tx_signature,
-                tx_type,
-                amount,
-                slot,
-                block_time
+            SELECT {TRANSACTION_COLUMNS}
             FROM transactions
             WHERE user_pubkey = $1
             ORDER BY slot DESC
+            "#
+        ))
+        .bind(user_pubkey)
+        .fetch_all(self.read_pool)
+        .await?;
+
+        Ok(rows.into_iter().map(map_row).collect())
+    }
+
+    /// One page of a user's transactions, newest first.
+    ///
+    /// Keyset pagination on `(slot, tx_signature)`: pass the `next_cursor` of
+    /// the previous page as `before` to continue. Each page costs an index
+    /// range scan regardless of how deep it is, served by `idx_tx_user_slot`.
+    pub async fn get_by_user_page(
+        &self,
+        user_pubkey: &str,
+        before: Option<&TransactionCursor>,
+        limit: i64,
+    ) -> anyhow::Result<TransactionPage> {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {TRANSACTION_COLUMNS}
+            FROM transactions
+            WHERE user_pubkey = $1
+              AND ($2::bigint IS NULL OR (slot, tx_signature) < ($2, $3))
+            ORDER BY slot DESC, tx_signature DESC
+            LIMIT $4
+            "#
+        ))
+        .bind(user_pubkey)
+        .bind(before.map(|c| c.slot))
+        .bind(before.map(|c| c.tx_signature.as_str()).unwrap_or(""))
+        .bind(limit)
+        .fetch_all(self.read_pool)
+        .await?;
+
+        Ok(TransactionPage::from_rows(rows.into_iter().map(map_row).collect(), limit))
+    }
+
+    /// Look up a transaction by signature, e.g. one pasted from an explorer.
+    ///
+    /// An exact match wins; otherwise `signature` is treated as a prefix
+    /// (at least `MIN_SIGNATURE_PREFIX` base58 characters) and up to 20
+    /// matches are returned, newest first.
+    pub async fn get_by_signature(&self, signature: &str) -> anyhow::Result<Vec<TransactionRow>> {
+        let exact = sqlx::query(&format!(
+            r#"
+            SELECT {TRANSACTION_COLUMNS}
+            FROM transactions
+            WHERE tx_signature = $1
+            "#
+        ))
+        .bind(signature)
+        .fetch_all(self.read_pool)
+        .await?;
+
+        if !exact.is_empty() {
+            return Ok(exact.into_iter().map(map_row).collect());
+        }
+
+        // Base58 has no LIKE wildcards, so a valid prefix needs no escaping
+        let is_base58 = signature.chars().all(|c| c.is_ascii_alphanumeric())
+            && !signature.contains(['0', 'O', 'I', 'l']);
+
+        if signature.len() < MIN_SIGNATURE_PREFIX || !is_base58 {
+            return Ok(vec![]);
+        }
+
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {TRANSACTION_COLUMNS}
+            FROM transactions
+            WHERE tx_signature LIKE $1 || '%'
+            ORDER BY slot DESC
+            LIMIT $2
+            "#
+        ))
+        .bind(signature)
+        .bind(SIGNATURE_PREFIX_LIMIT)
+        .fetch_all(self.read_pool)
+        .await?;
+
+        Ok(rows.into_iter().map(map_row).collect())
+    }
+
+    /// Lifetime deposited/withdrawn totals and activity range of a user,
+    /// aggregated in one query instead of paging through the history.
+    pub async fn user_totals(&self, user_pubkey: &str) -> anyhow::Result<UserTotals> {
+        let row = sqlx::query(
+            r#"
+            SELECT
+                COALESCE(SUM(amount) FILTER (WHERE tx_type = 'deposit'), 0)::BIGINT AS deposited,
+                COALESCE(SUM(amount) FILTER (WHERE tx_type = 'withdraw'), 0)::BIGINT AS withdrawn,
+                COUNT(*) AS transaction_count,
+                MIN(block_time) AS first_activity,
+                MAX(block_time) AS last_activity
+            FROM transactions
+            WHERE user_pubkey = $1
+            "#,
+        )
+        .bind(user_pubkey)
+        .fetch_one(self.read_pool)
+        .await?;
+
+        let deposited: i64 = row.get("deposited");
+        let withdrawn: i64 = row.get("withdrawn");
+
+        Ok(UserTotals {
+            deposited,
+            withdrawn,
+            net: deposited - withdrawn,
+            transaction_count: row.get("transaction_count"),
+            first_activity: row.get("first_activity"),
+            last_activity: row.get("last_activity"),
+        })
+    }
+
+    /// Deposited/withdrawn sums of a vault's indexed transactions, the source
+    /// of truth for its `total_deposited` / `total_withdrawn` columns.
+    pub async fn vault_flow_totals(&self, vault_pda: &str) -> anyhow::Result<FlowTotals> {
+        let row = sqlx::query(
+            r#"
+            SELECT
+                COALESCE(SUM(amount) FILTER (WHERE tx_type = 'deposit'), 0)::BIGINT AS deposited,
+                COALESCE(SUM(amount) FILTER (WHERE tx_type = 'withdraw'), 0)::BIGINT AS withdrawn
+            FROM transactions
+            WHERE vault_pda = $1
+            "#,
+        )
+        .bind(vault_pda)
+        .fetch_one(self.read_pool)
+        .await?;
+
+        Ok(FlowTotals {
+            deposited: row.get("deposited"),
+            withdrawn: row.get("withdrawn"),
+        })
+    }
+
+    /// A user's newest `limit` transactions at or after `since`, newest first.
+    pub async fn user_activity_since(
+        &self,
+        user_pubkey: &str,
+        since: NaiveDateTime,
+        limit: i64,
+    ) -> anyhow::Result<Vec<UserActivity>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT tx_type::text AS tx_type, amount, block_time
+            FROM transactions
+            WHERE user_pubkey = $1
+              AND block_time >= $2
+            ORDER BY block_time DESC
+            LIMIT $3
             "#,
         )
         .bind(user_pubkey)
-        .fetch_all(self.pool)
+        .bind(since)
+        .bind(limit)
+        .fetch_all(self.read_pool)
         .await?;
 
-        let rows = rows
+        Ok(rows
             .into_iter()
-            .map(|row: sqlx::postgres::PgRow| TransactionRow {
-                id: row.get("id"),
-                vault_pda: row.get("vault_pda"),
-                program_id: row.get("program_id"),
-                network: row.get("network"),
-                user_pubkey: row.get("user_pubkey"),
-                tx_signature: row.get("tx_signature"),
+            .map(|row| UserActivity {
                 tx_type: row.get("tx_type"),
                 amount: row.get("amount"),
-                slot: row.get("slot"),
                 block_time: row.get("block_time"),
             })
-            .collect();
+            .collect())
+    }
+
+    /// Vaults with at least one transaction at or after `since`. Only the
+    /// partitions covering `since..` are scanned.
+    pub async fn active_vaults_since(&self, since: NaiveDateTime) -> anyhow::Result<Vec<String>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT DISTINCT vault_pda
+            FROM transactions
+            WHERE block_time >= $1
+            "#,
+        )
+        .bind(since)
+        .fetch_all(self.read_pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| row.get("vault_pda")).collect())
+    }
+
+    /// `(signature, slot)` of the transactions recorded for a vault with a
+    /// slot in `min_slot..=max_slot`.
+    pub async fn signatures_for_vault(
+        &self,
+        vault_pda: &str,
+        min_slot: i64,
+        max_slot: i64,
+    ) -> anyhow::Result<Vec<(String, i64)>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT DISTINCT tx_signature, slot
+            FROM transactions
+            WHERE vault_pda = $1
+              AND slot BETWEEN $2 AND $3
+            "#,
+        )
+        .bind(vault_pda)
+        .bind(min_slot)
+        .bind(max_slot)
+        .fetch_all(self.read_pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get("tx_signature"), row.get("slot")))
+            .collect())
+    }
+
+    /// Transactions matching `filter`, newest first, paginated like
+    /// `get_by_user_page`. E.g. all withdrawals above X last month:
+    /// `tx_types: ["withdraw"]`, `from`/`to` the month, `min_amount: X`.
+    ///
+    /// `transactions` is partitioned by month of `block_time`, so setting
+    /// `from`/`to` limits the scan to the partitions covering that range.
+    pub async fn find(
+        &self,
+        filter: &TransactionFilter,
+        before: Option<&TransactionCursor>,
+        limit: i64,
+    ) -> anyhow::Result<TransactionPage> {
+        let tx_types = (!filter.tx_types.is_empty()).then_some(&filter.tx_types);
 
-        Ok(rows)
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {TRANSACTION_COLUMNS}
+            FROM transactions
+            WHERE ($1::text IS NULL OR user_pubkey = $1)
+              AND ($2::text IS NULL OR vault_pda = $2)
+              AND ($3::text[] IS NULL OR tx_type::text = ANY($3))
+              AND ($4::timestamp IS NULL OR block_time >= $4)
+              AND ($5::timestamp IS NULL OR block_time < $5)
+              AND ($6::bigint IS NULL OR amount >= $6)
+              AND ($7::bigint IS NULL OR amount <= $7)
+              AND ($8::bigint IS NULL OR (slot, tx_signature) < ($8, $9))
+            ORDER BY slot DESC, tx_signature DESC
+            LIMIT $10
+            "#
+        ))
+        .bind(&filter.user_pubkey)
+        .bind(&filter.vault_pda)
+        .bind(tx_types)
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(filter.min_amount)
+        .bind(filter.max_amount)
+        .bind(before.map(|c| c.slot))
+        .bind(before.map(|c| c.tx_signature.as_str()).unwrap_or(""))
+        .bind(limit)
+        .fetch_all(self.read_pool)
+        .await?;
+
+        Ok(TransactionPage::from_rows(rows.into_iter().map(map_row).collect(), limit))
     }
 }
 
+/// Columns selected into a `TransactionRow` (the enum is read back as text).
+const TRANSACTION_COLUMNS: &str = r#"
+    id,
+    vault_pda,
+    program_id,
+    network,
+    user_pubkey,
+    tx_signature,
+    tx_type::text AS tx_type,
+    amount,
+    slot,
+    block_time,
+    commitment,
+    event_payload
+"#;
+
+fn map_row(row: sqlx::postgres::PgRow) -> TransactionRow {
+    TransactionRow {
+        id: row.get("id"),
+        vault_pda: row.get("vault_pda"),
+        program_id: row.get("program_id"),
+        network: row.get("network"),
+        user_pubkey: row.get("user_pubkey"),
+        tx_signature: row.get("tx_signature"),
+        tx_type: row.get("tx_type"),
+        amount: row.get("amount"),
+        slot: row.get("slot"),
+        block_time: row.get("block_time"),
+        commitment: row.get("commitment"),
+        event_payload: row.get("event_payload"),
+    }
+}
+
+// Connection-level variants used by the indexer inside a database transaction.
+
+#[tracing::instrument(level = "debug", skip_all, fields(vault = %tx.vault_pda, operation = %tx.tx_type, amount = tx.amount, signature = %tx.tx_signature))]
+pub async fn insert_transaction(conn: &mut PgConnection, tx: &TransactionRow) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO transactions (
+            id,
+            vault_pda,
+            program_id,
+            network,
+            user_pubkey,
+            tx_signature,
+            tx_type,
+            amount,
+            slot,
+            block_time,
+            commitment,
+            event_payload
+        )
+        VALUES ($1,$2,$3,$4,$5,$6,$7::transaction_type,$8,$9,$10,$11,$12)
+        ON CONFLICT (tx_signature, block_time) DO NOTHING
+        "#,
+    )
+    .bind(tx.id)
+    .bind(&tx.vault_pda)
+    .bind(&tx.program_id)
+    .bind(&tx.network)
+    .bind(&tx.user_pubkey)
+    .bind(&tx.tx_signature)
+    .bind(&tx.tx_type)
+    .bind(tx.amount)
+    .bind(tx.slot)
+    .bind(tx.block_time)
+    .bind(&tx.commitment)
+    .bind(&tx.event_payload)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Convenience helper used by the indexer to persist a transaction.
+pub async fn insert_simple(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    user_pubkey: Option<&str>,
+    tx_signature: &str,
+    tx_type: &str,
+    amount: i64,
+    slot: i64,
+    block_time: i64,
+    commitment: &str,
+    event_payload: Option<&serde_json::Value>,
+) -> anyhow::Result<()> {
+    let row = TransactionRow {
+        id: Uuid::new_v4(),
+        vault_pda: vault_pda.to_string(),
+        program_id: "".to_string(),
+        network: "localnet".to_string(),
+        user_pubkey: user_pubkey.map(|s| s.to_string()),
+        tx_signature: tx_signature.to_string(),
+        tx_type: tx_type.to_string(),
+        amount,
+        slot,
+        // Interpret `block_time` as unix timestamp seconds.
+        block_time: {
+            use chrono::{DateTime, Utc};
+            let utc_dt = DateTime::<Utc>::from_timestamp(block_time, 0)
+                .unwrap_or_else(|| Utc::now());
+            utc_dt.naive_utc()
+        },
+        commitment: commitment.to_string(),
+        event_payload: event_payload.cloned(),
+    };
+
+    insert_transaction(conn, &row).await
+}
@@ -0,0 +1,96 @@
+use chrono::NaiveDateTime;
+use sqlx::{PgConnection, PgPool};
+
+use crate::db::vault_repo::VaultRow;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct UserRow {
+    pub pubkey: String,
+    pub created_at: NaiveDateTime,
+    /// Free-form attributes, e.g. a display name or risk tier.
+    pub metadata: serde_json::Value,
+    /// Delivery settings, e.g. `{"webhook_url": "...", "min_amount": 1000}`.
+    pub notification_preferences: serde_json::Value,
+}
+
+pub struct UserRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> UserRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn get_user(&self, pubkey: &str) -> anyhow::Result<Option<UserRow>> {
+        let row = sqlx::query_as::<_, UserRow>(r#"SELECT * FROM users WHERE pubkey = $1"#)
+            .bind(pubkey)
+            .fetch_optional(self.pool)
+            .await?;
+
+        Ok(row)
+    }
+
+    /// Create the user row if it doesn't exist yet.
+    pub async fn ensure_user(&self, pubkey: &str) -> anyhow::Result<()> {
+        let mut conn = self.pool.acquire().await?;
+
+        ensure_user(&mut *conn, pubkey).await
+    }
+
+    /// Replace the user's metadata. Returns `false` if the user is unknown.
+    pub async fn set_metadata(
+        &self,
+        pubkey: &str,
+        metadata: &serde_json::Value,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query(r#"UPDATE users SET metadata = $2 WHERE pubkey = $1"#)
+            .bind(pubkey)
+            .bind(metadata)
+            .execute(self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Replace the user's notification preferences. Returns `false` if the
+    /// user is unknown.
+    pub async fn set_notification_preferences(
+        &self,
+        pubkey: &str,
+        preferences: &serde_json::Value,
+    ) -> anyhow::Result<bool> {
+        let result =
+            sqlx::query(r#"UPDATE users SET notification_preferences = $2 WHERE pubkey = $1"#)
+                .bind(pubkey)
+                .bind(preferences)
+                .execute(self.pool)
+                .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Vaults owned by the user, oldest first.
+    pub async fn get_vaults(&self, pubkey: &str) -> anyhow::Result<Vec<VaultRow>> {
+        let rows = sqlx::query_as::<_, VaultRow>(
+            r#"SELECT * FROM vaults WHERE owner_pubkey = $1 ORDER BY created_at ASC"#,
+        )
+        .bind(pubkey)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(rows)
+    }
+}
+
+// Connection-level variant used by the indexer inside a database transaction.
+
+/// Create the user row if it doesn't exist yet.
+pub async fn ensure_user(conn: &mut PgConnection, pubkey: &str) -> anyhow::Result<()> {
+    sqlx::query(r#"INSERT INTO users (pubkey) VALUES ($1) ON CONFLICT (pubkey) DO NOTHING"#)
+        .bind(pubkey)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
@@ -1,7 +1,14 @@
 use chrono::NaiveDateTime;
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
 
-#[derive(Debug)]
+use crate::db::pool::DbPools;
+use crate::db::transaction_repo::FlowTotals;
+use crate::db::user_repo;
+use crate::error_handling::{VaultError, VaultResult};
+use crate::logging::Logger;
+
+#[derive(Debug, sqlx::FromRow)]
 pub struct VaultRow {
     pub vault_pda: String,
     pub program_id: String,
@@ -16,153 +23,459 @@ pub struct VaultRow {
     pub total_withdrawn: i64,
     pub created_at: NaiveDateTime,
     pub last_synced_at: NaiveDateTime,
+    /// Bumped by every update; writers that read-modify-write pass the
+    /// version they read and fail with `VaultError::VersionConflict` if it moved.
+    pub version: i64,
+    /// One of the `VaultStatus` names.
+    pub status: String,
+    /// Status the vault had before its on-chain close event, restored if the
+    /// close is rolled back.
+    pub status_before_close: Option<String>,
+}
+
+/// Lifecycle of a vault. Operators can only change balances while it is
+/// `Active`; on-chain events apply in every state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VaultStatus {
+    Active,
+    /// Winding down: no new activity is expected.
+    Closing,
+    /// Closed on-chain. Terminal.
+    Closed,
+    /// Held by an operator, e.g. during an incident.
+    Frozen,
+}
+
+impl VaultStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VaultStatus::Active => "active",
+            VaultStatus::Closing => "closing",
+            VaultStatus::Closed => "closed",
+            VaultStatus::Frozen => "frozen",
+        }
+    }
+
+    /// Whether an operator may move a vault from `self` to `to`. Staying in
+    /// the same state is allowed, so transitions are idempotent.
+    pub fn can_transition_to(self, to: VaultStatus) -> bool {
+        use VaultStatus::*;
+
+        matches!(
+            (self, to),
+            (Active, Closing | Frozen | Closed)
+                | (Closing, Active | Closed | Frozen)
+                | (Frozen, Active | Closing)
+        ) || self == to
+    }
+}
+
+impl std::str::FromStr for VaultStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "active" => Ok(VaultStatus::Active),
+            "closing" => Ok(VaultStatus::Closing),
+            "closed" => Ok(VaultStatus::Closed),
+            "frozen" => Ok(VaultStatus::Frozen),
+            other => anyhow::bail!("unknown vault status '{}'", other),
+        }
+    }
+}
+
+/// Criteria for `VaultRepository::list_vaults`. Unset fields don't filter.
+#[derive(Debug, Clone, Default)]
+pub struct VaultFilter {
+    pub mint: Option<String>,
+    pub network: Option<String>,
+    pub min_total_balance: Option<i64>,
+    /// Only vaults last synced before this time (e.g. to find stale ones).
+    pub synced_before: Option<NaiveDateTime>,
+    /// Only vaults last synced at or after this time.
+    pub synced_since: Option<NaiveDateTime>,
+}
+
+/// Ordering for `list_vaults`; ties are broken by `vault_pda`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VaultSort {
+    #[default]
+    CreatedAtAsc,
+    TotalBalanceDesc,
+    LastSyncedAtAsc,
+}
+
+impl VaultSort {
+    /// `(ORDER BY clause, keyset condition)`; `$6..$9` are the cursor binds.
+    fn sql(self) -> (&'static str, &'static str) {
+        match self {
+            VaultSort::CreatedAtAsc => (
+                "created_at ASC, vault_pda ASC",
+                "(created_at, vault_pda) > ($6, $9)",
+            ),
+            VaultSort::TotalBalanceDesc => (
+                "total_balance DESC, vault_pda DESC",
+                "(total_balance, vault_pda) < ($7, $9)",
+            ),
+            VaultSort::LastSyncedAtAsc => (
+                "last_synced_at ASC, vault_pda ASC",
+                "(last_synced_at, vault_pda) > ($8, $9)",
+            ),
+        }
+    }
+}
+
+/// Keyset position taken from the last row of a page. It carries every sort
+/// key, so it is only meaningful with the `VaultSort` that produced it.
+#[derive(Debug, Clone)]
+pub struct VaultCursor {
+    pub created_at: NaiveDateTime,
+    pub total_balance: i64,
+    pub last_synced_at: NaiveDateTime,
+    pub vault_pda: String,
+}
+
+#[derive(Debug)]
+pub struct VaultPage {
+    pub vaults: Vec<VaultRow>,
+    pub next_cursor: Option<VaultCursor>,
+}
+
+/// What a `VaultInitialized` event (plus indexer config) tells us about a
+/// new vault.
+#[derive(Debug)]
+pub struct NewVault<'a> {
+    pub vault_pda: &'a str,
+    pub owner_pubkey: &'a str,
+    pub mint: &'a str,
+    /// The vault PDA's associated token account for `mint`.
+    pub vault_token_account: &'a str,
+    pub program_id: &'a str,
+    pub network: &'a str,
+    /// Unix timestamp of the event.
+    pub timestamp: i64,
+}
+
+/// One immutable `balance_ledger` entry: a single balance mutation with the
+/// vault's balances immediately before and after it.
+#[derive(Debug, sqlx::FromRow)]
+pub struct LedgerRow {
+    pub id: i64,
+    pub vault_pda: String,
+    pub entry_type: String,
+    pub amount: i64,
+    pub total_before: i64,
+    pub total_after: i64,
+    pub available_before: i64,
+    pub available_after: i64,
+    pub locked_before: i64,
+    pub locked_after: i64,
+    pub created_at: NaiveDateTime,
+    /// Set on `adjustment` entries only, see `Adjustment`.
+    pub reason: Option<String>,
+    pub operator: Option<String>,
+    pub discrepancy_id: Option<Uuid>,
 }
 
 pub struct VaultRepository<'a> {
     pool: &'a PgPool,
+    read_pool: &'a PgPool,
 }
 
 impl<'a> VaultRepository<'a> {
     pub fn new(pool: &'a PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            read_pool: pool,
+        }
+    }
+
+    /// Route read-only queries to a replica, writes to the primary.
+    pub fn from_pools(pools: &'a DbPools) -> Self {
+        Self {
+            pool: pools.primary(),
+            read_pool: pools.read(),
+        }
     }
 
     /// Upsert a full vault row (low-level helper).
-    pub async fn upsert_vault(&self, vault: &VaultRow) -> anyhow::Result<()> {
-        sqlx::query!(
-            r#"
-            INSERT INTO vaults (
-                vault_pda,
-                program_id,
-                network,
-                owner_pubkey,
-                mint,
-                vault_token_account,
-                total_balance,
-                locked_balance,
-                available_balance,
-                total_deposited,
-                total_withdrawn,
-                created_at,
-                last_synced_at
-            )
-            VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13)
-            ON CONFLICT (vault_pda) DO UPDATE SET
-                total_balance = EXCLUDED.total_balance,
-                locked_balance = EXCLUDED.locked_balance,
-                available_balance = EXCLUDED.available_balance,
-                total_deposited = EXCLUDED.total_deposited,
-                total_withdrawn = EXCLUDED.total_withdrawn,
-                last_synced_at = EXCLUDED.last_synced_at
-            "#,
-            vault.vault_pda,
-            vault.program_id,
-            vault.network,
-            vault.owner_pubkey,
-            vault.mint,
-            vault.vault_token_account,
-            vault.total_balance,
-            vault.locked_balance,
-            vault.available_balance,
-            vault.total_deposited,
-            vault.total_withdrawn,
-            vault.created_at,
-            vault.last_synced_at,
-        )
-        .execute(self.pool)
-        .await?;
+    pub async fn upsert_vault(&self, vault: &VaultRow) -> VaultResult<()> {
+        let mut conn = self.pool.acquire().await?;
 
-        Ok(())
+        upsert_vault(&mut *conn, vault).await
     }
 
-    pub async fn get_vault(&self, vault_pda: &str) -> anyhow::Result<Option<VaultRow>> {
+    pub async fn get_vault(&self, vault_pda: &str) -> VaultResult<Option<VaultRow>> {
         let row = sqlx::query_as!(
             VaultRow,
             r#"SELECT * FROM vaults WHERE vault_pda = $1"#,
             vault_pda
         )
-        .fetch_optional(self.pool)
+        .fetch_optional(self.read_pool)
         .await?;
 
         Ok(row)
     }
 
     /// Return all vaults (used by reconciliation worker and analytics).
-    pub async fn get_all_vaults(&self) -> anyhow::Result<Vec<VaultRow>> {
+    pub async fn get_all_vaults(&self) -> VaultResult<Vec<VaultRow>> {
         let rows = sqlx::query_as!(
             VaultRow,
             r#"SELECT * FROM vaults ORDER BY created_at ASC"#
         )
-        .fetch_all(self.pool)
+        .fetch_all(self.read_pool)
         .await?;
 
         Ok(rows)
     }
 
-    /// Fetch the vault record for a given owner, if any.
-    pub async fn get_vault_by_owner(
+    /// One page of vaults matching `filter`, ordered by `sort`.
+    ///
+    /// Keyset paginated: pass the previous page's `next_cursor` to continue.
+    /// Unlike `get_all_vaults` this never holds more than `limit` rows.
+    pub async fn list_vaults(
+        &self,
+        filter: &VaultFilter,
+        sort: VaultSort,
+        cursor: Option<&VaultCursor>,
+        limit: i64,
+    ) -> VaultResult<VaultPage> {
+        let (order_by, after_cursor) = sort.sql();
+
+        let sql = format!(
+            r#"
+            SELECT *
+            FROM vaults
+            WHERE ($1::text IS NULL OR mint = $1)
+              AND ($2::text IS NULL OR network = $2)
+              AND ($3::bigint IS NULL OR total_balance >= $3)
+              AND ($4::timestamp IS NULL OR last_synced_at < $4)
+              AND ($5::timestamp IS NULL OR last_synced_at >= $5)
+              AND ($9::text IS NULL OR {after_cursor})
+            ORDER BY {order_by}
+            LIMIT $10
+            "#
+        );
+
+        let vaults = sqlx::query_as::<_, VaultRow>(&sql)
+            .bind(&filter.mint)
+            .bind(&filter.network)
+            .bind(filter.min_total_balance)
+            .bind(filter.synced_before)
+            .bind(filter.synced_since)
+            .bind(cursor.map(|c| c.created_at))
+            .bind(cursor.map(|c| c.total_balance))
+            .bind(cursor.map(|c| c.last_synced_at))
+            .bind(cursor.map(|c| c.vault_pda.as_str()))
+            .bind(limit)
+            .fetch_all(self.read_pool)
+            .await?;
+
+        let next_cursor = if vaults.len() as i64 == limit {
+            vaults.last().map(|v| VaultCursor {
+                created_at: v.created_at,
+                total_balance: v.total_balance,
+                last_synced_at: v.last_synced_at,
+                vault_pda: v.vault_pda.clone(),
+            })
+        } else {
+            None
+        };
+
+        Ok(VaultPage {
+            vaults,
+            next_cursor,
+        })
+    }
+
+    /// One page of the vaults holding `mint`, oldest first. Shorthand for
+    /// `list_vaults` with only the mint filter set.
+    pub async fn get_vaults_by_mint(
         &self,
-        owner_pubkey: &str,
-    ) -> anyhow::Result<Option<VaultRow>> {
+        mint: &str,
+        cursor: Option<&VaultCursor>,
+        limit: i64,
+    ) -> VaultResult<VaultPage> {
+        let filter = VaultFilter {
+            mint: Some(mint.to_string()),
+            ..Default::default()
+        };
+
+        self.list_vaults(&filter, VaultSort::CreatedAtAsc, cursor, limit)
+            .await
+    }
+
+    /// Number of vaults holding `mint`.
+    pub async fn count_vaults_by_mint(&self, mint: &str) -> VaultResult<i64> {
+        let count: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM vaults WHERE mint = $1"#)
+            .bind(mint)
+            .fetch_one(self.read_pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Vaults not synced since `cutoff`, most recently synced first.
+    ///
+    /// Candidates for stale-vault detection: a vault that was active until
+    /// recently is far more likely to have missed events than one that has
+    /// been dormant for months.
+    pub async fn get_vaults_synced_before(
+        &self,
+        cutoff: NaiveDateTime,
+        limit: i64,
+    ) -> VaultResult<Vec<VaultRow>> {
+        let rows = sqlx::query_as::<_, VaultRow>(
+            r#"
+            SELECT *
+            FROM vaults
+            WHERE last_synced_at < $1
+            ORDER BY last_synced_at DESC, vault_pda DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(cutoff)
+        .bind(limit)
+        .fetch_all(self.read_pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Vaults with no token account recorded. Rows written before the
+    /// indexer derived the vault's ATA have an empty `vault_token_account`.
+    pub async fn get_vaults_missing_token_account(&self) -> VaultResult<Vec<VaultRow>> {
+        let rows = sqlx::query_as::<_, VaultRow>(
+            r#"
+            SELECT *
+            FROM vaults
+            WHERE vault_token_account = ''
+            ORDER BY created_at ASC
+            "#,
+        )
+        .fetch_all(self.read_pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Fill in a missing token account. Returns `false` if the vault doesn't
+    /// exist or already has one, so a recorded account is never overwritten.
+    pub async fn set_missing_token_account(
+        &self,
+        vault_pda: &str,
+        vault_token_account: &str,
+    ) -> VaultResult<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE vaults
+            SET vault_token_account = $2, version = version + 1
+            WHERE vault_pda = $1
+              AND vault_token_account = ''
+            "#,
+        )
+        .bind(vault_pda)
+        .bind(vault_token_account)
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Overwrite `total_deposited` / `total_withdrawn` with `totals` derived
+    /// from the transaction history. Compare-and-swap on `expected_version`
+    /// like `set_balances`, but returns `false` instead of failing if the
+    /// vault moved, since the totals would have to be derived again anyway.
+    pub async fn set_flow_totals(
+        &self,
+        vault_pda: &str,
+        expected_version: i64,
+        totals: FlowTotals,
+    ) -> VaultResult<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE vaults
+            SET total_deposited = $3, total_withdrawn = $4, version = version + 1
+            WHERE vault_pda = $1
+              AND version = $2
+            "#,
+        )
+        .bind(vault_pda)
+        .bind(expected_version)
+        .bind(totals.deposited)
+        .bind(totals.withdrawn)
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Vaults updated after their most recent snapshot, i.e. whose `version`
+    /// moved past the one it recorded (or that have never been snapshotted).
+    pub async fn get_vaults_changed_since_snapshot(&self) -> VaultResult<Vec<VaultRow>> {
+        let mut conn = self.pool.acquire().await?;
+
+        get_vaults_changed_since_snapshot(&mut *conn).await
+    }
+
+    /// Fetch the vault record for a given owner, if any.
+    pub async fn get_vault_by_owner(&self, owner_pubkey: &str) -> VaultResult<Option<VaultRow>> {
         let row = sqlx::query_as!(
             VaultRow,
             r#"SELECT * FROM vaults WHERE owner_pubkey = $1"#,
             owner_pubkey,
         )
-        .fetch_optional(self.pool)
+        .fetch_optional(self.read_pool)
         .await?;
 
         Ok(row)
     }
 
     /// Compute total value locked (TVL) across all vaults.
-    pub async fn get_tvl(&self) -> anyhow::Result<i64> {
+    pub async fn get_tvl(&self) -> VaultResult<i64> {
         // Explicitly cast the SUM to BIGINT so SQLx doesn't require the
         // `bigdecimal` feature for NUMERIC.
         let tvl: i64 = sqlx::query_scalar!(
             r#"SELECT COALESCE(SUM(total_balance)::BIGINT, 0) AS "tvl!: i64" FROM vaults"#,
         )
-        .fetch_one(self.pool)
+        .fetch_one(self.read_pool)
         .await?;
 
         Ok(tvl)
     }
 
-    /// Insert a new vault when a `VaultInitialized` event is seen.
-    ///
-    /// Fields we don't get from the event are filled with sensible defaults.
-    pub async fn insert_new_vault(
+    /// Ledger entries of a vault, newest first. Pass the smallest `id` of
+    /// the previous page as `before_id` to continue.
+    pub async fn get_ledger(
         &self,
         vault_pda: &str,
-        owner_pubkey: &str,
-        mint: &str,
-        timestamp: i64,
-    ) -> anyhow::Result<()> {
-        // Convert unix timestamp -> NaiveDateTime, fall back to now() if conversion fails.
-        use chrono::{DateTime, Utc};
-        let created_at = {
-            let utc_dt = DateTime::<Utc>::from_timestamp(timestamp, 0)
-                .unwrap_or_else(|| Utc::now());
-            utc_dt.naive_utc()
-        };
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> VaultResult<Vec<LedgerRow>> {
+        let rows = sqlx::query_as::<_, LedgerRow>(
+            r#"
+            SELECT *
+            FROM balance_ledger
+            WHERE vault_pda = $1
+              AND ($2::bigint IS NULL OR id < $2)
+            ORDER BY id DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(vault_pda)
+        .bind(before_id)
+        .bind(limit)
+        .fetch_all(self.read_pool)
+        .await?;
 
-        let vault = VaultRow {
-            vault_pda: vault_pda.to_string(),
-            program_id: "".to_string(), // can be filled with real program id in a later migration
-            network: "localnet".to_string(),
-            owner_pubkey: owner_pubkey.to_string(),
-            mint: mint.to_string(),
-            vault_token_account: "".to_string(),
-            total_balance: 0,
-            locked_balance: 0,
-            available_balance: 0,
-            total_deposited: 0,
-            total_withdrawn: 0,
-            created_at,
-            last_synced_at: created_at,
-        };
+        Ok(rows)
+    }
+
+    /// Insert a new vault when a `VaultInitialized` event is seen.
+    pub async fn insert_new_vault(&self, vault: &NewVault<'_>) -> VaultResult<()> {
+        let mut conn = self.pool.acquire().await?;
 
-        self.upsert_vault(&vault).await
+        insert_new_vault(&mut *conn, vault).await
     }
 
     /// Set balances directly from an on-chain event (e.g. deposit).
@@ -171,90 +484,154 @@ impl<'a> VaultRepository<'a> {
         vault_pda: &str,
         new_total_balance: i64,
         timestamp: i64,
-    ) -> anyhow::Result<()> {
-        use chrono::{DateTime, Utc};
-        let utc_dt = DateTime::<Utc>::from_timestamp(timestamp, 0)
-            .unwrap_or_else(|| Utc::now());
-        let ts = utc_dt.naive_utc();
+    ) -> VaultResult<()> {
+        let mut tx = self.pool.begin().await?;
 
-        sqlx::query!(
-            r#"
-            UPDATE vaults
-            SET
-                total_balance     = $2,
-                available_balance = $2,
-                last_synced_at    = $3
-            WHERE vault_pda = $1
-            "#,
-            vault_pda,
-            new_total_balance,
-            ts,
-        )
-        .execute(self.pool)
-        .await?;
+        set_balance_from_event(&mut *tx, vault_pda, new_total_balance, timestamp).await?;
+
+        tx.commit().await?;
 
         Ok(())
     }
 
     /// Apply a withdraw event to the off-chain balances.
-    pub async fn apply_withdraw(&self, vault_pda: &str, amount: i64) -> anyhow::Result<()> {
-        sqlx::query!(
-            r#"
-            UPDATE vaults
-            SET
-                total_balance     = total_balance - $2,
-                available_balance = available_balance - $2,
-                total_withdrawn   = total_withdrawn + $2,
-                last_synced_at    = now()
-            WHERE vault_pda = $1
-            "#,
-            vault_pda,
-            amount,
-        )
-        .execute(self.pool)
-        .await?;
+    pub async fn apply_withdraw(&self, vault_pda: &str, amount: i64) -> VaultResult<()> {
+        let mut tx = self.pool.begin().await?;
+
+        apply_withdraw(&mut *tx, vault_pda, amount).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Reverse a deposit that was applied from a forked-out transaction.
+    pub async fn revert_deposit(&self, vault_pda: &str, amount: i64) -> VaultResult<()> {
+        let mut tx = self.pool.begin().await?;
+
+        revert_deposit(&mut *tx, vault_pda, amount).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Reverse a withdraw that was applied from a forked-out transaction.
+    pub async fn revert_withdraw(&self, vault_pda: &str, amount: i64) -> VaultResult<()> {
+        let mut tx = self.pool.begin().await?;
+
+        revert_withdraw(&mut *tx, vault_pda, amount).await?;
+
+        tx.commit().await?;
 
         Ok(())
     }
 
     /// Apply a lock event: move from available -> locked.
-    pub async fn apply_lock(&self, vault_pda: &str, amount: i64) -> anyhow::Result<()> {
-        sqlx::query!(
-            r#"
-            UPDATE vaults
-            SET
-                available_balance = available_balance - $2,
-                locked_balance    = locked_balance + $2,
-                last_synced_at    = now()
-            WHERE vault_pda = $1
-            "#,
-            vault_pda,
-            amount,
-        )
-        .execute(self.pool)
-        .await?;
+    pub async fn apply_lock(&self, vault_pda: &str, amount: i64) -> VaultResult<()> {
+        let mut tx = self.pool.begin().await?;
+
+        apply_lock(&mut *tx, vault_pda, amount).await?;
+
+        tx.commit().await?;
 
         Ok(())
     }
 
     /// Apply an unlock event: move from locked -> available.
-    pub async fn apply_unlock(&self, vault_pda: &str, amount: i64) -> anyhow::Result<()> {
-        sqlx::query!(
+    pub async fn apply_unlock(&self, vault_pda: &str, amount: i64) -> VaultResult<()> {
+        let mut tx = self.pool.begin().await?;
+
+        apply_unlock(&mut *tx, vault_pda, amount).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Move a vault to another lifecycle status (operator action).
+    pub async fn set_status(&self, vault_pda: &str, to: VaultStatus) -> VaultResult<()> {
+        let mut tx = self.pool.begin().await?;
+
+        set_status(&mut *tx, vault_pda, to).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Mark a vault closed after its on-chain close event.
+    pub async fn close_vault(&self, vault_pda: &str) -> VaultResult<()> {
+        let mut conn = self.pool.acquire().await?;
+
+        close_vault(&mut *conn, vault_pda).await
+    }
+
+    /// Restore the pre-close status of a vault whose close event was rolled back.
+    pub async fn revert_close(&self, vault_pda: &str) -> VaultResult<()> {
+        let mut conn = self.pool.acquire().await?;
+
+        revert_close(&mut *conn, vault_pda).await
+    }
+
+    /// Overwrite a vault's balances, e.g. an admin correction or a
+    /// reconciliation auto-heal, recorded in the ledger as `correction`.
+    ///
+    /// Compare-and-swap on `expected_version` (the `version` of the row the
+    /// new balances were computed from): fails with
+    /// `VaultError::VersionConflict` if anything updated the vault since.
+    /// Returns the new version.
+    pub async fn set_balances(
+        &self,
+        vault_pda: &str,
+        expected_version: i64,
+        total_balance: i64,
+        available_balance: i64,
+        locked_balance: i64,
+    ) -> VaultResult<i64> {
+        let mut tx = self.pool.begin().await?;
+
+        let current = sqlx::query_as::<_, Balances>(
             r#"
-            UPDATE vaults
-            SET
-                available_balance = available_balance + $2,
-                locked_balance    = locked_balance - $2,
-                last_synced_at    = now()
+            SELECT total_balance, available_balance, locked_balance, version, status
+            FROM vaults
             WHERE vault_pda = $1
             "#,
+        )
+        .bind(vault_pda)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| VaultError::AccountNotFound {
+            account: vault_pda.to_string(),
+        })?;
+
+        let delta = BalanceDelta {
+            total: total_balance - current.total_balance,
+            available: available_balance - current.available_balance,
+            locked: locked_balance - current.locked_balance,
+            ..Default::default()
+        };
+
+        let entry = LedgerEntry {
+            entry_type: "correction",
+            amount: total_balance,
+            adjustment: None,
+            origin: Origin::Operator,
+        };
+
+        let version = record_mutation(
+            &mut *tx,
             vault_pda,
-            amount,
+            entry,
+            delta,
+            None,
+            Some(expected_version),
         )
-        .execute(self.pool)
         .await?;
 
-        Ok(())
+        tx.commit().await?;
+
+        Ok(version)
     }
 
     /// Apply a transfer between two vaults.
@@ -263,44 +640,903 @@ impl<'a> VaultRepository<'a> {
         from_vault: &str,
         to_vault: &str,
         amount: i64,
-    ) -> anyhow::Result<()> {
+    ) -> VaultResult<()> {
         let mut tx = self.pool.begin().await?;
 
-        // Debit from_vault
-        sqlx::query!(
-            r#"
-            UPDATE vaults
-            SET
-                total_balance     = total_balance - $2,
-                available_balance = available_balance - $2,
-                last_synced_at    = now()
-            WHERE vault_pda = $1
-            "#,
-            from_vault,
-            amount,
+        apply_transfer(&mut *tx, from_vault, to_vault, amount).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+// Connection-level variants of the mutating queries, so the indexer can apply
+// all events of a transaction inside one database transaction.
+
+/// Upsert a full vault row (low-level helper).
+///
+/// An existing row is only overwritten if its `version` still equals
+/// `vault.version`, otherwise this fails with `VaultError::VersionConflict`
+/// instead of clobbering a concurrent update.
+#[tracing::instrument(level = "debug", skip_all, fields(vault = %vault.vault_pda))]
+pub async fn upsert_vault(conn: &mut PgConnection, vault: &VaultRow) -> VaultResult<()> {
+    user_repo::ensure_user(conn, &vault.owner_pubkey).await?;
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO vaults (
+            vault_pda,
+            program_id,
+            network,
+            owner_pubkey,
+            mint,
+            vault_token_account,
+            total_balance,
+            locked_balance,
+            available_balance,
+            total_deposited,
+            total_withdrawn,
+            created_at,
+            last_synced_at,
+            version
         )
-        .execute(&mut *tx)
-        .await?;
+        VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13,$14)
+        ON CONFLICT (vault_pda) DO UPDATE SET
+            total_balance = EXCLUDED.total_balance,
+            locked_balance = EXCLUDED.locked_balance,
+            available_balance = EXCLUDED.available_balance,
+            total_deposited = EXCLUDED.total_deposited,
+            total_withdrawn = EXCLUDED.total_withdrawn,
+            last_synced_at = EXCLUDED.last_synced_at,
+            version = vaults.version + 1
+        WHERE vaults.version = EXCLUDED.version
+        "#,
+    )
+    .bind(&vault.vault_pda)
+    .bind(&vault.program_id)
+    .bind(&vault.network)
+    .bind(&vault.owner_pubkey)
+    .bind(&vault.mint)
+    .bind(&vault.vault_token_account)
+    .bind(vault.total_balance)
+    .bind(vault.locked_balance)
+    .bind(vault.available_balance)
+    .bind(vault.total_deposited)
+    .bind(vault.total_withdrawn)
+    .bind(vault.created_at)
+    .bind(vault.last_synced_at)
+    .bind(vault.version)
+    .execute(&mut *conn)
+    .await?;
 
-        // Credit to_vault
-        sqlx::query!(
-            r#"
-            UPDATE vaults
-            SET
-                total_balance     = total_balance + $2,
-                available_balance = available_balance + $2,
-                last_synced_at    = now()
-            WHERE vault_pda = $1
-            "#,
-            to_vault,
+    // Zero rows: the row exists and was updated since `vault` was read
+    if result.rows_affected() == 0 {
+        let actual: i64 = sqlx::query_scalar(r#"SELECT version FROM vaults WHERE vault_pda = $1"#)
+            .bind(&vault.vault_pda)
+            .fetch_one(&mut *conn)
+            .await?;
+
+        return Err(VaultError::VersionConflict {
+            vault: vault.vault_pda.clone(),
+            expected: vault.version,
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+/// Insert a new vault when a `VaultInitialized` event is seen.
+///
+/// Fields we don't get from the event are filled with sensible defaults.
+#[tracing::instrument(level = "debug", skip_all, fields(vault = new_vault.vault_pda, user = new_vault.owner_pubkey))]
+pub async fn insert_new_vault(
+    conn: &mut PgConnection,
+    new_vault: &NewVault<'_>,
+) -> VaultResult<()> {
+    // Convert unix timestamp -> NaiveDateTime, fall back to now() if conversion fails.
+    use chrono::{DateTime, Utc};
+    let created_at = {
+        let utc_dt = DateTime::<Utc>::from_timestamp(new_vault.timestamp, 0)
+            .unwrap_or_else(|| Utc::now());
+        utc_dt.naive_utc()
+    };
+
+    // Vaults reference their owner, so the user row comes first
+    user_repo::ensure_user(conn, new_vault.owner_pubkey).await?;
+
+    // A replayed VaultInitialized must not reset an existing vault's balances
+    sqlx::query(
+        r#"
+        INSERT INTO vaults (
+            vault_pda,
+            program_id,
+            network,
+            owner_pubkey,
+            mint,
+            vault_token_account,
+            total_balance,
+            locked_balance,
+            available_balance,
+            total_deposited,
+            total_withdrawn,
+            created_at,
+            last_synced_at
+        )
+        VALUES ($1,$2,$3,$4,$5,$6,0,0,0,0,0,$7,$7)
+        ON CONFLICT (vault_pda) DO NOTHING
+        "#,
+    )
+    .bind(new_vault.vault_pda)
+    .bind(new_vault.program_id)
+    .bind(new_vault.network)
+    .bind(new_vault.owner_pubkey)
+    .bind(new_vault.mint)
+    .bind(new_vault.vault_token_account)
+    .bind(created_at)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// An indexed vault, `None` if it isn't indexed.
+pub async fn get_vault(conn: &mut PgConnection, vault_pda: &str) -> VaultResult<Option<VaultRow>> {
+    let row = sqlx::query_as!(
+        VaultRow,
+        r#"SELECT * FROM vaults WHERE vault_pda = $1"#,
+        vault_pda
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(row)
+}
+
+/// Vaults whose `version` moved past the one their latest snapshot recorded,
+/// or that have never been snapshotted.
+pub async fn get_vaults_changed_since_snapshot(
+    conn: &mut PgConnection,
+) -> VaultResult<Vec<VaultRow>> {
+    let rows = sqlx::query_as::<_, VaultRow>(
+        r#"
+        SELECT v.*
+        FROM vaults v
+        WHERE v.version > COALESCE(
+            (
+                SELECT s.vault_version
+                FROM balance_snapshots s
+                WHERE s.vault_pda = v.vault_pda
+                ORDER BY s.snapshot_time DESC
+                LIMIT 1
+            ),
+            -1
+        )
+        ORDER BY v.created_at ASC
+        "#,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(rows)
+}
+
+/// Mint of an indexed vault, `None` if the vault isn't indexed.
+pub async fn get_vault_mint(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+) -> VaultResult<Option<String>> {
+    let mint: Option<String> =
+        sqlx::query_scalar(r#"SELECT mint FROM vaults WHERE vault_pda = $1"#)
+            .bind(vault_pda)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+    Ok(mint)
+}
+
+/// Set balances directly from an on-chain event (e.g. deposit).
+pub async fn set_balance_from_event(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    new_total_balance: i64,
+    timestamp: i64,
+) -> VaultResult<()> {
+    use chrono::{DateTime, Utc};
+    let utc_dt = DateTime::<Utc>::from_timestamp(timestamp, 0)
+        .unwrap_or_else(|| Utc::now());
+    let ts = utc_dt.naive_utc();
+
+    let current = sqlx::query_as::<_, Balances>(
+        r#"
+        SELECT total_balance, available_balance, locked_balance, version, status
+        FROM vaults
+        WHERE vault_pda = $1
+        FOR UPDATE
+        "#,
+    )
+    .bind(vault_pda)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| VaultError::AccountNotFound {
+        account: vault_pda.to_string(),
+    })?;
+
+    let delta = BalanceDelta {
+        total: new_total_balance - current.total_balance,
+        available: new_total_balance - current.available_balance,
+        ..Default::default()
+    };
+
+    mutate_balances(
+        conn,
+        vault_pda,
+        "set_balance",
+        new_total_balance,
+        delta,
+        Some(ts),
+        None,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Apply a deposit as a delta (used when replaying history, where the
+/// event's absolute `new_balance` isn't stored).
+pub async fn apply_deposit(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    amount: i64,
+) -> VaultResult<()> {
+    let delta = BalanceDelta {
+        total: amount,
+        available: amount,
+        deposited: amount,
+        ..Default::default()
+    };
+
+    mutate_balances(conn, vault_pda, "deposit", amount, delta, None, None).await?;
+
+    Ok(())
+}
+
+/// Apply a withdraw event to the off-chain balances.
+pub async fn apply_withdraw(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    amount: i64,
+) -> VaultResult<()> {
+    let delta = BalanceDelta {
+        total: -amount,
+        available: -amount,
+        withdrawn: amount,
+        ..Default::default()
+    };
+
+    mutate_balances(conn, vault_pda, "withdraw", amount, delta, None, None).await?;
+
+    Ok(())
+}
+
+/// Reverse a deposit that was applied from a forked-out transaction.
+pub async fn revert_deposit(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    amount: i64,
+) -> VaultResult<()> {
+    let delta = BalanceDelta {
+        total: -amount,
+        available: -amount,
+        ..Default::default()
+    };
+
+    mutate_balances(conn, vault_pda, "revert_deposit", amount, delta, None, None).await?;
+
+    Ok(())
+}
+
+/// Reverse a withdraw that was applied from a forked-out transaction.
+pub async fn revert_withdraw(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    amount: i64,
+) -> VaultResult<()> {
+    let delta = BalanceDelta {
+        total: amount,
+        available: amount,
+        withdrawn: -amount,
+        ..Default::default()
+    };
+
+    mutate_balances(conn, vault_pda, "revert_withdraw", amount, delta, None, None).await?;
+
+    Ok(())
+}
+
+/// Apply a lock event: move from available -> locked.
+pub async fn apply_lock(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    amount: i64,
+) -> VaultResult<()> {
+    let delta = BalanceDelta {
+        available: -amount,
+        locked: amount,
+        ..Default::default()
+    };
+
+    mutate_balances(conn, vault_pda, "lock", amount, delta, None, None).await?;
+
+    Ok(())
+}
+
+/// Apply an unlock event: move from locked -> available.
+pub async fn apply_unlock(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    amount: i64,
+) -> VaultResult<()> {
+    let delta = BalanceDelta {
+        available: amount,
+        locked: -amount,
+        ..Default::default()
+    };
+
+    mutate_balances(conn, vault_pda, "unlock", amount, delta, None, None).await?;
+
+    Ok(())
+}
+
+/// Apply a transfer between two vaults.
+pub async fn apply_transfer(
+    conn: &mut PgConnection,
+    from_vault: &str,
+    to_vault: &str,
+    amount: i64,
+) -> VaultResult<()> {
+    apply_transfer_out(conn, from_vault, amount).await?;
+    apply_transfer_in(conn, to_vault, amount).await
+}
+
+/// Debit the sending vault of a transfer.
+pub async fn apply_transfer_out(
+    conn: &mut PgConnection,
+    from_vault: &str,
+    amount: i64,
+) -> VaultResult<()> {
+    let debit = BalanceDelta {
+        total: -amount,
+        available: -amount,
+        ..Default::default()
+    };
+    mutate_balances(conn, from_vault, "transfer_out", amount, debit, None, None).await?;
+
+    Ok(())
+}
+
+/// Credit the receiving vault of a transfer.
+pub async fn apply_transfer_in(
+    conn: &mut PgConnection,
+    to_vault: &str,
+    amount: i64,
+) -> VaultResult<()> {
+    let credit = BalanceDelta {
+        total: amount,
+        available: amount,
+        ..Default::default()
+    };
+    mutate_balances(conn, to_vault, "transfer_in", amount, credit, None, None).await?;
+
+    Ok(())
+}
+
+/// Zero a vault's balances and running totals before its history is replayed,
+/// recorded in the ledger as a `correction` so the before/after chain of the
+/// replayed entries starts from zero. Returns the new version.
+pub async fn reset_balances(conn: &mut PgConnection, vault_pda: &str) -> VaultResult<i64> {
+    let current = sqlx::query_as::<_, (i64, i64, i64, i64, i64)>(
+        r#"
+        SELECT total_balance, available_balance, locked_balance, total_deposited, total_withdrawn
+        FROM vaults
+        WHERE vault_pda = $1
+        FOR UPDATE
+        "#,
+    )
+    .bind(vault_pda)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| VaultError::AccountNotFound {
+        account: vault_pda.to_string(),
+    })?;
+    let (total, available, locked, deposited, withdrawn) = current;
+
+    let delta = BalanceDelta {
+        total: -total,
+        available: -available,
+        locked: -locked,
+        deposited: -deposited,
+        withdrawn: -withdrawn,
+    };
+
+    mutate_balances(conn, vault_pda, "correction", 0, delta, None, None).await
+}
+
+/// Why an `adjustment` ledger entry was written and who is accountable for it.
+#[derive(Debug)]
+pub struct Adjustment<'a> {
+    pub reason: &'a str,
+    pub operator: &'a str,
+    /// The reconciliation discrepancy this adjustment corrects.
+    pub discrepancy_id: Uuid,
+}
+
+/// Balances of a vault account as read from the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainBalances {
+    pub total: i64,
+    pub available: i64,
+    pub locked: i64,
+}
+
+/// Signed change an adjustment made to each balance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AdjustmentDelta {
+    pub total: i64,
+    pub available: i64,
+    pub locked: i64,
+}
+
+/// Bring a vault's balances to `onchain`, recorded in the ledger as one
+/// `adjustment` entry carrying `adjustment` (its amount is the change of the
+/// total). The change is computed from the row as it is now, under a row
+/// lock, and all three balances move together so total = available + locked
+/// keeps holding. Nothing is written if they already match.
+pub async fn apply_adjustment(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    onchain: &ChainBalances,
+    adjustment: &Adjustment<'_>,
+) -> VaultResult<AdjustmentDelta> {
+    let (total, available, locked, version) = sqlx::query_as::<_, (i64, i64, i64, i64)>(
+        r#"
+        SELECT total_balance, available_balance, locked_balance, version
+        FROM vaults
+        WHERE vault_pda = $1
+        FOR UPDATE
+        "#,
+    )
+    .bind(vault_pda)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| VaultError::AccountNotFound {
+        account: vault_pda.to_string(),
+    })?;
+
+    let applied = AdjustmentDelta {
+        total: onchain.total - total,
+        available: onchain.available - available,
+        locked: onchain.locked - locked,
+    };
+    if applied == AdjustmentDelta::default() {
+        return Ok(applied);
+    }
+
+    let delta = BalanceDelta {
+        total: applied.total,
+        available: applied.available,
+        locked: applied.locked,
+        ..Default::default()
+    };
+
+    let entry = LedgerEntry {
+        entry_type: "adjustment",
+        amount: applied.total,
+        adjustment: Some(adjustment),
+        origin: Origin::Operator,
+    };
+
+    record_mutation(conn, vault_pda, entry, delta, None, Some(version)).await?;
+
+    Ok(applied)
+}
+
+/// Move a vault to `to` if `VaultStatus::can_transition_to` allows it,
+/// otherwise fail with `VaultError::InvalidStatusTransition`.
+pub async fn set_status(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    to: VaultStatus,
+) -> VaultResult<()> {
+    let current: String =
+        sqlx::query_scalar(r#"SELECT status FROM vaults WHERE vault_pda = $1 FOR UPDATE"#)
+            .bind(vault_pda)
+            .fetch_optional(&mut *conn)
+            .await?
+            .ok_or_else(|| VaultError::AccountNotFound {
+                account: vault_pda.to_string(),
+            })?;
+
+    let from: VaultStatus = current.parse()?;
+
+    if !from.can_transition_to(to) {
+        return Err(VaultError::InvalidStatusTransition {
+            vault: vault_pda.to_string(),
+            from: current,
+            to: to.as_str().to_string(),
+        });
+    }
+
+    write_status(conn, vault_pda, to).await
+}
+
+/// Mark a vault closed after its on-chain close event. Unlike `set_status`
+/// this applies from any state: the chain has the final word. The status it
+/// had is kept for `revert_close`.
+#[tracing::instrument(level = "debug", skip_all, fields(vault = vault_pda))]
+pub async fn close_vault(conn: &mut PgConnection, vault_pda: &str) -> VaultResult<()> {
+    let result = sqlx::query(
+        r#"
+        UPDATE vaults
+        SET status_before_close = status, status = 'closed', version = version + 1
+        WHERE vault_pda = $1 AND status <> 'closed'
+        "#,
+    )
+    .bind(vault_pda)
+    .execute(&mut *conn)
+    .await?;
+
+    if result.rows_affected() > 0 {
+        tracing::info!("vault {} is now closed", vault_pda);
+    }
+
+    Ok(())
+}
+
+/// Undo `close_vault` when the close event is rolled back with its fork,
+/// restoring the status the vault had before. A vault an operator closed
+/// stays closed.
+#[tracing::instrument(level = "debug", skip_all, fields(vault = vault_pda))]
+pub async fn revert_close(conn: &mut PgConnection, vault_pda: &str) -> VaultResult<()> {
+    let restored: Option<String> = sqlx::query_scalar(
+        r#"
+        UPDATE vaults
+        SET status = status_before_close, status_before_close = NULL, version = version + 1
+        WHERE vault_pda = $1 AND status = 'closed' AND status_before_close IS NOT NULL
+        RETURNING status
+        "#,
+    )
+    .bind(vault_pda)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    if let Some(status) = restored {
+        tracing::info!("vault {} is now {}", vault_pda, status);
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(vault = vault_pda, status = status.as_str()))]
+async fn write_status(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    status: VaultStatus,
+) -> VaultResult<()> {
+    let result = sqlx::query(
+        r#"
+        UPDATE vaults
+        SET status = $2, version = version + 1
+        WHERE vault_pda = $1 AND status <> $2
+        "#,
+    )
+    .bind(vault_pda)
+    .bind(status.as_str())
+    .execute(&mut *conn)
+    .await?;
+
+    if result.rows_affected() > 0 {
+        tracing::info!("vault {} is now {}", vault_pda, status.as_str());
+    }
+
+    Ok(())
+}
+
+/// Balances of a vault row at one point in time.
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct Balances {
+    total_balance: i64,
+    available_balance: i64,
+    locked_balance: i64,
+    version: i64,
+    status: String,
+}
+
+
+/// Signed change applied to a vault row by one mutation.
+#[derive(Debug, Clone, Copy, Default)]
+struct BalanceDelta {
+    total: i64,
+    available: i64,
+    locked: i64,
+    deposited: i64,
+    withdrawn: i64,
+}
+
+/// Where a balance change comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Origin {
+    /// Derived from the chain. The funds already moved, so it applies
+    /// whatever the vault's status.
+    Chain,
+    /// An operator or API correction, only allowed on `active` vaults.
+    Operator,
+}
+
+/// What a `balance_ledger` entry records besides the balances themselves.
+struct LedgerEntry<'a> {
+    entry_type: &'a str,
+    amount: i64,
+    adjustment: Option<&'a Adjustment<'a>>,
+    origin: Origin,
+}
+
+/// Apply `delta` to a vault and append the matching `balance_ledger` entry.
+///
+/// The update only matches if no balance would go negative, so a zero-row
+/// result means either the vault isn't indexed or an invariant would break;
+/// both become a typed `VaultError`, and a rejected change is also logged as a
+/// state mismatch, since the chain allowed something our off-chain balances
+/// say it shouldn't have. The ledger row is written on the same connection,
+/// so inside a transaction it commits or rolls back with the balance change.
+///
+/// Changes from `Origin::Operator` are rejected with
+/// `VaultError::VaultNotActive` unless the vault is `active`. Chain-derived
+/// changes always apply, and are logged when they hit a vault that isn't.
+///
+/// Every update bumps `version`. With `expected_version` set the update is a
+/// compare-and-swap and fails with `VaultError::VersionConflict` if another
+/// writer got there first. Returns the new version.
+async fn mutate_balances(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    entry_type: &str,
+    amount: i64,
+    delta: BalanceDelta,
+    synced_at: Option<NaiveDateTime>,
+    expected_version: Option<i64>,
+) -> VaultResult<i64> {
+    let entry = LedgerEntry {
+        entry_type,
+        amount,
+        adjustment: None,
+        origin: Origin::Chain,
+    };
+
+    record_mutation(conn, vault_pda, entry, delta, synced_at, expected_version).await
+}
+
+/// `mutate_balances` with a full ledger entry and origin.
+#[tracing::instrument(level = "debug", skip_all, fields(vault = vault_pda, operation = entry.entry_type, amount = entry.amount))]
+async fn record_mutation(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    entry: LedgerEntry<'_>,
+    delta: BalanceDelta,
+    synced_at: Option<NaiveDateTime>,
+    expected_version: Option<i64>,
+) -> VaultResult<i64> {
+    let after = sqlx::query_as::<_, Balances>(
+        r#"
+        UPDATE vaults
+        SET
+            total_balance     = total_balance + $2,
+            available_balance = available_balance + $3,
+            locked_balance    = locked_balance + $4,
+            total_deposited   = total_deposited + $5,
+            total_withdrawn   = total_withdrawn + $6,
+            last_synced_at    = COALESCE($7, now()),
+            version           = version + 1
+        WHERE vault_pda = $1
+          AND total_balance + $2 >= 0
+          AND available_balance + $3 >= 0
+          AND locked_balance + $4 >= 0
+          AND ($8::bigint IS NULL OR version = $8)
+          AND (NOT $9 OR status = 'active')
+        RETURNING total_balance, available_balance, locked_balance, version, status
+        "#,
+    )
+    .bind(vault_pda)
+    .bind(delta.total)
+    .bind(delta.available)
+    .bind(delta.locked)
+    .bind(delta.deposited)
+    .bind(delta.withdrawn)
+    .bind(synced_at)
+    .bind(expected_version)
+    .bind(entry.origin == Origin::Operator)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let after = match after {
+        Some(after) => after,
+        None => {
+            return Err(rejected_update(conn, vault_pda, &entry, &delta, expected_version).await)
+        }
+    };
+
+    if after.status != VaultStatus::Active.as_str() {
+        tracing::warn!(
+            "applied on-chain {} of {} to {} vault {}",
+            entry.entry_type,
+            entry.amount,
+            after.status,
+            vault_pda
+        );
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO balance_ledger (
+            vault_pda,
+            entry_type,
             amount,
+            total_before,
+            total_after,
+            available_before,
+            available_after,
+            locked_before,
+            locked_after,
+            reason,
+            operator,
+            discrepancy_id
         )
-        .execute(&mut *tx)
-        .await?;
+        VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12)
+        "#,
+    )
+    .bind(vault_pda)
+    .bind(entry.entry_type)
+    .bind(entry.amount)
+    .bind(after.total_balance - delta.total)
+    .bind(after.total_balance)
+    .bind(after.available_balance - delta.available)
+    .bind(after.available_balance)
+    .bind(after.locked_balance - delta.locked)
+    .bind(after.locked_balance)
+    .bind(entry.adjustment.map(|a| a.reason))
+    .bind(entry.adjustment.map(|a| a.operator))
+    .bind(entry.adjustment.map(|a| a.discrepancy_id))
+    .execute(&mut *conn)
+    .await?;
 
-        tx.commit().await?;
+    Ok(after.version)
+}
 
-        Ok(())
+/// Explain why a guarded balance update matched no row.
+async fn rejected_update(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    entry: &LedgerEntry<'_>,
+    delta: &BalanceDelta,
+    expected_version: Option<i64>,
+) -> VaultError {
+    let current = sqlx::query_as::<_, Balances>(
+        r#"
+        SELECT total_balance, available_balance, locked_balance, version, status
+        FROM vaults
+        WHERE vault_pda = $1
+        "#,
+    )
+    .bind(vault_pda)
+    .fetch_optional(&mut *conn)
+    .await;
+
+    let current = match current {
+        Ok(Some(current)) => current,
+        Ok(None) => {
+            return VaultError::AccountNotFound {
+                account: vault_pda.to_string(),
+            }
+        }
+        Err(e) => return e.into(),
+    };
+
+    if entry.origin == Origin::Operator && current.status != VaultStatus::Active.as_str() {
+        return VaultError::VaultNotActive {
+            vault: vault_pda.to_string(),
+            status: current.status,
+        };
+    }
+
+    if let Some(expected) = expected_version.filter(|v| *v != current.version) {
+        return VaultError::VersionConflict {
+            vault: vault_pda.to_string(),
+            expected,
+            actual: current.version,
+        };
+    }
+
+    // Report the balance that would have gone negative
+    let (required, available) = if current.locked_balance + delta.locked < 0 {
+        (-delta.locked, current.locked_balance)
+    } else if current.available_balance + delta.available < 0 {
+        (-delta.available, current.available_balance)
+    } else {
+        (-delta.total, current.total_balance)
+    };
+
+    let required = required.max(0) as u64;
+    let available = available.max(0) as u64;
+
+    Logger::log_state_mismatch(vault_pda, required, available);
+
+    VaultError::InsufficientBalance {
+        required,
+        available,
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_transitions() {
+        use VaultStatus::*;
+
+        assert!(Active.can_transition_to(Frozen));
+        assert!(Frozen.can_transition_to(Active));
+        assert!(Closing.can_transition_to(Closed));
+        assert!(Closed.can_transition_to(Closed));
+
+        assert!(!Closed.can_transition_to(Active));
+        assert!(!Frozen.can_transition_to(Closed));
+    }
+
+    #[test]
+    fn test_status_round_trip() {
+        use VaultStatus::*;
+
+        for status in [Active, Closing, Closed, Frozen] {
+            assert_eq!(status.as_str().parse::<VaultStatus>().unwrap(), status);
+        }
+
+        assert!("open".parse::<VaultStatus>().is_err());
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres DATABASE_URL"]
+    async fn test_deposit_is_indexed_into_frozen_vault(pool: PgPool) {
+        let mut conn = pool.acquire().await.unwrap();
+        let new_vault = NewVault {
+            vault_pda: "vault",
+            owner_pubkey: "owner",
+            mint: "mint",
+            vault_token_account: "token_account",
+            program_id: "program",
+            network: "localnet",
+            timestamp: 1_700_000_000,
+        };
+        insert_new_vault(&mut conn, &new_vault).await.unwrap();
+        set_status(&mut conn, "vault", VaultStatus::Frozen)
+            .await
+            .unwrap();
+
+        set_balance_from_event(&mut conn, "vault", 500, 1_700_000_100)
+            .await
+            .unwrap();
+
+        let vault = get_vault(&mut conn, "vault").await.unwrap().unwrap();
+        assert_eq!(vault.total_balance, 500);
+        assert_eq!(vault.available_balance, 500);
+        assert_eq!(vault.status, "frozen");
+
+        // Operator corrections still need an active vault
+        let err = VaultRepository::new(&pool)
+            .set_balances("vault", vault.version, 0, 0, 0)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, VaultError::VaultNotActive { .. }));
+    }
+}
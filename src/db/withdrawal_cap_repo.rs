@@ -0,0 +1,223 @@
+use chrono::NaiveDateTime;
+use sqlx::{PgConnection, PgPool, Row};
+
+/// What a withdrawal cap applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapScope {
+    Vault,
+    /// Every vault of the mint without a cap of its own.
+    Mint,
+}
+
+impl CapScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CapScope::Vault => "vault",
+            CapScope::Mint => "mint",
+        }
+    }
+}
+
+impl std::str::FromStr for CapScope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "vault" => Ok(CapScope::Vault),
+            "mint" => Ok(CapScope::Mint),
+            other => anyhow::bail!("unknown cap scope '{}' (vault or mint)", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WithdrawalCapRow {
+    /// One of the `CapScope` names.
+    pub scope: String,
+    pub target: String,
+    pub daily_cap: i64,
+    pub updated_by: String,
+    pub updated_at: NaiveDateTime,
+}
+
+/// A vault's effective daily cap and what it withdrew in the last 24 hours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DailyCapStatus {
+    pub daily_cap: i64,
+    pub withdrawn: i64,
+}
+
+impl DailyCapStatus {
+    pub fn allows(&self, amount: i64) -> bool {
+        self.withdrawn.saturating_add(amount) <= self.daily_cap
+    }
+}
+
+pub struct WithdrawalCapRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> WithdrawalCapRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn set_cap(
+        &self,
+        scope: CapScope,
+        target: &str,
+        daily_cap: i64,
+        updated_by: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO withdrawal_caps (scope, target, daily_cap, updated_by)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (scope, target) DO UPDATE
+            SET daily_cap = EXCLUDED.daily_cap,
+                updated_by = EXCLUDED.updated_by,
+                updated_at = now()
+            "#,
+        )
+        .bind(scope.as_str())
+        .bind(target)
+        .bind(daily_cap)
+        .bind(updated_by)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// `false` if there was no such cap.
+    pub async fn remove_cap(&self, scope: CapScope, target: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query("DELETE FROM withdrawal_caps WHERE scope = $1 AND target = $2")
+            .bind(scope.as_str())
+            .bind(target)
+            .execute(self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list(&self) -> anyhow::Result<Vec<WithdrawalCapRow>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT scope, target, daily_cap, updated_by, updated_at
+            FROM withdrawal_caps
+            ORDER BY scope, target
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| WithdrawalCapRow {
+                scope: row.get("scope"),
+                target: row.get("target"),
+                daily_cap: row.get("daily_cap"),
+                updated_by: row.get("updated_by"),
+                updated_at: row.get("updated_at"),
+            })
+            .collect())
+    }
+
+    pub async fn daily_cap_status(
+        &self,
+        vault_pda: &str,
+        mint: &str,
+        at: NaiveDateTime,
+    ) -> anyhow::Result<Option<DailyCapStatus>> {
+        let mut conn = self.pool.acquire().await?;
+
+        daily_cap_status(&mut conn, vault_pda, mint, at).await
+    }
+}
+
+// Connection-level variants used by the indexer inside a database transaction.
+
+/// The cap of `vault_pda` (or else of `mint`) and the vault's indexed
+/// withdrawals in the 24 hours up to `at`. `None` when neither has a cap.
+pub async fn daily_cap_status(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    mint: &str,
+    at: NaiveDateTime,
+) -> anyhow::Result<Option<DailyCapStatus>> {
+    let row = sqlx::query(
+        r#"
+        SELECT
+            c.daily_cap,
+            (
+                SELECT COALESCE(SUM(amount), 0)::BIGINT
+                FROM transactions
+                WHERE vault_pda = $1
+                  AND tx_type = 'withdraw'
+                  AND block_time > $3 - INTERVAL '1 day'
+                  AND block_time <= $3
+            ) AS withdrawn
+        FROM withdrawal_caps c
+        WHERE (c.scope = 'vault' AND c.target = $1)
+           OR (c.scope = 'mint' AND c.target = $2)
+        ORDER BY c.scope = 'vault' DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(vault_pda)
+    .bind(mint)
+    .bind(at)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(row.map(|row| DailyCapStatus {
+        daily_cap: row.get("daily_cap"),
+        withdrawn: row.get("withdrawn"),
+    }))
+}
+
+/// Record an indexed withdrawal that went over its vault's cap. Recording
+/// the same one twice is a no-op.
+pub async fn record_breach(
+    conn: &mut PgConnection,
+    tx_signature: &str,
+    vault_pda: &str,
+    amount: i64,
+    status: &DailyCapStatus,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO withdrawal_cap_breaches
+            (tx_signature, vault_pda, amount, daily_volume, daily_cap)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (tx_signature, vault_pda) DO NOTHING
+        "#,
+    )
+    .bind(tx_signature)
+    .bind(vault_pda)
+    .bind(amount)
+    .bind(status.withdrawn)
+    .bind(status.daily_cap)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_daily_cap_status_allows() {
+        let status = DailyCapStatus {
+            daily_cap: 1_000,
+            withdrawn: 600,
+        };
+
+        assert!(status.allows(400));
+        assert!(!status.allows(401));
+        assert_eq!("mint".parse::<CapScope>().unwrap(), CapScope::Mint);
+        assert!("user".parse::<CapScope>().is_err());
+    }
+}
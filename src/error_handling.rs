@@ -1,6 +1,10 @@
-use std::time::Duration;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 use anyhow::Result;
-use tracing::warn;
+use solana_client::client_error::ClientError;
+use tracing::{info, warn, Instrument};
+use uuid::Uuid;
 
 // Config for retry logic - controls how many times we retry and how long we wait
 #[derive(Clone, Debug)]
@@ -9,6 +13,9 @@ pub struct RetryConfig {
     pub initial_delay_ms: u64,
     pub max_delay_ms: u64,
     pub backoff_multiplier: f64,
+    pub jitter: Jitter,
+    pub budget: Option<Arc<RetryBudget>>, // shared cap on retries against the dependency, if any
+    pub deadline: Option<Deadline>, // the caller's deadline, which no attempt or wait may run past
 }
 
 impl Default for RetryConfig {
@@ -18,63 +25,447 @@ impl Default for RetryConfig {
             initial_delay_ms: 100,
             max_delay_ms: 5000,
             backoff_multiplier: 2.0,
+            jitter: Jitter::Full,
+            budget: None,
+            deadline: None,
         }
     }
 }
-// Error types specific to vault operations
+
+impl RetryConfig {
+    // Draw retries from the budget shared by every caller of `dependency`
+    pub fn with_budget(mut self, dependency: &str) -> Self {
+        self.budget = Some(RetryBudget::for_dependency(dependency));
+        self
+    }
+
+    // Stop retrying when the next wait would end past `deadline`
+    pub fn with_deadline(mut self, deadline: Deadline) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+}
+
+static RETRY_BUDGETS: OnceLock<Mutex<HashMap<String, Arc<RetryBudget>>>> = OnceLock::new();
+
+// Token bucket bounding how many retries one dependency (an RPC endpoint, the
+// database) gets: every operation adds `ratio` tokens, up to `max_tokens`, and
+// every retry takes one. While the dependency keeps failing, only about `ratio`
+// of operations retry and the rest fail fast instead of piling on more load
 #[derive(Debug)]
+pub struct RetryBudget {
+    dependency: String,
+    ratio: f64,
+    max_tokens: f64,
+    tokens: Mutex<f64>,
+}
+
+impl RetryBudget {
+    pub const DEFAULT_RATIO: f64 = 0.1;
+    pub const DEFAULT_MAX_TOKENS: f64 = 10.0;
+
+    // A budget of its own, starting full
+    pub fn new(dependency: &str, ratio: f64, max_tokens: f64) -> Self {
+        Self {
+            dependency: dependency.to_string(),
+            ratio,
+            max_tokens,
+            tokens: Mutex::new(max_tokens),
+        }
+    }
+
+    // The process-wide budget for `dependency`, created with the defaults on first use
+    pub fn for_dependency(dependency: &str) -> Arc<RetryBudget> {
+        let budgets = RETRY_BUDGETS.get_or_init(Default::default);
+        let mut budgets = budgets.lock().unwrap_or_else(|e| e.into_inner());
+
+        budgets
+            .entry(dependency.to_string())
+            .or_insert_with(|| {
+                Arc::new(Self::new(
+                    dependency,
+                    Self::DEFAULT_RATIO,
+                    Self::DEFAULT_MAX_TOKENS,
+                ))
+            })
+            .clone()
+    }
+
+    pub fn dependency(&self) -> &str {
+        &self.dependency
+    }
+
+    pub fn tokens(&self) -> f64 {
+        *self.tokens.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Credit an operation's first attempt
+    pub fn deposit(&self) {
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        *tokens = (*tokens + self.ratio).min(self.max_tokens);
+    }
+
+    // Take a token for one retry, false if none is left
+    pub fn try_withdraw(&self) -> bool {
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+}
+
+// How much of each backoff delay is randomized
+// Without jitter, callers that failed together retry together and hit the RPC
+// in waves; spreading the waits out breaks those waves up
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Jitter {
+    // Wait exactly the backoff delay
+    None,
+    // Wait anywhere between 0 and the backoff delay
+    #[default]
+    Full,
+    // Wait half the backoff delay plus up to the other half
+    Equal,
+}
+
+impl Jitter {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Jitter::None => "none",
+            Jitter::Full => "full",
+            Jitter::Equal => "equal",
+        }
+    }
+
+    // The time to actually wait for a backoff delay of `delay_ms`
+    pub fn apply(self, delay_ms: u64) -> u64 {
+        self.scale(delay_ms, random_fraction())
+    }
+
+    // `apply` with the random draw `random` in [0, 1) passed in
+    fn scale(self, delay_ms: u64, random: f64) -> u64 {
+        match self {
+            Jitter::None => delay_ms,
+            Jitter::Full => (delay_ms as f64 * random) as u64,
+            Jitter::Equal => delay_ms / 2 + ((delay_ms - delay_ms / 2) as f64 * random) as u64,
+        }
+    }
+}
+
+impl std::str::FromStr for Jitter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "none" => Ok(Jitter::None),
+            "full" => Ok(Jitter::Full),
+            "equal" => Ok(Jitter::Equal),
+            other => anyhow::bail!("unknown jitter '{}', expected none, full or equal", other),
+        }
+    }
+}
+
+// Uniform in [0, 1), from the 53 low bits of a v4 UUID (all random), like API keys
+fn random_fraction() -> f64 {
+    let bits = Uuid::new_v4().as_u128() as u64 & ((1 << 53) - 1);
+    bits as f64 / (1u64 << 53) as f64
+}
+// Error types specific to vault operations
+// Failures from the database, RPC and account decoding keep the underlying error
+// as their source, so `{:#}` on an anyhow::Error built from one prints the whole chain
+#[derive(Debug, thiserror::Error)]
 pub enum VaultError {
+    #[error("Not enough balance: need {required} but only have {available}")]
     InsufficientBalance { required: u64, available: u64 },
+    #[error("User {user} doesn't have access to vault {vault}")]
     UnauthorizedAccess { user: String, vault: String },
+    #[error("Transaction failed: {reason}")]
     TransactionFailed { reason: String },
+    #[error("Can't connect to RPC at {endpoint}")]
     RpcConnectionError { endpoint: String },
+    #[error("Account {account} doesn't exist")]
     AccountNotFound { account: String },
+    #[error("Invalid amount: {amount}")]
     InvalidAmount { amount: u64 },
+    #[error("State mismatch: expected {expected} but got {actual}")]
     StateMismatch { expected: String, actual: String },
+    #[error("Can't lock collateral: {reason}")]
     LockingError { reason: String },
+    #[error("Serialization error: {reason}")]
     SerializationError { reason: String },
+    #[error(
+        "Vault {vault} was modified concurrently: expected version {expected} but found {actual}"
+    )]
+    VersionConflict { vault: String, expected: i64, actual: i64 },
+    #[error("Vault {vault} is {status}, balances can't change")]
+    VaultNotActive { vault: String, status: String },
+    #[error("Vault {vault} can't go from {from} to {to}")]
+    InvalidStatusTransition { vault: String, from: String, to: String },
+    #[error("Transaction {signature} was not indexed within {waited_ms}ms")]
+    NotIndexed { signature: String, waited_ms: u128 },
+    // Retryable: a hung connection may well answer on the next try
+    #[error("{operation} timed out after {after_ms}ms")]
+    Timeout { operation: &'static str, after_ms: u128 },
+    // sqlx errors convert through `db::error`, which picks the variants below when they apply
+    #[error("Database query failed")]
+    Database(#[source] sqlx::Error),
+    #[error("Conflicts with an existing row ({constraint})")]
+    UniqueViolation {
+        constraint: String,
+        #[source]
+        source: sqlx::Error,
+    },
+    #[error("Refers to a row that doesn't exist or is still referenced ({constraint})")]
+    ForeignKeyViolation {
+        constraint: String,
+        #[source]
+        source: sqlx::Error,
+    },
+    // Retryable: the transaction lost to a concurrent one and can run again as is
+    #[error("Database transaction conflicted with a concurrent one")]
+    SerializationFailure(#[source] sqlx::Error),
+    // Retryable: every connection was busy for the pool's acquire timeout
+    #[error("No database connection available")]
+    PoolTimeout,
+    // Boxed: ClientError is large and would bloat every VaultResult
+    #[error("RPC request failed")]
+    Rpc(#[source] Box<ClientError>),
+    // Borsh reports malformed account data as an io::Error
+    #[error("Can't decode account data")]
+    Decode(#[from] borsh::io::Error),
+    // Anything without a variant of its own, e.g. from helpers that return anyhow::Error
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+    // Another error, tagged with the operation, vault and user it happened for
+    #[error("{context}")]
+    InContext {
+        context: VaultContext,
+        #[source]
+        source: Box<VaultError>,
+    },
+}
+
+impl VaultError {
+    // The error itself, under any context attached to it
+    pub fn root(&self) -> &VaultError {
+        match self {
+            VaultError::InContext { source, .. } => source.root(),
+            other => other,
+        }
+    }
+
+    // The outermost context attached with `with_vault_context`, if any
+    pub fn context(&self) -> Option<&VaultContext> {
+        match self {
+            VaultError::InContext { context, .. } => Some(context),
+            _ => None,
+        }
+    }
 }
 
-impl std::fmt::Display for VaultError {
+impl From<ClientError> for VaultError {
+    fn from(error: ClientError) -> Self {
+        VaultError::Rpc(Box::new(error))
+    }
+}
+
+pub type VaultResult<T> = std::result::Result<T, VaultError>;
+
+// What an error was about, kept as separate fields rather than folded into the
+// message so logs can be searched by vault or user
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VaultContext {
+    pub operation: &'static str,
+    pub vault: String,
+    pub user: String,
+}
+
+impl std::fmt::Display for VaultContext {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} on vault {} for user {}",
+            self.operation, self.vault, self.user
+        )
+    }
+}
+
+// Attach a VaultContext to any error that converts into a VaultError
+pub trait VaultContextExt<T> {
+    fn with_vault_context(self, operation: &'static str, vault: &str, user: &str)
+        -> VaultResult<T>;
+}
+
+impl<T, E: Into<VaultError>> VaultContextExt<T> for std::result::Result<T, E> {
+    fn with_vault_context(
+        self,
+        operation: &'static str,
+        vault: &str,
+        user: &str,
+    ) -> VaultResult<T> {
+        self.map_err(|e| VaultError::InContext {
+            context: VaultContext {
+                operation,
+                vault: vault.to_string(),
+                user: user.to_string(),
+            },
+            source: Box::new(e.into()),
+        })
+    }
+}
+
+// When the caller (usually an API request) stops waiting for a result
+// Passed down through every layer, so nested timeouts and retries share the caller's
+// time budget instead of each starting a fresh one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    pub fn after(budget: Duration) -> Self {
+        Self(Instant::now() + budget)
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.remaining().is_zero()
+    }
+
+    // `limit`, cut short to the time that is left
+    pub fn cap(&self, limit: Duration) -> Duration {
+        limit.min(self.remaining())
+    }
+
+    // Run `fut` until the deadline
+    pub async fn run<F>(&self, fut: F) -> VaultResult<F::Output>
+    where
+        F: std::future::Future,
+    {
+        timeout_as("deadline", self.remaining(), fut).await
+    }
+}
+
+// Kinds of operation with a time limit of their own in `TimeoutPolicy`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OperationClass {
+    RpcRead,
+    RpcSend,
+    DbQuery,
+}
+
+impl OperationClass {
+    pub fn as_str(&self) -> &'static str {
         match self {
-            VaultError::InsufficientBalance { required, available } => {
-                write!(f, "Not enough balance: need {} but only have {}", required, available)
-            }
-            VaultError::UnauthorizedAccess { user, vault } => {
-                write!(f, "User {} doesn't have access to vault {}", user, vault)
-            }
-            VaultError::TransactionFailed { reason } => {
-                write!(f, "Transaction failed: {}", reason)
-            }
-            VaultError::RpcConnectionError { endpoint } => {
-                write!(f, "Can't connect to RPC at {}", endpoint)
-            }
-            VaultError::AccountNotFound { account } => {
-                write!(f, "Account {} doesn't exist", account)
-            }
-            VaultError::InvalidAmount { amount } => {
-                write!(f, "Invalid amount: {}", amount)
-            }
-            VaultError::StateMismatch { expected, actual } => {
-                write!(f, "State mismatch: expected {} but got {}", expected, actual)
-            }
-            VaultError::LockingError { reason } => {
-                write!(f, "Can't lock collateral: {}", reason)
-            }
-            VaultError::SerializationError { reason } => {
-                write!(f, "Serialization error: {}", reason)
-            }
+            OperationClass::RpcRead => "rpc_read",
+            OperationClass::RpcSend => "rpc_send",
+            OperationClass::DbQuery => "db_query",
         }
     }
+
+    // Span an operation of this class runs in, so traces show each RPC call
+    // and query under the request that made it
+    pub fn span(&self) -> tracing::Span {
+        tracing::info_span!("operation", otel.name = self.as_str(), otel.kind = "client")
+    }
 }
 
-impl std::error::Error for VaultError {}
+// How long each class of operation may take before it fails with `VaultError::Timeout`
+#[derive(Clone, Debug)]
+pub struct TimeoutPolicy {
+    pub rpc_read: Duration,
+    pub rpc_send: Duration, // longer: sending waits for confirmation
+    pub db_query: Duration,
+}
+
+impl Default for TimeoutPolicy {
+    fn default() -> Self {
+        Self {
+            rpc_read: Duration::from_secs(10),
+            rpc_send: Duration::from_secs(30),
+            db_query: Duration::from_secs(10),
+        }
+    }
+}
+
+impl TimeoutPolicy {
+    pub fn limit(&self, class: OperationClass) -> Duration {
+        match class {
+            OperationClass::RpcRead => self.rpc_read,
+            OperationClass::RpcSend => self.rpc_send,
+            OperationClass::DbQuery => self.db_query,
+        }
+    }
+
+    // Run `fut` under the limit for `class`
+    pub async fn run<T, F>(&self, class: OperationClass, fut: F) -> VaultResult<T>
+    where
+        F: std::future::Future<Output = VaultResult<T>>,
+    {
+        timeout_as(class.as_str(), self.limit(class), fut)
+            .instrument(class.span())
+            .await?
+    }
+
+    // `run`, giving up at `deadline` if that comes before the class limit
+    pub async fn run_within<T, F>(
+        &self,
+        class: OperationClass,
+        deadline: Deadline,
+        fut: F,
+    ) -> VaultResult<T>
+    where
+        F: std::future::Future<Output = VaultResult<T>>,
+    {
+        timeout_as(class.as_str(), deadline.cap(self.limit(class)), fut)
+            .instrument(class.span())
+            .await?
+    }
+}
+
+// Give up on `fut` after `duration` with a `VaultError::Timeout`
+pub async fn with_timeout<F>(duration: Duration, fut: F) -> VaultResult<F::Output>
+where
+    F: std::future::Future,
+{
+    timeout_as("operation", duration, fut).await
+}
+
+async fn timeout_as<F>(
+    operation: &'static str,
+    duration: Duration,
+    fut: F,
+) -> VaultResult<F::Output>
+where
+    F: std::future::Future,
+{
+    tokio::time::timeout(duration, fut)
+        .await
+        .map_err(|_| VaultError::Timeout {
+            operation,
+            after_ms: duration.as_millis(),
+        })
+}
 
 // Check if an error is worth retrying
 // Network errors should be retried, but permission errors should not
+// The whole source chain is checked, since a VaultError wrapping an RPC or
+// database failure only says which of the two failed
 pub fn is_retryable_error(error: &anyhow::Error) -> bool {
-    let error_msg = error.to_string().to_lowercase();
+    if let Some(VaultError::Timeout { .. }) =
+        error.downcast_ref::<VaultError>().map(VaultError::root)
+    {
+        return true;
+    }
+
+    if crate::db::error::is_transient(error) {
+        return true;
+    }
+
+    let error_msg = format!("{:#}", error).to_lowercase();
 
     error_msg.contains("timeout")
         || error_msg.contains("connection")
@@ -83,42 +474,92 @@ pub fn is_retryable_error(error: &anyhow::Error) -> bool {
         || error_msg.contains("rate limit")
 }
 
+// What a retried operation went through, returned whether it succeeded or not
+// so callers can log or record retries that a plain Ok would hide
+#[derive(Debug)]
+pub struct RetryOutcome<T> {
+    pub result: Result<T>,
+    pub attempts: u32,
+    pub total_delay: Duration,   // time spent waiting between attempts
+    pub errors: Vec<String>,     // the error each failed attempt ended with, oldest first
+    pub budget_exhausted: bool,  // gave up early because the retry budget was spent
+    pub deadline_exceeded: bool, // gave up early because the deadline left no time to retry
+}
+
+impl<T> RetryOutcome<T> {
+    // More than one attempt was needed (or made)
+    pub fn retried(&self) -> bool {
+        self.attempts > 1
+    }
+
+    pub fn into_result(self) -> Result<T> {
+        self.result
+    }
+}
+
 // Retry an async operation with exponential backoff
-// Waits longer between each attempt
+// Waits longer between each attempt, randomized by `config.jitter`
 pub async fn retry_with_backoff<F, Fut, T>(
     config: RetryConfig,
     mut f: F,
-) -> Result<T>
+) -> RetryOutcome<T>
 where
     F: FnMut() -> Fut,
     Fut: std::future::Future<Output = Result<T>>,
 {
     let mut attempt = 0;
     let mut delay = config.initial_delay_ms;
+    let mut total_delay = Duration::ZERO;
+    let mut errors = Vec::new();
+
+    if let Some(budget) = &config.budget {
+        budget.deposit();
+    }
 
     loop {
         attempt += 1;
 
-        match f().await {
-            Ok(result) => return Ok(result),
-            Err(e) => {
-                if attempt >= config.max_attempts || !is_retryable_error(&e) {
-                    return Err(e);
-                }
+        // An attempt still running at the deadline is abandoned
+        let attempt_result = match config.deadline {
+            Some(deadline) => deadline.run(f()).await.unwrap_or_else(|e| Err(e.into())),
+            None => f().await,
+        };
+        let e = match attempt_result {
+            Ok(value) => return finish(Ok(value), attempt, total_delay, errors),
+            Err(e) => e,
+        };
+        errors.push(format!("{:#}", e));
 
-                warn!(
-                    "Attempt {} failed: {}. Waiting {}ms before retry...",
-                    attempt,
-                    e,
-                    delay
-                );
+        if attempt >= config.max_attempts || !is_retryable_error(&e) {
+            return finish(Err(e), attempt, total_delay, errors);
+        }
 
-                tokio::time::sleep(Duration::from_millis(delay)).await;
+        let wait = config.jitter.apply(delay);
 
-                delay = ((delay as f64) * config.backoff_multiplier) as u64;
-                delay = delay.min(config.max_delay_ms);
-            }
+        if !deadline_allows_retry(&config, wait) {
+            let mut outcome = finish(Err(e), attempt, total_delay, errors);
+            outcome.deadline_exceeded = true;
+            return outcome;
         }
+
+        if !budget_allows_retry(&config) {
+            let mut outcome = finish(Err(e), attempt, total_delay, errors);
+            outcome.budget_exhausted = true;
+            return outcome;
+        }
+
+        warn!(
+            "Attempt {} failed: {}. Waiting {}ms before retry...",
+            attempt,
+            e,
+            wait
+        );
+
+        tokio::time::sleep(Duration::from_millis(wait)).await;
+        total_delay += Duration::from_millis(wait);
+
+        delay = ((delay as f64) * config.backoff_multiplier) as u64;
+        delay = delay.min(config.max_delay_ms);
     }
 }
 
@@ -126,36 +567,108 @@ where
 pub fn retry_sync<F, T>(
     config: RetryConfig,
     mut f: F,
-) -> Result<T>
+) -> RetryOutcome<T>
 where
     F: FnMut() -> Result<T>,
 {
     let mut attempt = 0;
     let mut delay = config.initial_delay_ms;
+    let mut total_delay = Duration::ZERO;
+    let mut errors = Vec::new();
+
+    if let Some(budget) = &config.budget {
+        budget.deposit();
+    }
 
     loop {
         attempt += 1;
 
-        match f() {
-            Ok(result) => return Ok(result),
-            Err(e) => {
-                if attempt >= config.max_attempts || !is_retryable_error(&e) {
-                    return Err(e);
-                }
+        let e = match f() {
+            Ok(value) => return finish(Ok(value), attempt, total_delay, errors),
+            Err(e) => e,
+        };
+        errors.push(format!("{:#}", e));
 
-                warn!(
-                    "Attempt {} failed: {}. Waiting {}ms before retry...",
-                    attempt,
-                    e,
-                    delay
-                );
+        if attempt >= config.max_attempts || !is_retryable_error(&e) {
+            return finish(Err(e), attempt, total_delay, errors);
+        }
 
-                std::thread::sleep(Duration::from_millis(delay));
+        let wait = config.jitter.apply(delay);
 
-                delay = ((delay as f64) * config.backoff_multiplier) as u64;
-                delay = delay.min(config.max_delay_ms);
-            }
+        if !deadline_allows_retry(&config, wait) {
+            let mut outcome = finish(Err(e), attempt, total_delay, errors);
+            outcome.deadline_exceeded = true;
+            return outcome;
         }
+
+        if !budget_allows_retry(&config) {
+            let mut outcome = finish(Err(e), attempt, total_delay, errors);
+            outcome.budget_exhausted = true;
+            return outcome;
+        }
+
+        warn!(
+            "Attempt {} failed: {}. Waiting {}ms before retry...",
+            attempt,
+            e,
+            wait
+        );
+
+        std::thread::sleep(Duration::from_millis(wait));
+        total_delay += Duration::from_millis(wait);
+
+        delay = ((delay as f64) * config.backoff_multiplier) as u64;
+        delay = delay.min(config.max_delay_ms);
+    }
+}
+
+// Wrap up a retry loop, noting operations that only succeeded after retrying
+fn finish<T>(
+    result: Result<T>,
+    attempts: u32,
+    total_delay: Duration,
+    errors: Vec<String>,
+) -> RetryOutcome<T> {
+    if result.is_ok() && attempts > 1 {
+        info!(
+            "Succeeded after {} attempts and {}ms of backoff",
+            attempts,
+            total_delay.as_millis()
+        );
+    }
+
+    RetryOutcome {
+        result,
+        attempts,
+        total_delay,
+        errors,
+        budget_exhausted: false,
+        deadline_exceeded: false,
+    }
+}
+
+// Whether waiting `wait_ms` for another attempt still ends before `config.deadline`
+fn deadline_allows_retry(config: &RetryConfig, wait_ms: u64) -> bool {
+    match config.deadline {
+        Some(deadline) if deadline.remaining() <= Duration::from_millis(wait_ms) => {
+            warn!("No time left before the deadline, failing without retrying");
+            false
+        }
+        _ => true,
+    }
+}
+
+// Take a token for the next retry from `config.budget`, if there is one
+fn budget_allows_retry(config: &RetryConfig) -> bool {
+    match &config.budget {
+        Some(budget) if !budget.try_withdraw() => {
+            warn!(
+                "Retry budget for {} is spent, failing without retrying",
+                budget.dependency()
+            );
+            false
+        }
+        _ => true,
     }
 }
 
@@ -171,6 +684,150 @@ mod tests {
         assert_eq!(config.max_delay_ms, 5000);
     }
 
+    #[test]
+    fn test_jitter_scales_delay() {
+        assert_eq!(Jitter::None.scale(1000, 0.25), 1000);
+        assert_eq!(Jitter::Full.scale(1000, 0.0), 0);
+        assert_eq!(Jitter::Full.scale(1000, 0.25), 250);
+        assert_eq!(Jitter::Equal.scale(1000, 0.0), 500);
+        assert_eq!(Jitter::Equal.scale(1000, 0.5), 750);
+        assert_eq!(Jitter::Equal.scale(1001, 0.999_999), 1000);
+    }
+
+    #[test]
+    fn test_jitter_stays_within_delay() {
+        for _ in 0..100 {
+            assert!(Jitter::Full.apply(400) < 400);
+            assert!((200..400).contains(&Jitter::Equal.apply(400)));
+        }
+
+        for jitter in [Jitter::None, Jitter::Full, Jitter::Equal] {
+            assert_eq!(jitter.as_str().parse::<Jitter>().unwrap(), jitter);
+        }
+    }
+
+    fn fast_retries() -> RetryConfig {
+        RetryConfig {
+            initial_delay_ms: 1,
+            jitter: Jitter::None,
+            ..RetryConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_outcome_after_recovery() {
+        let mut calls = 0;
+        let outcome = retry_with_backoff(fast_retries(), || {
+            calls += 1;
+            let call = calls;
+            async move {
+                if call < 3 {
+                    anyhow::bail!("request timeout on call {}", call);
+                }
+                Ok(call)
+            }
+        })
+        .await;
+
+        assert!(outcome.retried());
+        assert_eq!(outcome.attempts, 3);
+        assert_eq!(outcome.total_delay, Duration::from_millis(3));
+        assert_eq!(
+            outcome.errors,
+            vec!["request timeout on call 1", "request timeout on call 2"]
+        );
+        assert_eq!(outcome.into_result().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_retry_outcome_on_permanent_failure() {
+        let outcome: RetryOutcome<()> =
+            retry_sync(fast_retries(), || Err(anyhow::anyhow!("Invalid account")));
+
+        assert!(!outcome.retried());
+        assert_eq!(outcome.total_delay, Duration::ZERO);
+        assert_eq!(outcome.errors, vec!["Invalid account"]);
+        assert!(outcome.result.is_err());
+    }
+
+    #[test]
+    fn test_retry_budget_refills_per_operation() {
+        let budget = RetryBudget::new("rpc", 0.5, 2.0);
+
+        assert!(budget.try_withdraw());
+        assert!(budget.try_withdraw());
+        assert!(!budget.try_withdraw());
+
+        budget.deposit();
+        assert!(!budget.try_withdraw());
+        budget.deposit();
+        assert!(budget.try_withdraw());
+
+        for _ in 0..10 {
+            budget.deposit();
+        }
+        assert_eq!(budget.tokens(), 2.0);
+    }
+
+    #[test]
+    fn test_spent_budget_fails_fast() {
+        let config = RetryConfig {
+            max_attempts: 2,
+            budget: Some(Arc::new(RetryBudget::new("db", 0.1, 1.0))),
+            ..fast_retries()
+        };
+
+        let first: RetryOutcome<()> = retry_sync(config.clone(), || anyhow::bail!("timeout"));
+        assert_eq!(first.attempts, 2);
+        assert!(!first.budget_exhausted);
+
+        let second: RetryOutcome<()> = retry_sync(config, || anyhow::bail!("timeout"));
+        assert_eq!(second.attempts, 1);
+        assert!(second.budget_exhausted);
+    }
+
+    #[test]
+    fn test_budgets_are_shared_per_dependency() {
+        let a = RetryConfig::default().with_budget("https://rpc.example");
+        let b = RetryConfig::default().with_budget("https://rpc.example");
+        let c = RetryConfig::default().with_budget("postgres");
+
+        let budget = |config: &RetryConfig| config.budget.clone().unwrap();
+        assert!(Arc::ptr_eq(&budget(&a), &budget(&b)));
+        assert!(!Arc::ptr_eq(&budget(&a), &budget(&c)));
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_at_the_deadline() {
+        let config = RetryConfig {
+            initial_delay_ms: 50,
+            jitter: Jitter::None,
+            ..RetryConfig::default()
+        }
+        .with_deadline(Deadline::after(Duration::from_millis(20)));
+
+        let outcome: RetryOutcome<()> =
+            retry_with_backoff(config, || async { anyhow::bail!("connection reset") }).await;
+
+        assert_eq!(outcome.attempts, 1);
+        assert!(outcome.deadline_exceeded);
+        assert_eq!(outcome.total_delay, Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_deadline_bounds_timeouts() {
+        let deadline = Deadline::after(Duration::from_millis(5));
+        assert!(deadline.cap(Duration::from_secs(10)) <= Duration::from_millis(5));
+
+        let hung = std::future::pending::<VaultResult<()>>();
+        let err = TimeoutPolicy::default()
+            .run_within(OperationClass::RpcRead, deadline, hung)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("rpc_read timed out"));
+        assert!(deadline.is_expired());
+    }
+
     #[test]
     fn test_insufficient_balance_error() {
         let err = VaultError::InsufficientBalance {
@@ -193,6 +850,77 @@ mod tests {
         assert!(msg.contains("vault1"));
     }
 
+    #[test]
+    fn test_source_is_chained() {
+        use std::error::Error;
+
+        let io = std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "account data too short");
+        let err = VaultError::from(io);
+        assert!(matches!(err, VaultError::Decode(_)));
+        assert_eq!(err.to_string(), "Can't decode account data");
+        assert_eq!(err.source().unwrap().to_string(), "account data too short");
+
+        let chained = format!("{:#}", anyhow::Error::from(err));
+        assert_eq!(chained, "Can't decode account data: account data too short");
+    }
+
+    #[test]
+    fn test_vault_context() {
+        let failed: VaultResult<()> = Err(VaultError::InsufficientBalance {
+            required: 10,
+            available: 4,
+        });
+        let err = failed
+            .with_vault_context("withdraw", "vault1", "user1")
+            .unwrap_err();
+
+        let context = err.context().unwrap();
+        assert_eq!(context.operation, "withdraw");
+        assert_eq!(context.vault, "vault1");
+        assert_eq!(context.user, "user1");
+        assert!(matches!(err.root(), VaultError::InsufficientBalance { .. }));
+        assert_eq!(
+            format!("{:#}", anyhow::Error::from(err)),
+            "withdraw on vault vault1 for user user1: Not enough balance: need 10 but only have 4"
+        );
+
+        let timed_out: VaultResult<()> = Err(VaultError::Timeout {
+            operation: "db_query",
+            after_ms: 5,
+        });
+        let err = timed_out
+            .with_vault_context("get balance", "vault1", "user1")
+            .unwrap_err();
+        assert!(is_retryable_error(&err.into()));
+    }
+
+    #[test]
+    fn test_is_retryable_error_checks_sources() {
+        let io = std::io::Error::new(std::io::ErrorKind::TimedOut, "operation timed out: timeout");
+        let err = anyhow::Error::from(VaultError::from(io));
+        assert!(is_retryable_error(&err));
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout() {
+        let value = with_timeout(Duration::from_secs(1), async { 7 }).await;
+        assert_eq!(value.unwrap(), 7);
+
+        let err = with_timeout(Duration::from_millis(5), std::future::pending::<()>())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, VaultError::Timeout { after_ms: 5, .. }));
+        assert!(is_retryable_error(&err.into()));
+
+        let policy = TimeoutPolicy {
+            db_query: Duration::from_millis(5),
+            ..TimeoutPolicy::default()
+        };
+        let hung = std::future::pending::<VaultResult<()>>();
+        let err = policy.run(OperationClass::DbQuery, hung).await.unwrap_err();
+        assert_eq!(err.to_string(), "db_query timed out after 5ms");
+    }
+
     #[test]
     fn test_is_retryable_error_timeout() {
         use anyhow::anyhow;
@@ -0,0 +1,201 @@
+//! Operator-defined escalation rules, evaluated as security events are
+//! recorded.
+//!
+//! A rule counts a user's recent events at or above a severity, optionally
+//! of one type, and fires once the count within its window is reached:
+//!
+//! ```text
+//! 3 medium within 600 -> high
+//! 3 high unauthorized_access_attempt within 900 -> critical
+//! 1 critical -> block
+//! ```
+//!
+//! Escalating raises the severity of the event that tripped the rule;
+//! blocking also blocks the event's user as if they had used up their failed
+//! attempts. Rules are separated by `;` in `ESCALATION_RULES`.
+
+use crate::access_control::{AlertSeverity, SecurityEventType};
+
+/// What a rule does once it fires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscalationAction {
+    /// Raise the event to at least this severity.
+    Escalate(AlertSeverity),
+    /// Block the user, and raise the event to critical.
+    Block,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EscalationRule {
+    /// Events needed within `window`, including the one being recorded.
+    pub count: usize,
+    /// Only events at or above this severity count.
+    pub min_severity: AlertSeverity,
+    /// Only events of this type count; any type if `None`.
+    pub event_type: Option<SecurityEventType>,
+    pub window: chrono::Duration,
+    pub action: EscalationAction,
+}
+
+impl EscalationRule {
+    /// Rules used unless `ESCALATION_RULES` is set: repeated unauthorized
+    /// access is critical.
+    pub fn defaults() -> Vec<Self> {
+        vec![EscalationRule {
+            count: 3,
+            min_severity: AlertSeverity::High,
+            event_type: Some(SecurityEventType::UnauthorizedAccessAttempt),
+            window: chrono::Duration::minutes(15),
+            action: EscalationAction::Escalate(AlertSeverity::Critical),
+        }]
+    }
+
+    /// Whether an event of `event_type` at `severity` counts towards this rule.
+    pub fn matches(&self, event_type: &SecurityEventType, severity: AlertSeverity) -> bool {
+        severity >= self.min_severity && self.event_type.as_ref().is_none_or(|t| t == event_type)
+    }
+
+    /// The severity an event at `severity` ends up with once this rule fires.
+    pub fn escalated(&self, severity: AlertSeverity) -> AlertSeverity {
+        match self.action {
+            EscalationAction::Escalate(to) => severity.max(to),
+            EscalationAction::Block => AlertSeverity::Critical,
+        }
+    }
+}
+
+impl std::fmt::Display for EscalationRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.count, self.min_severity.as_str())?;
+        if let Some(event_type) = &self.event_type {
+            write!(f, " {}", event_type.as_str())?;
+        }
+        if self.count > 1 {
+            write!(f, " within {}", self.window.num_seconds())?;
+        }
+        match self.action {
+            EscalationAction::Escalate(to) => write!(f, " -> {}", to.as_str()),
+            EscalationAction::Block => write!(f, " -> block"),
+        }
+    }
+}
+
+impl std::str::FromStr for EscalationRule {
+    type Err = anyhow::Error;
+
+    /// `<count> <severity> [<event type>] [within <secs>] -> <severity|block>`
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (condition, action) = s
+            .split_once("->")
+            .ok_or_else(|| anyhow::anyhow!("rule '{}' has no '->'", s.trim()))?;
+
+        let mut words = condition.split_whitespace();
+        let count: usize = words
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("rule '{}' has no count", s.trim()))?
+            .parse()
+            .map_err(|_| anyhow::anyhow!("rule '{}' has an invalid count", s.trim()))?;
+        anyhow::ensure!(count > 0, "rule '{}' must count at least 1 event", s.trim());
+
+        let min_severity = words
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("rule '{}' has no severity", s.trim()))?
+            .parse()?;
+
+        let mut event_type = None;
+        let mut window = None;
+        while let Some(word) = words.next() {
+            if word == "within" {
+                let secs: i64 = words
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .filter(|secs| *secs > 0)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("rule '{}' needs seconds after 'within'", s.trim())
+                    })?;
+                window = Some(chrono::Duration::seconds(secs));
+            } else if event_type.is_none() && window.is_none() {
+                event_type = Some(word.parse()?);
+            } else {
+                anyhow::bail!("unexpected '{}' in rule '{}'", word, s.trim());
+            }
+        }
+
+        anyhow::ensure!(
+            count == 1 || window.is_some(),
+            "rule '{}' counts several events, so it needs 'within <secs>'",
+            s.trim()
+        );
+
+        let action = match action.trim() {
+            "block" => EscalationAction::Block,
+            severity => EscalationAction::Escalate(severity.parse()?),
+        };
+
+        Ok(Self {
+            count,
+            min_severity,
+            event_type,
+            window: window.unwrap_or_else(chrono::Duration::zero),
+            action,
+        })
+    }
+}
+
+/// Rules separated by `;`; blank entries are skipped.
+pub fn parse_rules(s: &str) -> anyhow::Result<Vec<EscalationRule>> {
+    s.split(';')
+        .filter(|rule| !rule.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = parse_rules(
+            "3 medium within 600 -> high; 1 critical -> block; \
+             2 high unauthorized_access_attempt within 60 -> critical;",
+        )
+        .unwrap();
+
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules[0].count, 3);
+        assert_eq!(rules[0].min_severity, AlertSeverity::Medium);
+        assert_eq!(rules[0].window, chrono::Duration::minutes(10));
+        assert_eq!(rules[0].action, EscalationAction::Escalate(AlertSeverity::High));
+        assert_eq!(rules[1].action, EscalationAction::Block);
+        assert_eq!(
+            rules[2].event_type,
+            Some(SecurityEventType::UnauthorizedAccessAttempt)
+        );
+
+        for rule in &rules {
+            assert_eq!(rule.to_string().parse::<EscalationRule>().unwrap(), *rule);
+        }
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!("3 medium -> high".parse::<EscalationRule>().is_err());
+        assert!("0 low -> high".parse::<EscalationRule>().is_err());
+        assert!("1 severe -> block".parse::<EscalationRule>().is_err());
+        assert!("1 low within x -> high".parse::<EscalationRule>().is_err());
+        assert!("1 low".parse::<EscalationRule>().is_err());
+    }
+
+    #[test]
+    fn test_matching_and_escalation() {
+        let rule: EscalationRule = "2 medium ip_banned within 60 -> high".parse().unwrap();
+
+        assert!(rule.matches(&SecurityEventType::IpBanned, AlertSeverity::Critical));
+        assert!(!rule.matches(&SecurityEventType::IpBanned, AlertSeverity::Low));
+        assert!(!rule.matches(&SecurityEventType::SuspiciousWithdrawal, AlertSeverity::High));
+
+        assert_eq!(rule.escalated(AlertSeverity::Medium), AlertSeverity::High);
+        assert_eq!(rule.escalated(AlertSeverity::Critical), AlertSeverity::Critical);
+    }
+}
@@ -52,6 +52,13 @@ pub struct CollateralUnlocked {
     pub amount: u64,
 }
 
+#[derive(BorshDeserialize)]
+pub struct VaultClosed {
+    pub vault: Pubkey,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
 #[derive(BorshDeserialize)]
 pub struct CollateralTransferred {
     pub from: Pubkey,
@@ -0,0 +1,225 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use futures::{StreamExt, TryStreamExt};
+use serde_json::{json, Value};
+use solana_sdk::commitment_config::CommitmentLevel;
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding};
+
+use crate::indexer::rate_limit::{RateLimitConfig, RateLimiter};
+use crate::rpc_endpoints::{failover_order, RpcFailover};
+
+/// How many times a throttled batch is retried before giving up.
+const MAX_RATE_LIMIT_RETRIES: u32 = 8;
+
+/// Default number of `getTransaction` calls packed into one JSON-RPC batch.
+pub const DEFAULT_BATCH_SIZE: usize = 50;
+
+/// Default number of batches in flight at once.
+pub const DEFAULT_CONCURRENCY: usize = 1;
+
+/// Fetches transactions with JSON-RPC batch requests, so catch-up costs one
+/// HTTP round-trip per `batch_size` signatures instead of one per signature.
+///
+/// Batches are spread over the endpoints by weight like `RpcFailover` calls,
+/// and a batch that fails or is throttled moves on to the next endpoint.
+pub struct BatchTransactionFetcher {
+    http: reqwest::Client,
+    /// `(weight, url)`, heaviest first; never empty.
+    endpoints: Vec<(u32, String)>,
+    turn: AtomicU64,
+    batch_size: usize,
+    concurrency: usize,
+    encoding: UiTransactionEncoding,
+    commitment: CommitmentLevel,
+    limiter: Arc<RateLimiter>,
+}
+
+impl BatchTransactionFetcher {
+    pub fn new(rpc_url: String) -> Self {
+        Self::with_endpoints(vec![(1, rpc_url)])
+    }
+
+    /// Fetch from the endpoints of `rpc`, e.g. every read endpoint.
+    pub fn from_failover(rpc: &RpcFailover) -> Self {
+        Self::with_endpoints(rpc.endpoints())
+    }
+
+    fn with_endpoints(endpoints: Vec<(u32, String)>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            endpoints,
+            turn: AtomicU64::new(0),
+            batch_size: DEFAULT_BATCH_SIZE,
+            concurrency: DEFAULT_CONCURRENCY,
+            encoding: UiTransactionEncoding::JsonParsed,
+            commitment: CommitmentLevel::Confirmed,
+            limiter: Arc::new(RateLimiter::new(RateLimitConfig::default())),
+        }
+    }
+
+    /// Share a rate limiter with the other RPC calls made against the same endpoint.
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// Commitment to fetch at. `getTransaction` doesn't accept `processed`,
+    /// so that level is fetched as `confirmed`.
+    pub fn with_commitment(mut self, commitment: CommitmentLevel) -> Self {
+        self.commitment = fetchable_commitment(commitment);
+        self
+    }
+
+    pub fn commitment(&self) -> CommitmentLevel {
+        self.commitment
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Send up to `concurrency` batches at once; all of them still go
+    /// through the rate limiter.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Fetch all `signatures`, preserving input order. Signatures the node
+    /// doesn't know about come back as `None`.
+    pub async fn fetch(
+        &self,
+        signatures: &[String],
+    ) -> anyhow::Result<Vec<(String, Option<EncodedConfirmedTransactionWithStatusMeta>)>> {
+        let chunks: Vec<Vec<Option<EncodedConfirmedTransactionWithStatusMeta>>> =
+            futures::stream::iter(signatures.chunks(self.batch_size))
+                .map(|chunk| self.fetch_chunk(chunk))
+                .buffered(self.concurrency)
+                .try_collect()
+                .await?;
+
+        Ok(signatures
+            .iter()
+            .cloned()
+            .zip(chunks.into_iter().flatten())
+            .collect())
+    }
+
+    async fn fetch_chunk(
+        &self,
+        chunk: &[String],
+    ) -> anyhow::Result<Vec<Option<EncodedConfirmedTransactionWithStatusMeta>>> {
+        let requests: Vec<Value> = chunk
+            .iter()
+            .enumerate()
+            .map(|(id, signature)| self.request_body(id, signature))
+            .collect();
+
+        let weights: Vec<u32> = self.endpoints.iter().map(|(weight, _)| *weight).collect();
+        let turn = self.turn.fetch_add(1, Ordering::Relaxed);
+        let mut order = failover_order(&weights, turn).into_iter().cycle();
+
+        let mut throttled = 0;
+        let mut failed = 0;
+        let responses: Vec<Value> = loop {
+            self.limiter.acquire().await;
+
+            let url = &self.endpoints[order.next().expect("at least one endpoint")].1;
+
+            match self.post(url, &requests).await {
+                Ok(Some(body)) => {
+                    self.limiter.on_success().await;
+                    break body;
+                }
+                Ok(None) => {
+                    throttled += 1;
+                    if throttled > MAX_RATE_LIMIT_RETRIES {
+                        anyhow::bail!(
+                            "rate limit: giving up after {} throttled attempts",
+                            throttled
+                        );
+                    }
+                    self.limiter.on_rate_limited().await;
+                }
+                // Every endpoint gets one try before the batch fails
+                Err(e) => {
+                    failed += 1;
+                    if failed >= self.endpoints.len() {
+                        return Err(e.into());
+                    }
+                    tracing::warn!(
+                        "batch fetch from {} failed, trying the next endpoint: {}",
+                        url,
+                        e
+                    );
+                }
+            }
+        };
+
+        // Batch responses may arrive in any order; match them back by id
+        let mut results: Vec<Option<EncodedConfirmedTransactionWithStatusMeta>> =
+            (0..chunk.len()).map(|_| None).collect();
+
+        for response in responses {
+            let id = response["id"]
+                .as_u64()
+                .ok_or_else(|| anyhow::anyhow!("batch response without id"))? as usize;
+
+            if let Some(error) = response.get("error") {
+                anyhow::bail!(
+                    "getTransaction failed for {}: {}",
+                    chunk.get(id).map(String::as_str).unwrap_or("?"),
+                    error
+                );
+            }
+
+            let result = response.get("result").cloned().unwrap_or(Value::Null);
+            if result.is_null() {
+                continue;
+            }
+
+            if let Some(slot) = results.get_mut(id) {
+                *slot = Some(serde_json::from_value(result)?);
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// POST one batch to `url`; `None` if the endpoint throttled it.
+    async fn post(&self, url: &str, requests: &[Value]) -> reqwest::Result<Option<Vec<Value>>> {
+        let response = self.http.post(url).json(requests).send().await?;
+
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Ok(None);
+        }
+
+        Ok(Some(response.error_for_status()?.json().await?))
+    }
+
+    fn request_body(&self, id: usize, signature: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "getTransaction",
+            "params": [
+                signature,
+                {
+                    "encoding": self.encoding,
+                    "commitment": self.commitment,
+                    "maxSupportedTransactionVersion": 0,
+                }
+            ]
+        })
+    }
+}
+
+/// Commitment usable with `getTransaction`, which rejects `processed`.
+pub fn fetchable_commitment(commitment: CommitmentLevel) -> CommitmentLevel {
+    match commitment {
+        CommitmentLevel::Processed => CommitmentLevel::Confirmed,
+        other => other,
+    }
+}
@@ -0,0 +1,150 @@
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction,
+    EncodedTransactionWithStatusMeta, UiConfirmedBlock,
+};
+
+use crate::indexer::instruction_decoder::account_keys;
+
+/// How the indexer discovers program transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IngestionMode {
+    /// `getSignaturesForAddress` followed by batched `getTransaction`.
+    Signatures,
+    /// Whole blocks via `getBlock`, filtered locally. Cheaper when most
+    /// blocks contain program activity.
+    Blocks,
+}
+
+impl std::str::FromStr for IngestionMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "signatures" => Ok(Self::Signatures),
+            "blocks" => Ok(Self::Blocks),
+            other => anyhow::bail!("unknown ingestion mode '{}'", other),
+        }
+    }
+}
+
+/// First signature of a transaction, which identifies it.
+pub fn transaction_signature(tx: &EncodedTransactionWithStatusMeta) -> Option<String> {
+    match &tx.transaction {
+        EncodedTransaction::Json(ui_tx) => ui_tx.signatures.first().cloned(),
+        _ => None,
+    }
+}
+
+/// Whether the transaction references the program in any account slot.
+///
+/// Checking accounts rather than top-level instructions also catches
+/// transactions that reach the program through CPI.
+pub fn mentions_program(tx: &EncodedTransactionWithStatusMeta, program_id: &str) -> bool {
+    account_keys(tx).iter().any(|key| key == program_id)
+}
+
+/// Whether the transaction succeeded. Failed ones are in blocks too, but
+/// changed nothing on-chain.
+fn succeeded(tx: &EncodedTransactionWithStatusMeta) -> bool {
+    tx.meta.as_ref().is_some_and(|meta| meta.err.is_none())
+}
+
+/// Successful transactions in `block` that touch `program_id`, in block
+/// order, as `(signature, index in block, transaction)` ready for
+/// `process_transaction`.
+pub fn program_transactions(
+    slot: u64,
+    block: UiConfirmedBlock,
+    program_id: &Pubkey,
+) -> Vec<(String, i32, EncodedConfirmedTransactionWithStatusMeta)> {
+    let program = program_id.to_string();
+    let block_time = block.block_time;
+
+    block
+        .transactions
+        .unwrap_or_default()
+        .into_iter()
+        .enumerate()
+        .filter(|(_, tx)| succeeded(tx) && mentions_program(tx, &program))
+        .filter_map(|(index, tx)| {
+            let signature = transaction_signature(&tx)?;
+
+            Some((
+                signature,
+                index as i32,
+                EncodedConfirmedTransactionWithStatusMeta {
+                    slot,
+                    transaction: tx,
+                    block_time,
+                },
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ingestion_mode() {
+        assert_eq!("blocks".parse::<IngestionMode>().unwrap(), IngestionMode::Blocks);
+        assert_eq!(
+            " Signatures ".parse::<IngestionMode>().unwrap(),
+            IngestionMode::Signatures
+        );
+        assert!("slots".parse::<IngestionMode>().is_err());
+    }
+
+    fn block_tx(signature: &str, program: &Pubkey, err: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "transaction": {
+                "signatures": [signature],
+                "message": {
+                    "header": {
+                        "numRequiredSignatures": 1,
+                        "numReadonlySignedAccounts": 0,
+                        "numReadonlyUnsignedAccounts": 1
+                    },
+                    "accountKeys": [Pubkey::new_unique().to_string(), program.to_string()],
+                    "recentBlockhash": "11111111111111111111111111111111",
+                    "instructions": []
+                }
+            },
+            "meta": {
+                "err": err,
+                "status": { "Ok": null },
+                "fee": 5000,
+                "preBalances": [],
+                "postBalances": []
+            }
+        })
+    }
+
+    #[test]
+    fn test_failed_transactions_are_skipped() {
+        let program = Pubkey::new_unique();
+        let block: UiConfirmedBlock = serde_json::from_value(serde_json::json!({
+            "previousBlockhash": "11111111111111111111111111111111",
+            "blockhash": "11111111111111111111111111111111",
+            "parentSlot": 9,
+            "transactions": [
+                block_tx(
+                    "failed",
+                    &program,
+                    serde_json::json!({ "InstructionError": [0, "InvalidArgument"] })
+                ),
+                block_tx("ok", &program, serde_json::Value::Null)
+            ],
+            "blockTime": 1_700_000_000,
+            "blockHeight": 10
+        }))
+        .unwrap();
+
+        let txs = program_transactions(10, block, &program);
+        assert_eq!(txs.len(), 1);
+        // The position in the block still counts the failed transaction
+        assert_eq!((txs[0].0.as_str(), txs[0].1), ("ok", 1));
+    }
+}
@@ -1,11 +1,46 @@
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
 use borsh::BorshDeserialize;
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::option_serializer::OptionSerializer;
 use solana_transaction_status::EncodedTransactionWithStatusMeta;
 
+use std::sync::OnceLock;
+
 use crate::idl;
+use crate::indexer::idl_decoder::{self, IdlEventDecoder};
+use crate::indexer::instruction_decoder;
+
+/// IDL-driven decoder installed at startup; when absent the built-in
+/// discriminator table below is used.
+static IDL_DECODER: OnceLock<IdlEventDecoder> = OnceLock::new();
+
+/// Install the IDL-driven decoder. Returns an error if one is already installed.
+pub fn install_idl_decoder(decoder: IdlEventDecoder) -> anyhow::Result<()> {
+    IDL_DECODER
+        .set(decoder)
+        .map_err(|_| anyhow::anyhow!("IDL event decoder already installed"))
+}
+
+/// Anchor's `EVENT_IX_TAG` (0x1d9acb512ea545e4) in little-endian byte order.
+///
+/// `emit_cpi!` self-invokes the program through the event-authority PDA with
+/// instruction data `EVENT_IX_TAG || event discriminator || borsh payload`.
+const EVENT_IX_TAG_LE: [u8; 8] = [0xe4, 0x45, 0xa5, 0x2e, 0x51, 0xcb, 0x9a, 0x1d];
+
+/// Seed of the PDA `emit_cpi!` signs its self-invocation with.
+const EVENT_AUTHORITY_SEED: &[u8] = b"__event_authority";
+
+/// The program's event-authority PDA.
+pub fn event_authority(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[EVENT_AUTHORITY_SEED], program_id).0
+}
 
-#[derive(Debug)]
+/// Serialized with an `"event"` tag, e.g. `{"event": "deposit", "amount": 5, ..}`,
+/// which is what the indexer stores as `transactions.event_payload`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
 pub enum VaultEvent {
     VaultAuthorityInitialized {
         admin: String,
@@ -43,9 +78,22 @@ pub enum VaultEvent {
         to: String,
         amount: u64,
     },
+    VaultClosed {
+        vault: String,
+        owner: String,
+        timestamp: i64,
+    },
+    /// An event present in the IDL that the indexer doesn't apply.
+    Unknown {
+        name: String,
+        fields: serde_json::Value,
+    },
 }
 
-pub fn decode_events(tx: &EncodedTransactionWithStatusMeta) -> anyhow::Result<Vec<VaultEvent>> {
+pub fn decode_events(
+    tx: &EncodedTransactionWithStatusMeta,
+    program_id: &Pubkey,
+) -> anyhow::Result<Vec<VaultEvent>> {
     let mut events = vec![];
 
     let meta = match &tx.meta {
@@ -53,13 +101,62 @@ pub fn decode_events(tx: &EncodedTransactionWithStatusMeta) -> anyhow::Result<Ve
         None => return Ok(events),
     };
 
-    use solana_transaction_status::option_serializer::OptionSerializer;
+    if let OptionSerializer::Some(logs) = &meta.log_messages {
+        events.extend(decode_log_messages(logs)?);
+    }
+
+    events.extend(decode_inner_instructions(tx, program_id)?);
+
+    Ok(events)
+}
+
+/// Decode events emitted with `emit_cpi!`, which land in inner-instruction
+/// data instead of base64 program logs.
+///
+/// Only self-invocations of `program_id` through its event authority count:
+/// any program can invoke itself with event-shaped data, so an event from
+/// anywhere else would be forged.
+pub fn decode_inner_instructions(
+    tx: &EncodedTransactionWithStatusMeta,
+    program_id: &Pubkey,
+) -> anyhow::Result<Vec<VaultEvent>> {
+    let mut events = vec![];
+
+    let program = program_id.to_string();
+    let authority = event_authority(program_id).to_string();
+
+    for (ix_program, accounts, data) in instruction_decoder::inner_instructions(tx) {
+        if ix_program != program || accounts.first() != Some(&authority) {
+            continue;
+        }
+
+        if let Some(event) = decode_event_cpi_data(&data)? {
+            events.push(event);
+        }
+    }
+
+    Ok(events)
+}
 
-    let logs = match &meta.log_messages {
-        OptionSerializer::Some(l) => l,
-        _ => return Ok(events),
+fn decode_event_cpi_data(data: &str) -> anyhow::Result<Option<VaultEvent>> {
+    let bytes = match bs58::decode(data).into_vec() {
+        Ok(b) => b,
+        Err(_) => return Ok(None),
     };
 
+    match bytes.strip_prefix(&EVENT_IX_TAG_LE[..]) {
+        Some(event_data) => parse_event(event_data),
+        None => Ok(None),
+    }
+}
+
+/// Decode Anchor events from raw program log lines.
+///
+/// Shared by the polling path (logs from `get_transaction`) and the streaming
+/// path (logs pushed by `logsSubscribe`).
+pub fn decode_log_messages(logs: &[String]) -> anyhow::Result<Vec<VaultEvent>> {
+    let mut events = vec![];
+
     for log in logs {
         // Anchor event logs
         if let Some(payload) = log.strip_prefix("Program log: ") {
@@ -83,6 +180,13 @@ pub fn decode_events(tx: &EncodedTransactionWithStatusMeta) -> anyhow::Result<Ve
 }
 
 fn parse_event(data: &[u8]) -> anyhow::Result<Option<VaultEvent>> {
+    if let Some(decoder) = IDL_DECODER.get() {
+        return decoder
+            .decode(data)?
+            .map(idl_decoder::to_vault_event)
+            .transpose();
+    }
+
     if data.len() < 8 {
         return Ok(None);
     }
@@ -164,6 +268,125 @@ fn parse_event(data: &[u8]) -> anyhow::Result<Option<VaultEvent>> {
             }))
         }
 
+        // VaultClosed
+        [238, 129, 38, 228, 227, 118, 249, 215] => {
+            let ev = idl::VaultClosed::try_from_slice(&data[8..])?;
+            Ok(Some(VaultEvent::VaultClosed {
+                vault: ev.vault.to_string(),
+                owner: ev.owner.to_string(),
+                timestamp: ev.timestamp,
+            }))
+        }
+
         _ => Ok(None),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_event_cpi_data() {
+        let vault = solana_sdk::pubkey::Pubkey::new_unique();
+
+        // CollateralLocked discriminator + borsh(vault, amount)
+        let mut data = EVENT_IX_TAG_LE.to_vec();
+        data.extend_from_slice(&[185, 146, 119, 8, 41, 179, 88, 96]);
+        data.extend_from_slice(vault.as_ref());
+        data.extend_from_slice(&500u64.to_le_bytes());
+
+        let encoded = bs58::encode(&data).into_string();
+
+        match decode_event_cpi_data(&encoded).unwrap() {
+            Some(VaultEvent::Lock { vault: v, amount }) => {
+                assert_eq!(v, vault.to_string());
+                assert_eq!(amount, 500);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    /// A transaction whose only inner instruction is `data` sent to `program`
+    /// with `authority` as its account.
+    fn cpi_tx(
+        program: &Pubkey,
+        authority: &Pubkey,
+        data: &[u8],
+    ) -> EncodedTransactionWithStatusMeta {
+        serde_json::from_value(serde_json::json!({
+            "transaction": {
+                "signatures": [],
+                "message": {
+                    "header": {
+                        "numRequiredSignatures": 1,
+                        "numReadonlySignedAccounts": 0,
+                        "numReadonlyUnsignedAccounts": 2
+                    },
+                    "accountKeys": [
+                        Pubkey::new_unique().to_string(),
+                        program.to_string(),
+                        authority.to_string()
+                    ],
+                    "recentBlockhash": "11111111111111111111111111111111",
+                    "instructions": []
+                }
+            },
+            "meta": {
+                "err": null,
+                "status": { "Ok": null },
+                "fee": 5000,
+                "preBalances": [],
+                "postBalances": [],
+                "innerInstructions": [{
+                    "index": 0,
+                    "instructions": [{
+                        "programIdIndex": 1,
+                        "accounts": [2],
+                        "data": bs58::encode(data).into_string(),
+                        "stackHeight": 2
+                    }]
+                }]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_event_cpi_must_come_from_vault_program() {
+        let program = Pubkey::new_unique();
+        let foreign = Pubkey::new_unique();
+
+        let mut data = EVENT_IX_TAG_LE.to_vec();
+        data.extend_from_slice(&[185, 146, 119, 8, 41, 179, 88, 96]);
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(&500u64.to_le_bytes());
+
+        let genuine = cpi_tx(&program, &event_authority(&program), &data);
+        assert_eq!(
+            decode_inner_instructions(&genuine, &program).unwrap().len(),
+            1
+        );
+
+        // Another program invoking itself with the same payload
+        let forged = cpi_tx(&foreign, &event_authority(&foreign), &data);
+        assert!(decode_inner_instructions(&forged, &program)
+            .unwrap()
+            .is_empty());
+
+        // The vault program without its event authority
+        let unsigned = cpi_tx(&program, &Pubkey::new_unique(), &data);
+        assert!(decode_inner_instructions(&unsigned, &program)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_non_event_instruction_ignored() {
+        // A regular instruction (deposit discriminator) is not an event CPI
+        let data = [242u8, 35, 198, 137, 82, 225, 242, 182, 1, 0, 0, 0, 0, 0, 0, 0];
+        let encoded = bs58::encode(&data).into_string();
+
+        assert!(decode_event_cpi_data(&encoded).unwrap().is_none());
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashSet;
+
+use crate::indexer::event_decoder::VaultEvent;
+
+/// Include/exclude rules deciding which decoded events the indexer applies.
+///
+/// An empty include set means "everything"; excludes always win. Mint rules
+/// need the vault's mint, which only `VaultInitialized` carries, so for other
+/// events the caller looks it up from the indexed vault row.
+#[derive(Clone, Debug, Default)]
+pub struct EventFilter {
+    pub include_mints: HashSet<String>,
+    pub exclude_mints: HashSet<String>,
+    pub include_vaults: HashSet<String>,
+    pub exclude_vaults: HashSet<String>,
+    pub include_event_types: HashSet<String>,
+    pub exclude_event_types: HashSet<String>,
+}
+
+impl EventFilter {
+    /// Whether no rule is configured, so every event passes.
+    pub fn is_empty(&self) -> bool {
+        self.include_mints.is_empty()
+            && self.exclude_mints.is_empty()
+            && self.include_vaults.is_empty()
+            && self.exclude_vaults.is_empty()
+            && self.include_event_types.is_empty()
+            && self.exclude_event_types.is_empty()
+    }
+
+    /// Whether mint rules apply, i.e. the caller has to resolve the vault's mint.
+    pub fn filters_mints(&self) -> bool {
+        !self.include_mints.is_empty() || !self.exclude_mints.is_empty()
+    }
+
+    /// Decide whether `event` on `vault` (with `mint`, if known) is indexed.
+    ///
+    /// With an include list for mints, events on vaults whose mint is unknown
+    /// are dropped: their `VaultInitialized` was filtered out too.
+    pub fn allows(&self, event: &VaultEvent, vault: Option<&str>, mint: Option<&str>) -> bool {
+        let event_type = event_type(event);
+
+        if !passes(&self.include_event_types, &self.exclude_event_types, Some(event_type)) {
+            return false;
+        }
+
+        if !passes(&self.include_vaults, &self.exclude_vaults, vault) {
+            return false;
+        }
+
+        passes(&self.include_mints, &self.exclude_mints, mint)
+    }
+}
+
+fn passes(include: &HashSet<String>, exclude: &HashSet<String>, value: Option<&str>) -> bool {
+    match value {
+        Some(v) => (include.is_empty() || include.contains(v)) && !exclude.contains(v),
+        None => include.is_empty(),
+    }
+}
+
+/// Name used for an event in filter configuration.
+pub fn event_type(event: &VaultEvent) -> &str {
+    match event {
+        VaultEvent::VaultInitialized { .. } => "initialize",
+        VaultEvent::Deposit { .. } => "deposit",
+        VaultEvent::Withdraw { .. } => "withdraw",
+        VaultEvent::Lock { .. } => "lock",
+        VaultEvent::Unlock { .. } => "unlock",
+        VaultEvent::Transfer { .. } => "transfer",
+        VaultEvent::VaultClosed { .. } => "close",
+        VaultEvent::ProgramAuthorized { .. } => "program_authorized",
+        VaultEvent::VaultAuthorityInitialized { .. } => "vault_authority_initialized",
+        VaultEvent::Unknown { name, .. } => name,
+    }
+}
+
+/// Parse a comma separated list into a set, ignoring blanks.
+pub fn parse_list(raw: &str) -> HashSet<String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock(vault: &str) -> VaultEvent {
+        VaultEvent::Lock {
+            vault: vault.to_string(),
+            amount: 1,
+        }
+    }
+
+    #[test]
+    fn test_empty_filter_allows_everything() {
+        let filter = EventFilter::default();
+        assert!(filter.is_empty());
+        assert!(filter.allows(&lock("v1"), Some("v1"), None));
+    }
+
+    #[test]
+    fn test_exclude_wins_over_include() {
+        let filter = EventFilter {
+            include_vaults: parse_list("v1,v2"),
+            exclude_vaults: parse_list("v2"),
+            ..Default::default()
+        };
+        assert!(filter.allows(&lock("v1"), Some("v1"), None));
+        assert!(!filter.allows(&lock("v2"), Some("v2"), None));
+        assert!(!filter.allows(&lock("v3"), Some("v3"), None));
+    }
+
+    #[test]
+    fn test_mint_include_drops_unknown_mint() {
+        let filter = EventFilter {
+            include_mints: parse_list("mintA"),
+            ..Default::default()
+        };
+        assert!(filter.allows(&lock("v1"), Some("v1"), Some("mintA")));
+        assert!(!filter.allows(&lock("v1"), Some("v1"), Some("mintB")));
+        assert!(!filter.allows(&lock("v1"), Some("v1"), None));
+    }
+
+    #[test]
+    fn test_event_type_filter() {
+        let filter = EventFilter {
+            exclude_event_types: parse_list("lock, unlock"),
+            ..Default::default()
+        };
+        assert!(!filter.allows(&lock("v1"), Some("v1"), None));
+    }
+}
@@ -0,0 +1,254 @@
+use std::time::Duration;
+
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_config::{CommitmentConfig, RpcBlockConfig};
+use solana_client::rpc_request::RpcError;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::{TransactionConfirmationStatus, TransactionDetails};
+use sqlx::{PgConnection, PgPool};
+use tracing::{info, warn};
+
+use crate::db::processed_events::{self, AppliedEventRow};
+use crate::db::vault_repo;
+use crate::error_handling::VaultResult;
+use crate::rpc_endpoints::RpcFailover;
+
+/// `getSignatureStatuses` accepts at most 256 signatures per call.
+const STATUS_BATCH_SIZE: i64 = 256;
+
+/// Slots a node's status cache covers behind the finalized slot. Within it a
+/// node has the ledger for every slot, so its answer that a slot was skipped
+/// can be trusted; further back the slot may just have been pruned.
+const STATUS_CACHE_SLOTS: i64 = 300;
+
+/// `getBlock` error codes for a slot that has no block.
+const SLOT_SKIPPED: i64 = -32007;
+const LONG_TERM_STORAGE_SLOT_SKIPPED: i64 = -32009;
+
+/// What the finalized block at a signature's slot says about it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockPresence {
+    Included,
+    Missing,
+    /// No block was finalized at the slot.
+    SlotSkipped,
+    /// The block couldn't be fetched, so nothing is known.
+    Unavailable,
+}
+
+#[derive(Debug, Default)]
+pub struct FinalityReport {
+    pub finalized: usize,
+    pub rolled_back: usize,
+    pub pending: usize,
+}
+
+/// Re-check recently indexed signatures against the cluster's finalized slot.
+///
+/// Signatures that reached `finalized` are marked as such. A signature the
+/// status lookup doesn't know, although its slot is behind the finalized slot,
+/// is only suspect: nodes forget old signatures too. It is rolled back only if
+/// the finalized block at its slot doesn't contain it, or, within
+/// `STATUS_CACHE_SLOTS`, if no block was finalized at that slot.
+pub async fn verify_recent(rpc: &RpcFailover, pool: &PgPool) -> anyhow::Result<FinalityReport> {
+    let mut report = FinalityReport::default();
+
+    let finalized_slot =
+        rpc.call(|rpc| rpc.get_slot_with_commitment(CommitmentConfig::finalized()))? as i64;
+
+    let pending = processed_events::get_unfinalized(pool, STATUS_BATCH_SIZE).await?;
+    if pending.is_empty() {
+        return Ok(report);
+    }
+
+    let signatures = pending
+        .iter()
+        .map(|row| row.tx_signature.parse::<Signature>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let statuses = rpc
+        .call(|rpc| rpc.get_signature_statuses_with_history(&signatures))?
+        .value;
+
+    for (row, status) in pending.iter().zip(statuses) {
+        match status {
+            Some(status)
+                if status.confirmation_status
+                    == Some(TransactionConfirmationStatus::Finalized) =>
+            {
+                processed_events::mark_finalized(pool, &row.tx_signature).await?;
+                report.finalized += 1;
+            }
+            None if row.slot <= finalized_slot => {
+                let recent = row.slot >= finalized_slot - STATUS_CACHE_SLOTS;
+
+                match block_presence(rpc, row.slot as u64, &row.tx_signature) {
+                    BlockPresence::Included => {
+                        processed_events::mark_finalized(pool, &row.tx_signature).await?;
+                        report.finalized += 1;
+                    }
+                    BlockPresence::Missing => {
+                        warn!(
+                            "signature {} is not in the finalized block {}, rolling back",
+                            row.tx_signature, row.slot
+                        );
+                        rollback_signature(pool, &row.tx_signature).await?;
+                        report.rolled_back += 1;
+                    }
+                    BlockPresence::SlotSkipped if recent => {
+                        warn!(
+                            "slot {} of signature {} was skipped, rolling back",
+                            row.slot, row.tx_signature
+                        );
+                        rollback_signature(pool, &row.tx_signature).await?;
+                        report.rolled_back += 1;
+                    }
+                    _ => report.pending += 1,
+                }
+            }
+            _ => report.pending += 1,
+        }
+    }
+
+    info!(
+        "finality check: {} finalized, {} rolled back, {} pending (finalized slot {})",
+        report.finalized, report.rolled_back, report.pending, finalized_slot
+    );
+
+    Ok(report)
+}
+
+/// Look `signature` up in the finalized block at `slot`.
+fn block_presence(rpc: &RpcFailover, slot: u64, signature: &str) -> BlockPresence {
+    let block = rpc.call(|rpc| {
+        rpc.get_block_with_config(
+            slot,
+            RpcBlockConfig {
+                encoding: None,
+                transaction_details: Some(TransactionDetails::Signatures),
+                rewards: Some(false),
+                commitment: Some(CommitmentConfig::finalized()),
+                max_supported_transaction_version: Some(0),
+            },
+        )
+    });
+
+    match block {
+        Ok(block) => {
+            if block
+                .signatures
+                .unwrap_or_default()
+                .iter()
+                .any(|s| s == signature)
+            {
+                BlockPresence::Included
+            } else {
+                BlockPresence::Missing
+            }
+        }
+        Err(e) if is_slot_skipped(&e) => BlockPresence::SlotSkipped,
+        Err(e) => {
+            warn!(
+                "block {} unavailable, can't check signature {}: {}",
+                slot, signature, e
+            );
+            BlockPresence::Unavailable
+        }
+    }
+}
+
+fn is_slot_skipped(error: &ClientError) -> bool {
+    matches!(
+        error.kind(),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. })
+            if *code == SLOT_SKIPPED || *code == LONG_TERM_STORAGE_SLOT_SKIPPED
+    )
+}
+
+/// Reverse the balance effects of an indexed transaction and forget it, so a
+/// re-appearance of the signature on the canonical fork is indexed afresh.
+///
+/// Runs in one DB transaction: a failure part-way leaves the signature
+/// applied as it was, to be rolled back on the next check.
+pub async fn rollback_signature(pool: &PgPool, signature: &str) -> anyhow::Result<()> {
+    let mut db_tx = pool.begin().await?;
+
+    let applied = processed_events::get_applied_events(&mut *db_tx, signature).await?;
+
+    // Undo in reverse order of application
+    for event in applied.iter().rev() {
+        revert_event(&mut *db_tx, event).await?;
+    }
+
+    processed_events::delete_processed(&mut *db_tx, signature).await?;
+
+    db_tx.commit().await?;
+
+    Ok(())
+}
+
+async fn revert_event(conn: &mut PgConnection, event: &AppliedEventRow) -> VaultResult<()> {
+    let vault = event.vault_pda.as_str();
+
+    match event.event_type.as_str() {
+        "deposit" => vault_repo::revert_deposit(conn, vault, event.amount).await,
+        "withdraw" => vault_repo::revert_withdraw(conn, vault, event.amount).await,
+        "lock" => vault_repo::apply_unlock(conn, vault, event.amount).await,
+        "unlock" => vault_repo::apply_lock(conn, vault, event.amount).await,
+        "transfer" => match &event.counterparty_vault {
+            Some(to) => vault_repo::apply_transfer(conn, to, vault, event.amount).await,
+            None => Ok(()),
+        },
+        "close" => vault_repo::revert_close(conn, vault).await,
+        // Vault creation is left in place: the account is re-created on the
+        // canonical fork in practice, and an empty vault row is harmless.
+        _ => Ok(()),
+    }
+}
+
+/// Periodically runs `verify_recent`, finalizing indexed signatures and
+/// rolling back the ones dropped with a fork.
+pub struct FinalityChecker {
+    rpc: RpcFailover,
+    pool: PgPool,
+    interval: Duration,
+}
+
+impl FinalityChecker {
+    pub fn new(rpc: RpcFailover, pool: PgPool, interval: Duration) -> Self {
+        Self {
+            rpc,
+            pool,
+            interval,
+        }
+    }
+
+    /// Check once, batch after batch, until a batch finalizes nothing.
+    pub async fn run_once(&self) -> anyhow::Result<FinalityReport> {
+        let mut total = FinalityReport::default();
+
+        loop {
+            let report = verify_recent(&self.rpc, &self.pool).await?;
+            total.finalized += report.finalized;
+            total.rolled_back += report.rolled_back;
+            total.pending = report.pending;
+
+            if report.finalized + report.rolled_back == 0 {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// Run forever, checking once per interval.
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let mut ticker = tokio::time::interval(self.interval);
+
+        loop {
+            ticker.tick().await;
+
+            if let Err(e) = self.run_once().await {
+                warn!("finality check failed: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Context};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::indexer::event_decoder::VaultEvent;
+
+/// Borsh layout of a single IDL field type.
+#[derive(Debug, Clone)]
+enum FieldType {
+    Bool,
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    U128,
+    I128,
+    Pubkey,
+    String,
+    Bytes,
+    Vec(Box<FieldType>),
+    Option(Box<FieldType>),
+    Array(Box<FieldType>, usize),
+    Defined(String),
+}
+
+#[derive(Debug, Clone)]
+struct EventLayout {
+    name: String,
+    fields: Vec<(String, FieldType)>,
+}
+
+/// An event decoded purely from the IDL description.
+#[derive(Debug, Clone)]
+pub struct DecodedEvent {
+    pub name: String,
+    pub fields: Value,
+}
+
+/// Event decoder whose discriminator table and Borsh layouts come from the
+/// Anchor IDL JSON instead of being hardcoded.
+///
+/// Supports both the current IDL format (explicit `discriminator` arrays,
+/// event fields in `types`) and the legacy format (fields inline on the
+/// event, discriminator = `sha256("event:<Name>")[..8]`).
+pub struct IdlEventDecoder {
+    events: HashMap<[u8; 8], EventLayout>,
+    types: HashMap<String, Vec<(String, FieldType)>>,
+}
+
+impl IdlEventDecoder {
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read IDL at {}", path))?;
+        Self::from_json(&raw)
+    }
+
+    pub fn from_json(raw: &str) -> anyhow::Result<Self> {
+        let idl: Value = serde_json::from_str(raw).context("IDL is not valid JSON")?;
+
+        let mut types = HashMap::new();
+        for ty in idl["types"].as_array().into_iter().flatten() {
+            let name = ty["name"].as_str().ok_or_else(|| anyhow!("IDL type without name"))?;
+            if ty["type"]["kind"] == "struct" {
+                types.insert(name.to_string(), parse_fields(&ty["type"]["fields"])?);
+            }
+        }
+
+        let mut events = HashMap::new();
+        for ev in idl["events"].as_array().into_iter().flatten() {
+            let name = ev["name"].as_str().ok_or_else(|| anyhow!("IDL event without name"))?;
+
+            let discriminator = match ev["discriminator"].as_array() {
+                Some(bytes) => {
+                    let bytes = bytes
+                        .iter()
+                        .map(|b| b.as_u64().map(|b| b as u8))
+                        .collect::<Option<Vec<u8>>>()
+                        .ok_or_else(|| anyhow!("invalid discriminator for event {}", name))?;
+                    <[u8; 8]>::try_from(bytes.as_slice())
+                        .map_err(|_| anyhow!("discriminator for event {} is not 8 bytes", name))?
+                }
+                None => legacy_discriminator(name),
+            };
+
+            let fields = if ev["fields"].is_array() {
+                parse_fields(&ev["fields"])?
+            } else {
+                types
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("no type definition for event {}", name))?
+            };
+
+            events.insert(
+                discriminator,
+                EventLayout {
+                    name: name.to_string(),
+                    fields,
+                },
+            );
+        }
+
+        Ok(Self { events, types })
+    }
+
+    pub fn event_names(&self) -> Vec<&str> {
+        self.events.values().map(|e| e.name.as_str()).collect()
+    }
+
+    /// Decode `discriminator || borsh payload`. Returns `None` for unknown discriminators.
+    pub fn decode(&self, data: &[u8]) -> anyhow::Result<Option<DecodedEvent>> {
+        if data.len() < 8 {
+            return Ok(None);
+        }
+
+        let mut discriminator = [0u8; 8];
+        discriminator.copy_from_slice(&data[..8]);
+
+        let layout = match self.events.get(&discriminator) {
+            Some(l) => l,
+            None => return Ok(None),
+        };
+
+        let mut reader = BorshReader { data: &data[8..] };
+        let fields = self.read_struct(&mut reader, &layout.fields)?;
+
+        Ok(Some(DecodedEvent {
+            name: layout.name.clone(),
+            fields,
+        }))
+    }
+
+    fn read_struct(
+        &self,
+        reader: &mut BorshReader<'_>,
+        fields: &[(String, FieldType)],
+    ) -> anyhow::Result<Value> {
+        let mut obj = Map::new();
+        for (name, ty) in fields {
+            obj.insert(name.clone(), self.read_value(reader, ty)?);
+        }
+        Ok(Value::Object(obj))
+    }
+
+    fn read_value(&self, reader: &mut BorshReader<'_>, ty: &FieldType) -> anyhow::Result<Value> {
+        let value = match ty {
+            FieldType::Bool => json!(reader.take(1)?[0] != 0),
+            FieldType::U8 => json!(reader.take(1)?[0]),
+            FieldType::I8 => json!(reader.take(1)?[0] as i8),
+            FieldType::U16 => json!(u16::from_le_bytes(reader.array()?)),
+            FieldType::I16 => json!(i16::from_le_bytes(reader.array()?)),
+            FieldType::U32 => json!(u32::from_le_bytes(reader.array()?)),
+            FieldType::I32 => json!(i32::from_le_bytes(reader.array()?)),
+            FieldType::U64 => json!(u64::from_le_bytes(reader.array()?)),
+            FieldType::I64 => json!(i64::from_le_bytes(reader.array()?)),
+            // 128-bit integers don't fit JSON numbers losslessly
+            FieldType::U128 => json!(u128::from_le_bytes(reader.array()?).to_string()),
+            FieldType::I128 => json!(i128::from_le_bytes(reader.array()?).to_string()),
+            FieldType::Pubkey => json!(Pubkey::new_from_array(reader.array()?).to_string()),
+            FieldType::String => {
+                let len = u32::from_le_bytes(reader.array()?) as usize;
+                json!(String::from_utf8(reader.take(len)?.to_vec())?)
+            }
+            FieldType::Bytes => {
+                let len = u32::from_le_bytes(reader.array()?) as usize;
+                json!(reader.take(len)?.to_vec())
+            }
+            FieldType::Vec(inner) => {
+                let len = u32::from_le_bytes(reader.array()?) as usize;
+                let items = (0..len)
+                    .map(|_| self.read_value(reader, inner))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                Value::Array(items)
+            }
+            FieldType::Option(inner) => match reader.take(1)?[0] {
+                0 => Value::Null,
+                _ => self.read_value(reader, inner)?,
+            },
+            FieldType::Array(inner, len) => {
+                let items = (0..*len)
+                    .map(|_| self.read_value(reader, inner))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                Value::Array(items)
+            }
+            FieldType::Defined(name) => {
+                let fields = self
+                    .types
+                    .get(name)
+                    .ok_or_else(|| anyhow!("unsupported IDL type {}", name))?;
+                self.read_struct(reader, fields)?
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+/// Map a generically decoded event onto the typed `VaultEvent` the indexer
+/// applies. Events the indexer has no handling for become `VaultEvent::Unknown`.
+pub fn to_vault_event(event: DecodedEvent) -> anyhow::Result<VaultEvent> {
+    let f = &event.fields;
+
+    let ev = match event.name.as_str() {
+        "VaultAuthorityInitialized" => VaultEvent::VaultAuthorityInitialized {
+            admin: str_field(f, "admin")?,
+        },
+        "ProgramAuthorized" => VaultEvent::ProgramAuthorized {
+            program_id: str_field(f, "program_id")?,
+        },
+        "VaultInitialized" => VaultEvent::VaultInitialized {
+            vault: str_field(f, "vault")?,
+            owner: str_field(f, "owner")?,
+            mint: str_field(f, "mint")?,
+            timestamp: i64_field(f, "timestamp")?,
+        },
+        "DepositEvent" => VaultEvent::Deposit {
+            user: str_field(f, "user")?,
+            amount: u64_field(f, "amount")?,
+            new_balance: u64_field(f, "new_balance")?,
+            timestamp: i64_field(f, "timestamp")?,
+        },
+        "CollateralWithdrawn" => VaultEvent::Withdraw {
+            vault: str_field(f, "vault")?,
+            user: str_field(f, "user")?,
+            amount: u64_field(f, "amount")?,
+        },
+        "CollateralLocked" => VaultEvent::Lock {
+            vault: str_field(f, "vault")?,
+            amount: u64_field(f, "amount")?,
+        },
+        "CollateralUnlocked" => VaultEvent::Unlock {
+            vault: str_field(f, "vault")?,
+            amount: u64_field(f, "amount")?,
+        },
+        "CollateralTransferred" => VaultEvent::Transfer {
+            from: str_field(f, "from")?,
+            to: str_field(f, "to")?,
+            amount: u64_field(f, "amount")?,
+        },
+        "VaultClosed" => VaultEvent::VaultClosed {
+            vault: str_field(f, "vault")?,
+            owner: str_field(f, "owner")?,
+            timestamp: i64_field(f, "timestamp")?,
+        },
+        _ => VaultEvent::Unknown {
+            name: event.name,
+            fields: event.fields,
+        },
+    };
+
+    Ok(ev)
+}
+
+fn str_field(fields: &Value, name: &str) -> anyhow::Result<String> {
+    fields[name]
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow!("event field {} missing or not a string", name))
+}
+
+fn u64_field(fields: &Value, name: &str) -> anyhow::Result<u64> {
+    fields[name]
+        .as_u64()
+        .ok_or_else(|| anyhow!("event field {} missing or not a u64", name))
+}
+
+fn i64_field(fields: &Value, name: &str) -> anyhow::Result<i64> {
+    fields[name]
+        .as_i64()
+        .ok_or_else(|| anyhow!("event field {} missing or not an i64", name))
+}
+
+fn legacy_discriminator(event_name: &str) -> [u8; 8] {
+    let hash = Sha256::digest(format!("event:{}", event_name).as_bytes());
+    let mut out = [0u8; 8];
+    out.copy_from_slice(&hash[..8]);
+    out
+}
+
+fn parse_fields(fields: &Value) -> anyhow::Result<Vec<(String, FieldType)>> {
+    fields
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|field| {
+            let name = field["name"]
+                .as_str()
+                .ok_or_else(|| anyhow!("IDL field without name"))?;
+            Ok((name.to_string(), parse_type(&field["type"])?))
+        })
+        .collect()
+}
+
+fn parse_type(ty: &Value) -> anyhow::Result<FieldType> {
+    if let Some(name) = ty.as_str() {
+        return Ok(match name {
+            "bool" => FieldType::Bool,
+            "u8" => FieldType::U8,
+            "i8" => FieldType::I8,
+            "u16" => FieldType::U16,
+            "i16" => FieldType::I16,
+            "u32" => FieldType::U32,
+            "i32" => FieldType::I32,
+            "u64" => FieldType::U64,
+            "i64" => FieldType::I64,
+            "u128" => FieldType::U128,
+            "i128" => FieldType::I128,
+            "pubkey" | "publicKey" => FieldType::Pubkey,
+            "string" => FieldType::String,
+            "bytes" => FieldType::Bytes,
+            other => bail!("unsupported IDL primitive type {}", other),
+        });
+    }
+
+    if let Some(inner) = ty.get("vec") {
+        return Ok(FieldType::Vec(Box::new(parse_type(inner)?)));
+    }
+    if let Some(inner) = ty.get("option") {
+        return Ok(FieldType::Option(Box::new(parse_type(inner)?)));
+    }
+    if let Some(array) = ty.get("array").and_then(|a| a.as_array()) {
+        let inner = array.first().ok_or_else(|| anyhow!("array type without element"))?;
+        let len = array
+            .get(1)
+            .and_then(|l| l.as_u64())
+            .ok_or_else(|| anyhow!("array type without length"))?;
+        return Ok(FieldType::Array(Box::new(parse_type(inner)?), len as usize));
+    }
+    if let Some(defined) = ty.get("defined") {
+        // New format: {"defined": {"name": "X"}}, legacy: {"defined": "X"}
+        let name = defined
+            .get("name")
+            .and_then(|n| n.as_str())
+            .or_else(|| defined.as_str())
+            .ok_or_else(|| anyhow!("invalid defined type"))?;
+        return Ok(FieldType::Defined(name.to_string()));
+    }
+
+    bail!("unsupported IDL type {}", ty)
+}
+
+struct BorshReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> BorshReader<'a> {
+    fn take(&mut self, n: usize) -> anyhow::Result<&'a [u8]> {
+        if self.data.len() < n {
+            bail!("event payload truncated");
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDL: &str = r#"{
+        "events": [
+            { "name": "CollateralLocked", "discriminator": [185, 146, 119, 8, 41, 179, 88, 96] }
+        ],
+        "types": [
+            {
+                "name": "CollateralLocked",
+                "type": {
+                    "kind": "struct",
+                    "fields": [
+                        { "name": "vault", "type": "pubkey" },
+                        { "name": "amount", "type": "u64" }
+                    ]
+                }
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_decode_event_from_idl() {
+        let decoder = IdlEventDecoder::from_json(IDL).unwrap();
+        let vault = Pubkey::new_unique();
+
+        let mut data = vec![185, 146, 119, 8, 41, 179, 88, 96];
+        data.extend_from_slice(vault.as_ref());
+        data.extend_from_slice(&42u64.to_le_bytes());
+
+        let decoded = decoder.decode(&data).unwrap().unwrap();
+        assert_eq!(decoded.name, "CollateralLocked");
+
+        match to_vault_event(decoded).unwrap() {
+            VaultEvent::Lock { vault: v, amount } => {
+                assert_eq!(v, vault.to_string());
+                assert_eq!(amount, 42);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unknown_discriminator() {
+        let decoder = IdlEventDecoder::from_json(IDL).unwrap();
+        assert!(decoder.decode(&[0u8; 16]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_legacy_inline_fields() {
+        let idl = r#"{
+            "events": [
+                { "name": "Ping", "fields": [ { "name": "count", "type": "u32", "index": false } ] }
+            ]
+        }"#;
+        let decoder = IdlEventDecoder::from_json(idl).unwrap();
+
+        let mut data = legacy_discriminator("Ping").to_vec();
+        data.extend_from_slice(&7u32.to_le_bytes());
+
+        let decoded = decoder.decode(&data).unwrap().unwrap();
+        assert_eq!(decoded.fields["count"], 7);
+        assert!(matches!(to_vault_event(decoded).unwrap(), VaultEvent::Unknown { .. }));
+    }
+}
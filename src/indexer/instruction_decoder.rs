@@ -0,0 +1,394 @@
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{
+    EncodedTransaction, EncodedTransactionWithStatusMeta, UiInstruction, UiMessage,
+    UiParsedInstruction,
+};
+
+use crate::indexer::event_decoder::VaultEvent;
+
+// Instruction discriminators from the IDL (same values as in TransactionBuilder)
+const DEPOSIT_IX: [u8; 8] = [242, 35, 198, 137, 82, 225, 242, 182];
+const WITHDRAW_IX: [u8; 8] = [183, 18, 70, 156, 148, 109, 161, 34];
+const LOCK_IX: [u8; 8] = [161, 216, 135, 122, 12, 104, 211, 101];
+const UNLOCK_IX: [u8; 8] = [167, 213, 221, 147, 129, 209, 132, 190];
+
+/// Marker the validator appends once a transaction's log budget is exhausted.
+const LOG_TRUNCATED_MARKER: &str = "Log truncated";
+
+/// Whether the validator truncated this transaction's logs.
+pub fn logs_truncated(tx: &EncodedTransactionWithStatusMeta) -> bool {
+    match tx.meta.as_ref().map(|m| &m.log_messages) {
+        Some(OptionSerializer::Some(logs)) => logs.iter().any(|l| l.contains(LOG_TRUNCATED_MARKER)),
+        _ => false,
+    }
+}
+
+/// Merge the events decoded from truncated logs with those rebuilt from
+/// instruction data. Only kinds `decode_instructions` rebuilt anything for
+/// are replaced; any other kind keeps what the logs still show, e.g. a lock
+/// whose instruction couldn't be decoded.
+pub fn replace_truncated(
+    logged: Vec<VaultEvent>,
+    tx: &EncodedTransactionWithStatusMeta,
+    program_id: &Pubkey,
+    block_time: Option<i64>,
+) -> anyhow::Result<Vec<VaultEvent>> {
+    let rebuilt = decode_instructions(tx, program_id, block_time)?;
+
+    let mut events: Vec<VaultEvent> = logged
+        .into_iter()
+        .filter(|e| {
+            let kind = std::mem::discriminant(e);
+            !rebuilt.iter().any(|r| std::mem::discriminant(r) == kind)
+        })
+        .collect();
+    events.extend(rebuilt);
+
+    Ok(events)
+}
+
+/// Reconstruct balance-affecting events from the program's instructions,
+/// for transactions whose event logs were truncated. Instructions other
+/// programs invoked through CPI (e.g. a lock by an authorized caller) are
+/// included, in the order they ran.
+///
+/// A deposit's resulting balance is not part of the instruction, so it is
+/// taken from the vault token account's post-transaction token balance.
+pub fn decode_instructions(
+    tx: &EncodedTransactionWithStatusMeta,
+    program_id: &Pubkey,
+    block_time: Option<i64>,
+) -> anyhow::Result<Vec<VaultEvent>> {
+    let mut events = vec![];
+
+    // Failed transactions have no balance effects
+    match &tx.meta {
+        Some(meta) if meta.err.is_none() => {}
+        _ => return Ok(events),
+    }
+
+    let (account_keys, instructions) = match message_parts(tx) {
+        Some(parts) => parts,
+        None => return Ok(events),
+    };
+
+    let program = program_id.to_string();
+
+    for (ix_program, accounts, data) in executed_instructions(tx, &account_keys, instructions) {
+        if ix_program != program {
+            continue;
+        }
+
+        let data = match bs58::decode(&data).into_vec() {
+            Ok(d) if d.len() >= 16 => d,
+            _ => continue,
+        };
+
+        let amount = u64::from_le_bytes(data[8..16].try_into()?);
+
+        let event = match &data[..8] {
+            d if d == DEPOSIT_IX && accounts.len() >= 4 => {
+                match post_token_balance(tx, &account_keys, &accounts[3]) {
+                    Some(new_balance) => VaultEvent::Deposit {
+                        user: accounts[0].clone(),
+                        amount,
+                        new_balance,
+                        timestamp: block_time.unwrap_or(0),
+                    },
+                    None => {
+                        tracing::warn!(
+                            "no post token balance for vault account {}, skipping deposit",
+                            accounts[3]
+                        );
+                        continue;
+                    }
+                }
+            }
+            d if d == WITHDRAW_IX && accounts.len() >= 2 => VaultEvent::Withdraw {
+                vault: accounts[1].clone(),
+                user: accounts[0].clone(),
+                amount,
+            },
+            d if d == LOCK_IX && accounts.len() >= 2 => VaultEvent::Lock {
+                vault: accounts[1].clone(),
+                amount,
+            },
+            d if d == UNLOCK_IX && accounts.len() >= 2 => VaultEvent::Unlock {
+                vault: accounts[1].clone(),
+                amount,
+            },
+            _ => continue,
+        };
+
+        events.push(event);
+    }
+
+    Ok(events)
+}
+
+/// Every account the transaction references, including lookup table loads.
+pub fn account_keys(tx: &EncodedTransactionWithStatusMeta) -> Vec<String> {
+    message_parts(tx).map(|(keys, _)| keys).unwrap_or_default()
+}
+
+/// `(program, accounts, base58 data)` per inner instruction, in the order
+/// they ran. Accounts of a transaction whose keys can't be read are empty.
+pub fn inner_instructions(
+    tx: &EncodedTransactionWithStatusMeta,
+) -> Vec<(String, Vec<String>, String)> {
+    let sets = match tx.meta.as_ref().map(|m| &m.inner_instructions) {
+        Some(OptionSerializer::Some(sets)) => sets,
+        _ => return vec![],
+    };
+
+    let keys = account_keys(tx);
+
+    sets.iter()
+        .flat_map(|set| &set.instructions)
+        .map(|ix| instruction_parts(&keys, ix))
+        .collect()
+}
+
+/// Top-level instructions, each followed by the inner instructions it
+/// invoked.
+fn executed_instructions(
+    tx: &EncodedTransactionWithStatusMeta,
+    keys: &[String],
+    top_level: Vec<(String, Vec<String>, String)>,
+) -> Vec<(String, Vec<String>, String)> {
+    let sets = match tx.meta.as_ref().map(|m| &m.inner_instructions) {
+        Some(OptionSerializer::Some(sets)) => sets.as_slice(),
+        _ => &[],
+    };
+
+    top_level
+        .into_iter()
+        .enumerate()
+        .flat_map(|(index, ix)| {
+            let inner = sets
+                .iter()
+                .filter(move |set| usize::from(set.index) == index)
+                .flat_map(|set| &set.instructions)
+                .map(|ix| instruction_parts(keys, ix));
+
+            std::iter::once(ix).chain(inner)
+        })
+        .collect()
+}
+
+/// `(program, accounts, base58 data)` of one instruction. Fully parsed
+/// instructions belong to well-known programs (token, system), so only their
+/// program is kept; they stay in the list so positions match the message.
+fn instruction_parts(keys: &[String], ix: &UiInstruction) -> (String, Vec<String>, String) {
+    match ix {
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(p)) => {
+            (p.program_id.clone(), p.accounts.clone(), p.data.clone())
+        }
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(p)) => {
+            (p.program_id.clone(), vec![], String::new())
+        }
+        UiInstruction::Compiled(c) => compiled_parts(keys, c),
+    }
+}
+
+/// Account keys plus `(program, accounts, base58 data)` per top-level instruction.
+fn message_parts(
+    tx: &EncodedTransactionWithStatusMeta,
+) -> Option<(Vec<String>, Vec<(String, Vec<String>, String)>)> {
+    let ui_tx = match &tx.transaction {
+        EncodedTransaction::Json(ui_tx) => ui_tx,
+        _ => return None,
+    };
+
+    match &ui_tx.message {
+        UiMessage::Parsed(msg) => {
+            let keys: Vec<String> = msg.account_keys.iter().map(|k| k.pubkey.clone()).collect();
+
+            let ixs = msg
+                .instructions
+                .iter()
+                .map(|ix| instruction_parts(&keys, ix))
+                .collect();
+
+            Some((keys, ixs))
+        }
+        UiMessage::Raw(msg) => {
+            // v0 messages index into static keys followed by the addresses
+            // loaded from lookup tables (writable first, then readonly)
+            let mut keys = msg.account_keys.clone();
+            keys.extend(loaded_addresses(tx));
+
+            let ixs = msg
+                .instructions
+                .iter()
+                .map(|c| compiled_parts(&keys, c))
+                .collect();
+
+            Some((keys, ixs))
+        }
+    }
+}
+
+/// Addresses a v0 transaction loaded from address lookup tables, in the
+/// order the runtime appends them to the account list.
+fn loaded_addresses(tx: &EncodedTransactionWithStatusMeta) -> Vec<String> {
+    match tx.meta.as_ref().map(|m| &m.loaded_addresses) {
+        Some(OptionSerializer::Some(loaded)) => loaded
+            .writable
+            .iter()
+            .chain(loaded.readonly.iter())
+            .cloned()
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn compiled_parts(
+    keys: &[String],
+    ix: &solana_transaction_status::UiCompiledInstruction,
+) -> (String, Vec<String>, String) {
+    let key = |i: u8| keys.get(i as usize).cloned().unwrap_or_default();
+
+    (
+        key(ix.program_id_index),
+        ix.accounts.iter().map(|i| key(*i)).collect(),
+        ix.data.clone(),
+    )
+}
+
+fn post_token_balance(
+    tx: &EncodedTransactionWithStatusMeta,
+    account_keys: &[String],
+    token_account: &str,
+) -> Option<u64> {
+    let balances = match &tx.meta.as_ref()?.post_token_balances {
+        OptionSerializer::Some(b) => b,
+        _ => return None,
+    };
+
+    balances
+        .iter()
+        .find(|b| account_keys.get(b.account_index as usize).map(String::as_str) == Some(token_account))
+        .and_then(|b| b.ui_token_amount.amount.parse::<u64>().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_transaction_status::UiCompiledInstruction;
+
+    #[test]
+    fn test_compiled_parts_resolves_indices() {
+        let keys = vec!["user".to_string(), "vault".to_string(), "program".to_string()];
+        let ix = UiCompiledInstruction {
+            program_id_index: 2,
+            accounts: vec![0, 1],
+            data: bs58::encode(WITHDRAW_IX).into_string(),
+            stack_height: None,
+        };
+
+        let (program, accounts, _) = compiled_parts(&keys, &ix);
+        assert_eq!(program, "program");
+        assert_eq!(accounts, vec!["user", "vault"]);
+    }
+
+    #[test]
+    fn test_truncated_logs_keep_cpi_lock() {
+        let program = Pubkey::new_unique();
+        let caller = Pubkey::new_unique();
+        let vault = Pubkey::new_unique();
+
+        let mut lock = LOCK_IX.to_vec();
+        lock.extend_from_slice(&700u64.to_le_bytes());
+
+        // The caller program's instruction locks through a CPI into the vault
+        let tx: EncodedTransactionWithStatusMeta = serde_json::from_value(serde_json::json!({
+            "transaction": {
+                "signatures": [],
+                "message": {
+                    "header": {
+                        "numRequiredSignatures": 1,
+                        "numReadonlySignedAccounts": 0,
+                        "numReadonlyUnsignedAccounts": 3
+                    },
+                    "accountKeys": [
+                        Pubkey::new_unique().to_string(),
+                        vault.to_string(),
+                        caller.to_string(),
+                        program.to_string(),
+                        Pubkey::new_unique().to_string()
+                    ],
+                    "recentBlockhash": "11111111111111111111111111111111",
+                    "instructions": [{
+                        "programIdIndex": 2,
+                        "accounts": [1],
+                        "data": "",
+                        "stackHeight": null
+                    }]
+                }
+            },
+            "meta": {
+                "err": null,
+                "status": { "Ok": null },
+                "fee": 5000,
+                "preBalances": [],
+                "postBalances": [],
+                "logMessages": ["Log truncated"],
+                "innerInstructions": [{
+                    "index": 0,
+                    "instructions": [{
+                        "programIdIndex": 3,
+                        "accounts": [2, 1, 4],
+                        "data": bs58::encode(&lock).into_string(),
+                        "stackHeight": 2
+                    }]
+                }]
+            }
+        }))
+        .unwrap();
+
+        assert!(logs_truncated(&tx));
+
+        // The logs still showed a deposit, and the lock before they ran out
+        let logged = vec![
+            VaultEvent::Deposit {
+                user: "user".to_string(),
+                amount: 5,
+                new_balance: 5,
+                timestamp: 0,
+            },
+            VaultEvent::Lock {
+                vault: vault.to_string(),
+                amount: 700,
+            },
+        ];
+
+        let events = replace_truncated(logged, &tx, &program, None).unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], VaultEvent::Deposit { amount: 5, .. }));
+        match &events[1] {
+            VaultEvent::Lock { vault: v, amount } => {
+                assert_eq!(v, &vault.to_string());
+                assert_eq!(*amount, 700);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_compiled_parts_missing_index_is_empty() {
+        let keys = vec!["program".to_string()];
+        let ix = UiCompiledInstruction {
+            program_id_index: 0,
+            accounts: vec![3],
+            data: String::new(),
+            stack_height: None,
+        };
+
+        // Indices past the static keys belong to lookup tables; without them
+        // the account can't be named
+        let (_, accounts, _) = compiled_parts(&keys, &ix);
+        assert_eq!(accounts, vec![""]);
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tracing::warn;
+
+/// Callback invoked with the current lag (in slots) when it crosses the threshold.
+pub type LagAlertCallback = Arc<dyn Fn(u64) + Send + Sync>;
+
+/// Tracks how far the indexer is behind the chain tip.
+///
+/// The alert callback fires once when lag goes above the threshold and is
+/// re-armed when lag drops back under it, so a stuck indexer doesn't flood
+/// whatever the callback pages.
+pub struct LagMonitor {
+    threshold_slots: u64,
+    on_alert: Option<LagAlertCallback>,
+    alerting: AtomicBool,
+}
+
+impl LagMonitor {
+    pub fn new(threshold_slots: u64) -> Self {
+        Self {
+            threshold_slots,
+            on_alert: None,
+            alerting: AtomicBool::new(false),
+        }
+    }
+
+    pub fn with_alert(mut self, on_alert: LagAlertCallback) -> Self {
+        self.on_alert = Some(on_alert);
+        self
+    }
+
+    /// Record the chain slot and the indexer's last processed slot, returning the lag.
+    pub fn observe(&self, chain_slot: u64, last_processed_slot: u64) -> u64 {
+        let lag = chain_slot.saturating_sub(last_processed_slot);
+
+        metrics::gauge!("indexer_chain_slot").set(chain_slot as f64);
+        metrics::gauge!("indexer_last_processed_slot").set(last_processed_slot as f64);
+        metrics::gauge!("indexer_lag_slots").set(lag as f64);
+
+        if lag > self.threshold_slots {
+            if !self.alerting.swap(true, Ordering::SeqCst) {
+                warn!(
+                    "indexer is {} slots behind the chain (threshold {})",
+                    lag, self.threshold_slots
+                );
+                if let Some(on_alert) = &self.on_alert {
+                    on_alert(lag);
+                }
+            }
+        } else {
+            self.alerting.store(false, Ordering::SeqCst);
+        }
+
+        lag
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU64;
+
+    #[test]
+    fn test_alert_fires_once_until_recovered() {
+        let fired = Arc::new(AtomicU64::new(0));
+        let fired_cb = fired.clone();
+
+        let monitor = LagMonitor::new(100).with_alert(Arc::new(move |_| {
+            fired_cb.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        assert_eq!(monitor.observe(1_000, 950), 50);
+        assert_eq!(fired.load(Ordering::SeqCst), 0);
+
+        monitor.observe(1_000, 800);
+        monitor.observe(1_010, 800);
+        assert_eq!(fired.load(Ordering::SeqCst), 1);
+
+        // Recovers, then falls behind again
+        monitor.observe(1_020, 1_000);
+        monitor.observe(1_300, 1_000);
+        assert_eq!(fired.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,3 +1,18 @@
 pub mod vault_indexer;
 pub mod event_decoder;
 pub mod process_transaction;
+pub mod finality;
+pub mod lag;
+pub mod snapshot_scheduler;
+pub mod idl_decoder;
+pub mod batch_fetch;
+pub mod rate_limit;
+pub mod replay;
+pub mod instruction_decoder;
+pub mod event_filter;
+pub mod block_ingest;
+pub mod pruning;
+pub mod token_delta;
+
+pub mod partition_maintenance;
+pub mod service;
\ No newline at end of file
@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use sqlx::PgPool;
+use tracing::{info, warn};
+
+use crate::db::partitions;
+
+/// Periodically creates `transactions` partitions ahead of time, so inserts
+/// never fall through to the default partition.
+pub struct PartitionMaintainer {
+    pool: PgPool,
+    months_ahead: u32,
+    interval: Duration,
+}
+
+impl PartitionMaintainer {
+    pub fn new(pool: PgPool, months_ahead: u32, interval: Duration) -> Self {
+        Self {
+            pool,
+            months_ahead,
+            interval,
+        }
+    }
+
+    /// Create any missing partitions. Returns how many were created.
+    pub async fn run_once(&self) -> anyhow::Result<u32> {
+        let today = Utc::now().date_naive();
+
+        let created =
+            partitions::ensure_transaction_partitions(&self.pool, today, self.months_ahead)
+                .await?;
+
+        if created > 0 {
+            info!("created {} transactions partitions", created);
+        }
+
+        Ok(created)
+    }
+
+    /// Run forever, checking once per interval.
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let mut ticker = tokio::time::interval(self.interval);
+
+        loop {
+            ticker.tick().await;
+
+            if let Err(e) = self.run_once().await {
+                warn!("transactions partition maintenance failed: {}", e);
+            }
+        }
+    }
+}
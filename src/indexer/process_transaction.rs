@@ -1,146 +1,587 @@
-use sqlx::PgPool;
-use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use solana_sdk::commitment_config::CommitmentLevel;
+use solana_sdk::pubkey::Pubkey;
+use sqlx::{PgConnection, PgPool};
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransactionWithStatusMeta,
+    UiTransactionStatusMeta,
+};
+use uuid::Uuid;
 
 use crate::db::{
-    processed_events::ProcessedEventsRepo,
-    snapshot_repo::SnapshotRepository,
-    transaction_repo::TransactionRepository,
-    vault_repo::VaultRepository,
+    error as db_error,
+    processed_events::{self, AppliedEventRow, ProcessedEventsRepo},
+    reconciliation_repo::{self, DiscrepancyComponent, NewDiscrepancy},
+    transaction_repo,
+    vault_repo,
+    withdrawal_cap_repo,
+};
+use crate::error_handling::{VaultError, VaultResult};
+use crate::indexer::event_decoder::{
+    decode_events, decode_inner_instructions, decode_log_messages, VaultEvent,
 };
-use crate::indexer::event_decoder::{decode_events, VaultEvent};
+use crate::indexer::event_filter::EventFilter;
+use crate::indexer::instruction_decoder;
+use crate::indexer::token_delta;
+use crate::network::TokenProgram;
+use crate::reconciliation::tolerance::{SeverityThresholds, ToleranceConfig};
 use crate::transaction_builder::TransactionBuilder;
 
+/// Everything needed to index a transaction besides the transaction itself.
+pub struct IndexContext<'a> {
+    pub pool: &'a PgPool,
+    pub program_id: &'a Pubkey,
+    pub filter: &'a EventFilter,
+    /// Network label stored on new vault rows, e.g. "mainnet-beta".
+    pub network: &'a str,
+    /// Token program vault token accounts are derived with.
+    pub token_program: TokenProgram,
+    /// Commitment the transaction was observed at; stored with every indexed row.
+    pub commitment: CommitmentLevel,
+    /// Drift tolerances and severity tiers, the same ones the reconciler uses.
+    pub tolerance: &'a ToleranceConfig,
+}
+
+/// Name a commitment level is stored under.
+pub fn commitment_label(commitment: CommitmentLevel) -> &'static str {
+    match commitment {
+        CommitmentLevel::Processed => "processed",
+        CommitmentLevel::Confirmed => "confirmed",
+        CommitmentLevel::Finalized => "finalized",
+    }
+}
+
+/// Index a fetched transaction. `tx_index` is its position in the block, if
+/// known.
+#[tracing::instrument(name = "index_transaction", skip_all, fields(signature = %signature))]
 pub async fn process_transaction(
     tx: &EncodedConfirmedTransactionWithStatusMeta,
     signature: &str,
-    pool: &PgPool,
-    program_id: &solana_sdk::pubkey::Pubkey,
+    tx_index: Option<i32>,
+    ctx: &IndexContext<'_>,
 ) -> anyhow::Result<()> {
-    let processed_repo = ProcessedEventsRepo::new(pool);
+    let processed_repo = ProcessedEventsRepo::new(ctx.pool);
 
     if processed_repo.is_processed(&signature).await? {
         return Ok(()); // already indexed
     }
 
-    let events = decode_events(&tx.transaction)?;
+    // A failed transaction changed nothing on-chain, even if its logs show
+    // events emitted before it failed; mark it so it isn't fetched again
+    if tx
+        .transaction
+        .meta
+        .as_ref()
+        .is_some_and(|meta| meta.err.is_some())
+    {
+        let commitment = commitment_label(ctx.commitment);
+        return processed_repo
+            .mark_processed_at(signature, tx.slot as i64, commitment)
+            .await;
+    }
 
-    let tx_repo = TransactionRepository::new(pool);
-    let vault_repo = VaultRepository::new(pool);
-    let snapshot_repo = SnapshotRepository::new(pool);
+    // Every stored row is dated by the block time, so without one the
+    // signature stays unprocessed and the gap audit picks it up again
+    let Some(block_time) = tx.block_time else {
+        tracing::warn!("no block time for {} yet, deferring it", signature);
+        metrics::counter!("indexer_deferred_transactions_total").increment(1);
+        return Ok(());
+    };
 
-    let tx_builder = TransactionBuilder::new(*program_id);
+    let mut events = decode_events(&tx.transaction, ctx.program_id)?;
 
-    let slot = tx.slot as i64;
-    let block_time = tx.block_time.unwrap_or(0);
+    // Truncated logs lose trailing events; rebuild the balance-affecting ones
+    // from instruction data instead (emit_cpi events are never truncated).
+    if instruction_decoder::logs_truncated(&tx.transaction)
+        && !has_inner_instruction_events(&tx.transaction, ctx.program_id)
+    {
+        tracing::warn!("logs truncated for {}, decoding instruction data", signature);
 
-    for event in events {
-        match event {
-            VaultEvent::VaultInitialized {
-                vault,
-                owner,
-                mint,
-                timestamp,
-            } => {
-                vault_repo
-                    .insert_new_vault(&vault, &owner, &mint, timestamp)
-                    .await?;
-            }
+        events = instruction_decoder::replace_truncated(
+            events,
+            &tx.transaction,
+            ctx.program_id,
+            tx.block_time,
+        )?;
+    }
 
-            VaultEvent::Deposit {
-                user,
-                amount,
-                new_balance,
-                timestamp,
-            } => {
-                let (vault_pda, _) = tx_builder.derive_vault_pda(&user.parse()?);
-
-                tx_repo
-                    .insert_simple(
-                        &vault_pda.to_string(),
-                        Some(&user),
-                        &signature,
-                        "deposit",
-                        amount as i64,
-                        slot,
-                        block_time,
-                    )
-                    .await?;
-
-                vault_repo
-                    .set_balance_from_event(
-                        &vault_pda.to_string(),
-                        new_balance as i64,
-                        timestamp,
-                    )
-                    .await?;
-            }
+    let meta = tx.transaction.meta.as_ref();
 
-            VaultEvent::Withdraw {
-                vault,
-                user,
-                amount,
-            } => {
-                tx_repo
-                    .insert_simple(
-                        &vault,
-                        Some(&user),
-                        &signature,
-                        "withdraw",
-                        amount as i64,
-                        slot,
-                        block_time,
-                    )
-                    .await?;
-
-                vault_repo
-                    .apply_withdraw(&vault, amount as i64)
-                    .await?;
-            }
+    apply_events(events, signature, tx.slot, tx_index, block_time, meta, ctx).await
+}
 
-            VaultEvent::Lock { vault, amount } => {
-                vault_repo.apply_lock(&vault, amount as i64).await?;
-            }
+fn has_inner_instruction_events(
+    tx: &EncodedTransactionWithStatusMeta,
+    program_id: &Pubkey,
+) -> bool {
+    decode_inner_instructions(tx, program_id)
+        .map(|events| !events.is_empty())
+        .unwrap_or(false)
+}
 
-            VaultEvent::Unlock { vault, amount } => {
-                vault_repo.apply_unlock(&vault, amount as i64).await?;
-            }
+/// Index a transaction from a `logsSubscribe` notification.
+///
+/// The notification only carries logs and the slot; `block_time` is the
+/// slot's, looked up by the caller.
+#[tracing::instrument(name = "index_logs", skip_all, fields(signature = %signature, slot))]
+pub async fn process_logs(
+    logs: &[String],
+    signature: &str,
+    slot: u64,
+    tx_index: Option<i32>,
+    block_time: i64,
+    ctx: &IndexContext<'_>,
+) -> anyhow::Result<()> {
+    let processed_repo = ProcessedEventsRepo::new(ctx.pool);
+
+    if processed_repo.is_processed(signature).await? {
+        return Ok(()); // already indexed
+    }
+
+    let events = decode_log_messages(logs)?;
 
-            VaultEvent::Transfer { from, to, amount } => {
-                vault_repo
-                    .apply_transfer(&from, &to, amount as i64)
-                    .await?;
+    apply_events(events, signature, slot, tx_index, block_time, None, ctx).await
+}
+
+/// Times a signature's events are re-applied after losing a serialization
+/// race (or deadlock) to a concurrent writer.
+const MAX_SERIALIZATION_RETRIES: u32 = 3;
+
+/// Apply decoded events, running the whole database transaction again when
+/// Postgres aborts it as a serialization failure. The aborted attempt was
+/// rolled back, so nothing is applied twice.
+async fn apply_events(
+    events: Vec<VaultEvent>,
+    signature: &str,
+    slot: u64,
+    tx_index: Option<i32>,
+    block_time: i64,
+    meta: Option<&UiTransactionStatusMeta>,
+    ctx: &IndexContext<'_>,
+) -> anyhow::Result<()> {
+    let started = std::time::Instant::now();
+    let mut attempt = 0;
+
+    loop {
+        match apply_events_once(&events, signature, slot, tx_index, block_time, meta, ctx).await {
+            Err(e)
+                if db_error::is_serialization_failure(&e)
+                    && attempt < MAX_SERIALIZATION_RETRIES =>
+            {
+                attempt += 1;
+                metrics::counter!("indexer_serialization_retries_total").increment(1);
+                tracing::warn!(
+                    "serialization failure indexing {}, retrying: {}",
+                    signature,
+                    e
+                );
             }
+            result => {
+                let outcome = if result.is_ok() { "indexed" } else { "failed" };
+                metrics::counter!("indexer_transactions_total", "outcome" => outcome).increment(1);
+                metrics::histogram!("indexer_apply_duration_seconds")
+                    .record(started.elapsed().as_secs_f64());
 
-            VaultEvent::ProgramAuthorized { .. } => {
-                // Optional: persist for analytics / audit
+                return result;
             }
+        }
+    }
+}
+
+/// Apply decoded events to the off-chain state and mark the signature processed.
+///
+/// All balance mutations, the per-event idempotency keys and the
+/// `processed_events` row are written in one database transaction, so a crash
+/// mid-way leaves nothing half-applied. The `(signature, event_index)` key is
+/// recorded before each event is applied and skips events that were already
+/// applied, e.g. when a signature is replayed after `processed_events` pruning.
+/// Events rejected by `filter` are skipped; the signature is still marked
+/// processed so it isn't fetched again.
+///
+/// With the transaction's `meta`, the token balance changes are checked
+/// against the events in the same database transaction, so a mismatch is
+/// recorded for the reconciler whenever the events are applied.
+async fn apply_events_once(
+    events: &[VaultEvent],
+    signature: &str,
+    slot: u64,
+    tx_index: Option<i32>,
+    block_time: i64,
+    meta: Option<&UiTransactionStatusMeta>,
+    ctx: &IndexContext<'_>,
+) -> anyhow::Result<()> {
+    let tx_builder = TransactionBuilder::new(*ctx.program_id).with_token_program(ctx.token_program);
+    let filter = ctx.filter;
+    let commitment = commitment_label(ctx.commitment);
+
+    let slot = slot as i64;
+
+    let mut db_tx = ctx.pool.begin().await?;
+
+    for (index, event) in events.iter().enumerate() {
+        let effect = event_effect(event, &tx_builder)?;
 
-            VaultEvent::VaultAuthorityInitialized { .. } => {
-                // Optional: persist authority metadata
+        if !filter.is_empty() && !is_allowed(&mut db_tx, filter, event, effect.as_ref()).await? {
+            continue;
+        }
+
+        if let Some((event_type, vault_pda, counterparty_vault, amount)) = effect {
+            let first_application = processed_events::record_applied_event(
+                &mut *db_tx,
+                &AppliedEventRow {
+                    tx_signature: signature.to_string(),
+                    event_index: index as i32,
+                    event_type: event_type.to_string(),
+                    vault_pda,
+                    counterparty_vault,
+                    amount,
+                    slot,
+                    tx_index,
+                },
+            )
+            .await?;
+
+            if !first_application {
+                continue;
             }
         }
+
+        apply_event(&mut db_tx, event.clone(), signature, slot, block_time, ctx, &tx_builder)
+            .await?;
+    }
+
+    // After the events, so the vault rows they create exist
+    if let Some(meta) = meta {
+        token_delta::cross_check(
+            &mut db_tx,
+            meta,
+            events,
+            &tx_builder,
+            ctx.tolerance,
+            signature,
+        )
+        .await?;
     }
 
-    // Simple snapshotting strategy: snapshot all vaults at this transaction's time.
-    // In a real system you might throttle this (e.g. hourly).
-    if let Some(block_time) = tx.block_time {
-        use chrono::{DateTime, Utc};
-        use crate::db::vault_repo::VaultRow;
-
-        let ts = {
-            let utc_dt = DateTime::<Utc>::from_timestamp(block_time, 0)
-                .unwrap_or_else(|| Utc::now());
-            utc_dt.naive_utc()
-        };
-
-        let all_vaults: Vec<VaultRow> = vault_repo.get_all_vaults().await?;
-        snapshot_repo
-            .snapshot_all_vaults(&all_vaults, ts)
+    processed_events::mark_processed_at(&mut *db_tx, signature, slot, commitment).await?;
+
+    db_tx.commit().await?;
+
+    Ok(())
+}
+
+/// Check an event against the configured filter, resolving the vault's mint
+/// from the event or the indexed vault row when mint rules are set.
+async fn is_allowed(
+    conn: &mut PgConnection,
+    filter: &EventFilter,
+    event: &VaultEvent,
+    effect: Option<&(&'static str, String, Option<String>, i64)>,
+) -> anyhow::Result<bool> {
+    let vault = effect.map(|(_, vault_pda, _, _)| vault_pda.as_str());
+
+    let mint = match event {
+        VaultEvent::VaultInitialized { mint, .. } => Some(mint.clone()),
+        _ if filter.filters_mints() => match vault {
+            Some(v) => vault_repo::get_vault_mint(conn, v).await?,
+            None => None,
+        },
+        _ => None,
+    };
+
+    Ok(filter.allows(event, vault, mint.as_deref()))
+}
+
+/// Balance effect of an event as `(event_type, vault, counterparty, amount)`,
+/// recorded so the event is applied once and can be reversed on rollback.
+fn event_effect(
+    event: &VaultEvent,
+    tx_builder: &TransactionBuilder,
+) -> anyhow::Result<Option<(&'static str, String, Option<String>, i64)>> {
+    let effect = match event {
+        VaultEvent::VaultInitialized { vault, .. } => Some(("initialize", vault.clone(), None, 0)),
+
+        VaultEvent::Deposit { user, amount, .. } => {
+            let (vault_pda, _) = tx_builder.derive_vault_pda(&user.parse()?);
+            Some(("deposit", vault_pda.to_string(), None, *amount as i64))
+        }
+
+        VaultEvent::Withdraw { vault, amount, .. } => {
+            Some(("withdraw", vault.clone(), None, *amount as i64))
+        }
+
+        VaultEvent::Lock { vault, amount } => Some(("lock", vault.clone(), None, *amount as i64)),
+
+        VaultEvent::Unlock { vault, amount } => {
+            Some(("unlock", vault.clone(), None, *amount as i64))
+        }
+
+        VaultEvent::Transfer { from, to, amount } => {
+            Some(("transfer", from.clone(), Some(to.clone()), *amount as i64))
+        }
+
+        VaultEvent::VaultClosed { vault, .. } => Some(("close", vault.clone(), None, 0)),
+
+        VaultEvent::ProgramAuthorized { .. }
+        | VaultEvent::VaultAuthorityInitialized { .. }
+        | VaultEvent::Unknown { .. } => None,
+    };
+
+    Ok(effect)
+}
+
+async fn apply_event(
+    conn: &mut PgConnection,
+    event: VaultEvent,
+    signature: &str,
+    slot: i64,
+    block_time: i64,
+    ctx: &IndexContext<'_>,
+    tx_builder: &TransactionBuilder,
+) -> anyhow::Result<()> {
+    let commitment = commitment_label(ctx.commitment);
+    let payload = serde_json::to_value(&event)?;
+
+    match event {
+        VaultEvent::VaultInitialized {
+            vault,
+            owner,
+            mint,
+            timestamp,
+        } => {
+            let vault_token_account =
+                tx_builder.derive_token_account(&vault.parse()?, &mint.parse()?);
+
+            let new_vault = vault_repo::NewVault {
+                vault_pda: &vault,
+                owner_pubkey: &owner,
+                mint: &mint,
+                vault_token_account: &vault_token_account.to_string(),
+                program_id: &ctx.program_id.to_string(),
+                network: ctx.network,
+                timestamp,
+            };
+
+            vault_repo::insert_new_vault(conn, &new_vault).await?;
+        }
+
+        VaultEvent::Deposit {
+            user,
+            amount,
+            new_balance,
+            timestamp,
+        } => {
+            let (vault_pda, _) = tx_builder.derive_vault_pda(&user.parse()?);
+
+            transaction_repo::insert_simple(
+                conn,
+                &vault_pda.to_string(),
+                Some(&user),
+                signature,
+                "deposit",
+                amount as i64,
+                slot,
+                block_time,
+                commitment,
+                Some(&payload),
+            )
+            .await?;
+
+            let vault_pda = vault_pda.to_string();
+            let result =
+                vault_repo::set_balance_from_event(conn, &vault_pda, new_balance as i64, timestamp)
+                    .await;
+            record_rejected(
+                conn,
+                &vault_pda,
+                DiscrepancyComponent::Total,
+                signature,
+                &ctx.tolerance.severity,
+                result,
+            )
+            .await?;
+        }
+
+        VaultEvent::Withdraw {
+            vault,
+            user,
+            amount,
+        } => {
+            transaction_repo::insert_simple(
+                conn,
+                &vault,
+                Some(&user),
+                signature,
+                "withdraw",
+                amount as i64,
+                slot,
+                block_time,
+                commitment,
+                Some(&payload),
+            )
+            .await?;
+
+            let result = vault_repo::apply_withdraw(conn, &vault, amount as i64).await;
+            record_rejected(
+                conn,
+                &vault,
+                DiscrepancyComponent::Available,
+                signature,
+                &ctx.tolerance.severity,
+                result,
+            )
+            .await?;
+
+            flag_cap_breach(conn, &vault, signature, amount as i64, block_time).await?;
+        }
+
+        VaultEvent::Lock { vault, amount } => {
+            let result = vault_repo::apply_lock(conn, &vault, amount as i64).await;
+            record_rejected(
+                conn,
+                &vault,
+                DiscrepancyComponent::Available,
+                signature,
+                &ctx.tolerance.severity,
+                result,
+            )
+            .await?;
+        }
+
+        VaultEvent::Unlock { vault, amount } => {
+            let result = vault_repo::apply_unlock(conn, &vault, amount as i64).await;
+            record_rejected(
+                conn,
+                &vault,
+                DiscrepancyComponent::Locked,
+                signature,
+                &ctx.tolerance.severity,
+                result,
+            )
+            .await?;
+        }
+
+        VaultEvent::Transfer { from, to, amount } => {
+            // The receiving vault is credited even if the sender's debit is rejected
+            let result = vault_repo::apply_transfer_out(conn, &from, amount as i64).await;
+            record_rejected(
+                conn,
+                &from,
+                DiscrepancyComponent::Available,
+                signature,
+                &ctx.tolerance.severity,
+                result,
+            )
             .await?;
+            vault_repo::apply_transfer_in(conn, &to, amount as i64).await?;
+        }
+
+        VaultEvent::VaultClosed { vault, .. } => {
+            vault_repo::close_vault(conn, &vault).await?;
+        }
+
+        VaultEvent::ProgramAuthorized { .. } => {
+            // Optional: persist for analytics / audit
+        }
+
+        VaultEvent::VaultAuthorityInitialized { .. } => {
+            // Optional: persist authority metadata
+        }
+
+        VaultEvent::Unknown { .. } => {
+            // Decodable from the IDL but has no balance effect we know of
+        }
     }
 
-    processed_repo.mark_processed(&signature).await?;
+    Ok(())
+}
+
+/// Let an on-chain balance change that our balances can't absorb through.
+///
+/// The chain already made the change, so failing here would only retry the
+/// signature forever and stall indexing behind it. Instead the shortfall is
+/// recorded in `reconciliation_logs` for the reconciler (the state mismatch
+/// was logged when the update was rejected) and the rest of the transaction
+/// is indexed as usual. Any other error is returned.
+async fn record_rejected<T>(
+    conn: &mut PgConnection,
+    vault_pda: &str,
+    component: DiscrepancyComponent,
+    signature: &str,
+    severity: &SeverityThresholds,
+    result: VaultResult<T>,
+) -> anyhow::Result<()> {
+    let (required, available) = match result {
+        Ok(_) => return Ok(()),
+        Err(VaultError::InsufficientBalance {
+            required,
+            available,
+        }) => (required as i64, available as i64),
+        Err(e) => return Err(e.into()),
+    };
+
+    tracing::warn!(
+        "{} needs {} of vault {}'s {} balance but only {} is indexed, recording a discrepancy",
+        signature,
+        required,
+        vault_pda,
+        component.as_str(),
+        available
+    );
+    metrics::counter!("indexer_rejected_changes_total").increment(1);
+
+    // An insufficient balance means the row exists
+    let Some(vault) = vault_repo::get_vault(conn, vault_pda).await? else {
+        return Ok(());
+    };
+
+    reconciliation_repo::insert_discrepancy(
+        &mut *conn,
+        &NewDiscrepancy {
+            id: Uuid::new_v4(),
+            vault_pda,
+            program_id: &vault.program_id,
+            network: &vault.network,
+            component,
+            severity: severity.classify(required, available),
+            onchain_balance: required,
+            offchain_balance: available,
+            discrepancy: required - available,
+        },
+    )
+    .await?;
 
     Ok(())
 }
 
+/// Record a withdrawal that took its vault over the daily cap. The API
+/// refuses to build those, so it bypassed the API.
+async fn flag_cap_breach(
+    conn: &mut PgConnection,
+    vault: &str,
+    signature: &str,
+    amount: i64,
+    block_time: i64,
+) -> anyhow::Result<()> {
+    let at = chrono::DateTime::from_timestamp(block_time, 0)
+        .ok_or_else(|| anyhow::anyhow!("invalid block time {}", block_time))?
+        .naive_utc();
+    let mint = vault_repo::get_vault_mint(conn, vault).await?.unwrap_or_default();
+
+    let status = match withdrawal_cap_repo::daily_cap_status(conn, vault, &mint, at).await? {
+        Some(status) if status.withdrawn > status.daily_cap => status,
+        _ => return Ok(()),
+    };
+
+    tracing::warn!(
+        "withdrawal {} took vault {} to {} in a day, over its cap of {}",
+        signature,
+        vault,
+        status.withdrawn,
+        status.daily_cap
+    );
+
+    withdrawal_cap_repo::record_breach(conn, signature, vault, amount, &status).await?;
+    metrics::counter!("indexer_withdrawal_cap_breaches_total").increment(1);
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use sqlx::PgPool;
+use tracing::{info, warn};
+
+use crate::db::processed_events;
+
+/// How long finalized signatures stay in `processed_events`.
+///
+/// Either bound may be unset. Only finalized rows are ever pruned, and the
+/// per-event keys in `applied_events` are kept, so a pruned signature that is
+/// fetched again (gap audit, backfill) is recognised and not re-applied.
+#[derive(Clone, Debug)]
+pub struct RetentionPolicy {
+    pub max_age: Option<Duration>,
+    pub max_rows: Option<i64>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age: Some(Duration::from_secs(30 * 24 * 3600)),
+            max_rows: None,
+        }
+    }
+}
+
+/// Periodically prunes `processed_events` according to a `RetentionPolicy`.
+pub struct ProcessedEventsPruner {
+    pool: PgPool,
+    policy: RetentionPolicy,
+    interval: Duration,
+}
+
+impl ProcessedEventsPruner {
+    pub fn new(pool: PgPool, policy: RetentionPolicy, interval: Duration) -> Self {
+        Self {
+            pool,
+            policy,
+            interval,
+        }
+    }
+
+    /// Prune once. Returns the number of rows removed.
+    pub async fn run_once(&self) -> anyhow::Result<u64> {
+        if self.policy.max_age.is_none() && self.policy.max_rows.is_none() {
+            return Ok(0);
+        }
+
+        let finalized_before = match self.policy.max_age {
+            Some(age) => Some((Utc::now() - chrono::Duration::from_std(age)?).naive_utc()),
+            None => None,
+        };
+
+        let pruned =
+            processed_events::prune(&self.pool, finalized_before, self.policy.max_rows).await?;
+
+        if pruned > 0 {
+            info!("pruned {} processed_events rows", pruned);
+        }
+
+        Ok(pruned)
+    }
+
+    /// Run forever, pruning once per interval.
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let mut ticker = tokio::time::interval(self.interval);
+
+        loop {
+            ticker.tick().await;
+
+            if let Err(e) = self.run_once().await {
+                warn!("processed_events pruning failed: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// Rate limit for a single RPC endpoint.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimitConfig {
+    pub requests_per_sec: f64,
+    pub burst: f64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_sec: 10.0,
+            burst: 20.0,
+        }
+    }
+}
+
+/// Parse per-endpoint limits in the form `url=rps:burst,url=rps:burst`.
+pub fn parse_endpoint_limits(raw: &str) -> anyhow::Result<HashMap<String, RateLimitConfig>> {
+    let mut limits = HashMap::new();
+
+    for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (url, limit) = entry
+            .rsplit_once('=')
+            .ok_or_else(|| anyhow::anyhow!("invalid rate limit entry: {}", entry))?;
+        let (rps, burst) = limit
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("rate limit must be rps:burst, got {}", limit))?;
+
+        limits.insert(
+            url.trim().to_string(),
+            RateLimitConfig {
+                requests_per_sec: rps.trim().parse()?,
+                burst: burst.trim().parse()?,
+            },
+        );
+    }
+
+    Ok(limits)
+}
+
+/// Whether an error looks like the RPC provider throttling us.
+pub fn is_rate_limit_error(error: &anyhow::Error) -> bool {
+    let msg = error.to_string().to_lowercase();
+    msg.contains("429") || msg.contains("rate limit") || msg.contains("too many requests")
+}
+
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+    current_rate: f64,
+}
+
+/// Token bucket with adaptive rate (additive increase, multiplicative decrease).
+///
+/// Every observed 429 halves the refill rate (down to a floor) and drains the
+/// bucket; every success nudges the rate back towards the configured limit.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    min_rate: f64,
+    state: Mutex<BucketState>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        let state = BucketState {
+            tokens: config.burst,
+            last_refill: Instant::now(),
+            current_rate: config.requests_per_sec,
+        };
+
+        Self {
+            min_rate: (config.requests_per_sec / 16.0).max(0.1),
+            config,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Wait until a request may be sent.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+
+                let now = Instant::now();
+                let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+                state.tokens = (state.tokens + elapsed * state.current_rate).min(self.config.burst);
+                state.last_refill = now;
+
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - state.tokens) / state.current_rate)
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    pub async fn on_rate_limited(&self) {
+        let mut state = self.state.lock().await;
+        state.current_rate = (state.current_rate / 2.0).max(self.min_rate);
+        state.tokens = 0.0;
+
+        warn!(
+            "RPC rate limited, slowing down to {:.2} req/s",
+            state.current_rate
+        );
+    }
+
+    pub async fn on_success(&self) {
+        let mut state = self.state.lock().await;
+        let step = self.config.requests_per_sec * 0.05;
+        state.current_rate = (state.current_rate + step).min(self.config.requests_per_sec);
+    }
+
+    pub async fn current_rate(&self) -> f64 {
+        self.state.lock().await.current_rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_endpoint_limits() {
+        let limits =
+            parse_endpoint_limits("https://api.devnet.solana.com=5:10, http://127.0.0.1:8899=100:200")
+                .unwrap();
+
+        assert_eq!(
+            limits["https://api.devnet.solana.com"],
+            RateLimitConfig {
+                requests_per_sec: 5.0,
+                burst: 10.0
+            }
+        );
+        assert_eq!(limits["http://127.0.0.1:8899"].requests_per_sec, 100.0);
+    }
+
+    #[test]
+    fn test_is_rate_limit_error() {
+        assert!(is_rate_limit_error(&anyhow::anyhow!("HTTP status client error (429 Too Many Requests)")));
+        assert!(!is_rate_limit_error(&anyhow::anyhow!("Invalid account")));
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_rate() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            requests_per_sec: 16.0,
+            burst: 4.0,
+        });
+
+        limiter.on_rate_limited().await;
+        assert_eq!(limiter.current_rate().await, 8.0);
+
+        for _ in 0..100 {
+            limiter.on_rate_limited().await;
+        }
+        assert_eq!(limiter.current_rate().await, 1.0);
+
+        for _ in 0..100 {
+            limiter.on_success().await;
+        }
+        assert_eq!(limiter.current_rate().await, 16.0);
+    }
+}
@@ -0,0 +1,207 @@
+use solana_client::rpc_config::{CommitmentConfig, RpcBlockConfig};
+use solana_transaction_status::TransactionDetails;
+use sqlx::{PgPool, Row};
+use tracing::{info, warn};
+
+use crate::db::vault_repo;
+use crate::rpc_endpoints::RpcFailover;
+
+/// Events are re-applied in pages of this size.
+const REPLAY_PAGE_SIZE: i64 = 5_000;
+
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    pub vaults_reset: u64,
+    pub snapshots_deleted: u64,
+    pub events_replayed: u64,
+}
+
+/// Look up the block position of indexed transactions that share a slot with
+/// another one and were indexed without it, so `replay` applies them in
+/// execution order.
+///
+/// The indexer doesn't fetch positions (only block ingestion gets them for
+/// free), since most slots hold a single program transaction and need none.
+/// This costs one `getBlock` per slot that does. Slots whose block can't be
+/// fetched keep their signature order. Returns the number of slots resolved.
+pub async fn resolve_block_positions(pool: &PgPool, rpc: &RpcFailover) -> anyhow::Result<u64> {
+    let slots: Vec<i64> = sqlx::query_scalar(
+        r#"
+        SELECT slot
+        FROM applied_events
+        GROUP BY slot
+        HAVING count(DISTINCT tx_signature) > 1
+           AND bool_or(tx_index IS NULL)
+        ORDER BY slot
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut resolved = 0;
+
+    for slot in slots {
+        let block = rpc.call(|rpc| {
+            rpc.get_block_with_config(
+                slot as u64,
+                RpcBlockConfig {
+                    encoding: None,
+                    transaction_details: Some(TransactionDetails::Signatures),
+                    rewards: Some(false),
+                    commitment: Some(CommitmentConfig::finalized()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+        });
+
+        let signatures = match block {
+            Ok(block) => block.signatures.unwrap_or_default(),
+            Err(e) => {
+                warn!(
+                    "block {} unavailable, its transactions keep signature order: {}",
+                    slot, e
+                );
+                continue;
+            }
+        };
+
+        let positions: Vec<i32> = (0..signatures.len() as i32).collect();
+
+        sqlx::query(
+            r#"
+            UPDATE applied_events a
+            SET tx_index = p.tx_index
+            FROM unnest($2::text[], $3::int[]) AS p(tx_signature, tx_index)
+            WHERE a.slot = $1
+              AND a.tx_signature = p.tx_signature
+            "#,
+        )
+        .bind(slot)
+        .bind(&signatures)
+        .bind(&positions)
+        .execute(pool)
+        .await?;
+
+        resolved += 1;
+    }
+
+    Ok(resolved)
+}
+
+/// Rebuild vault balances from the stored event history without touching the RPC.
+///
+/// Inside a single database transaction, all derived balance columns are
+/// zeroed through a `correction` ledger entry per vault, snapshots are
+/// dropped, and every row of `applied_events` is re-applied in chronological
+/// order (slot, then position in the block, then event index; see
+/// `resolve_block_positions`). Vaults closed on-chain
+/// are closed again by their replayed close events; frozen and closing
+/// vaults keep their status. Fixes to balance math can therefore be applied retroactively by
+/// running this after deploying them. Snapshots are repopulated by the next
+/// `SnapshotScheduler` pass, since every vault now looks changed.
+pub async fn replay(pool: &PgPool) -> anyhow::Result<ReplayReport> {
+    let mut report = ReplayReport::default();
+    let mut db_tx = pool.begin().await?;
+
+    // `closed` is derived from the chain and re-derived by the replayed close
+    // events; operator statuses survive the replay
+    sqlx::query(
+        r#"
+        UPDATE vaults
+        SET status = status_before_close, status_before_close = NULL
+        WHERE status = 'closed' AND status_before_close IS NOT NULL
+        "#,
+    )
+    .execute(&mut *db_tx)
+    .await?;
+
+    let vaults: Vec<String> = sqlx::query_scalar("SELECT vault_pda FROM vaults ORDER BY vault_pda")
+        .fetch_all(&mut *db_tx)
+        .await?;
+
+    for vault_pda in &vaults {
+        vault_repo::reset_balances(&mut db_tx, vault_pda).await?;
+        report.vaults_reset += 1;
+    }
+
+    report.snapshots_deleted = sqlx::query("DELETE FROM balance_snapshots")
+        .execute(&mut *db_tx)
+        .await?
+        .rows_affected();
+
+    // Transactions whose block position is unknown go last in their slot
+    let mut cursor: (i64, i32, String, i32) = (i64::MIN, i32::MIN, String::new(), -1);
+
+    loop {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                a.slot,
+                COALESCE(a.tx_index, 2147483647) AS tx_index,
+                a.tx_signature,
+                a.event_index,
+                a.event_type,
+                a.vault_pda,
+                a.counterparty_vault,
+                a.amount
+            FROM applied_events a
+            WHERE (a.slot, COALESCE(a.tx_index, 2147483647), a.tx_signature, a.event_index)
+                > ($1, $2, $3, $4)
+            ORDER BY a.slot, COALESCE(a.tx_index, 2147483647), a.tx_signature, a.event_index
+            LIMIT $5
+            "#,
+        )
+        .bind(cursor.0)
+        .bind(cursor.1)
+        .bind(&cursor.2)
+        .bind(cursor.3)
+        .bind(REPLAY_PAGE_SIZE)
+        .fetch_all(&mut *db_tx)
+        .await?;
+
+        if rows.is_empty() {
+            break;
+        }
+
+        for row in &rows {
+            let event_type: String = row.get("event_type");
+            let vault_pda: String = row.get("vault_pda");
+            let counterparty: Option<String> = row.get("counterparty_vault");
+            let amount: i64 = row.get("amount");
+
+            match event_type.as_str() {
+                "deposit" => vault_repo::apply_deposit(&mut db_tx, &vault_pda, amount).await?,
+                "withdraw" => vault_repo::apply_withdraw(&mut db_tx, &vault_pda, amount).await?,
+                "lock" => vault_repo::apply_lock(&mut db_tx, &vault_pda, amount).await?,
+                "unlock" => vault_repo::apply_unlock(&mut db_tx, &vault_pda, amount).await?,
+                "transfer" => {
+                    if let Some(to) = &counterparty {
+                        vault_repo::apply_transfer(&mut db_tx, &vault_pda, to, amount).await?;
+                    }
+                }
+                "close" => vault_repo::close_vault(&mut db_tx, &vault_pda).await?,
+                // "initialize": the vault row itself is kept, nothing to re-derive
+                _ => {}
+            }
+
+            report.events_replayed += 1;
+        }
+
+        let last = rows.last().expect("non-empty page");
+        cursor = (
+            last.get("slot"),
+            last.get("tx_index"),
+            last.get("tx_signature"),
+            last.get("event_index"),
+        );
+    }
+
+    db_tx.commit().await?;
+
+    info!(
+        "replay complete: {} vaults reset, {} snapshots dropped, {} events replayed",
+        report.vaults_reset, report.snapshots_deleted, report.events_replayed
+    );
+
+    Ok(report)
+}
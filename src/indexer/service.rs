@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use solana_client::rpc_config::CommitmentConfig;
+use sqlx::PgPool;
+use tracing::info;
+
+use crate::config::Config;
+use crate::indexer::block_ingest::IngestionMode;
+use crate::indexer::event_decoder::install_idl_decoder;
+use crate::indexer::finality::FinalityChecker;
+use crate::indexer::idl_decoder::IdlEventDecoder;
+use crate::indexer::lag::LagMonitor;
+use crate::indexer::partition_maintenance::PartitionMaintainer;
+use crate::indexer::pruning::ProcessedEventsPruner;
+use crate::indexer::snapshot_scheduler::SnapshotScheduler;
+use crate::indexer::vault_indexer::{BackfillFrom, VaultIndexer};
+use crate::rpc_endpoints::RpcRole;
+
+/// Index the program and run the indexer's background jobs (finality checks,
+/// gap audit, snapshots, pruning, partition maintenance) until one of them
+/// stops.
+///
+/// Dropping the future mid-transaction rolls back the open DB transaction,
+/// so cancelling it at any point leaves no partial state.
+pub async fn run(config: &Config, pool: PgPool) -> anyhow::Result<()> {
+    let indexer = build_indexer(config, pool.clone())?;
+
+    // Finality is judged against the finalized slot whatever the indexing
+    // commitment, so this reads at finalized
+    let finality = FinalityChecker::new(
+        config
+            .rpc_endpoints
+            .failover(RpcRole::Read, CommitmentConfig::finalized()),
+        pool.clone(),
+        Duration::from_secs(config.finality_check_interval_secs),
+    );
+
+    let snapshots = SnapshotScheduler::new(
+        pool.clone(),
+        Duration::from_secs(config.snapshot_interval_secs),
+    );
+
+    let pruner = ProcessedEventsPruner::new(
+        pool.clone(),
+        config.retention.clone(),
+        Duration::from_secs(config.prune_interval_secs),
+    );
+
+    let partitions = PartitionMaintainer::new(
+        pool,
+        config.transaction_partitions_ahead,
+        Duration::from_secs(config.partition_maintenance_interval_secs),
+    );
+
+    let poll_interval = Duration::from_secs(config.indexer_poll_interval_secs);
+    let gap_audit_interval = Duration::from_secs(config.gap_audit_interval_secs);
+
+    tokio::select! {
+        result = run_indexer(&indexer, config, poll_interval) => {
+            result.context("indexer stopped")
+        }
+        result = indexer.run_gap_audit(config.gap_audit_window, gap_audit_interval) => {
+            result.context("gap audit stopped")
+        }
+        result = finality.run() => result.context("finality checker stopped"),
+        result = snapshots.run() => result.context("snapshot scheduler stopped"),
+        result = pruner.run() => result.context("processed_events pruner stopped"),
+        result = partitions.run() => result.context("partition maintenance stopped"),
+    }
+}
+
+/// Index the program's history from `from` once, resuming an earlier
+/// backfill from the same start point if it was interrupted.
+pub async fn backfill(config: &Config, pool: PgPool, from: BackfillFrom) -> anyhow::Result<()> {
+    build_indexer(config, pool)?.backfill(from).await
+}
+
+fn build_indexer(config: &Config, pool: PgPool) -> anyhow::Result<VaultIndexer> {
+    if let Some(path) = &config.idl_path {
+        install_idl_decoder(IdlEventDecoder::from_file(path)?)?;
+        info!("using IDL-driven event decoding from {}", path);
+    }
+
+    let rpc = config.rpc_endpoints.failover(
+        RpcRole::Read,
+        CommitmentConfig {
+            commitment: config.indexer_commitment,
+        },
+    );
+
+    let mut indexer = VaultIndexer::new(rpc, pool, config.program_id)
+        .with_rate_limit(config.rate_limit_for(&config.rpc_url))
+        .with_batch_fetch(
+            config.indexer_batch_size,
+            config.indexer_backfill_concurrency,
+        )
+        .with_lag_monitor(LagMonitor::new(config.indexer_lag_alert_slots))
+        .with_event_filter(config.event_filter.clone())
+        .with_commitment(config.indexer_commitment)
+        .with_network(config.network.clone())
+        .with_token_program(config.token_program)
+        .with_tolerance(config.reconciliation_tolerance.clone());
+
+    if config.stale_vault_minutes > 0 {
+        let stale_after = Duration::from_secs(config.stale_vault_minutes * 60);
+        indexer = indexer.with_stale_vault_check(stale_after);
+    }
+
+    Ok(indexer)
+}
+
+async fn run_indexer(
+    indexer: &VaultIndexer,
+    config: &Config,
+    poll_interval: Duration,
+) -> anyhow::Result<()> {
+    match config.indexer_mode {
+        IngestionMode::Signatures => {
+            let ws_urls = config.rpc_endpoints.urls(RpcRole::Websocket);
+            indexer.run_streaming(&ws_urls, poll_interval).await
+        }
+        IngestionMode::Blocks => indexer.run_blocks(poll_interval).await,
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use sqlx::PgPool;
+use tracing::{info, warn};
+
+use crate::db::{processed_events, snapshot_repo::SnapshotRepository, vault_repo};
+
+/// Periodically snapshots vault balances, independent of transaction volume.
+///
+/// Only vaults whose `version` moved past their latest snapshot are written,
+/// so idle vaults cost nothing per tick. Each snapshot records the indexer's
+/// last processed slot, which historical verification compares it at.
+pub struct SnapshotScheduler {
+    pool: PgPool,
+    interval: Duration,
+}
+
+impl SnapshotScheduler {
+    pub fn new(pool: PgPool, interval: Duration) -> Self {
+        Self { pool, interval }
+    }
+
+    /// Snapshot every vault that changed since its last snapshot.
+    /// Returns the number of vaults snapshotted.
+    pub async fn run_once(&self) -> anyhow::Result<usize> {
+        let snapshot_repo = SnapshotRepository::new(&self.pool);
+
+        // Read the balances and the last processed slot from one database
+        // snapshot: the indexer commits each transaction's balance changes
+        // together with its processed_events row, so the slot matches the
+        // balances even while it keeps indexing.
+        let mut db_tx = self.pool.begin().await?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .execute(&mut *db_tx)
+            .await?;
+        let changed = vault_repo::get_vaults_changed_since_snapshot(&mut db_tx).await?;
+        let indexed_slot = processed_events::last_processed_slot(&mut *db_tx).await?;
+        db_tx.commit().await?;
+
+        if changed.is_empty() {
+            return Ok(0);
+        }
+
+        let snapshot_time = Utc::now().naive_utc();
+        snapshot_repo
+            .snapshot_all_vaults(&changed, snapshot_time, indexed_slot)
+            .await?;
+
+        info!("snapshotted {} changed vaults", changed.len());
+
+        Ok(changed.len())
+    }
+
+    /// Run forever, snapshotting once per interval.
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let mut ticker = tokio::time::interval(self.interval);
+
+        loop {
+            ticker.tick().await;
+
+            if let Err(e) = self.run_once().await {
+                warn!("snapshot pass failed: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{UiTransactionStatusMeta, UiTransactionTokenBalance};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::db::reconciliation_repo::{self, DiscrepancyComponent, NewDiscrepancy};
+use crate::db::vault_repo;
+use crate::indexer::event_decoder::VaultEvent;
+use crate::logging::Logger;
+use crate::reconciliation::tolerance::ToleranceConfig;
+use crate::transaction_builder::TransactionBuilder;
+
+/// Token balance of one owner before and after a transaction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OwnerBalance {
+    pub pre: i128,
+    pub post: i128,
+}
+
+impl OwnerBalance {
+    pub fn delta(&self) -> i128 {
+        self.post - self.pre
+    }
+}
+
+/// Sum `pre_token_balances` / `post_token_balances` per token account owner.
+///
+/// Accounts created by the transaction have no pre entry and count as 0.
+pub fn owner_balances(meta: &UiTransactionStatusMeta) -> HashMap<String, OwnerBalance> {
+    let mut balances: HashMap<String, OwnerBalance> = HashMap::new();
+
+    let mut add = |entries: &OptionSerializer<Vec<UiTransactionTokenBalance>>, post: bool| {
+        if let OptionSerializer::Some(entries) = entries {
+            for entry in entries {
+                let owner = match &entry.owner {
+                    OptionSerializer::Some(owner) => owner.clone(),
+                    _ => continue,
+                };
+                let amount = entry.ui_token_amount.amount.parse::<i128>().unwrap_or(0);
+
+                let balance = balances.entry(owner).or_default();
+                if post {
+                    balance.post += amount;
+                } else {
+                    balance.pre += amount;
+                }
+            }
+        }
+    };
+
+    add(&meta.pre_token_balances, false);
+    add(&meta.post_token_balances, true);
+
+    balances
+}
+
+/// Net token movement into each vault implied by the decoded events.
+pub fn expected_deltas(
+    events: &[VaultEvent],
+    tx_builder: &TransactionBuilder,
+) -> HashMap<String, i128> {
+    let mut deltas: HashMap<String, i128> = HashMap::new();
+
+    for event in events {
+        match event {
+            VaultEvent::Deposit { user, amount, .. } => {
+                if let Ok(user) = user.parse() {
+                    let (vault_pda, _) = tx_builder.derive_vault_pda(&user);
+                    *deltas.entry(vault_pda.to_string()).or_default() += *amount as i128;
+                }
+            }
+            VaultEvent::Withdraw { vault, amount, .. } => {
+                *deltas.entry(vault.clone()).or_default() -= *amount as i128;
+            }
+            _ => {}
+        }
+    }
+
+    deltas
+}
+
+/// Compare each vault's decoded movement with its actual token balance
+/// change in the transaction and record mismatches in `reconciliation_logs`
+/// for the reconciler.
+///
+/// Runs on the indexer's connection, so the discrepancy rows commit or roll
+/// back with the events they are about. Vaults that aren't indexed (e.g.
+/// filtered out) and drift within the mint's tolerance are skipped. Returns
+/// the number of mismatches recorded.
+pub async fn cross_check(
+    conn: &mut PgConnection,
+    meta: &UiTransactionStatusMeta,
+    events: &[VaultEvent],
+    tx_builder: &TransactionBuilder,
+    tolerance: &ToleranceConfig,
+    signature: &str,
+) -> anyhow::Result<usize> {
+    let expected = expected_deltas(events, tx_builder);
+    if expected.is_empty() {
+        return Ok(0);
+    }
+
+    let actual = owner_balances(meta);
+
+    let mut mismatches = 0;
+
+    for (vault_pda, expected_delta) in expected {
+        let balance = actual.get(&vault_pda).copied().unwrap_or_default();
+        if balance.delta() == expected_delta {
+            continue;
+        }
+
+        let vault = match vault_repo::get_vault(conn, &vault_pda).await? {
+            Some(v) => v,
+            None => continue,
+        };
+
+        let onchain = balance.post as i64;
+        let offchain = (balance.pre + expected_delta) as i64;
+
+        if tolerance.for_mint(&vault.mint).allows(onchain, offchain) {
+            continue;
+        }
+
+        tracing::warn!(
+            "token delta mismatch in {} for vault {}: events say {}, token balances say {}",
+            signature,
+            vault_pda,
+            expected_delta,
+            balance.delta()
+        );
+        Logger::log_state_mismatch(&vault_pda, offchain as u64, onchain as u64);
+
+        reconciliation_repo::insert_discrepancy(
+            &mut *conn,
+            &NewDiscrepancy {
+                id: Uuid::new_v4(),
+                vault_pda: &vault_pda,
+                program_id: &vault.program_id,
+                network: &vault.network,
+                component: DiscrepancyComponent::TokenAccount,
+                severity: tolerance.severity.classify(onchain, offchain),
+                onchain_balance: onchain,
+                offchain_balance: offchain,
+                discrepancy: onchain - offchain,
+            },
+        )
+        .await?;
+
+        mismatches += 1;
+    }
+
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+
+    #[test]
+    fn test_expected_deltas_nets_events_per_vault() {
+        let builder = TransactionBuilder::new(Pubkey::new_unique());
+        let user = Pubkey::new_unique();
+        let (vault, _) = builder.derive_vault_pda(&user);
+
+        let events = vec![
+            VaultEvent::Deposit {
+                user: user.to_string(),
+                amount: 100,
+                new_balance: 100,
+                timestamp: 0,
+            },
+            VaultEvent::Withdraw {
+                vault: vault.to_string(),
+                user: user.to_string(),
+                amount: 30,
+            },
+            VaultEvent::Lock {
+                vault: vault.to_string(),
+                amount: 50,
+            },
+        ];
+
+        let deltas = expected_deltas(&events, &builder);
+        assert_eq!(deltas.get(&vault.to_string()), Some(&70));
+    }
+
+    #[test]
+    fn test_owner_balance_delta() {
+        let balance = OwnerBalance { pre: 10, post: 4 };
+        assert_eq!(balance.delta(), -6);
+    }
+}
@@ -1,49 +1,888 @@
-use solana_client::rpc_client::RpcClient;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use futures::StreamExt;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_client::rpc_config::{
+    CommitmentConfig, RpcBlockConfig, RpcTransactionConfig, RpcTransactionLogsConfig,
+    RpcTransactionLogsFilter,
+};
+use solana_sdk::commitment_config::CommitmentLevel;
 use solana_sdk::{pubkey::Pubkey, signature::Signature};
-use solana_transaction_status::UiTransactionEncoding;
+use solana_transaction_status::{TransactionDetails, UiTransactionEncoding};
 use sqlx::PgPool;
+use tracing::{debug, info, warn};
+
+use crate::db::backfill_repo::BackfillRepository;
+use crate::db::processed_events;
+use crate::db::vault_repo::VaultRepository;
+use crate::indexer::batch_fetch::{fetchable_commitment, BatchTransactionFetcher};
+use crate::indexer::block_ingest::program_transactions;
+use crate::indexer::event_filter::EventFilter;
+use crate::indexer::lag::LagMonitor;
+use crate::indexer::rate_limit::{is_rate_limit_error, RateLimitConfig, RateLimiter};
+use crate::indexer::process_transaction::{process_logs, process_transaction, IndexContext};
+use crate::network::TokenProgram;
+use crate::reconciliation::tolerance::ToleranceConfig;
+use crate::rpc_endpoints::RpcFailover;
+
+/// Prefix of the progress rows used by `VaultIndexer::backfill`, see
+/// `BackfillFrom::job_name`.
+const BACKFILL_JOB: &str = "history";
+
+/// Progress row that missed signatures found by `audit_gaps` are queued under.
+const GAP_REPAIR_JOB: &str = "gap_repair";
+
+/// Progress row for `VaultIndexer::run_blocks`.
+const BLOCKS_JOB: &str = "blocks";
+
+/// Slots requested per `get_blocks` call in block ingestion mode.
+const BLOCK_RANGE: u64 = 100;
+
+/// How many times a throttled RPC call is retried before giving up.
+const MAX_RATE_LIMIT_RETRIES: u32 = 8;
+
+/// Page size for `get_signatures_for_address` (the RPC maximum).
+const SIGNATURE_PAGE_SIZE: usize = 1000;
+
+/// Most unfetched signatures retried per gap audit pass.
+const UNFETCHED_RETRY_BATCH: i64 = 500;
+
+/// Most stale-vault candidates checked against the chain per pass.
+const STALE_VAULT_CANDIDATES: i64 = 500;
+
+/// Where a historical backfill should start from.
+#[derive(Debug, Clone)]
+pub enum BackfillFrom {
+    /// Index everything after (and including) this signature.
+    Signature(Signature),
+    /// Index everything at or after this slot.
+    Slot(u64),
+    /// Index the program's entire history.
+    Genesis,
+}
 
-use crate::indexer::process_transaction::process_transaction;
+impl BackfillFrom {
+    /// Progress row of a backfill from this start point. Each start point
+    /// is its own job, so a later backfill reaching further back isn't
+    /// mistaken for the completed one.
+    pub fn job_name(&self) -> String {
+        match self {
+            BackfillFrom::Signature(sig) => format!("{}:signature:{}", BACKFILL_JOB, sig),
+            BackfillFrom::Slot(slot) => format!("{}:slot:{}", BACKFILL_JOB, slot),
+            BackfillFrom::Genesis => format!("{}:genesis", BACKFILL_JOB),
+        }
+    }
+}
+
+/// `genesis`, `slot:<slot>` or a transaction signature.
+impl std::str::FromStr for BackfillFrom {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim() {
+            "genesis" => Ok(BackfillFrom::Genesis),
+            s => match s.strip_prefix("slot:") {
+                Some(slot) => Ok(BackfillFrom::Slot(
+                    slot.parse()
+                        .map_err(|_| anyhow::anyhow!("invalid slot '{}'", slot))?,
+                )),
+                None => Ok(BackfillFrom::Signature(s.parse().map_err(|_| {
+                    anyhow::anyhow!("expected genesis, slot:<slot> or a signature, got '{}'", s)
+                })?)),
+            },
+        }
+    }
+}
+
+/// A vault with on-chain activity newer than its last sync.
+#[derive(Debug)]
+pub struct StaleVault {
+    pub vault_pda: String,
+    pub last_synced_at: NaiveDateTime,
+    /// Newest signature mentioning the vault.
+    pub latest_signature: String,
+    pub latest_slot: u64,
+    pub latest_block_time: i64,
+}
+
+/// Outcome of one `VaultIndexer::audit_gaps` pass.
+#[derive(Debug, Default)]
+pub struct GapReport {
+    pub scanned: usize,
+    pub missing: usize,
+    /// Previously unfetched signatures whose transaction was found this time.
+    pub refetched: usize,
+}
 
 pub struct VaultIndexer {
-    rpc: RpcClient,
+    rpc: RpcFailover,
     pool: PgPool,
     program_id: Pubkey,
+    fetcher: BatchTransactionFetcher,
+    limiter: Arc<RateLimiter>,
+    lag_monitor: Option<LagMonitor>,
+    filter: EventFilter,
+    commitment: CommitmentLevel,
+    stale_after: Option<Duration>,
+    network: String,
+    token_program: TokenProgram,
+    tolerance: ToleranceConfig,
 }
 
 impl VaultIndexer {
-    pub fn new(rpc: RpcClient, pool: PgPool, program_id: Pubkey) -> Self {
+    /// `rpc` is a single client or the failover set of the read endpoints.
+    pub fn new(rpc: impl Into<RpcFailover>, pool: PgPool, program_id: Pubkey) -> Self {
+        let rpc = rpc.into();
+        let limiter = Arc::new(RateLimiter::new(RateLimitConfig::default()));
+        let fetcher =
+            BatchTransactionFetcher::from_failover(&rpc).with_rate_limiter(limiter.clone());
+
         Self {
             rpc,
             pool,
             program_id,
+            fetcher,
+            limiter,
+            lag_monitor: None,
+            filter: EventFilter::default(),
+            commitment: CommitmentLevel::Confirmed,
+            stale_after: None,
+            network: "localnet".to_string(),
+            token_program: TokenProgram::Token2022,
+            tolerance: ToleranceConfig::default(),
+        }
+    }
+
+    /// Throttle all RPC traffic of this indexer with the given per-endpoint limit.
+    pub fn with_rate_limit(mut self, config: RateLimitConfig) -> Self {
+        self.limiter = Arc::new(RateLimiter::new(config));
+        self.fetcher = self.fetcher.with_rate_limiter(self.limiter.clone());
+        self
+    }
+
+    /// Fetch transactions `batch_size` per JSON-RPC batch, with up to
+    /// `concurrency` batches in flight during catch-up and backfill.
+    pub fn with_batch_fetch(mut self, batch_size: usize, concurrency: usize) -> Self {
+        self.fetcher = self
+            .fetcher
+            .with_batch_size(batch_size)
+            .with_concurrency(concurrency);
+        self
+    }
+
+    /// Run a blocking RPC call under the rate limiter, slowing down and
+    /// retrying when the provider answers with 429 / "rate limit". Unreachable
+    /// endpoints are failed over by `RpcFailover`.
+    async fn rpc_call<T, F>(&self, mut call: F) -> anyhow::Result<T>
+    where
+        F: FnMut(&RpcClient) -> Result<T, solana_client::client_error::ClientError>,
+    {
+        let mut attempt = 0;
+
+        loop {
+            self.limiter.acquire().await;
+
+            match self.rpc.call(&mut call).map_err(anyhow::Error::from) {
+                Ok(value) => {
+                    self.limiter.on_success().await;
+                    return Ok(value);
+                }
+                Err(e) if is_rate_limit_error(&e) && attempt < MAX_RATE_LIMIT_RETRIES => {
+                    attempt += 1;
+                    self.limiter.on_rate_limited().await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Report indexer lag to the metrics registry (and alert hook) after each pass.
+    pub fn with_lag_monitor(mut self, lag_monitor: LagMonitor) -> Self {
+        self.lag_monitor = Some(lag_monitor);
+        self
+    }
+
+    /// Only apply events that pass `filter`.
+    pub fn with_event_filter(mut self, filter: EventFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Index at `commitment`. Transactions are fetched at `confirmed` when
+    /// `processed` is chosen, since `getTransaction` doesn't support it.
+    pub fn with_commitment(mut self, commitment: CommitmentLevel) -> Self {
+        self.commitment = commitment;
+        self.fetcher = self.fetcher.with_commitment(commitment);
+        self
+    }
+
+    /// `getSignaturesForAddress` rejects `processed` just like `getTransaction`.
+    fn signature_commitment(&self) -> CommitmentConfig {
+        CommitmentConfig {
+            commitment: fetchable_commitment(self.commitment),
         }
     }
 
+    /// Network label recorded on vaults this indexer creates.
+    pub fn with_network(mut self, network: impl Into<String>) -> Self {
+        self.network = network.into();
+        self
+    }
+
+    /// Token program vault token accounts are derived with; token-2022 by
+    /// default.
+    pub fn with_token_program(mut self, token_program: TokenProgram) -> Self {
+        self.token_program = token_program;
+        self
+    }
+
+    /// Drift tolerances and severity tiers for the discrepancies recorded
+    /// while indexing, e.g. the reconciler's.
+    pub fn with_tolerance(mut self, tolerance: ToleranceConfig) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Also look for stale vaults (see `get_stale_vaults`) on every gap
+    /// audit pass.
+    pub fn with_stale_vault_check(mut self, older_than: Duration) -> Self {
+        self.stale_after = Some(older_than);
+        self
+    }
+
+    /// Context for transactions fetched with `getTransaction`.
+    fn fetch_context(&self) -> IndexContext<'_> {
+        IndexContext {
+            pool: &self.pool,
+            program_id: &self.program_id,
+            filter: &self.filter,
+            network: &self.network,
+            token_program: self.token_program,
+            commitment: self.fetcher.commitment(),
+            tolerance: &self.tolerance,
+        }
+    }
+
+    /// Compare the chain's current slot with the last slot the indexer processed.
+    pub async fn check_lag(&self) -> anyhow::Result<Option<u64>> {
+        let monitor = match &self.lag_monitor {
+            Some(m) => m,
+            None => return Ok(None),
+        };
+
+        let chain_slot = self.rpc_call(|rpc| rpc.get_slot()).await?;
+        let last_processed = processed_events::last_processed_slot(&self.pool)
+            .await?
+            .unwrap_or(0);
+
+        Ok(Some(monitor.observe(chain_slot, last_processed as u64)))
+    }
+
     pub async fn run_once(&self) -> anyhow::Result<()> {
-        let signatures = self
-            .rpc
-            .get_signatures_for_address(&self.program_id)?;
+        let page = self
+            .rpc_call(|rpc| {
+                rpc.get_signatures_for_address_with_config(
+                    &self.program_id,
+                    GetConfirmedSignaturesForAddress2Config {
+                        commitment: Some(self.signature_commitment()),
+                        ..Default::default()
+                    },
+                )
+            })
+            .await?;
+
+        // Apply oldest first so e.g. a withdraw never lands before its deposit
+        let signatures = chronological_order(
+            page.into_iter().map(|info| (info.signature, info.slot)).collect(),
+        );
+
+        self.process_signatures(&signatures).await?;
+
+        self.check_lag().await?;
+
+        Ok(())
+    }
+
+    /// Fetch the given signatures in batches and index them in order.
+    async fn process_signatures(&self, signatures: &[String]) -> anyhow::Result<()> {
+        let repo = BackfillRepository::new(&self.pool);
+
+        for (signature, tx) in self.fetcher.fetch(signatures).await? {
+            let tx = match tx {
+                Some(tx) => tx,
+                None => {
+                    warn!("transaction {} not found, queued for retry", signature);
+                    repo.record_unfetched(&signature, None).await?;
+                    continue;
+                }
+            };
+
+            // All logic (including idempotency) is handled here; the block
+            // position is only looked up by a replay that needs it
+            process_transaction(&tx, &signature, None, &self.fetch_context()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Backfill historical transactions for the program.
+    ///
+    /// Signatures are discovered by paging backwards through
+    /// `get_signatures_for_address` and stored in `backfill_signatures`, then
+    /// processed oldest-first. Both phases record progress in the DB, so an
+    /// interrupted backfill resumes where it stopped when called again.
+    pub async fn backfill(&self, from: BackfillFrom) -> anyhow::Result<()> {
+        let repo = BackfillRepository::new(&self.pool);
+        let job_name = from.job_name();
+        let job = repo
+            .get_or_create_job(&job_name, &self.program_id.to_string())
+            .await?;
+
+        if !job.discovery_complete {
+            let mut before = job
+                .discovery_cursor
+                .as_deref()
+                .map(|s| s.parse::<Signature>())
+                .transpose()?;
+
+            let until = match &from {
+                BackfillFrom::Signature(sig) => Some(*sig),
+                _ => None,
+            };
+            let min_slot = match &from {
+                BackfillFrom::Slot(slot) => Some(*slot),
+                _ => None,
+            };
+
+            loop {
+                let page = self
+                    .rpc_call(|rpc| {
+                        rpc.get_signatures_for_address_with_config(
+                            &self.program_id,
+                            GetConfirmedSignaturesForAddress2Config {
+                                before,
+                                until,
+                                limit: Some(SIGNATURE_PAGE_SIZE),
+                                commitment: Some(self.signature_commitment()),
+                            },
+                        )
+                    })
+                    .await?;
+
+                let exhausted = page.len() < SIGNATURE_PAGE_SIZE;
+
+                let in_range: Vec<(String, i64)> = page
+                    .iter()
+                    .filter(|info| min_slot.map_or(true, |min| info.slot >= min))
+                    .map(|info| (info.signature.clone(), info.slot as i64))
+                    .collect();
+
+                let reached_min_slot = in_range.len() < page.len();
+                let complete = exhausted || reached_min_slot;
+
+                let cursor = page.last().map(|info| info.signature.clone());
+
+                repo.record_discovered_page(&job_name, &in_range, cursor.as_deref(), complete)
+                    .await?;
+
+                info!(
+                    "backfill discovered {} signatures (cursor: {:?})",
+                    in_range.len(),
+                    cursor
+                );
+
+                if complete {
+                    break;
+                }
+
+                before = cursor.map(|s| s.parse::<Signature>()).transpose()?;
+            }
+
+            // The `until` signature itself is excluded by the RPC, so index it explicitly
+            if let BackfillFrom::Signature(sig) = &from {
+                let slot = self
+                    .rpc_call(|rpc| {
+                        rpc.get_transaction_with_config(
+                            sig,
+                            RpcTransactionConfig {
+                                encoding: Some(UiTransactionEncoding::JsonParsed),
+                                commitment: Some(self.signature_commitment()),
+                                max_supported_transaction_version: Some(0),
+                            },
+                        )
+                    })
+                    .await?
+                    .slot;
+                repo.record_discovered_page(&job_name, &[(sig.to_string(), slot as i64)], None, true)
+                    .await?;
+            }
+        }
+
+        self.process_pending(&repo, &job_name).await?;
+
+        info!("backfill {} complete", job_name);
+
+        Ok(())
+    }
+
+    /// Fetch and index the unprocessed signatures queued under `job_name`,
+    /// oldest first.
+    async fn process_pending(
+        &self,
+        repo: &BackfillRepository<'_>,
+        job_name: &str,
+    ) -> anyhow::Result<()> {
+        loop {
+            let pending = repo.next_pending(job_name, 500).await?;
+            if pending.is_empty() {
+                break;
+            }
+
+            let signatures: Vec<String> =
+                pending.iter().map(|row| row.tx_signature.clone()).collect();
+
+            let fetched = self.fetcher.fetch(&signatures).await?;
 
-        for sig_info in signatures {
-            let signature = sig_info.signature.clone();
+            for (row, (signature, tx)) in pending.iter().zip(fetched) {
+                if let Some(tx) = tx {
+                    process_transaction(&tx, &signature, None, &self.fetch_context()).await?;
+                } else {
+                    warn!("transaction {} not found, queued for retry", signature);
+                    repo.record_unfetched(&signature, Some(row.slot)).await?;
+                }
 
-            let sig = signature.parse::<Signature>()?;
+                repo.mark_signature_processed(job_name, &row.tx_signature, row.slot)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fetch the signatures no transaction was found for earlier again,
+    /// indexing the ones that turn up. Returns how many did.
+    async fn retry_unfetched(&self) -> anyhow::Result<usize> {
+        let repo = BackfillRepository::new(&self.pool);
+
+        let signatures = repo.unfetched(UNFETCHED_RETRY_BATCH).await?;
+        if signatures.is_empty() {
+            return Ok(0);
+        }
+
+        let mut found = 0;
+
+        for (signature, tx) in self.fetcher.fetch(&signatures).await? {
+            match tx {
+                Some(tx) => {
+                    process_transaction(&tx, &signature, None, &self.fetch_context()).await?;
+                    repo.clear_unfetched(&signature).await?;
+                    found += 1;
+                }
+                None => repo.record_unfetched(&signature, None).await?,
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Look for program transactions the indexer missed.
+    ///
+    /// Retries the signatures whose transaction couldn't be fetched before,
+    /// then compares the newest `window` signatures from
+    /// `get_signatures_for_address` with `processed_events` and queues every
+    /// gap for processing. Signatures past the last processed slot are left
+    /// alone: the regular indexing pass hasn't reached them yet.
+    pub async fn audit_gaps(&self, window: usize) -> anyhow::Result<GapReport> {
+        let refetched = self.retry_unfetched().await?;
+
+        let last_processed = match processed_events::last_processed_slot(&self.pool).await? {
+            Some(slot) => slot as u64,
+            None => {
+                return Ok(GapReport {
+                    refetched,
+                    ..Default::default()
+                })
+            }
+        };
+
+        let mut before = None;
+        let mut candidates: Vec<(String, i64)> = vec![];
+        let mut scanned = 0;
+
+        while scanned < window {
+            let limit = SIGNATURE_PAGE_SIZE.min(window - scanned);
+
+            let page = self
+                .rpc_call(|rpc| {
+                    rpc.get_signatures_for_address_with_config(
+                        &self.program_id,
+                        GetConfirmedSignaturesForAddress2Config {
+                            before,
+                            until: None,
+                            limit: Some(limit),
+                            commitment: Some(self.signature_commitment()),
+                        },
+                    )
+                })
+                .await?;
+
+            scanned += page.len();
+
+            before = match page.last() {
+                Some(info) => Some(info.signature.parse::<Signature>()?),
+                None => break,
+            };
+
+            // Failed transactions aren't indexed by the streaming path either
+            candidates.extend(
+                page.iter()
+                    .filter(|info| info.err.is_none() && info.slot <= last_processed)
+                    .map(|info| (info.signature.clone(), info.slot as i64)),
+            );
+
+            if page.len() < limit {
+                break;
+            }
+        }
+
+        let signatures: Vec<String> = candidates.iter().map(|(sig, _)| sig.clone()).collect();
+        let unprocessed: HashSet<String> =
+            processed_events::find_unprocessed(&self.pool, &signatures)
+                .await?
+                .into_iter()
+                .collect();
+
+        let missing: Vec<(String, i64)> = candidates
+            .into_iter()
+            .filter(|(sig, _)| unprocessed.contains(sig))
+            .collect();
+
+        if !missing.is_empty() {
+            warn!("gap audit found {} missed signatures, repairing", missing.len());
+
+            metrics::counter!("indexer_gap_signatures_total").increment(missing.len() as u64);
+
+            let repo = BackfillRepository::new(&self.pool);
+            repo.get_or_create_job(GAP_REPAIR_JOB, &self.program_id.to_string())
+                .await?;
+            repo.enqueue_signatures(GAP_REPAIR_JOB, &missing).await?;
+
+            self.process_pending(&repo, GAP_REPAIR_JOB).await?;
+        }
+
+        Ok(GapReport {
+            scanned,
+            missing: missing.len(),
+            refetched,
+        })
+    }
+
+    /// Run `audit_gaps` every `interval`, logging failures and carrying on.
+    pub async fn run_gap_audit(&self, window: usize, interval: Duration) -> anyhow::Result<()> {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+
+            match self.audit_gaps(window).await {
+                Ok(report) => info!(
+                    "gap audit scanned {} signatures, repaired {}, refetched {}",
+                    report.scanned, report.missing, report.refetched
+                ),
+                Err(e) => warn!("gap audit failed: {}", e),
+            }
+
+            if let Some(older_than) = self.stale_after {
+                if let Err(e) = self.get_stale_vaults(older_than).await {
+                    warn!("stale vault check failed: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Vaults whose `last_synced_at` is older than `older_than` even though
+    /// a successful transaction mentioning them landed after that sync.
+    ///
+    /// This is the clearest sign that the indexer is dropping events for
+    /// specific vaults rather than lagging overall. Each candidate costs one
+    /// `get_signatures_for_address` call, so at most `STALE_VAULT_CANDIDATES`
+    /// (the most recently active) are checked per pass.
+    pub async fn get_stale_vaults(&self, older_than: Duration) -> anyhow::Result<Vec<StaleVault>> {
+        let cutoff = (Utc::now() - chrono::Duration::from_std(older_than)?).naive_utc();
+
+        let candidates = VaultRepository::new(&self.pool)
+            .get_vaults_synced_before(cutoff, STALE_VAULT_CANDIDATES)
+            .await?;
+
+        let mut stale = vec![];
+
+        for vault in candidates {
+            let address = vault.vault_pda.parse::<Pubkey>()?;
+
+            let page = self
+                .rpc_call(|rpc| {
+                    rpc.get_signatures_for_address_with_config(
+                        &address,
+                        GetConfirmedSignaturesForAddress2Config {
+                            before: None,
+                            until: None,
+                            limit: Some(1),
+                            commitment: Some(self.signature_commitment()),
+                        },
+                    )
+                })
+                .await?;
+
+            let latest = match page.into_iter().next() {
+                Some(info) if info.err.is_none() => info,
+                _ => continue,
+            };
+
+            let block_time = match latest.block_time {
+                Some(t) => t,
+                None => continue,
+            };
+
+            if block_time > vault.last_synced_at.and_utc().timestamp() {
+                warn!(
+                    "vault {} last synced at {} but has activity at {} ({})",
+                    vault.vault_pda, vault.last_synced_at, block_time, latest.signature
+                );
+
+                stale.push(StaleVault {
+                    vault_pda: vault.vault_pda,
+                    last_synced_at: vault.last_synced_at,
+                    latest_signature: latest.signature,
+                    latest_slot: latest.slot,
+                    latest_block_time: block_time,
+                });
+            }
+        }
 
-            let tx = self
-                .rpc
-                .get_transaction(&sig, UiTransactionEncoding::JsonParsed)?;
+        metrics::gauge!("indexer_stale_vaults").set(stale.len() as f64);
 
-            // All logic (including idempotency) is handled here
-            process_transaction(
-                &tx,
-                &signature,
-                &self.pool,
-                &self.program_id,
+        Ok(stale)
+    }
+
+    /// Index every block in `start_slot..=end_slot`, keeping only the
+    /// transactions that touch the program. Returns how many were indexed.
+    pub async fn ingest_blocks(&self, start_slot: u64, end_slot: u64) -> anyhow::Result<usize> {
+        let slots = self
+            .rpc_call(|rpc| {
+                rpc.get_blocks_with_commitment(
+                    start_slot,
+                    Some(end_slot),
+                    self.signature_commitment(),
+                )
+            })
+            .await?;
+
+        let mut indexed = 0;
+
+        for slot in slots {
+            let block = self
+                .rpc_call(|rpc| {
+                    rpc.get_block_with_config(
+                        slot,
+                        RpcBlockConfig {
+                            encoding: Some(UiTransactionEncoding::JsonParsed),
+                            transaction_details: Some(TransactionDetails::Full),
+                            rewards: Some(false),
+                            commitment: Some(self.signature_commitment()),
+                            max_supported_transaction_version: Some(0),
+                        },
+                    )
+                })
+                .await?;
+
+            for (signature, tx_index, tx) in program_transactions(slot, block, &self.program_id) {
+                process_transaction(&tx, &signature, Some(tx_index), &self.fetch_context()).await?;
+                indexed += 1;
+            }
+        }
+
+        Ok(indexed)
+    }
+
+    /// Index by walking blocks instead of signatures.
+    ///
+    /// Resumes after the slot recorded for the `blocks` job (or the last
+    /// processed slot on first run) and follows the chain tip, sleeping for
+    /// `poll_interval` whenever it has caught up.
+    pub async fn run_blocks(&self, poll_interval: Duration) -> anyhow::Result<()> {
+        let repo = BackfillRepository::new(&self.pool);
+        let job = repo
+            .get_or_create_job(BLOCKS_JOB, &self.program_id.to_string())
+            .await?;
+
+        let mut next_slot = match job.last_processed_slot {
+            Some(slot) => slot as u64 + 1,
+            None => match processed_events::last_processed_slot(&self.pool).await? {
+                Some(slot) => slot as u64 + 1,
+                None => {
+                    self.rpc_call(|rpc| rpc.get_slot_with_commitment(self.signature_commitment()))
+                        .await?
+                }
+            },
+        };
+
+        loop {
+            let tip = self
+                .rpc_call(|rpc| rpc.get_slot_with_commitment(self.signature_commitment()))
+                .await?;
+
+            if next_slot > tip {
+                tokio::time::sleep(poll_interval).await;
+                continue;
+            }
+
+            let end_slot = tip.min(next_slot + BLOCK_RANGE - 1);
+            let indexed = self.ingest_blocks(next_slot, end_slot).await?;
+
+            if indexed > 0 {
+                info!("indexed {} transactions from slots {}..={}", indexed, next_slot, end_slot);
+            }
+
+            repo.record_slot_progress(BLOCKS_JOB, end_slot as i64).await?;
+            next_slot = end_slot + 1;
+
+            self.check_lag().await?;
+        }
+    }
+
+    /// Index in real time from program logs pushed over the WebSocket.
+    ///
+    /// Every (re)connect is preceded by a polling pass so that anything missed
+    /// while the subscription was down is caught up. When the subscription
+    /// drops we fall back to polling and retry after `poll_interval`, on the
+    /// next of `ws_urls` if the subscription could not be established.
+    pub async fn run_streaming(
+        &self,
+        ws_urls: &[String],
+        poll_interval: Duration,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(!ws_urls.is_empty(), "no WebSocket endpoint configured");
+        let mut current = 0;
+
+        loop {
+            if let Err(e) = self.run_once().await {
+                warn!("polling catch-up failed: {}", e);
+            }
+
+            match self.stream_logs(&ws_urls[current]).await {
+                Ok(()) => warn!("log subscription closed, falling back to polling"),
+                Err(e) => {
+                    warn!("log subscription failed: {}, falling back to polling", e);
+                    current = (current + 1) % ws_urls.len();
+                }
+            }
+
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+
+    async fn stream_logs(&self, ws_url: &str) -> anyhow::Result<()> {
+        let client = PubsubClient::new(ws_url).await?;
+
+        let (mut stream, unsubscribe) = client
+            .logs_subscribe(
+                RpcTransactionLogsFilter::Mentions(vec![self.program_id.to_string()]),
+                RpcTransactionLogsConfig {
+                    commitment: Some(CommitmentConfig {
+                        commitment: self.commitment,
+                    }),
+                },
             )
             .await?;
+
+        info!("subscribed to program logs at {}", ws_url);
+
+        while let Some(notification) = stream.next().await {
+            let slot = notification.context.slot;
+            let logs = notification.value;
+
+            // Failed transactions don't change on-chain state
+            if logs.err.is_some() {
+                continue;
+            }
+
+            // Notifications arrive at the subscription's own commitment
+            let ctx = IndexContext {
+                commitment: self.commitment,
+                ..self.fetch_context()
+            };
+
+            // Without the slot's block time the signature stays unprocessed
+            // and the gap audit picks it up
+            let block_time = match self.rpc_call(|rpc| rpc.get_block_time(slot)).await {
+                Ok(block_time) => block_time,
+                Err(e) => {
+                    debug!(
+                        "no block time for slot {} yet, deferring {}: {}",
+                        slot, logs.signature, e
+                    );
+                    metrics::counter!("indexer_deferred_transactions_total").increment(1);
+                    continue;
+                }
+            };
+
+            process_logs(&logs.logs, &logs.signature, slot, None, block_time, &ctx).await?;
         }
 
+        drop(stream);
+        unsubscribe().await;
+
         Ok(())
     }
 }
+
+/// Put a newest-first `getSignaturesForAddress` page into execution order.
+///
+/// The RPC lists transactions of the same slot in reverse block order, so
+/// reversing the page before a stable sort by slot also orders transactions
+/// within a slot by their index in the block.
+pub fn chronological_order(mut page: Vec<(String, u64)>) -> Vec<String> {
+    page.reverse();
+    page.sort_by_key(|(_, slot)| *slot);
+
+    page.into_iter().map(|(signature, _)| signature).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chronological_order() {
+        let page = vec![
+            ("c".to_string(), 12),
+            ("b2".to_string(), 11),
+            ("b1".to_string(), 11),
+            ("a".to_string(), 10),
+        ];
+
+        assert_eq!(chronological_order(page), vec!["a", "b1", "b2", "c"]);
+    }
+
+    #[test]
+    fn test_backfill_jobs_are_keyed_by_start_point() {
+        let genesis: BackfillFrom = "genesis".parse().unwrap();
+        let slot: BackfillFrom = "slot:250000000".parse().unwrap();
+        let signature = Signature::default();
+        let from_signature: BackfillFrom = signature.to_string().parse().unwrap();
+
+        assert_eq!(genesis.job_name(), "history:genesis");
+        assert_eq!(slot.job_name(), "history:slot:250000000");
+        assert_eq!(
+            from_signature.job_name(),
+            format!("history:signature:{}", signature)
+        );
+
+        assert!("slot:abc".parse::<BackfillFrom>().is_err());
+        assert!("yesterday".parse::<BackfillFrom>().is_err());
+    }
+}
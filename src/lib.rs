@@ -4,22 +4,38 @@
 //! including initialization, deposits, withdrawals, and balance tracking.
 
 pub mod access_control;
+pub mod alerting;
+pub mod anomaly;
+pub mod audit;
 pub mod api;
+pub mod auth;
 pub mod config;
 pub mod cpi_manager;
 pub mod db;
 pub mod error_handling;
+pub mod escalation;
 pub mod idl;
 pub mod indexer;
+pub mod log_file;
+pub mod log_sampling;
 pub mod logging;
+pub mod metrics;
+pub mod network;
 pub mod reconciliation;
+pub mod rpc_endpoints;
+pub mod secrets;
+pub mod shutdown;
 pub mod states;
+pub mod telemetry;
 pub mod transaction_builder;
 pub mod vault_manager;
 
 // Re-export commonly used types
 pub use config::Config;
-pub use error_handling::{RetryConfig, VaultError};
+pub use error_handling::{
+    Deadline, Jitter, RetryBudget, RetryConfig, RetryOutcome, VaultContext, VaultContextExt,
+    VaultError, VaultResult,
+};
 pub use states::CollateralVault;
 pub use transaction_builder::TransactionBuilder;
 pub use vault_manager::VaultManager;
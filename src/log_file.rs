@@ -0,0 +1,199 @@
+//! Optional log file output, alongside stdout, for deployments that can't
+//! ship stdout to a collector.
+//!
+//! Files rotate hourly, daily or once they reach a size, and only the newest
+//! `max_files` are kept. Lines are written by a background thread, so a slow
+//! disk doesn't hold up the code that logs.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+
+/// When a log file is closed and a new one started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    /// Once the file would grow past `LogFileConfig::max_bytes`.
+    Size,
+}
+
+impl std::str::FromStr for LogRotation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "hourly" => Ok(Self::Hourly),
+            "daily" => Ok(Self::Daily),
+            "size" => Ok(Self::Size),
+            other => anyhow::bail!("unknown log rotation '{}'", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogFileConfig {
+    pub directory: PathBuf,
+    /// Files are named `<name>.<date>.log` (time rotation) or `<name>.log`
+    /// (size rotation). Defaults to the binary's service name.
+    pub name: Option<String>,
+    pub rotation: LogRotation,
+    /// Size at which the file is rotated, for `LogRotation::Size`.
+    pub max_bytes: u64,
+    /// Files kept, the current one included; older ones are deleted.
+    pub max_files: usize,
+}
+
+/// Writer for the file layer. Keep the guard alive until the binary exits;
+/// dropping it flushes the lines still queued.
+pub fn writer(
+    config: &LogFileConfig,
+    default_name: &str,
+) -> anyhow::Result<(NonBlocking, WorkerGuard)> {
+    let name = config.name.as_deref().unwrap_or(default_name);
+    fs::create_dir_all(&config.directory)?;
+
+    let rotation = match config.rotation {
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Size => {
+            let file = SizeRollingFile::open(
+                config.directory.join(format!("{}.log", name)),
+                config.max_bytes,
+                config.max_files,
+            )?;
+            return Ok(tracing_appender::non_blocking(file));
+        }
+    };
+
+    let appender = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(name)
+        .filename_suffix("log")
+        .max_log_files(config.max_files)
+        .build(&config.directory)?;
+
+    Ok(tracing_appender::non_blocking(appender))
+}
+
+/// Appends to `path` until it would pass `max_bytes`, then shifts it to
+/// `path.1` (and `path.1` to `path.2`, and so on), deleting what falls past
+/// `max_files`.
+struct SizeRollingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl SizeRollingFile {
+    fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let file = append_to(&path)?;
+        let written = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            file,
+            written,
+            max_bytes,
+            max_files,
+        })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    fn roll(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        let keep = self.max_files.saturating_sub(1);
+        if keep > 0 {
+            match fs::remove_file(self.rotated(keep)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            for n in (1..keep).rev() {
+                let from = self.rotated(n);
+                if from.exists() {
+                    fs::rename(from, self.rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+
+        self.file = File::create(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A line longer than max_bytes still goes into a file of its own
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.roll()?;
+        }
+
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn append_to(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_rotation_keeps_max_files() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("vault.log");
+
+        let mut file = SizeRollingFile::open(path.clone(), 20, 3).unwrap();
+        for line in [
+            "first line\n",
+            "second line\n",
+            "third line\n",
+            "fourth line\n",
+        ] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth line\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("vault.log.1")).unwrap(),
+            "third line\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("vault.log.2")).unwrap(),
+            "second line\n"
+        );
+        assert!(!dir.join("vault.log.3").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotation_names() {
+        assert_eq!("daily".parse::<LogRotation>().unwrap(), LogRotation::Daily);
+        assert_eq!("Size".parse::<LogRotation>().unwrap(), LogRotation::Size);
+        assert!("weekly".parse::<LogRotation>().is_err());
+    }
+}
@@ -0,0 +1,162 @@
+//! Sampling of high-volume log targets.
+//!
+//! `LOG_SAMPLING` keeps a fraction of a target's events below `warn`: with
+//! `rpc=0.01,database=0.01`, one in a hundred `rpc` and `database` events at
+//! `info`, `debug` or `trace` is logged. Warnings and errors are always logged.
+//! A rule also covers the targets nested under it (`vault_backend::indexer`
+//! covers `vault_backend::indexer::lag`); the most specific rule applies.
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Share of a target's events to log, from 0 (none) to 1 (all).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplingRule {
+    pub target: String,
+    pub rate: f64,
+}
+
+impl SamplingRule {
+    fn covers(&self, target: &str) -> bool {
+        target
+            .strip_prefix(self.target.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    }
+}
+
+impl FromStr for SamplingRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (target, rate) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("expected <target>=<rate>, got '{}'", s))?;
+        let target = target.trim();
+        let rate: f64 = rate
+            .trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid sampling rate '{}'", rate.trim()))?;
+
+        anyhow::ensure!(!target.is_empty(), "missing target in '{}'", s);
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&rate),
+            "sampling rate of {} must be between 0 and 1",
+            target
+        );
+
+        Ok(Self {
+            target: target.to_string(),
+            rate,
+        })
+    }
+}
+
+/// Comma separated rules, e.g. `rpc=0.01,database=0.01`.
+pub fn parse_rules(s: &str) -> anyhow::Result<Vec<SamplingRule>> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(SamplingRule::from_str)
+        .collect()
+}
+
+/// Layer that drops the events `LOG_SAMPLING` leaves out, for every output.
+///
+/// Sampling is by count rather than at random: a rate of 0.01 keeps the
+/// first of every hundred events of the target.
+pub struct LogSampler {
+    /// Most specific target first.
+    rules: Vec<(SamplingRule, AtomicU64)>,
+}
+
+impl LogSampler {
+    pub fn new(mut rules: Vec<SamplingRule>) -> Self {
+        rules.sort_by(|a, b| b.target.len().cmp(&a.target.len()));
+
+        Self {
+            rules: rules
+                .into_iter()
+                .map(|rule| (rule, AtomicU64::new(0)))
+                .collect(),
+        }
+    }
+
+    /// Whether to log the next event of `target` at `level`.
+    pub fn keep(&self, target: &str, level: &Level) -> bool {
+        // More verbose levels compare greater
+        if *level <= Level::WARN {
+            return true;
+        }
+
+        let Some((rule, seen)) = self.rules.iter().find(|(rule, _)| rule.covers(target)) else {
+            return true;
+        };
+        if rule.rate <= 0.0 {
+            return false;
+        }
+
+        let every = (1.0 / rule.rate).round().max(1.0) as u64;
+        seen.fetch_add(1, Ordering::Relaxed) % every == 0
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogSampler {
+    fn event_enabled(&self, event: &Event<'_>, _ctx: Context<'_, S>) -> bool {
+        let metadata = event.metadata();
+        self.keep(metadata.target(), metadata.level())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = parse_rules("rpc=0.01, database = 0.5,").unwrap();
+        assert_eq!(
+            rules,
+            vec![
+                SamplingRule {
+                    target: "rpc".to_string(),
+                    rate: 0.01
+                },
+                SamplingRule {
+                    target: "database".to_string(),
+                    rate: 0.5
+                },
+            ]
+        );
+
+        assert!(parse_rules("rpc").is_err());
+        assert!(parse_rules("rpc=2").is_err());
+        assert!(parse_rules("=0.5").is_err());
+    }
+
+    #[test]
+    fn test_keeps_one_in_n_below_warn() {
+        let sampler = LogSampler::new(parse_rules("rpc=0.25").unwrap());
+
+        let kept = (0..8)
+            .filter(|_| sampler.keep("rpc", &Level::DEBUG))
+            .count();
+        assert_eq!(kept, 2);
+
+        assert!((0..8).all(|_| sampler.keep("rpc", &Level::ERROR)));
+        assert!((0..8).all(|_| sampler.keep("rpc", &Level::WARN)));
+        assert!((0..8).all(|_| sampler.keep("database", &Level::DEBUG)));
+    }
+
+    #[test]
+    fn test_most_specific_rule_applies() {
+        let sampler =
+            LogSampler::new(parse_rules("vault_backend=1,vault_backend::indexer=0").unwrap());
+
+        assert!(!sampler.keep("vault_backend::indexer::lag", &Level::INFO));
+        assert!(sampler.keep("vault_backend::api", &Level::INFO));
+        assert!(sampler.keep("vault_backend_other", &Level::INFO));
+    }
+}
@@ -1,20 +1,31 @@
-use tracing::{info, debug, warn, error};
-use chrono::Utc;
+use tracing::{info, info_span, debug, warn, error, Span};
+use std::error::Error;
 use std::time::Instant;
 
-// Logging utilities for vault operations
+use crate::audit::{self, AuditCategory};
+use crate::error_handling::VaultError;
+
+// Logging utilities for vault operations. Everything is logged as typed fields
+// (`operation`, `vault`, `user`, `amount`, `duration_ms`, ...) under a fixed
+// message, so logs can be filtered and aggregated by field. Calls that carry a
+// count or a duration also record it as a metric (see `metrics`)
 pub struct Logger;
 
 impl Logger {
+    // Span for one API request, so whatever is logged while handling it
+    // carries its request id
+    pub fn request_span(request_id: &str, method: &str, path: &str) -> Span {
+        info_span!(target: "api", "request", request_id, method, path)
+    }
+
     // Log when a vault operation starts
     pub fn log_vault_operation_start(operation: &str, user: &str, vault: &str) {
         info!(
             target: "vault_operations",
-            "[START] {} | User: {} | Vault: {} | Time: {}",
             operation,
             user,
             vault,
-            Utc::now().to_rfc3339()
+            "vault operation started"
         );
     }
 
@@ -25,14 +36,15 @@ impl Logger {
         vault: &str,
         duration_ms: u128,
     ) {
+        record_vault_operation(operation, "success", duration_ms);
+
         info!(
             target: "vault_operations",
-            "[SUCCESS] {} | User: {} | Vault: {} | Time: {}ms | At: {}",
             operation,
             user,
             vault,
             duration_ms,
-            Utc::now().to_rfc3339()
+            "vault operation succeeded"
         );
     }
 
@@ -44,207 +56,310 @@ impl Logger {
         error: &str,
         duration_ms: u128,
     ) {
+        record_vault_operation(operation, "error", duration_ms);
+
         error!(
             target: "vault_operations",
-            "[ERROR] {} | User: {} | Vault: {} | Error: {} | Time: {}ms | At: {}",
             operation,
             user,
             vault,
             error,
             duration_ms,
-            Utc::now().to_rfc3339()
+            "vault operation failed"
         );
     }
 
+    /// Log a failed operation with the operation, vault and user its error
+    /// was tagged with (`with_vault_context`) as fields of their own
+    pub fn log_vault_error(err: &VaultError) {
+        let mut message = err.root().to_string();
+        let mut source = err.root().source();
+        while let Some(cause) = source {
+            message.push_str(&format!(": {}", cause));
+            source = cause.source();
+        }
+
+        match err.context() {
+            Some(context) => error!(
+                target: "vault_operations",
+                operation = context.operation,
+                vault = %context.vault,
+                user = %context.user,
+                error = %message,
+                "vault operation failed"
+            ),
+            None => error!(
+                target: "vault_operations",
+                error = %message,
+                "vault operation failed"
+            ),
+        }
+    }
+
     /// Log deposit transaction
     pub fn log_deposit(user: &str, amount: u64, tx_sig: &str) {
+        metrics::counter!("vault_deposits_total").increment(1);
+
         info!(
             target: "transactions",
-            "[DEPOSIT] User: {} | Amount: {} | Signature: {} | Timestamp: {}",
+            operation = "deposit",
             user,
             amount,
-            tx_sig,
-            Utc::now().to_rfc3339()
+            signature = tx_sig,
+            "deposit"
         );
     }
 
     /// Log withdrawal transaction
     pub fn log_withdrawal(user: &str, amount: u64, tx_sig: &str) {
+        metrics::counter!("vault_withdrawals_total").increment(1);
+
         info!(
             target: "transactions",
-            "[WITHDRAWAL] User: {} | Amount: {} | Signature: {} | Timestamp: {}",
+            operation = "withdraw",
             user,
             amount,
-            tx_sig,
-            Utc::now().to_rfc3339()
+            signature = tx_sig,
+            "withdrawal"
         );
     }
 
-    /// Log balance change
+    /// Log balance change (also audited)
     pub fn log_balance_change(user: &str, old_balance: u64, new_balance: u64, reason: &str) {
+        audit::record(
+            AuditCategory::BalanceChange,
+            user,
+            None,
+            serde_json::json!({
+                "old_balance": old_balance,
+                "new_balance": new_balance,
+                "reason": reason,
+            }),
+        );
+
         debug!(
             target: "balances",
-            "[BALANCE_CHANGE] User: {} | Old: {} | New: {} | Reason: {} | Timestamp: {}",
             user,
             old_balance,
             new_balance,
             reason,
-            Utc::now().to_rfc3339()
+            "balance changed"
         );
     }
 
     /// Log reconciliation event
     pub fn log_reconciliation(vault: &str, on_chain: u64, off_chain: u64, status: &str) {
+        metrics::counter!("reconciliation_results_total", "status" => status.to_string())
+            .increment(1);
+
         info!(
             target: "reconciliation",
-            "[RECONCILIATION] Vault: {} | On-chain: {} | Off-chain: {} | Status: {} | Timestamp: {}",
             vault,
             on_chain,
             off_chain,
             status,
-            Utc::now().to_rfc3339()
+            "vault reconciled"
         );
     }
 
     /// Log lock operation
     pub fn log_lock_operation(user: &str, amount: u64, reason: &str) {
+        metrics::counter!("collateral_locks_total").increment(1);
+
         info!(
             target: "locking",
-            "[LOCK] User: {} | Amount: {} | Reason: {} | Timestamp: {}",
+            operation = "lock",
             user,
             amount,
             reason,
-            Utc::now().to_rfc3339()
+            "collateral locked"
         );
     }
 
     /// Log unlock operation
     pub fn log_unlock_operation(user: &str, amount: u64, reason: &str) {
+        metrics::counter!("collateral_unlocks_total").increment(1);
+
         info!(
             target: "locking",
-            "[UNLOCK] User: {} | Amount: {} | Reason: {} | Timestamp: {}",
+            operation = "unlock",
             user,
             amount,
             reason,
-            Utc::now().to_rfc3339()
+            "collateral unlocked"
         );
     }
 
     /// Log RPC call
     pub fn log_rpc_call(method: &str, params: &str) {
-        debug!(
-            target: "rpc",
-            "[RPC_CALL] Method: {} | Params: {} | Timestamp: {}",
-            method,
-            params,
-            Utc::now().to_rfc3339()
-        );
+        debug!(target: "rpc", method, params, "rpc call");
     }
 
     /// Log RPC response
     pub fn log_rpc_response(method: &str, duration_ms: u128, success: bool) {
+        let outcome = if success { "success" } else { "error" };
+        metrics::counter!(
+            "rpc_requests_total",
+            "method" => method.to_string(),
+            "outcome" => outcome
+        )
+        .increment(1);
+        metrics::histogram!("rpc_request_duration_seconds", "method" => method.to_string())
+            .record(duration_ms as f64 / 1000.0);
+
         if success {
-            debug!(
-                target: "rpc",
-                "[RPC_RESPONSE_OK] Method: {} | Duration: {}ms | Timestamp: {}",
-                method,
-                duration_ms,
-                Utc::now().to_rfc3339()
-            );
+            debug!(target: "rpc", method, duration_ms, "rpc call succeeded");
         } else {
-            warn!(
-                target: "rpc",
-                "[RPC_RESPONSE_ERROR] Method: {} | Duration: {}ms | Timestamp: {}",
-                method,
-                duration_ms,
-                Utc::now().to_rfc3339()
-            );
+            warn!(target: "rpc", method, duration_ms, "rpc call failed");
         }
     }
 
     /// Log database operation
     pub fn log_db_operation(operation: &str, table: &str, duration_ms: u128) {
+        metrics::histogram!(
+            "db_operation_duration_seconds",
+            "operation" => operation.to_string(),
+            "table" => table.to_string()
+        )
+        .record(duration_ms as f64 / 1000.0);
+
         debug!(
             target: "database",
-            "[DB_{}] Table: {} | Duration: {}ms | Timestamp: {}",
             operation,
             table,
             duration_ms,
-            Utc::now().to_rfc3339()
+            "database operation"
         );
     }
 
     /// Log API request
-    pub fn log_api_request(method: &str, path: &str, user: &str) {
-        info!(
-            target: "api",
-            "[REQUEST] {} {} | User: {} | Timestamp: {}",
-            method,
-            path,
-            user,
-            Utc::now().to_rfc3339()
-        );
+    pub fn log_api_request(request_id: &str, method: &str, path: &str) {
+        debug!(target: "api", request_id, method, path, "request received");
     }
 
     /// Log API response
-    pub fn log_api_response(method: &str, path: &str, status: u16, duration_ms: u128) {
+    pub fn log_api_response(
+        request_id: &str,
+        method: &str,
+        path: &str,
+        principal: &str,
+        status: u16,
+        duration_ms: u128,
+    ) {
+        metrics::counter!(
+            "http_requests_total",
+            "method" => method.to_string(),
+            "status" => status.to_string()
+        )
+        .increment(1);
+        metrics::histogram!("http_request_duration_seconds", "method" => method.to_string())
+            .record(duration_ms as f64 / 1000.0);
+
         info!(
             target: "api",
-            "[RESPONSE] {} {} | Status: {} | Duration: {}ms | Timestamp: {}",
+            request_id,
             method,
             path,
+            user = principal,
             status,
             duration_ms,
-            Utc::now().to_rfc3339()
+            "request completed"
         );
     }
 
-    /// Log security event
+    /// Log security event (also audited)
     pub fn log_security_event(event_type: &str, user: &str, details: &str, severity: &str) {
+        metrics::counter!(
+            "security_events_total",
+            "event_type" => event_type.to_string(),
+            "severity" => severity.to_string()
+        )
+        .increment(1);
+
+        audit::record(
+            AuditCategory::SecurityEvent,
+            user,
+            None,
+            serde_json::json!({
+                "event_type": event_type,
+                "details": details,
+                "severity": severity,
+            }),
+        );
+
         error!(
             target: "security",
-            "[SECURITY][{}] Type: {} | User: {} | Details: {} | Timestamp: {}",
-            severity,
             event_type,
             user,
             details,
-            Utc::now().to_rfc3339()
+            severity,
+            "security event"
         );
     }
 
     /// Log state mismatch
     pub fn log_state_mismatch(vault: &str, expected: u64, actual: u64) {
+        metrics::counter!("state_mismatches_total").increment(1);
+
         warn!(
             target: "consistency",
-            "[STATE_MISMATCH] Vault: {} | Expected: {} | Actual: {} | Timestamp: {}",
             vault,
             expected,
             actual,
-            Utc::now().to_rfc3339()
+            "state mismatch"
         );
     }
 
     /// Log retry attempt
     pub fn log_retry_attempt(operation: &str, attempt: u32, reason: &str) {
+        metrics::counter!("retry_attempts_total", "operation" => operation.to_string())
+            .increment(1);
+
         warn!(
             target: "retry",
-            "[RETRY] Operation: {} | Attempt: {} | Reason: {} | Timestamp: {}",
             operation,
             attempt,
             reason,
-            Utc::now().to_rfc3339()
+            "retrying"
         );
     }
 
-    /// Log CPI call
+    /// Log CPI call (also audited)
     pub fn log_cpi_call(caller: &str, target_program: &str, instruction: &str) {
+        metrics::counter!("cpi_calls_total", "instruction" => instruction.to_string()).increment(1);
+
+        audit::record(
+            AuditCategory::CpiCall,
+            caller,
+            Some(target_program),
+            serde_json::json!({ "instruction": instruction }),
+        );
+
         info!(
             target: "cpi",
-            "[CPI] Caller: {} | Target: {} | Instruction: {} | Timestamp: {}",
+            operation = instruction,
             caller,
             target_program,
-            instruction,
-            Utc::now().to_rfc3339()
+            "cpi call"
+        );
+    }
+
+    /// Log an admin or operator action (also audited)
+    pub fn log_admin_action(actor: &str, action: &str, target: &str, status: u16) {
+        audit::record(
+            AuditCategory::AdminAction,
+            actor,
+            Some(target),
+            serde_json::json!({ "action": action, "status": status }),
+        );
+
+        info!(
+            target: "admin",
+            user = actor,
+            operation = action,
+            path = target,
+            status,
+            "admin action"
         );
     }
 
@@ -252,15 +367,27 @@ impl Logger {
     pub fn log_indexer_event(event_type: &str, tx_sig: &str, details: &str) {
         debug!(
             target: "indexer",
-            "[EVENT] Type: {} | Signature: {} | Details: {} | Timestamp: {}",
             event_type,
-            tx_sig,
+            signature = tx_sig,
             details,
-            Utc::now().to_rfc3339()
+            "indexed event"
         );
     }
 }
 
+/// `vault_operations_total` and `vault_operation_duration_seconds`, by
+/// operation and outcome.
+fn record_vault_operation(operation: &str, outcome: &'static str, duration_ms: u128) {
+    metrics::counter!(
+        "vault_operations_total",
+        "operation" => operation.to_string(),
+        "outcome" => outcome
+    )
+    .increment(1);
+    metrics::histogram!("vault_operation_duration_seconds", "operation" => operation.to_string())
+        .record(duration_ms as f64 / 1000.0);
+}
+
 /// Timer for measuring operation duration
 pub struct OperationTimer {
     start: Instant,
@@ -281,11 +408,17 @@ impl OperationTimer {
 
     pub fn log_completion(&self) {
         let elapsed = self.elapsed_ms();
+        metrics::histogram!(
+            "operation_duration_seconds",
+            "operation" => self.operation_name.clone()
+        )
+        .record(elapsed as f64 / 1000.0);
+
         info!(
             target: "performance",
-            "[OPERATION_COMPLETE] {}: {}ms",
-            self.operation_name,
-            elapsed
+            operation = %self.operation_name,
+            duration_ms = elapsed,
+            "operation completed"
         );
     }
 
@@ -294,10 +427,10 @@ impl OperationTimer {
         if elapsed > threshold_ms {
             warn!(
                 target: "performance",
-                "[SLOW_OPERATION] {}: {}ms (threshold: {}ms)",
-                self.operation_name,
-                elapsed,
-                threshold_ms
+                operation = %self.operation_name,
+                duration_ms = elapsed,
+                threshold_ms,
+                "slow operation"
             );
         }
     }
@@ -311,9 +444,9 @@ impl Drop for OperationTimer {
             // Log slow operations
             warn!(
                 target: "performance",
-                "[OPERATION_TIMEOUT] {}: {}ms",
-                self.operation_name,
-                elapsed
+                operation = %self.operation_name,
+                duration_ms = elapsed,
+                "operation took over a second"
             );
         }
     }
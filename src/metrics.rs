@@ -0,0 +1,349 @@
+//! Metrics behind the Prometheus `/metrics` endpoints.
+//!
+//! Code records through the `metrics` crate (`counter!`, `gauge!`,
+//! `histogram!`), which lands in the process-wide `MetricsRegistry` once
+//! `install` has run. Histograms share `BUCKETS`, so record durations in
+//! seconds.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+
+use metrics::{
+    Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+    SharedString, Unit,
+};
+
+/// Upper bounds of the histogram buckets, in seconds.
+pub const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Series of one metric, keyed by their rendered labels (`{vault="..."}`, or
+/// empty for the unlabeled series).
+type Series<T> = BTreeMap<String, Arc<T>>;
+type Family<T> = RwLock<BTreeMap<String, Series<T>>>;
+
+/// Process-wide registry of named gauges, counters and histograms.
+///
+/// Kept deliberately small: values live in memory and are rendered in the
+/// Prometheus text format by whatever exposes a metrics endpoint.
+pub struct MetricsRegistry {
+    gauges: Family<GaugeCell>,
+    counters: Family<CounterCell>,
+    histograms: Family<HistogramCell>,
+}
+
+static REGISTRY: OnceLock<MetricsRegistry> = OnceLock::new();
+
+/// Route the `metrics` macros to `MetricsRegistry::global()`. Only the first
+/// call in a process has an effect.
+pub fn install() {
+    let _ = metrics::set_global_recorder(GlobalRecorder);
+}
+
+impl MetricsRegistry {
+    pub fn new() -> Self {
+        Self {
+            gauges: RwLock::new(BTreeMap::new()),
+            counters: RwLock::new(BTreeMap::new()),
+            histograms: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// The shared registry used across the crate.
+    pub fn global() -> &'static MetricsRegistry {
+        REGISTRY.get_or_init(MetricsRegistry::new)
+    }
+
+    pub fn set_gauge(&self, name: &str, value: i64) {
+        series(&self.gauges, name, String::new()).set(value as f64);
+    }
+
+    pub fn gauge(&self, name: &str) -> Option<i64> {
+        lookup(&self.gauges, name).map(|gauge| gauge.get() as i64)
+    }
+
+    pub fn increment_counter(&self, name: &str, by: u64) {
+        series(&self.counters, name, String::new()).increment(by);
+    }
+
+    pub fn counter(&self, name: &str) -> u64 {
+        lookup(&self.counters, name)
+            .map(|counter| counter.0.load(Ordering::Relaxed))
+            .unwrap_or(0)
+    }
+
+    pub fn record_histogram(&self, name: &str, value: f64) {
+        series(&self.histograms, name, String::new()).record(value);
+    }
+
+    /// How many values the unlabeled `name` histogram has recorded.
+    pub fn histogram_count(&self, name: &str) -> u64 {
+        lookup(&self.histograms, name)
+            .map(|histogram| histogram.count.load(Ordering::Relaxed))
+            .unwrap_or(0)
+    }
+
+    /// Render all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        for (name, series) in read(&self.gauges).iter() {
+            out.push_str(&format!("# TYPE {} gauge\n", name));
+            for (labels, gauge) in series {
+                out.push_str(&format!("{}{} {}\n", name, labels, gauge.get()));
+            }
+        }
+
+        for (name, series) in read(&self.counters).iter() {
+            out.push_str(&format!("# TYPE {} counter\n", name));
+            for (labels, counter) in series {
+                let value = counter.0.load(Ordering::Relaxed);
+                out.push_str(&format!("{}{} {}\n", name, labels, value));
+            }
+        }
+
+        for (name, series) in read(&self.histograms).iter() {
+            out.push_str(&format!("# TYPE {} histogram\n", name));
+            for (labels, histogram) in series {
+                histogram.render(name, labels, &mut out);
+            }
+        }
+
+        out
+    }
+}
+
+impl Default for MetricsRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn read<T>(family: &Family<T>) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, Series<T>>> {
+    family.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn lookup<T>(family: &Family<T>, name: &str) -> Option<Arc<T>> {
+    read(family).get(name)?.get("").cloned()
+}
+
+fn series<T: Default>(family: &Family<T>, name: &str, labels: String) -> Arc<T> {
+    if let Some(cell) = read(family).get(name).and_then(|s| s.get(&labels)) {
+        return cell.clone();
+    }
+
+    family
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(name.to_string())
+        .or_default()
+        .entry(labels)
+        .or_default()
+        .clone()
+}
+
+/// `{a="1",b="2"}`, or empty without labels.
+fn render_labels(key: &Key) -> String {
+    let labels: Vec<String> = key
+        .labels()
+        .map(|label| format!("{}=\"{}\"", label.key(), escape(label.value())))
+        .collect();
+
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[derive(Default)]
+struct CounterCell(AtomicU64);
+
+impl CounterFn for CounterCell {
+    fn increment(&self, value: u64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn absolute(&self, value: u64) {
+        self.0.fetch_max(value, Ordering::Relaxed);
+    }
+}
+
+/// An `f64`, stored as its bits.
+#[derive(Default)]
+struct GaugeCell(AtomicU64);
+
+impl GaugeCell {
+    fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn update(&self, f: impl Fn(f64) -> f64) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some(f(f64::from_bits(bits)).to_bits())
+            });
+    }
+}
+
+impl GaugeFn for GaugeCell {
+    fn increment(&self, value: f64) {
+        self.update(|current| current + value);
+    }
+
+    fn decrement(&self, value: f64) {
+        self.update(|current| current - value);
+    }
+
+    fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Cumulative bucket counts, as Prometheus reports them.
+struct HistogramCell {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum: GaugeCell,
+}
+
+impl Default for HistogramCell {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+            sum: GaugeCell::default(),
+        }
+    }
+}
+
+impl HistogramCell {
+    fn render(&self, name: &str, labels: &str, out: &mut String) {
+        // `le` joins the series' own labels
+        let with_le = |le: &str| match labels.strip_suffix('}') {
+            Some(labels) => format!("{},le=\"{}\"}}", labels, le),
+            None => format!("{{le=\"{}\"}}", le),
+        };
+        let count = self.count.load(Ordering::Relaxed);
+
+        for (bound, bucket) in BUCKETS.iter().zip(&self.buckets) {
+            let value = bucket.load(Ordering::Relaxed);
+            out.push_str(&format!(
+                "{}_bucket{} {}\n",
+                name,
+                with_le(&bound.to_string()),
+                value
+            ));
+        }
+        out.push_str(&format!("{}_bucket{} {}\n", name, with_le("+Inf"), count));
+        out.push_str(&format!("{}_sum{} {}\n", name, labels, self.sum.get()));
+        out.push_str(&format!("{}_count{} {}\n", name, labels, count));
+    }
+}
+
+impl HistogramFn for HistogramCell {
+    fn record(&self, value: f64) {
+        for (bound, bucket) in BUCKETS.iter().zip(&self.buckets) {
+            if value <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.increment(value);
+    }
+}
+
+/// `metrics` recorder writing to `MetricsRegistry::global()`.
+struct GlobalRecorder;
+
+impl Recorder for GlobalRecorder {
+    fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_histogram(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        let registry = MetricsRegistry::global();
+        Counter::from_arc(series(&registry.counters, key.name(), render_labels(key)))
+    }
+
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        let registry = MetricsRegistry::global();
+        Gauge::from_arc(series(&registry.gauges, key.name(), render_labels(key)))
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        let registry = MetricsRegistry::global();
+        Histogram::from_arc(series(&registry.histograms, key.name(), render_labels(key)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gauge_and_counter() {
+        let registry = MetricsRegistry::new();
+        registry.set_gauge("lag", 5);
+        registry.set_gauge("lag", 7);
+        registry.increment_counter("events", 2);
+        registry.increment_counter("events", 3);
+
+        assert_eq!(registry.gauge("lag"), Some(7));
+        assert_eq!(registry.counter("events"), 5);
+        assert_eq!(registry.counter("missing"), 0);
+    }
+
+    #[test]
+    fn test_render_prometheus_format() {
+        let registry = MetricsRegistry::new();
+        registry.set_gauge("indexer_lag_slots", 42);
+
+        let rendered = registry.render();
+        assert!(rendered.contains("# TYPE indexer_lag_slots gauge"));
+        assert!(rendered.contains("indexer_lag_slots 42"));
+    }
+
+    #[test]
+    fn test_render_histogram() {
+        let registry = MetricsRegistry::new();
+        registry.record_histogram("run_seconds", 0.25);
+        registry.record_histogram("run_seconds", 3.0);
+
+        let rendered = registry.render();
+        assert!(rendered.contains("# TYPE run_seconds histogram"));
+        assert!(rendered.contains("run_seconds_bucket{le=\"0.1\"} 0"));
+        assert!(rendered.contains("run_seconds_bucket{le=\"0.25\"} 1"));
+        assert!(rendered.contains("run_seconds_bucket{le=\"5\"} 2"));
+        assert!(rendered.contains("run_seconds_bucket{le=\"+Inf\"} 2"));
+        assert!(rendered.contains("run_seconds_sum 3.25"));
+        assert!(rendered.contains("run_seconds_count 2"));
+    }
+
+    #[test]
+    fn test_macros_record_labeled_series() {
+        install();
+
+        metrics::counter!("test_requests_total", "status" => "200").increment(2);
+        metrics::counter!("test_requests_total", "status" => "500").increment(1);
+        metrics::histogram!("test_latency_seconds", "method" => "GET").record(0.02);
+
+        let rendered = MetricsRegistry::global().render();
+        assert!(rendered.contains("test_requests_total{status=\"200\"} 2"));
+        assert!(rendered.contains("test_requests_total{status=\"500\"} 1"));
+        assert!(rendered.contains("test_latency_seconds_bucket{method=\"GET\",le=\"0.025\"} 1"));
+        assert!(rendered.contains("test_latency_seconds_count{method=\"GET\"} 1"));
+    }
+}
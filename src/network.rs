@@ -0,0 +1,153 @@
+//! Named Solana clusters the services can run against.
+//!
+//! `NETWORK` picks a profile, which supplies the RPC endpoints, token program
+//! and commitment used unless they are configured explicitly. Each setting
+//! can also be scoped to one network with the profile's prefix, e.g.
+//! `DEVNET_PROGRAM_ID` or `MAINNET_RPC_URL`, so one config file can describe
+//! every cluster the program is deployed to.
+
+use solana_sdk::commitment_config::CommitmentLevel;
+use solana_sdk::pubkey::Pubkey;
+
+pub const SPL_TOKEN_PROGRAM_ID: Pubkey =
+    solana_sdk::pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+
+pub const TOKEN_2022_PROGRAM_ID: Pubkey =
+    solana_sdk::pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
+/// Token program vault token accounts belong to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenProgram {
+    SplToken,
+    Token2022,
+}
+
+impl TokenProgram {
+    pub fn id(&self) -> Pubkey {
+        match self {
+            TokenProgram::SplToken => SPL_TOKEN_PROGRAM_ID,
+            TokenProgram::Token2022 => TOKEN_2022_PROGRAM_ID,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenProgram::SplToken => "spl-token",
+            TokenProgram::Token2022 => "token-2022",
+        }
+    }
+}
+
+impl std::str::FromStr for TokenProgram {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "spl-token" | "spl_token" => Ok(TokenProgram::SplToken),
+            "token-2022" | "token_2022" => Ok(TokenProgram::Token2022),
+            other => anyhow::bail!("unknown token program '{}'", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkProfile {
+    /// Label stored with indexed rows.
+    pub name: &'static str,
+    /// Prefix of the settings scoped to this network, e.g. `DEVNET_RPC_URL`.
+    pub prefix: &'static str,
+    pub rpc_url: &'static str,
+    pub ws_url: &'static str,
+    pub token_program: TokenProgram,
+    /// Default `COMMITMENT`.
+    pub commitment: CommitmentLevel,
+}
+
+pub const PROFILES: &[NetworkProfile] = &[
+    NetworkProfile {
+        name: "localnet",
+        prefix: "LOCALNET",
+        rpc_url: "http://127.0.0.1:8899",
+        ws_url: "ws://127.0.0.1:8900",
+        token_program: TokenProgram::Token2022,
+        commitment: CommitmentLevel::Confirmed,
+    },
+    NetworkProfile {
+        name: "devnet",
+        prefix: "DEVNET",
+        rpc_url: "https://api.devnet.solana.com",
+        ws_url: "wss://api.devnet.solana.com",
+        token_program: TokenProgram::Token2022,
+        commitment: CommitmentLevel::Confirmed,
+    },
+    NetworkProfile {
+        name: "mainnet-beta",
+        prefix: "MAINNET",
+        rpc_url: "https://api.mainnet-beta.solana.com",
+        ws_url: "wss://api.mainnet-beta.solana.com",
+        token_program: TokenProgram::Token2022,
+        // Reorgs below finalized are rare but costly to unwind with real funds
+        commitment: CommitmentLevel::Finalized,
+    },
+];
+
+impl NetworkProfile {
+    /// The profile called `name`; "mainnet" also selects "mainnet-beta".
+    pub fn named(name: &str) -> anyhow::Result<&'static NetworkProfile> {
+        let name = name.trim().to_lowercase();
+        let name = if name == "mainnet" {
+            "mainnet-beta"
+        } else {
+            name.as_str()
+        };
+
+        PROFILES.iter().find(|p| p.name == name).ok_or_else(|| {
+            let known: Vec<&str> = PROFILES.iter().map(|p| p.name).collect();
+            anyhow::anyhow!(
+                "unknown network '{}', expected one of {}",
+                name,
+                known.join(", ")
+            )
+        })
+    }
+
+    /// Name of `setting` scoped to this network, e.g. `DEVNET_PROGRAM_ID`.
+    pub fn scoped(&self, setting: &str) -> String {
+        format!("{}_{}", self.prefix, setting)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_lookup() {
+        let devnet = NetworkProfile::named("devnet").unwrap();
+        assert_eq!(devnet.rpc_url, "https://api.devnet.solana.com");
+        assert_eq!(devnet.scoped("PROGRAM_ID"), "DEVNET_PROGRAM_ID");
+
+        assert_eq!(
+            NetworkProfile::named("Mainnet").unwrap().name,
+            "mainnet-beta"
+        );
+        assert_eq!(
+            NetworkProfile::named("mainnet-beta").unwrap().commitment,
+            CommitmentLevel::Finalized
+        );
+        assert!(NetworkProfile::named("testnet").is_err());
+    }
+
+    #[test]
+    fn test_token_program_parsing() {
+        assert_eq!(
+            "spl-token".parse::<TokenProgram>().unwrap(),
+            TokenProgram::SplToken
+        );
+        assert_eq!(
+            "token-2022".parse::<TokenProgram>().unwrap().id(),
+            TOKEN_2022_PROGRAM_ID
+        );
+        assert!("token-2023".parse::<TokenProgram>().is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use std::str::FromStr;
+
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::UiTransactionEncoding;
+use sqlx::PgPool;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::db::snapshot_repo::{SnapshotRepository, SnapshotVerificationRow};
+use crate::db::vault_repo::{VaultRepository, VaultRow};
+use crate::indexer::token_delta::owner_balances;
+
+/// Page size for `get_signatures_for_address` (the RPC maximum).
+const SIGNATURE_PAGE_SIZE: usize = 1000;
+
+/// Outcome of `SnapshotVerifier::verify_at_slot`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SnapshotVerificationReport {
+    pub checked: usize,
+    pub mismatched: usize,
+    /// Vaults without a snapshot indexed at or before the slot (older
+    /// snapshots don't record an indexed slot), or without a token account to
+    /// read the chain balance from.
+    pub skipped: usize,
+    /// Vaults whose check failed, e.g. because the chain balance couldn't be
+    /// read. The other vaults are still checked.
+    pub failed: usize,
+}
+
+/// Balance `owner` held in token accounts touched by the last successful
+/// transaction on `token_account` at or before `slot`, or 0 if it has none
+/// (the account didn't exist yet).
+///
+/// Plain RPC can't return account data as of a past slot, so the balance is
+/// read from that transaction's `postTokenBalances`. Signatures are paged
+/// newest-first, so the further back `slot` is, the more calls this costs;
+/// older transactions need an archive node.
+pub fn token_balance_at(
+    rpc: &RpcClient,
+    token_account: &Pubkey,
+    owner: &str,
+    slot: u64,
+) -> anyhow::Result<u64> {
+    let mut before = None;
+
+    loop {
+        let page = rpc.get_signatures_for_address_with_config(
+            token_account,
+            GetConfirmedSignaturesForAddress2Config {
+                before,
+                until: None,
+                limit: Some(SIGNATURE_PAGE_SIZE),
+                commitment: None,
+            },
+        )?;
+
+        let last_before = page
+            .iter()
+            .find(|info| info.err.is_none() && info.slot <= slot);
+
+        if let Some(info) = last_before {
+            let tx = rpc.get_transaction_with_config(
+                &info.signature.parse::<Signature>()?,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Json),
+                    commitment: None,
+                    max_supported_transaction_version: Some(0),
+                },
+            )?;
+
+            let meta = tx
+                .transaction
+                .meta
+                .ok_or_else(|| anyhow::anyhow!("transaction {} has no meta", info.signature))?;
+
+            let post = owner_balances(&meta)
+                .get(owner)
+                .map(|balance| balance.post)
+                .unwrap_or(0);
+
+            return Ok(post.try_into()?);
+        }
+
+        if page.len() < SIGNATURE_PAGE_SIZE {
+            return Ok(0);
+        }
+
+        before = match page.last() {
+            Some(info) => Some(info.signature.parse::<Signature>()?),
+            None => return Ok(0),
+        };
+    }
+}
+
+/// Checks historical `balance_snapshots` against the chain, to show that
+/// balances used for statements are trustworthy and not just current ones.
+pub struct SnapshotVerifier {
+    /// Should point at an archive node for slots older than its retention.
+    rpc: RpcClient,
+    pool: PgPool,
+}
+
+impl SnapshotVerifier {
+    pub fn new(rpc: RpcClient, pool: PgPool) -> Self {
+        Self { rpc, pool }
+    }
+
+    /// Compare every vault's newest snapshot indexed at or before `slot` with
+    /// its token account balance at the snapshot's indexed slot, so a lagging
+    /// indexer doesn't show up as a mismatch. Each check is stored in
+    /// `snapshot_verifications`, matching, mismatching or failed; a failed
+    /// vault doesn't stop the others from being checked.
+    pub async fn verify_at_slot(&self, slot: u64) -> anyhow::Result<SnapshotVerificationReport> {
+        let vault_repo = VaultRepository::new(&self.pool);
+        let snapshot_repo = SnapshotRepository::new(&self.pool);
+
+        let mut report = SnapshotVerificationReport::default();
+
+        for vault in vault_repo.get_all_vaults().await? {
+            if vault.vault_token_account.is_empty() {
+                warn!(
+                    "skipping vault {}: no token account recorded",
+                    vault.vault_pda
+                );
+                report.skipped += 1;
+                continue;
+            }
+
+            let snapshot = match snapshot_repo
+                .latest_indexed_at_or_before(&vault.vault_pda, slot as i64)
+                .await
+            {
+                Ok(Some(snapshot)) => snapshot,
+                Ok(None) => {
+                    report.skipped += 1;
+                    continue;
+                }
+                Err(e) => {
+                    warn!("failed to load snapshot of {}: {}", vault.vault_pda, e);
+                    report.failed += 1;
+                    continue;
+                }
+            };
+            let Some(indexed_slot) = snapshot.indexed_slot else {
+                report.skipped += 1;
+                continue;
+            };
+
+            let mut verification = SnapshotVerificationRow {
+                id: Uuid::new_v4(),
+                vault_pda: vault.vault_pda.clone(),
+                snapshot_time: snapshot.snapshot_time,
+                target_slot: slot as i64,
+                snapshot_balance: snapshot.total_balance,
+                onchain_balance: None,
+                matches: None,
+                error: None,
+            };
+
+            match self.onchain_balance(&vault, indexed_slot as u64) {
+                Ok(onchain) => {
+                    let matches = onchain == snapshot.total_balance;
+                    if !matches {
+                        warn!(
+                            "snapshot of {} at slot {} says {}, chain says {}",
+                            vault.vault_pda, indexed_slot, snapshot.total_balance, onchain
+                        );
+                        report.mismatched += 1;
+                    }
+                    verification.onchain_balance = Some(onchain);
+                    verification.matches = Some(matches);
+                    report.checked += 1;
+                }
+                Err(e) => {
+                    warn!(
+                        "failed to verify snapshot of {} at slot {}: {}",
+                        vault.vault_pda, indexed_slot, e
+                    );
+                    verification.error = Some(e.to_string());
+                    report.failed += 1;
+                }
+            }
+
+            if let Err(e) = snapshot_repo.record_verification(&verification).await {
+                warn!(
+                    "failed to record verification of {}: {}",
+                    vault.vault_pda, e
+                );
+            }
+        }
+
+        info!(
+            "verified snapshots at slot {}: {} checked, {} mismatched, {} skipped, {} failed",
+            slot, report.checked, report.mismatched, report.skipped, report.failed
+        );
+
+        Ok(report)
+    }
+
+    /// Balance of `vault`'s token account at `slot`.
+    fn onchain_balance(&self, vault: &VaultRow, slot: u64) -> anyhow::Result<i64> {
+        let token_account = Pubkey::from_str(&vault.vault_token_account)?;
+        let balance = token_balance_at(&self.rpc, &token_account, &vault.vault_pda, slot)?;
+
+        Ok(balance.try_into()?)
+    }
+}
@@ -1,2 +1,7 @@
 pub mod worker;
 pub mod onchain;
+pub mod historical;
+pub mod schedule;
+pub mod signatures;
+pub mod tolerance;
+pub mod service;
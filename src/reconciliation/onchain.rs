@@ -1,14 +1,104 @@
-use solana_client::rpc_client::RpcClient;
+use borsh::BorshDeserialize;
+use chrono::{DateTime, NaiveDateTime};
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_sdk::account::Account;
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
 use spl_token::solana_program::program_pack::Pack;
 use spl_token::state::Account as TokenAccount;
 
+use crate::states::CollateralVault;
+
+/// Page size for `get_signatures_for_address` (the RPC maximum).
+const SIGNATURE_PAGE_SIZE: usize = 1000;
+
+/// Fetch `address` at the client's commitment (`RECONCILIATION_COMMITMENT`,
+/// finalized by default), so state that may still roll back is never compared.
+fn fetch_account(rpc: &RpcClient, address: &Pubkey) -> anyhow::Result<Account> {
+    rpc.get_account_with_commitment(address, rpc.commitment())?
+        .value
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "account {} not found at {:?} commitment",
+                address,
+                rpc.commitment().commitment
+            )
+        })
+}
+
+/// Block time of the cluster's current slot at the client's commitment
+pub fn fetch_chain_time(rpc: &RpcClient) -> anyhow::Result<NaiveDateTime> {
+    let slot = rpc.get_slot_with_commitment(rpc.commitment())?;
+    let block_time = rpc.get_block_time(slot)?;
+
+    DateTime::from_timestamp(block_time, 0)
+        .map(|time| time.naive_utc())
+        .ok_or_else(|| anyhow::anyhow!("invalid block time {} for slot {}", block_time, slot))
+}
+
 /// Fetch SPL token balance for a token account
 pub fn fetch_token_balance(
     rpc: &RpcClient,
     token_account: &Pubkey,
 ) -> anyhow::Result<u64> {
-    let account = rpc.get_account(token_account)?;
+    let account = fetch_account(rpc, token_account)?;
     let token = TokenAccount::unpack(&account.data)?;
     Ok(token.amount)
 }
+
+/// Fetch and decode the vault account at `vault_pda`
+pub fn fetch_vault_state(
+    rpc: &RpcClient,
+    vault_pda: &Pubkey,
+) -> anyhow::Result<CollateralVault> {
+    let account = fetch_account(rpc, vault_pda)?;
+    let vault = CollateralVault::try_from_slice(&account.data)?;
+    Ok(vault)
+}
+
+/// `(signature, slot)` of the newest `limit` successful transactions that
+/// reference `address`, newest first. The flag is `true` when the address has
+/// no older history than what was returned.
+pub fn fetch_recent_signatures(
+    rpc: &RpcClient,
+    address: &Pubkey,
+    limit: usize,
+) -> anyhow::Result<(Vec<(String, i64)>, bool)> {
+    let mut before = None;
+    let mut signatures = vec![];
+    let mut scanned = 0;
+
+    while scanned < limit {
+        let page_size = SIGNATURE_PAGE_SIZE.min(limit - scanned);
+
+        let page = rpc.get_signatures_for_address_with_config(
+            address,
+            GetConfirmedSignaturesForAddress2Config {
+                before,
+                until: None,
+                limit: Some(page_size),
+                commitment: None,
+            },
+        )?;
+
+        scanned += page.len();
+
+        // Failed transactions are never indexed
+        signatures.extend(
+            page.iter()
+                .filter(|info| info.err.is_none())
+                .map(|info| (info.signature.clone(), info.slot as i64)),
+        );
+
+        if page.len() < page_size {
+            return Ok((signatures, true));
+        }
+
+        before = match page.last() {
+            Some(info) => Some(info.signature.parse::<Signature>()?),
+            None => return Ok((signatures, true)),
+        };
+    }
+
+    Ok((signatures, false))
+}
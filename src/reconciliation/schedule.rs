@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+
+use crate::db::vault_repo::VaultRow;
+
+/// Which vaults a reconciliation pass covers.
+///
+/// Vaults synced or transacted on within `active_window` are reconciled every
+/// pass. Dormant vaults are split into `dormant_every` stable groups and one
+/// group is reconciled per pass, so each is visited every `dormant_every`
+/// passes. `dormant_every` of 1 reconciles everything every pass.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReconciliationSchedule {
+    pub active_window: Duration,
+    pub dormant_every: u32,
+}
+
+impl Default for ReconciliationSchedule {
+    fn default() -> Self {
+        Self {
+            active_window: Duration::from_secs(3600),
+            dormant_every: 1,
+        }
+    }
+}
+
+impl ReconciliationSchedule {
+    /// Start of the activity window for a pass running at `now`.
+    pub fn active_since(&self, now: NaiveDateTime) -> NaiveDateTime {
+        chrono::Duration::from_std(self.active_window)
+            .ok()
+            .and_then(|window| now.checked_sub_signed(window))
+            .unwrap_or(NaiveDateTime::MIN)
+    }
+
+    /// Vaults due in pass number `pass`. `recently_transacted` holds vault
+    /// PDAs with transactions since `active_since`.
+    pub fn select<'v>(
+        &self,
+        vaults: &'v [VaultRow],
+        recently_transacted: &HashSet<String>,
+        active_since: NaiveDateTime,
+        pass: u64,
+    ) -> Vec<&'v VaultRow> {
+        let groups = u64::from(self.dormant_every.max(1));
+        let due_group = pass % groups;
+
+        vaults
+            .iter()
+            .filter(|vault| {
+                vault.last_synced_at >= active_since
+                    || recently_transacted.contains(&vault.vault_pda)
+                    || dormant_group(&vault.vault_pda, groups) == due_group
+            })
+            .collect()
+    }
+}
+
+/// Stable group of a vault, so a dormant vault keeps its slot in the
+/// rotation across restarts and as other vaults come and go.
+fn dormant_group(vault_pda: &str, groups: u64) -> u64 {
+    let hash = vault_pda.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x100_0000_01b3)
+    });
+
+    hash % groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    fn vault(pda: &str, last_synced_at: NaiveDateTime) -> VaultRow {
+        VaultRow {
+            vault_pda: pda.to_string(),
+            program_id: String::new(),
+            network: String::new(),
+            owner_pubkey: String::new(),
+            mint: String::new(),
+            vault_token_account: String::new(),
+            total_balance: 0,
+            locked_balance: 0,
+            available_balance: 0,
+            total_deposited: 0,
+            total_withdrawn: 0,
+            created_at: last_synced_at,
+            last_synced_at,
+            version: 0,
+            status: "active".to_string(),
+            status_before_close: None,
+        }
+    }
+
+    #[test]
+    fn test_active_vaults_every_pass_dormant_in_rotation() {
+        let now = DateTime::<Utc>::from_timestamp(1_700_000_000, 0)
+            .unwrap()
+            .naive_utc();
+        let long_ago = now - chrono::Duration::days(30);
+
+        let schedule = ReconciliationSchedule {
+            active_window: Duration::from_secs(600),
+            dormant_every: 4,
+        };
+        let since = schedule.active_since(now);
+
+        let mut vaults = vec![vault("synced", now), vault("transacted", long_ago)];
+        vaults.extend((0..20).map(|i| vault(&format!("dormant-{}", i), long_ago)));
+
+        let transacted = HashSet::from(["transacted".to_string()]);
+
+        let mut dormant_visits = 0;
+        for pass in 0..4 {
+            let due = schedule.select(&vaults, &transacted, since, pass);
+
+            assert!(due.iter().any(|v| v.vault_pda == "synced"));
+            assert!(due.iter().any(|v| v.vault_pda == "transacted"));
+            dormant_visits += due
+                .iter()
+                .filter(|v| v.vault_pda.starts_with("dormant"))
+                .count();
+        }
+
+        // Every dormant vault exactly once per rotation
+        assert_eq!(dormant_visits, 20);
+    }
+
+    #[test]
+    fn test_default_schedule_selects_everything() {
+        let now = DateTime::<Utc>::from_timestamp(1_700_000_000, 0)
+            .unwrap()
+            .naive_utc();
+        let vaults: Vec<_> = (0..5)
+            .map(|i| vault(&format!("v{}", i), now - chrono::Duration::days(i)))
+            .collect();
+
+        let schedule = ReconciliationSchedule::default();
+        let due = schedule.select(&vaults, &HashSet::new(), schedule.active_since(now), 7);
+
+        assert_eq!(due.len(), vaults.len());
+    }
+}
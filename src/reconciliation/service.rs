@@ -0,0 +1,50 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use solana_client::rpc_client::RpcClient;
+use sqlx::PgPool;
+use tracing::info;
+
+use crate::access_control::AccessControlManager;
+use crate::config::Config;
+use crate::reconciliation::worker::ReconciliationWorker;
+
+/// Reconcile indexed balances with the chain every
+/// `RECONCILIATION_INTERVAL_SECS` until the worker stops.
+///
+/// A pass interrupted by cancelling the future only loses its remaining
+/// reads; the discrepancies already logged are committed individually.
+pub async fn run(config: &Config, pool: PgPool) -> anyhow::Result<()> {
+    // The worker reads chain state at the client's commitment, finalized
+    // by default so unfinalized state is never compared
+    let rpc = RpcClient::new_with_commitment(
+        config.rpc_url.clone(),
+        config.reconciliation_commitment_config(),
+    );
+    let mut worker = ReconciliationWorker::new(rpc, pool.clone(), config.program_id)
+        .with_token_program(config.token_program)
+        .with_tolerance(config.reconciliation_tolerance.clone())
+        .with_schedule(config.reconciliation_schedule.clone());
+
+    if let Some(threshold) = config.reconciliation_alert_drift {
+        let access_control = AccessControlManager::new()
+            .with_pool(pool)
+            .with_escalation_rules(config.escalation_rules.clone())
+            .with_alerting(&config.alerting);
+
+        worker = worker.with_drift_alerts(Arc::new(access_control), threshold);
+    }
+
+    if let Some(window) = config.reconciliation_signature_window {
+        worker = worker.with_signature_check(window);
+    }
+
+    if let Some(batch_size) = config.reconciliation_batch_size {
+        worker = worker.with_batch_size(batch_size);
+    }
+
+    let interval = Duration::from_secs(config.reconciliation_interval_secs);
+    info!("reconciling every {:?}", interval);
+
+    worker.run(interval).await
+}
@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+
+/// Signatures one side of a vault's history has and the other lacks, as
+/// `(signature, slot)`.
+#[derive(Debug, Default, PartialEq)]
+pub struct SignatureGaps {
+    /// On-chain but never processed by the indexer.
+    pub missing: Vec<(String, i64)>,
+    /// Recorded in `transactions` but not on-chain.
+    pub extra: Vec<(String, i64)>,
+}
+
+impl SignatureGaps {
+    pub fn len(&self) -> usize {
+        self.missing.len() + self.extra.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Compare a vault's on-chain signatures with what the indexer recorded.
+///
+/// `onchain` should only hold signatures at or below the indexer's last
+/// processed slot, since newer ones simply haven't been reached yet.
+/// `unprocessed` is the subset of `onchain` with no `processed_events` row.
+/// `recorded` is read over the same slot range `onchain` covers, so
+/// transactions beyond the fetched window aren't reported as extra.
+pub fn signature_gaps(
+    onchain: &[(String, i64)],
+    unprocessed: &HashSet<String>,
+    recorded: &[(String, i64)],
+) -> SignatureGaps {
+    let onchain_set: HashSet<&str> = onchain.iter().map(|(sig, _)| sig.as_str()).collect();
+    let recorded_set: HashSet<&str> = recorded.iter().map(|(sig, _)| sig.as_str()).collect();
+
+    SignatureGaps {
+        missing: onchain
+            .iter()
+            .filter(|(sig, _)| unprocessed.contains(sig) && !recorded_set.contains(sig.as_str()))
+            .cloned()
+            .collect(),
+        extra: recorded
+            .iter()
+            .filter(|(sig, _)| !onchain_set.contains(sig.as_str()))
+            .cloned()
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sigs(entries: &[(&str, i64)]) -> Vec<(String, i64)> {
+        entries.iter().map(|(s, slot)| (s.to_string(), *slot)).collect()
+    }
+
+    #[test]
+    fn test_reports_missing_and_extra() {
+        let onchain = sigs(&[("a", 10), ("b", 11), ("c", 12)]);
+        let recorded = sigs(&[("a", 10), ("x", 11)]);
+        let unprocessed = HashSet::from(["c".to_string()]);
+
+        let gaps = signature_gaps(&onchain, &unprocessed, &recorded);
+
+        // "b" was processed without a transaction row (e.g. a filtered event)
+        assert_eq!(gaps.missing, sigs(&[("c", 12)]));
+        assert_eq!(gaps.extra, sigs(&[("x", 11)]));
+        assert_eq!(gaps.len(), 2);
+    }
+
+    #[test]
+    fn test_matching_history_has_no_gaps() {
+        let onchain = sigs(&[("a", 10), ("b", 11)]);
+
+        assert!(signature_gaps(&onchain, &HashSet::new(), &onchain).is_empty());
+    }
+}
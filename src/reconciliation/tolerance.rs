@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use crate::db::reconciliation_repo::DiscrepancySeverity;
+
+/// How far an off-chain balance may drift from the chain before it is
+/// recorded as a discrepancy. Drift within either bound is ignored.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DriftTolerance {
+    /// Base units of the mint.
+    pub absolute: u64,
+    /// Percent of the on-chain balance.
+    pub percent: f64,
+}
+
+impl DriftTolerance {
+    pub fn allows(&self, onchain: i64, offchain: i64) -> bool {
+        let drift = onchain.abs_diff(offchain);
+
+        drift <= self.absolute
+            || drift as f64 <= onchain.unsigned_abs() as f64 * self.percent / 100.0
+    }
+}
+
+/// Drift, as a percent of the on-chain balance, at which a discrepancy
+/// moves up a severity tier. Anything below `medium_percent` is `Low`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeverityThresholds {
+    pub medium_percent: f64,
+    pub high_percent: f64,
+    pub critical_percent: f64,
+}
+
+impl Default for SeverityThresholds {
+    fn default() -> Self {
+        Self {
+            medium_percent: 1.0,
+            high_percent: 5.0,
+            critical_percent: 25.0,
+        }
+    }
+}
+
+impl SeverityThresholds {
+    /// Drift against an empty on-chain balance is always `Critical`.
+    pub fn classify(&self, onchain: i64, offchain: i64) -> DiscrepancySeverity {
+        let drift = onchain.abs_diff(offchain);
+
+        if drift == 0 {
+            return DiscrepancySeverity::Low;
+        }
+        if onchain == 0 {
+            return DiscrepancySeverity::Critical;
+        }
+
+        let percent = drift as f64 * 100.0 / onchain.unsigned_abs() as f64;
+
+        if percent >= self.critical_percent {
+            DiscrepancySeverity::Critical
+        } else if percent >= self.high_percent {
+            DiscrepancySeverity::High
+        } else if percent >= self.medium_percent {
+            DiscrepancySeverity::Medium
+        } else {
+            DiscrepancySeverity::Low
+        }
+    }
+}
+
+/// Tolerances and severity tiers used by the reconciliation worker.
+#[derive(Clone, Debug, Default)]
+pub struct ToleranceConfig {
+    pub default: DriftTolerance,
+    /// Overrides of `default` keyed by mint.
+    pub per_mint: HashMap<String, DriftTolerance>,
+    pub severity: SeverityThresholds,
+}
+
+impl ToleranceConfig {
+    pub fn for_mint(&self, mint: &str) -> &DriftTolerance {
+        self.per_mint.get(mint).unwrap_or(&self.default)
+    }
+}
+
+/// Parse per-mint tolerances in the form `mint=absolute:percent,...`.
+pub fn parse_mint_tolerances(raw: &str) -> anyhow::Result<HashMap<String, DriftTolerance>> {
+    let mut tolerances = HashMap::new();
+
+    for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (mint, tolerance) = entry
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("invalid tolerance entry: {}", entry))?;
+
+        tolerances.insert(mint.trim().to_string(), parse_tolerance(tolerance)?);
+    }
+
+    Ok(tolerances)
+}
+
+/// Parse a single `absolute:percent` tolerance.
+pub fn parse_tolerance(raw: &str) -> anyhow::Result<DriftTolerance> {
+    let (absolute, percent) = raw
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("tolerance must be absolute:percent, got {}", raw))?;
+
+    let tolerance = DriftTolerance {
+        absolute: absolute.trim().parse()?,
+        percent: percent.trim().parse()?,
+    };
+
+    anyhow::ensure!(
+        tolerance.percent >= 0.0,
+        "negative tolerance percent: {}",
+        raw
+    );
+
+    Ok(tolerance)
+}
+
+/// Parse severity tiers in the form `medium:high:critical` (percents).
+pub fn parse_severity_thresholds(raw: &str) -> anyhow::Result<SeverityThresholds> {
+    let tiers = raw
+        .split(':')
+        .map(|t| t.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let [medium_percent, high_percent, critical_percent] = tiers[..] else {
+        anyhow::bail!("severity tiers must be medium:high:critical, got {}", raw);
+    };
+
+    anyhow::ensure!(
+        medium_percent <= high_percent && high_percent <= critical_percent,
+        "severity tiers must be ascending, got {}",
+        raw
+    );
+
+    Ok(SeverityThresholds {
+        medium_percent,
+        high_percent,
+        critical_percent,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tolerance_either_bound_allows() {
+        let tolerance = DriftTolerance {
+            absolute: 10,
+            percent: 1.0,
+        };
+
+        assert!(tolerance.allows(5_000, 4_990));
+        assert!(tolerance.allows(5_000, 5_050));
+        assert!(!tolerance.allows(5_000, 5_051));
+        assert!(DriftTolerance::default().allows(7, 7));
+        assert!(!DriftTolerance::default().allows(7, 8));
+    }
+
+    #[test]
+    fn test_classify_tiers() {
+        let tiers = SeverityThresholds::default();
+
+        assert_eq!(tiers.classify(1_000, 1_005), DiscrepancySeverity::Low);
+        assert_eq!(tiers.classify(1_000, 1_010), DiscrepancySeverity::Medium);
+        assert_eq!(tiers.classify(1_000, 940), DiscrepancySeverity::High);
+        assert_eq!(tiers.classify(1_000, 2_000), DiscrepancySeverity::Critical);
+        assert_eq!(tiers.classify(0, 1), DiscrepancySeverity::Critical);
+    }
+
+    #[test]
+    fn test_parse_tolerances_and_tiers() {
+        let tolerances = parse_mint_tolerances("MintA=100:0.5, MintB=0:2").unwrap();
+        assert_eq!(tolerances["MintA"].absolute, 100);
+        assert_eq!(tolerances["MintB"].percent, 2.0);
+
+        assert!(parse_mint_tolerances("MintA=100").is_err());
+        assert!(parse_tolerance("1:-1").is_err());
+
+        let tiers = parse_severity_thresholds("2:10:50").unwrap();
+        assert_eq!(tiers.high_percent, 10.0);
+        assert!(parse_severity_thresholds("10:2:50").is_err());
+        assert!(parse_severity_thresholds("1:2").is_err());
+    }
+}
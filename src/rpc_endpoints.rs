@@ -0,0 +1,375 @@
+//! Weighted RPC endpoints with roles, configured through `RPC_URLS`.
+//!
+//! Entries are separated by commas; each is a URL followed by an optional
+//! weight and roles joined with `+`:
+//!
+//! ```text
+//! https://reads.example 3 read, https://send.example send, wss://reads.example ws
+//! ```
+//!
+//! Without roles, http(s) endpoints are used for `read` and `send` and ws(s)
+//! ones for `ws`; the weight defaults to 1. Each call starts at an endpoint
+//! picked in proportion to the weights and fails over to the others,
+//! heaviest first, when an endpoint can't be reached.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcRole {
+    /// Account, balance and history queries.
+    Read,
+    /// Blockhashes and transaction submission.
+    Send,
+    /// Log subscriptions.
+    Websocket,
+}
+
+impl RpcRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RpcRole::Read => "read",
+            RpcRole::Send => "send",
+            RpcRole::Websocket => "ws",
+        }
+    }
+}
+
+impl std::str::FromStr for RpcRole {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "read" => Ok(RpcRole::Read),
+            "send" => Ok(RpcRole::Send),
+            "ws" | "websocket" => Ok(RpcRole::Websocket),
+            other => anyhow::bail!("unknown RPC role '{}'", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcEndpoint {
+    pub url: String,
+    pub weight: u32,
+    pub roles: Vec<RpcRole>,
+}
+
+impl std::str::FromStr for RpcEndpoint {
+    type Err = anyhow::Error;
+
+    /// `<url> [<weight>] [<role>+<role>...]`
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut words = s.split_whitespace();
+        let url = words
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("empty RPC endpoint"))?
+            .to_string();
+
+        let is_ws = url.starts_with("ws://") || url.starts_with("wss://");
+        anyhow::ensure!(
+            is_ws || url.starts_with("http://") || url.starts_with("https://"),
+            "RPC endpoint '{}' must be an http(s) or ws(s) URL",
+            url
+        );
+
+        let mut weight = None;
+        let mut roles = None;
+        for word in words {
+            if let Ok(w) = word.parse::<u32>() {
+                anyhow::ensure!(weight.is_none(), "'{}' has more than one weight", s.trim());
+                anyhow::ensure!(w > 0, "'{}' needs a weight of at least 1", s.trim());
+                weight = Some(w);
+            } else {
+                anyhow::ensure!(
+                    roles.is_none(),
+                    "'{}' has more than one role list",
+                    s.trim()
+                );
+                roles = Some(
+                    word.split('+')
+                        .map(str::parse)
+                        .collect::<anyhow::Result<Vec<RpcRole>>>()?,
+                );
+            }
+        }
+
+        let roles = roles.unwrap_or_else(|| {
+            if is_ws {
+                vec![RpcRole::Websocket]
+            } else {
+                vec![RpcRole::Read, RpcRole::Send]
+            }
+        });
+
+        for role in &roles {
+            anyhow::ensure!(
+                is_ws == (*role == RpcRole::Websocket),
+                "'{}' can't serve the {} role",
+                url,
+                role.as_str()
+            );
+        }
+
+        Ok(Self {
+            url,
+            weight: weight.unwrap_or(1),
+            roles,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcEndpoints {
+    endpoints: Vec<RpcEndpoint>,
+}
+
+impl RpcEndpoints {
+    /// `rpc_url` for reads and sends, `ws_url` for subscriptions.
+    pub fn single(rpc_url: &str, ws_url: &str) -> Self {
+        Self {
+            endpoints: vec![
+                RpcEndpoint {
+                    url: rpc_url.to_string(),
+                    weight: 1,
+                    roles: vec![RpcRole::Read, RpcRole::Send],
+                },
+                RpcEndpoint {
+                    url: ws_url.to_string(),
+                    weight: 1,
+                    roles: vec![RpcRole::Websocket],
+                },
+            ],
+        }
+    }
+
+    /// Parse `RPC_URLS`. Roles no entry covers are served by `rpc_url` and
+    /// `ws_url`.
+    pub fn parse(raw: &str, rpc_url: &str, ws_url: &str) -> anyhow::Result<Self> {
+        let mut endpoints = raw
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(str::parse)
+            .collect::<anyhow::Result<Vec<RpcEndpoint>>>()?;
+
+        for fallback in Self::single(rpc_url, ws_url).endpoints {
+            let missing: Vec<RpcRole> = fallback
+                .roles
+                .iter()
+                .copied()
+                .filter(|role| !endpoints.iter().any(|e| e.roles.contains(role)))
+                .collect();
+            if !missing.is_empty() {
+                endpoints.push(RpcEndpoint {
+                    roles: missing,
+                    ..fallback
+                });
+            }
+        }
+
+        Ok(Self { endpoints })
+    }
+
+    /// Endpoints serving `role`, heaviest first.
+    pub fn for_role(&self, role: RpcRole) -> Vec<&RpcEndpoint> {
+        let mut endpoints: Vec<&RpcEndpoint> = self
+            .endpoints
+            .iter()
+            .filter(|e| e.roles.contains(&role))
+            .collect();
+        endpoints.sort_by(|a, b| b.weight.cmp(&a.weight));
+        endpoints
+    }
+
+    /// URLs serving `role`, heaviest first.
+    pub fn urls(&self, role: RpcRole) -> Vec<String> {
+        self.for_role(role)
+            .into_iter()
+            .map(|e| e.url.clone())
+            .collect()
+    }
+
+    /// Clients for every endpoint serving `role`.
+    pub fn failover(&self, role: RpcRole, commitment: CommitmentConfig) -> RpcFailover {
+        RpcFailover {
+            clients: self
+                .for_role(role)
+                .into_iter()
+                .map(|e| {
+                    (
+                        e.weight,
+                        RpcClient::new_with_commitment(e.url.clone(), commitment),
+                    )
+                })
+                .collect(),
+            turn: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Clients for the endpoints of one role, tried in failover order.
+pub struct RpcFailover {
+    /// Heaviest first; never empty.
+    clients: Vec<(u32, RpcClient)>,
+    turn: AtomicU64,
+}
+
+impl RpcFailover {
+    pub fn single(client: RpcClient) -> Self {
+        Self {
+            clients: vec![(1, client)],
+            turn: AtomicU64::new(0),
+        }
+    }
+
+    /// The heaviest endpoint's client.
+    pub fn primary(&self) -> &RpcClient {
+        &self.clients[0].1
+    }
+
+    /// `(weight, url)` of every endpoint, heaviest first, for requests made
+    /// without an `RpcClient`.
+    pub fn endpoints(&self) -> Vec<(u32, String)> {
+        self.clients
+            .iter()
+            .map(|(weight, client)| (*weight, client.url()))
+            .collect()
+    }
+
+    /// Run `call` against an endpoint picked by weight, moving on to the
+    /// next one while endpoints can't be reached. Other errors are returned
+    /// as they are.
+    pub fn call<T, F>(&self, mut call: F) -> Result<T, ClientError>
+    where
+        F: FnMut(&RpcClient) -> Result<T, ClientError>,
+    {
+        let weights: Vec<u32> = self.clients.iter().map(|(weight, _)| *weight).collect();
+        let turn = self.turn.fetch_add(1, Ordering::Relaxed);
+        let mut order = failover_order(&weights, turn).into_iter().peekable();
+
+        loop {
+            let index = order.next().expect("RpcFailover has at least one client");
+            let _span = tracing::info_span!(
+                "rpc_call",
+                otel.kind = "client",
+                rpc.endpoint = %self.clients[index].1.url()
+            )
+            .entered();
+
+            match call(&self.clients[index].1) {
+                Err(e) if is_unreachable(&e) && order.peek().is_some() => {
+                    tracing::warn!(
+                        "RPC endpoint {} unreachable, failing over: {}",
+                        self.clients[index].1.url(),
+                        e
+                    );
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl From<RpcClient> for RpcFailover {
+    fn from(client: RpcClient) -> Self {
+        Self::single(client)
+    }
+}
+
+/// Indices of `weights` to try: one picked in proportion to its weight for
+/// this `turn`, then the rest heaviest first.
+pub(crate) fn failover_order(weights: &[u32], turn: u64) -> Vec<usize> {
+    let total: u64 = weights.iter().map(|w| *w as u64).sum();
+    let mut point = turn % total.max(1);
+    let first = weights
+        .iter()
+        .position(|w| {
+            if point < *w as u64 {
+                true
+            } else {
+                point -= *w as u64;
+                false
+            }
+        })
+        .unwrap_or(0);
+
+    let mut rest: Vec<usize> = (0..weights.len()).filter(|i| *i != first).collect();
+    rest.sort_by(|a, b| weights[*b].cmp(&weights[*a]));
+
+    std::iter::once(first).chain(rest).collect()
+}
+
+/// Transport failures, as opposed to errors the endpoint answered with.
+fn is_unreachable(error: &ClientError) -> bool {
+    matches!(
+        error.kind(),
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_endpoints() {
+        let endpoints = RpcEndpoints::parse(
+            "https://reads.example 3 read, https://send.example send+read, \
+             wss://reads.example",
+            "http://127.0.0.1:8899",
+            "ws://127.0.0.1:8900",
+        )
+        .unwrap();
+
+        assert_eq!(
+            endpoints.urls(RpcRole::Read),
+            vec!["https://reads.example", "https://send.example"]
+        );
+        assert_eq!(endpoints.urls(RpcRole::Send), vec!["https://send.example"]);
+        assert_eq!(
+            endpoints.urls(RpcRole::Websocket),
+            vec!["wss://reads.example"]
+        );
+    }
+
+    #[test]
+    fn test_missing_roles_fall_back_to_rpc_url() {
+        let endpoints = RpcEndpoints::parse(
+            "https://reads.example read",
+            "http://127.0.0.1:8899",
+            "ws://127.0.0.1:8900",
+        )
+        .unwrap();
+
+        assert_eq!(endpoints.urls(RpcRole::Send), vec!["http://127.0.0.1:8899"]);
+        assert_eq!(
+            endpoints.urls(RpcRole::Websocket),
+            vec!["ws://127.0.0.1:8900"]
+        );
+        assert_eq!(endpoints.urls(RpcRole::Read), vec!["https://reads.example"]);
+    }
+
+    #[test]
+    fn test_invalid_endpoints() {
+        assert!("reads.example".parse::<RpcEndpoint>().is_err());
+        assert!("https://a.example 0".parse::<RpcEndpoint>().is_err());
+        assert!("https://a.example fetch".parse::<RpcEndpoint>().is_err());
+        assert!("https://a.example ws".parse::<RpcEndpoint>().is_err());
+        assert!("wss://a.example send".parse::<RpcEndpoint>().is_err());
+    }
+
+    #[test]
+    fn test_failover_order_follows_weights() {
+        let weights = [3, 1];
+        let firsts: Vec<usize> = (0..8)
+            .map(|turn| failover_order(&weights, turn)[0])
+            .collect();
+        assert_eq!(firsts, vec![0, 0, 0, 1, 0, 0, 0, 1]);
+
+        assert_eq!(failover_order(&[1, 5, 2], 0), vec![0, 1, 2]);
+        assert_eq!(failover_order(&[1, 5, 2], 3), vec![1, 2, 0]);
+    }
+}
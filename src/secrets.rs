@@ -0,0 +1,462 @@
+//! Secrets kept in a secrets manager instead of the environment.
+//!
+//! A secret setting (see `SECRET_SETTINGS`) whose value is `secret:<name>`
+//! is read from the backend picked by `SECRETS_BACKEND`: `aws` (Secrets
+//! Manager), `vault` (HashiCorp Vault, KV v2) or `gcp` (Secret Manager).
+//! `secret:<name>#<field>` picks one field of a JSON secret; Vault secrets
+//! default to their `value` field. Fetched secrets are cached for
+//! `SECRETS_CACHE_TTL_SECS`, and `SecretStore::watch` re-reads one on that
+//! interval so rotated credentials are picked up without a restart.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use base64::Engine;
+use tokio::sync::{OnceCell, RwLock};
+use tracing::{info, warn};
+
+/// Settings that may hold a `secret:` reference instead of the value.
+pub const SECRET_SETTINGS: &[&str] = &[
+    "DATABASE_URL",
+    "DATABASE_REPLICA_URLS",
+    "PAYER_KEYPAIR",
+    "ALERT_WEBHOOK_URL",
+    "SLACK_WEBHOOK_URL",
+    "PAGERDUTY_ROUTING_KEY",
+];
+
+const REFERENCE_PREFIX: &str = "secret:";
+
+/// Default `SECRETS_CACHE_TTL_SECS`.
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(300);
+
+const GCP_SECRET_MANAGER_URL: &str = "https://secretmanager.googleapis.com/v1";
+
+const GCP_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+/// `secret:<name>[#<field>]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretRef {
+    pub name: String,
+    pub field: Option<String>,
+}
+
+impl SecretRef {
+    /// The reference in `value`, or `None` for a plain value.
+    pub fn parse(value: &str) -> Option<anyhow::Result<Self>> {
+        let reference = value.trim().strip_prefix(REFERENCE_PREFIX)?;
+        let (name, field) = match reference.split_once('#') {
+            Some((name, field)) => (name, Some(field.to_string())),
+            None => (reference, None),
+        };
+
+        if name.is_empty() || field.as_deref() == Some("") {
+            return Some(Err(anyhow::anyhow!(
+                "invalid secret reference '{}', expected secret:<name>[#<field>]",
+                value.trim()
+            )));
+        }
+
+        Some(Ok(Self {
+            name: name.to_string(),
+            field,
+        }))
+    }
+}
+
+#[derive(Clone)]
+pub enum SecretsBackend {
+    /// Credentials and region come from the usual AWS sources (environment,
+    /// profile, instance role); `AWS_REGION` overrides the region.
+    Aws { region: Option<String> },
+    /// `VAULT_ADDR`, `VAULT_TOKEN` and the KV v2 mount (`VAULT_MOUNT`,
+    /// default "secret").
+    Vault {
+        addr: String,
+        token: String,
+        mount: String,
+    },
+    /// `GCP_PROJECT`; the access token is `GCP_ACCESS_TOKEN` if set, else
+    /// the instance service account's from the metadata server.
+    Gcp {
+        project: String,
+        access_token: Option<String>,
+    },
+}
+
+impl SecretsBackend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SecretsBackend::Aws { .. } => "aws",
+            SecretsBackend::Vault { .. } => "vault",
+            SecretsBackend::Gcp { .. } => "gcp",
+        }
+    }
+
+    /// The backend `SECRETS_BACKEND` selects, or `None` when it is unset or
+    /// "env". `var` looks up the other settings.
+    pub fn from_settings(
+        var: impl Fn(&str) -> Option<String>,
+    ) -> anyhow::Result<Option<SecretsBackend>> {
+        let required = |name: &str| {
+            var(name).ok_or_else(|| anyhow::anyhow!("{} is required by SECRETS_BACKEND", name))
+        };
+
+        let backend = match var("SECRETS_BACKEND").map(|raw| raw.trim().to_lowercase()) {
+            None => return Ok(None),
+            Some(name) => match name.as_str() {
+                "" | "env" => return Ok(None),
+                "aws" => SecretsBackend::Aws {
+                    region: var("AWS_REGION"),
+                },
+                "vault" => SecretsBackend::Vault {
+                    addr: required("VAULT_ADDR")?.trim_end_matches('/').to_string(),
+                    token: required("VAULT_TOKEN")?,
+                    mount: var("VAULT_MOUNT").unwrap_or_else(|| "secret".to_string()),
+                },
+                "gcp" => SecretsBackend::Gcp {
+                    project: required("GCP_PROJECT")?,
+                    access_token: var("GCP_ACCESS_TOKEN"),
+                },
+                other => anyhow::bail!(
+                    "unknown SECRETS_BACKEND '{}', expected env, aws, vault or gcp",
+                    other
+                ),
+            },
+        };
+
+        Ok(Some(backend))
+    }
+}
+
+/// Fetches secrets from one backend, caching them for `ttl`.
+pub struct SecretStore {
+    backend: SecretsBackend,
+    ttl: Duration,
+    http: reqwest::Client,
+    aws: OnceCell<aws_sdk_secretsmanager::Client>,
+    /// Raw secrets by name, with when they were fetched.
+    cache: RwLock<HashMap<String, (String, Instant)>>,
+    /// Settings that were resolved through this store.
+    references: HashMap<String, SecretRef>,
+}
+
+impl std::fmt::Debug for SecretStore {
+    // Never print cached secrets
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretStore")
+            .field("backend", &self.backend.as_str())
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SecretStore {
+    pub fn new(backend: SecretsBackend, ttl: Duration) -> Self {
+        Self {
+            backend,
+            ttl,
+            http: reqwest::Client::new(),
+            aws: OnceCell::new(),
+            cache: RwLock::new(HashMap::new()),
+            references: HashMap::new(),
+        }
+    }
+
+    /// Remember that `setting` was read from `reference`, for `watch`.
+    pub fn with_reference(mut self, setting: &str, reference: SecretRef) -> Self {
+        self.references.insert(setting.to_string(), reference);
+        self
+    }
+
+    pub fn backend(&self) -> &SecretsBackend {
+        &self.backend
+    }
+
+    /// The reference `setting` was resolved from, if it was a secret.
+    pub fn reference(&self, setting: &str) -> Option<&SecretRef> {
+        self.references.get(setting)
+    }
+
+    /// The secret `reference` points at, from the cache while it is fresh.
+    pub async fn get(&self, reference: &SecretRef) -> anyhow::Result<String> {
+        let cached = self
+            .cache
+            .read()
+            .await
+            .get(&reference.name)
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < self.ttl)
+            .map(|(raw, _)| raw.clone());
+
+        let raw = match cached {
+            Some(raw) => raw,
+            None => self.refresh(&reference.name).await?,
+        };
+
+        self.select_field(&raw, reference)
+    }
+
+    /// Re-read `setting` every `ttl` and pass it to `on_change` whenever it
+    /// differs from the last value. Returns at once if `setting` wasn't a
+    /// secret.
+    pub async fn watch<F>(&self, setting: &str, mut on_change: F)
+    where
+        F: FnMut(&str) -> anyhow::Result<()>,
+    {
+        let Some(reference) = self.reference(setting) else {
+            return;
+        };
+        let mut last = self.get(reference).await.ok();
+
+        loop {
+            tokio::time::sleep(self.ttl).await;
+
+            let value = match self.get(reference).await {
+                Ok(value) => value,
+                Err(e) => {
+                    warn!("could not refresh secret for {}: {:#}", setting, e);
+                    continue;
+                }
+            };
+
+            if last.as_deref() == Some(value.as_str()) {
+                continue;
+            }
+
+            match on_change(&value) {
+                Ok(()) => {
+                    info!("{} rotated in secret {}", setting, reference.name);
+                    last = Some(value);
+                }
+                Err(e) => warn!("could not apply rotated {}: {:#}", setting, e),
+            }
+        }
+    }
+
+    async fn refresh(&self, name: &str) -> anyhow::Result<String> {
+        let raw = self.fetch(name).await.with_context(|| {
+            format!(
+                "could not read secret '{}' from {}",
+                name,
+                self.backend.as_str()
+            )
+        })?;
+
+        self.cache
+            .write()
+            .await
+            .insert(name.to_string(), (raw.clone(), Instant::now()));
+
+        Ok(raw)
+    }
+
+    fn select_field(&self, raw: &str, reference: &SecretRef) -> anyhow::Result<String> {
+        let field = match (&reference.field, &self.backend) {
+            (Some(field), _) => field.as_str(),
+            // KV v2 secrets are always objects
+            (None, SecretsBackend::Vault { .. }) => "value",
+            (None, _) => return Ok(raw.to_string()),
+        };
+
+        json_field(raw, field)
+            .with_context(|| format!("secret '{}' has no field '{}'", reference.name, field))
+    }
+
+    async fn fetch(&self, name: &str) -> anyhow::Result<String> {
+        match &self.backend {
+            SecretsBackend::Aws { region } => {
+                let client = self
+                    .aws
+                    .get_or_init(|| async {
+                        let loader = aws_config::defaults(aws_config::BehaviorVersion::latest());
+                        let loader = match region {
+                            Some(region) => loader.region(aws_config::Region::new(region.clone())),
+                            None => loader,
+                        };
+                        aws_sdk_secretsmanager::Client::new(&loader.load().await)
+                    })
+                    .await;
+
+                let output = client.get_secret_value().secret_id(name).send().await?;
+                match (output.secret_string(), output.secret_binary()) {
+                    (Some(value), _) => Ok(value.to_string()),
+                    (None, Some(binary)) => Ok(String::from_utf8(binary.as_ref().to_vec())?),
+                    (None, None) => anyhow::bail!("secret has no value"),
+                }
+            }
+            SecretsBackend::Vault { addr, token, mount } => {
+                let body: serde_json::Value = self
+                    .http
+                    .get(format!("{}/v1/{}/data/{}", addr, mount, name))
+                    .header("X-Vault-Token", token)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+
+                let data = body
+                    .pointer("/data/data")
+                    .ok_or_else(|| anyhow::anyhow!("not a KV v2 secret"))?;
+                Ok(data.to_string())
+            }
+            SecretsBackend::Gcp {
+                project,
+                access_token,
+            } => {
+                let access_token = match access_token {
+                    Some(token) => token.clone(),
+                    None => self.gcp_metadata_token().await?,
+                };
+
+                let body: serde_json::Value = self
+                    .http
+                    .get(format!(
+                        "{}/projects/{}/secrets/{}/versions/latest:access",
+                        GCP_SECRET_MANAGER_URL, project, name
+                    ))
+                    .bearer_auth(access_token)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+
+                let data = body
+                    .pointer("/payload/data")
+                    .and_then(|data| data.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("response has no payload"))?;
+                let bytes = base64::engine::general_purpose::STANDARD.decode(data)?;
+                Ok(String::from_utf8(bytes)?)
+            }
+        }
+    }
+
+    async fn gcp_metadata_token(&self) -> anyhow::Result<String> {
+        let body: serde_json::Value = self
+            .http
+            .get(GCP_TOKEN_URL)
+            .header("Metadata-Flavor", "Google")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("no GCP_ACCESS_TOKEN and no metadata server")?;
+
+        body.get("access_token")
+            .and_then(|token| token.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("metadata server returned no access_token"))
+    }
+}
+
+/// `field` of the JSON object `raw`, as a string.
+fn json_field(raw: &str, field: &str) -> anyhow::Result<String> {
+    let object: serde_json::Value = serde_json::from_str(raw).context("secret is not JSON")?;
+    match object.get(field) {
+        Some(serde_json::Value::String(value)) => Ok(value.clone()),
+        Some(serde_json::Value::Null) | None => anyhow::bail!("field is missing"),
+        Some(other) => Ok(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reference() {
+        assert!(SecretRef::parse("postgres://localhost/vault").is_none());
+
+        let reference = SecretRef::parse("secret:prod/vault-backend#database_url")
+            .unwrap()
+            .unwrap();
+        assert_eq!(reference.name, "prod/vault-backend");
+        assert_eq!(reference.field.as_deref(), Some("database_url"));
+
+        let whole = SecretRef::parse(" secret:payer-keypair ").unwrap().unwrap();
+        assert_eq!(whole.field, None);
+
+        assert!(SecretRef::parse("secret:").unwrap().is_err());
+        assert!(SecretRef::parse("secret:name#").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_backend_selection() {
+        let settings = |pairs: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                pairs
+                    .iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+
+        assert!(SecretsBackend::from_settings(settings(&[]))
+            .unwrap()
+            .is_none());
+        assert!(
+            SecretsBackend::from_settings(settings(&[("SECRETS_BACKEND", "env")]))
+                .unwrap()
+                .is_none()
+        );
+
+        let vault = SecretsBackend::from_settings(settings(&[
+            ("SECRETS_BACKEND", "vault"),
+            ("VAULT_ADDR", "https://vault.internal:8200/"),
+            ("VAULT_TOKEN", "s.token"),
+        ]))
+        .unwrap()
+        .unwrap();
+        match vault {
+            SecretsBackend::Vault { addr, mount, .. } => {
+                assert_eq!(addr, "https://vault.internal:8200");
+                assert_eq!(mount, "secret");
+            }
+            _ => panic!("expected the vault backend"),
+        }
+
+        assert!(SecretsBackend::from_settings(settings(&[("SECRETS_BACKEND", "vault")])).is_err());
+        assert!(SecretsBackend::from_settings(settings(&[("SECRETS_BACKEND", "gcp")])).is_err());
+        assert!(SecretsBackend::from_settings(settings(&[("SECRETS_BACKEND", "azure")])).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cached_secret_fields() {
+        let store = SecretStore::new(
+            SecretsBackend::Vault {
+                addr: "http://127.0.0.1:1".to_string(),
+                token: String::new(),
+                mount: "secret".to_string(),
+            },
+            Duration::from_secs(60),
+        );
+        store.cache.write().await.insert(
+            "vault-backend".to_string(),
+            (
+                r#"{"value":"postgres://db/vault","port":5432}"#.to_string(),
+                Instant::now(),
+            ),
+        );
+
+        let reference = |raw: &str| SecretRef::parse(raw).unwrap().unwrap();
+        assert_eq!(
+            store.get(&reference("secret:vault-backend")).await.unwrap(),
+            "postgres://db/vault"
+        );
+        assert_eq!(
+            store
+                .get(&reference("secret:vault-backend#port"))
+                .await
+                .unwrap(),
+            "5432"
+        );
+        assert!(store
+            .get(&reference("secret:vault-backend#user"))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,153 @@
+//! Log output and optional OpenTelemetry trace export for the service binaries.
+//!
+//! Logs go to stdout, filtered by `RUST_LOG` as before, and to rolling files
+//! as well when `LOG_FILE_DIR` is set (see `log_file`). `LOG_SAMPLING` thins
+//! out noisy targets in both (see `log_sampling`). When
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` (or `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`) is
+//! set, spans at `info` and above are also exported over OTLP/HTTP; the
+//! exporter reads the other standard `OTEL_*` variables (headers, timeout,
+//! `OTEL_SERVICE_NAME`) itself. Incoming `traceparent` headers are honored, so
+//! an API request joins the caller's trace.
+
+use axum::extract::{MatchedPath, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use tracing::Instrument;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::log_file::{self, LogFileConfig};
+use crate::log_sampling::{LogSampler, SamplingRule};
+
+/// Flushes the spans and log lines still buffered when dropped, so keep it
+/// alive until the binary exits.
+pub struct TelemetryGuard {
+    provider: Option<SdkTracerProvider>,
+    _log_file: Option<WorkerGuard>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("failed to flush traces: {}", e);
+            }
+        }
+    }
+}
+
+/// Install the global subscriber and the `metrics` recorder. `service_name`
+/// names the exported traces unless `OTEL_SERVICE_NAME` is set, and the log
+/// files unless `LOG_FILE_NAME` is.
+pub fn init(
+    service_name: &'static str,
+    log_file: Option<&LogFileConfig>,
+    log_sampling: &[SamplingRule],
+) -> anyhow::Result<TelemetryGuard> {
+    let provider = if otlp_configured() {
+        Some(tracer_provider(service_name)?)
+    } else {
+        None
+    };
+
+    let otel = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer(service_name))
+            .with_filter(LevelFilter::INFO)
+    });
+
+    let (file_writer, log_file_guard) = match log_file {
+        Some(config) => {
+            let (writer, guard) = log_file::writer(config, service_name)?;
+            (Some(writer), Some(guard))
+        }
+        None => (None, None),
+    };
+    let file = file_writer.map(|writer| {
+        tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(writer)
+            .with_filter(EnvFilter::from_default_env())
+    });
+
+    tracing_subscriber::registry()
+        .with(LogSampler::new(log_sampling.to_vec()))
+        .with(tracing_subscriber::fmt::layer().with_filter(EnvFilter::from_default_env()))
+        .with(file)
+        .with(otel)
+        .init();
+    crate::metrics::install();
+
+    Ok(TelemetryGuard {
+        provider,
+        _log_file: log_file_guard,
+    })
+}
+
+fn otlp_configured() -> bool {
+    [
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    ]
+    .iter()
+    .any(|var| std::env::var(var).is_ok_and(|v| !v.trim().is_empty()))
+}
+
+fn tracer_provider(service_name: &'static str) -> anyhow::Result<SdkTracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()?;
+
+    let mut resource = Resource::builder();
+    if std::env::var_os("OTEL_SERVICE_NAME").is_none() {
+        resource = resource.with_service_name(service_name);
+    }
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource.build())
+        .build();
+
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    opentelemetry::global::set_tracer_provider(provider.clone());
+
+    Ok(provider)
+}
+
+/// Run the request in a server span named after its route, continuing the
+/// trace from the request's `traceparent` header if it has one.
+pub async fn trace_request(req: Request, next: Next) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| req.uri().path().to_string());
+
+    let span = tracing::info_span!(
+        "http_request",
+        otel.name = format!("{} {}", req.method(), route),
+        otel.kind = "server",
+        http.request.method = %req.method(),
+        http.route = %route,
+        http.response.status_code = tracing::field::Empty,
+    );
+
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(req.headers()))
+    });
+    span.set_parent(parent);
+
+    let response = next.run(req).instrument(span.clone()).await;
+    span.record("http.response.status_code", response.status().as_u16());
+
+    response
+}
@@ -6,8 +6,7 @@ use solana_sdk::{
 use solana_system_interface::program::ID as SYSTEM_PROGRAM_ID;
 use spl_associated_token_account::get_associated_token_address_with_program_id;
 
-const TOKEN_2022_PROGRAM_ID: Pubkey =
-    solana_sdk::pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+use crate::network::TokenProgram;
 
 const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
     solana_sdk::pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
@@ -15,12 +14,22 @@ const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
 // Builds Solana transactions for vault operations
 pub struct TransactionBuilder {
     program_id: Pubkey, // this program id it public key of the user.
+    token_program: Pubkey, // token program the vault token accounts belong to
 }
 
 impl TransactionBuilder {
 
     pub fn new(program_id: Pubkey) -> Self { // this is the factory function for the transaction builder
-        Self { program_id }
+        Self {
+            program_id,
+            token_program: TokenProgram::Token2022.id(),
+        }
+    }
+
+    // use another token program than token-2022, see `NetworkProfile`
+    pub fn with_token_program(mut self, token_program: TokenProgram) -> Self {
+        self.token_program = token_program.id();
+        self
     }
 
 
@@ -28,6 +37,11 @@ impl TransactionBuilder {
         Pubkey::find_program_address(&[b"vault", user.as_ref()], &self.program_id)
     }
 
+    // derive the associated token account for an owner (user wallet or vault pda)
+    pub fn derive_token_account(&self, owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+        get_associated_token_address_with_program_id(owner, mint, &self.token_program)
+    }
+
     pub fn build_deposit_ix(
         &self,
         user: &Pubkey,
@@ -38,10 +52,10 @@ impl TransactionBuilder {
         let (vault_pda, _) = self.derive_vault_pda(user);
 
         let user_token_account =
-            get_associated_token_address_with_program_id(user, mint, &TOKEN_2022_PROGRAM_ID);
+            get_associated_token_address_with_program_id(user, mint, &self.token_program);
 
         let vault_token_account =
-            get_associated_token_address_with_program_id(&vault_pda, mint, &TOKEN_2022_PROGRAM_ID);
+            get_associated_token_address_with_program_id(&vault_pda, mint, &self.token_program);
 
         let discriminator: [u8; 8] = [242, 35, 198, 137, 82, 225, 242, 182]; // this is the discriminator for the deposit instruction extracted from the idl 
         let mut data = discriminator.to_vec();
@@ -53,7 +67,7 @@ impl TransactionBuilder {
             AccountMeta::new(user_token_account, false),
             AccountMeta::new(vault_token_account, false),
             AccountMeta::new_readonly(*mint, false),
-            AccountMeta::new_readonly(TOKEN_2022_PROGRAM_ID, false),
+            AccountMeta::new_readonly(self.token_program, false),
         ];
 
         Ok(Instruction {
@@ -72,7 +86,7 @@ impl TransactionBuilder {
         let (vault_pda, vault_bump) = self.derive_vault_pda(user);
 
         let vault_token_account =
-            get_associated_token_address_with_program_id(&vault_pda, mint, &TOKEN_2022_PROGRAM_ID);
+            get_associated_token_address_with_program_id(&vault_pda, mint, &self.token_program);
 
         let discriminator: [u8; 8] = [48, 191, 163, 44, 71, 129, 63, 164]; // this is the discriminator for initialize vault instruction from our idl
 
@@ -85,7 +99,7 @@ impl TransactionBuilder {
             AccountMeta::new(vault_pda, false),
             AccountMeta::new(vault_token_account, false),
             AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
-            AccountMeta::new_readonly(TOKEN_2022_PROGRAM_ID, false),
+            AccountMeta::new_readonly(self.token_program, false),
             AccountMeta::new_readonly(ASSOCIATED_TOKEN_PROGRAM_ID, false),
             AccountMeta::new_readonly(sysvar::rent::ID, false),
         ];
@@ -106,10 +120,10 @@ impl TransactionBuilder {
         let (vault_pda, _) = self.derive_vault_pda(user);
 
         let vault_token_account =
-            get_associated_token_address_with_program_id(&vault_pda, mint, &TOKEN_2022_PROGRAM_ID);
+            get_associated_token_address_with_program_id(&vault_pda, mint, &self.token_program);
 
         let user_token_account =
-            get_associated_token_address_with_program_id(user, mint, &TOKEN_2022_PROGRAM_ID);
+            get_associated_token_address_with_program_id(user, mint, &self.token_program);
 
         let discriminator: [u8; 8] = [183, 18, 70, 156, 148, 109, 161, 34];
 
@@ -122,7 +136,7 @@ impl TransactionBuilder {
             AccountMeta::new(vault_token_account, false),
             AccountMeta::new(user_token_account, false),
             AccountMeta::new_readonly(*mint, false),
-            AccountMeta::new_readonly(TOKEN_2022_PROGRAM_ID, false),
+            AccountMeta::new_readonly(self.token_program, false),
         ];
 
         Ok(Instruction {
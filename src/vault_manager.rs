@@ -2,24 +2,38 @@
 // this is the vualt manager and it can sign and send transacation to the blockchain on user's behave given that 
 // we give the user keypair . In this version I am not supporthing user's private key but it can be implemented using MPC and then this can be implemented
 
+use crate::config::Config;
+use crate::error_handling::{Deadline, VaultError, VaultResult};
+use crate::rpc_endpoints::{RpcEndpoints, RpcFailover, RpcRole};
 use crate::transaction_builder::TransactionBuilder;
+use anyhow::Context;
 use borsh::BorshDeserialize;
 use solana_client::{
     rpc_client::RpcClient,
     rpc_config::CommitmentConfig,
 };
+use solana_client::nonce_utils;
 use solana_sdk::{
+    hash::Hash,
+    instruction::Instruction,
+    message::Message,
+    nonce::state::State as NonceState,
     pubkey::Pubkey,
     signature::{Keypair, Signature, Signer},
     transaction::Transaction,
 };
+use solana_system_interface::instruction as system_instruction;
 
+use crate::db::processed_events;
 use crate::states::CollateralVault;
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
 
 // VaultManager handles the core operations of vaults
 // It manages initialization, deposits, withdrawals, and balance tracking
 pub struct VaultManager {
-    rpc_client: RpcClient, // Rpc connection url to the network
+    rpc_client: RpcFailover, // Rpc connections used for reads
+    sender: RpcFailover, // Rpc connections used for blockhashes and sending transactions
     tx_builder: TransactionBuilder,
     payer: Keypair, // the payer who pays the required fees
 }
@@ -27,82 +41,193 @@ pub struct VaultManager {
 impl VaultManager {
     // Create a new VaultManager instance with given RPC endpoint and program ID
     pub fn new(rpc_url: String, program_id: Pubkey, payer: Keypair) -> Self {
-        let rpc_client = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed());
+        let rpc_client =
+            RpcClient::new_with_commitment(rpc_url.clone(), CommitmentConfig::confirmed());
+        let sender = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed());
         let tx_builder = TransactionBuilder::new(program_id);
 
         Self {
-            rpc_client,
+            rpc_client: rpc_client.into(),
+            sender: sender.into(),
             tx_builder,
             payer,
         }
     }
 
+    // Create a VaultManager for the configured network and commitment, paying with the
+    // configured payer. Fails in no-signing mode, see `Signing`
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let payer = config.signing.payer()?;
+        let mut manager = Self::new(config.rpc_url.clone(), config.program_id, payer)
+            .with_endpoints(&config.rpc_endpoints, config.commitment_config());
+        manager.tx_builder = manager.tx_builder.with_token_program(config.token_program);
+        Ok(manager)
+    }
+
+    // Read from and send through the weighted endpoints of `RPC_URLS` instead of a single url,
+    // at the given commitment instead of confirmed
+    pub fn with_endpoints(
+        mut self,
+        endpoints: &RpcEndpoints,
+        commitment: CommitmentConfig,
+    ) -> Self {
+        self.rpc_client = endpoints.failover(RpcRole::Read, commitment);
+        self.sender = endpoints.failover(RpcRole::Send, commitment);
+        self
+    }
+
     // Initialize a new vault for a user
     // This creates the vault account on-chain and records it
-    pub fn initialize_vault(&self, user: &Keypair, mint: &Pubkey) -> anyhow::Result<Signature> {
+    #[tracing::instrument(name = "vault_operation", skip_all, fields(operation = "initialize", user = %user.pubkey(), vault = %self.vault_of(&user.pubkey()), mint = %mint))]
+    pub fn initialize_vault(&self, user: &Keypair, mint: &Pubkey) -> VaultResult<Signature> {
         
         let ix = self
             .tx_builder
             .build_initialize_vault_ix(&user.pubkey(), mint)?;
 
-        let recent_blockhash = self.rpc_client.get_latest_blockhash()?;
+        let recent_blockhash = self.sender.call(|rpc| rpc.get_latest_blockhash())?;
 
         let mut tx = Transaction::new_with_payer(&[ix], Some(&self.payer.pubkey()));
 
         tx.sign(&[&self.payer, user], recent_blockhash);
 
-        let sig = self.rpc_client.send_and_confirm_transaction(&tx)?;
+        let sig = self.send_and_confirm("initialize", &tx)?;
 
         Ok(sig)
     }
 
     // Process a deposit to a user's vault
     // Transfers tokens from user's wallet to the vault account
-    pub fn deposit(&self, user: &Keypair, mint: &Pubkey, amount: u64) -> anyhow::Result<Signature> {
+    #[tracing::instrument(name = "vault_operation", skip_all, fields(operation = "deposit", user = %user.pubkey(), vault = %self.vault_of(&user.pubkey()), amount = amount))]
+    pub fn deposit(&self, user: &Keypair, mint: &Pubkey, amount: u64) -> VaultResult<Signature> {
+        self.preflight_deposit(&user.pubkey(), mint, amount)?;
+
         let ix = self
             .tx_builder
             .build_deposit_ix(&user.pubkey(), mint, amount)?;
 
-        let recent_blockhash = self.rpc_client.get_latest_blockhash()?;
+        let recent_blockhash = self.sender.call(|rpc| rpc.get_latest_blockhash())?;
 
         let mut tx = Transaction::new_with_payer(&[ix], Some(&self.payer.pubkey()));
 
         tx.sign(&[&self.payer, user], recent_blockhash);
 
-        let signature = self.rpc_client.send_and_confirm_transaction(&tx)?;
+        let signature = self.send_and_confirm("deposit", &tx)?;
 
         Ok(signature)
     }
 
     // Process a withdrawal from a user's vault
     // Transfers tokens from vault back to user's wallet
+    #[tracing::instrument(name = "vault_operation", skip_all, fields(operation = "withdraw", user = %user.pubkey(), vault = %self.vault_of(&user.pubkey()), amount = amount))]
     pub fn withdraw(
         &self,
         user: &Keypair,
         mint: &Pubkey,
         amount: u64,
-    ) -> anyhow::Result<Signature> {
+    ) -> VaultResult<Signature> {
+        self.preflight_withdraw(&user.pubkey(), mint, amount)?;
+
         let ix = self
             .tx_builder
             .build_withdraw_ix(&user.pubkey(), mint, amount)?;
 
-        let recent_blockhash = self.rpc_client.get_latest_blockhash()?;
+        let recent_blockhash = self.sender.call(|rpc| rpc.get_latest_blockhash())?;
 
         let mut tx = Transaction::new_with_payer(&[ix], Some(&self.payer.pubkey()));
 
         tx.sign(&[&self.payer, user], recent_blockhash);
 
-        let sig = self.rpc_client.send_and_confirm_transaction(&tx)?;
+        let sig = self.send_and_confirm("withdraw", &tx)?;
 
         Ok(sig)
     }
 
+    // Check a deposit before sending it: the user's token account must exist and hold enough tokens
+    #[tracing::instrument(level = "debug", skip_all, fields(user = %user, amount = amount))]
+    pub fn preflight_deposit(&self, user: &Pubkey, mint: &Pubkey, amount: u64) -> VaultResult<()> {
+        if amount == 0 {
+            return Err(VaultError::InvalidAmount { amount });
+        }
+
+        let user_token_account = self.tx_builder.derive_token_account(user, mint);
+
+        self.ensure_token_balance(&user_token_account, amount)
+    }
+
+    // Check a withdrawal before sending it: the vault must exist with enough available balance
+    // and the vault token account must actually hold the tokens
+    #[tracing::instrument(level = "debug", skip_all, fields(user = %user, amount = amount))]
+    pub fn preflight_withdraw(&self, user: &Pubkey, mint: &Pubkey, amount: u64) -> VaultResult<()> {
+        if amount == 0 {
+            return Err(VaultError::InvalidAmount { amount });
+        }
+
+        let (vault_pda, _) = self.tx_builder.derive_vault_pda(user);
+
+        let vault_account = self
+            .rpc_client
+            .call(|rpc| rpc.get_account_with_commitment(&vault_pda, rpc.commitment()))?
+            .value
+            .ok_or_else(|| VaultError::AccountNotFound {
+                account: vault_pda.to_string(),
+            })?;
+
+        let vault = CollateralVault::try_from_slice(&vault_account.data)?;
+
+        if vault.available_balance < amount {
+            return Err(VaultError::InsufficientBalance {
+                required: amount,
+                available: vault.available_balance,
+            });
+        }
+
+        let vault_token_account = self.tx_builder.derive_token_account(&vault_pda, mint);
+
+        self.ensure_token_balance(&vault_token_account, amount)
+    }
+
+    // Make sure a token account exists and holds at least `required` base units
+    fn ensure_token_balance(&self, token_account: &Pubkey, required: u64) -> VaultResult<()> {
+        let exists = self
+            .rpc_client
+            .call(|rpc| rpc.get_account_with_commitment(token_account, rpc.commitment()))?
+            .value
+            .is_some();
+
+        if !exists {
+            return Err(VaultError::AccountNotFound {
+                account: token_account.to_string(),
+            });
+        }
+
+        let balance = self
+            .rpc_client
+            .call(|rpc| rpc.get_token_account_balance(token_account))?;
+        let available = balance
+            .amount
+            .parse::<u64>()
+            .map_err(|e| VaultError::SerializationError {
+                reason: e.to_string(),
+            })?;
+
+        if available < required {
+            return Err(VaultError::InsufficientBalance {
+                required,
+                available,
+            });
+        }
+
+        Ok(())
+    }
+
     // Get the current state of a vault from the blockchain
-    pub fn get_vault_state(&self, user: &Pubkey) -> anyhow::Result<CollateralVault> {
+    #[tracing::instrument(level = "debug", skip_all, fields(user = %user))]
+    pub fn get_vault_state(&self, user: &Pubkey) -> VaultResult<CollateralVault> {
 
         let (vault_pda, _) = self.tx_builder.derive_vault_pda(user);
 
-        let account = self.rpc_client.get_account(&vault_pda)?;
+        let account = self.rpc_client.call(|rpc| rpc.get_account(&vault_pda))?;
 
         let vault = CollateralVault::try_from_slice(&account.data)?;
 
@@ -110,23 +235,201 @@ impl VaultManager {
     }
 
     // Get both available and locked balance for a vault
-    pub fn get_balances(&self, user: &Pubkey) -> anyhow::Result<(u64, u64)> {
+    pub fn get_balances(&self, user: &Pubkey) -> VaultResult<(u64, u64)> {
         let vault = self.get_vault_state(user)?;
         Ok((vault.available_balance, vault.locked_balance))
     }
 
     // Get recent transaction signatures for an address
     // Used to track vault activity
-    pub fn get_recent_transactions(&self, address: &Pubkey) -> anyhow::Result<Vec<Signature>> {
-        let sig_infos = self.rpc_client.get_signatures_for_address(address)?;
+    pub fn get_recent_transactions(&self, address: &Pubkey) -> VaultResult<Vec<Signature>> {
+        let sig_infos = self
+            .rpc_client
+            .call(|rpc| rpc.get_signatures_for_address(address))?;
 
         let signatures = sig_infos
             .into_iter()
             .map(|info| info.signature.parse::<Signature>())
-            .collect::<Result<Vec<_>, _>>()?;
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| VaultError::SerializationError {
+                reason: e.to_string(),
+            })?;
 
         Ok(signatures)
     }
+
+    // Wait until the indexer has processed a signature so callers get read-your-writes semantics
+    // The indexer marks a signature processed only after its balance updates are written,
+    // so seeing it in processed_events means Postgres already reflects the transaction
+    // Gives up once the caller's deadline passes
+    #[tracing::instrument(skip_all, fields(signature = %signature))]
+    pub async fn wait_until_indexed(
+        &self,
+        signature: &Signature,
+        pool: &PgPool,
+        deadline: Deadline,
+    ) -> VaultResult<()> {
+        let sig = signature.to_string();
+        let started = Instant::now();
+
+        loop {
+            if processed_events::is_processed(pool, &sig).await? {
+                return Ok(());
+            }
+
+            if deadline.is_expired() {
+                return Err(VaultError::NotIndexed {
+                    signature: sig,
+                    waited_ms: started.elapsed().as_millis(),
+                });
+            }
+
+            tokio::time::sleep(deadline.cap(Duration::from_millis(250))).await;
+        }
+    }
+
+    // Create a durable nonce account whose authority is the payer
+    // Nonce-based transactions don't expire like recent blockhashes do, so they
+    // can sit in an approval queue (e.g. ops sign-off) for as long as needed
+    #[tracing::instrument(name = "vault_operation", skip_all, fields(operation = "create_nonce_account", nonce = %nonce_account.pubkey()))]
+    pub fn create_nonce_account(&self, nonce_account: &Keypair) -> VaultResult<Signature> {
+        let lamports = self
+            .rpc_client
+            .call(|rpc| rpc.get_minimum_balance_for_rent_exemption(NonceState::size()))?;
+
+        let ixs = system_instruction::create_nonce_account(
+            &self.payer.pubkey(),
+            &nonce_account.pubkey(),
+            &self.payer.pubkey(),
+            lamports,
+        );
+
+        let recent_blockhash = self.sender.call(|rpc| rpc.get_latest_blockhash())?;
+
+        let mut tx = Transaction::new_with_payer(&ixs, Some(&self.payer.pubkey()));
+
+        tx.sign(&[&self.payer, nonce_account], recent_blockhash);
+
+        let sig = self.send_and_confirm("create_nonce_account", &tx)?;
+
+        Ok(sig)
+    }
+
+    // Read the blockhash currently stored in a nonce account
+    pub fn get_nonce_blockhash(&self, nonce_pubkey: &Pubkey) -> VaultResult<Hash> {
+        let rpc = self.rpc_client.primary();
+        let account = nonce_utils::get_account_with_commitment(rpc, nonce_pubkey, rpc.commitment())
+            .context("can't read nonce account")?;
+
+        let data = nonce_utils::data_from_account(&account).context("invalid nonce account")?;
+
+        Ok(data.blockhash())
+    }
+
+    // Build an unsigned transaction that uses the durable nonce instead of a recent blockhash
+    // The advance_nonce instruction is prepended, as required by the runtime
+    pub fn build_nonce_transaction(
+        &self,
+        instructions: &[Instruction],
+        nonce_pubkey: &Pubkey,
+    ) -> VaultResult<Transaction> {
+        let nonce_blockhash = self.get_nonce_blockhash(nonce_pubkey)?;
+
+        Ok(self.nonce_transaction(instructions, nonce_pubkey, nonce_blockhash))
+    }
+
+    // Build an unsigned nonce-based withdrawal so it can wait for approval before signing
+    pub fn build_nonce_withdraw_tx(
+        &self,
+        user: &Pubkey,
+        mint: &Pubkey,
+        amount: u64,
+        nonce_pubkey: &Pubkey,
+    ) -> VaultResult<Transaction> {
+        let ix = self.tx_builder.build_withdraw_ix(user, mint, amount)?;
+
+        self.build_nonce_transaction(&[ix], nonce_pubkey)
+    }
+
+    // Sign a nonce-based transaction with the payer plus any extra signers and send it
+    // The payer is the nonce authority, so its signature also authorizes advancing the nonce
+    #[tracing::instrument(
+        name = "vault_operation",
+        skip_all,
+        fields(operation = "nonce_transaction")
+    )]
+    pub fn send_nonce_transaction(
+        &self,
+        mut tx: Transaction,
+        signers: &[&Keypair],
+    ) -> VaultResult<Signature> {
+        let nonce_blockhash = tx.message.recent_blockhash;
+
+        let mut all_signers: Vec<&Keypair> = vec![&self.payer];
+        all_signers.extend_from_slice(signers);
+
+        tx.try_sign(&all_signers, nonce_blockhash)
+            .map_err(|e| VaultError::TransactionFailed {
+                reason: e.to_string(),
+            })?;
+
+        let sig = self.send_and_confirm("nonce_transaction", &tx)?;
+
+        Ok(sig)
+    }
+
+    // Send a signed transaction and wait for it to be confirmed, recording
+    // how long that took and whether it landed
+    #[tracing::instrument(level = "debug", skip_all, fields(signature, duration_ms))]
+    fn send_and_confirm(
+        &self,
+        operation: &'static str,
+        tx: &Transaction,
+    ) -> VaultResult<Signature> {
+        let started = Instant::now();
+        let result = self.sender.call(|rpc| rpc.send_and_confirm_transaction(tx));
+        let elapsed = started.elapsed();
+
+        let span = tracing::Span::current();
+        span.record("duration_ms", elapsed.as_millis() as u64);
+        if let Ok(signature) = &result {
+            span.record("signature", tracing::field::display(signature));
+        }
+
+        let outcome = if result.is_ok() { "confirmed" } else { "failed" };
+        metrics::counter!(
+            "vault_transactions_total",
+            "operation" => operation,
+            "outcome" => outcome
+        )
+        .increment(1);
+        metrics::histogram!("vault_transaction_confirm_seconds", "operation" => operation)
+            .record(elapsed.as_secs_f64());
+
+        Ok(result?)
+    }
+
+    // The vault PDA of `user`, for span fields
+    fn vault_of(&self, user: &Pubkey) -> Pubkey {
+        self.tx_builder.derive_vault_pda(user).0
+    }
+
+    fn nonce_transaction(
+        &self,
+        instructions: &[Instruction],
+        nonce_pubkey: &Pubkey,
+        nonce_blockhash: Hash,
+    ) -> Transaction {
+        let mut message = Message::new_with_nonce(
+            instructions.to_vec(),
+            Some(&self.payer.pubkey()),
+            nonce_pubkey,
+            &self.payer.pubkey(),
+        );
+        message.recent_blockhash = nonce_blockhash;
+
+        Transaction::new_unsigned(message)
+    }
 }
 
 #[cfg(test)]
@@ -190,6 +493,28 @@ mod tests {
         assert_ne!(pda1, pda2);
     }
 
+    #[test]
+    fn test_nonce_transaction_starts_with_advance_nonce() {
+        let vm = create_test_vault_manager();
+        let tx_builder = create_test_tx_builder();
+        let user = Keypair::new();
+        let mint = Pubkey::new_unique();
+        let nonce_pubkey = Pubkey::new_unique();
+        let nonce_blockhash = Hash::new_unique();
+
+        let ix = tx_builder
+            .build_withdraw_ix(&user.pubkey(), &mint, 1_000)
+            .unwrap();
+        let tx = vm.nonce_transaction(&[ix], &nonce_pubkey, nonce_blockhash);
+
+        assert_eq!(tx.message.recent_blockhash, nonce_blockhash);
+        assert_eq!(tx.message.instructions.len(), 2);
+
+        let first_program = tx.message.account_keys
+            [tx.message.instructions[0].program_id_index as usize];
+        assert_eq!(first_program, solana_system_interface::program::ID);
+    }
+
     #[test]
     fn test_deposit_request_creation() {
         let amount = 1_000_000_000u64;